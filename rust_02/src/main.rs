@@ -1,5 +1,6 @@
+use std::collections::HashMap;
 use std::fs::{File, OpenOptions};
-use std::io::{Read, Write, Seek, SeekFrom};
+use std::io::{self, Read, Write, Seek, SeekFrom};
 use std::env;
 use std::process;
 
@@ -14,8 +15,18 @@ fn main() {
     let mut file_path = String::new();
     let mut mode = String::new();
     let mut hex_data = String::new();
-    let mut offset = 0usize;
+    let mut offset_expr: Option<String> = None;
     let mut size = 0usize;
+    let mut squeeze = true;
+    let mut range_expr: Option<String> = None;
+    let mut store_at_expr: Option<String> = None;
+    let mut annotations_path: Option<String> = None;
+    let mut interactive = false;
+    let mut algo = String::from("crc32");
+    let mut little_endian = true;
+    let mut resync = false;
+    let mut block_size = 32usize;
+    let mut positional: Vec<String> = Vec::new();
 
     let mut i = 1;
     while i < args.len() {
@@ -29,6 +40,76 @@ fn main() {
                     process::exit(1);
                 }
             }
+            "diff" => {
+                mode = "diff".to_string();
+                i += 1;
+            }
+            "--resync" => {
+                resync = true;
+                i += 1;
+            }
+            "--block" => {
+                if i + 1 < args.len() {
+                    block_size = match args[i + 1].parse() {
+                        Ok(v) if v > 0 => v,
+                        _ => {
+                            eprintln!("Erreur: block invalide");
+                            process::exit(1);
+                        }
+                    };
+                    i += 2;
+                } else {
+                    eprintln!("Erreur: --block nécessite une valeur");
+                    process::exit(1);
+                }
+            }
+            "fixsum" => {
+                mode = "fixsum".to_string();
+                i += 1;
+            }
+            "--range" => {
+                if i + 1 < args.len() {
+                    range_expr = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    eprintln!("Erreur: --range nécessite une valeur");
+                    process::exit(1);
+                }
+            }
+            "--store-at" => {
+                if i + 1 < args.len() {
+                    store_at_expr = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    eprintln!("Erreur: --store-at nécessite une valeur");
+                    process::exit(1);
+                }
+            }
+            "--algo" => {
+                if i + 1 < args.len() {
+                    algo = args[i + 1].clone();
+                    i += 2;
+                } else {
+                    eprintln!("Erreur: --algo nécessite une valeur");
+                    process::exit(1);
+                }
+            }
+            "--endian" => {
+                if i + 1 < args.len() {
+                    little_endian = match args[i + 1].as_str() {
+                        "le" => true,
+                        "be" => false,
+                        _ => {
+                            eprintln!("Erreur: --endian doit valoir le ou be");
+                            process::exit(1);
+                        }
+                    };
+                    i += 2;
+                } else {
+                    eprintln!("Erreur: --endian nécessite une valeur");
+                    process::exit(1);
+                }
+            }
             "-r" | "--read" => {
                 mode = "read".to_string();
                 i += 1;
@@ -45,24 +126,7 @@ fn main() {
             }
             "-o" | "--offset" => {
                 if i + 1 < args.len() {
-                    let offset_str = &args[i + 1];
-                    offset = if offset_str.starts_with("0x") || offset_str.starts_with("0X") {
-                        match usize::from_str_radix(&offset_str[2..], 16) {
-                            Ok(v) => v,
-                            Err(_) => {
-                                eprintln!("Erreur: offset hexadécimal invalide");
-                                process::exit(1);
-                            }
-                        }
-                    } else {
-                        match offset_str.parse() {
-                            Ok(v) => v,
-                            Err(_) => {
-                                eprintln!("Erreur: offset invalide");
-                                process::exit(1);
-                            }
-                        }
-                    };
+                    offset_expr = Some(args[i + 1].clone());
                     i += 2;
                 } else {
                     eprintln!("Erreur: --offset nécessite une valeur");
@@ -84,10 +148,31 @@ fn main() {
                     process::exit(1);
                 }
             }
+            "-a" | "--annotations" => {
+                if i + 1 < args.len() {
+                    annotations_path = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    eprintln!("Erreur: --annotations nécessite une valeur");
+                    process::exit(1);
+                }
+            }
+            "-i" | "--interactive" => {
+                interactive = true;
+                i += 1;
+            }
+            "--no-squeeze" => {
+                squeeze = false;
+                i += 1;
+            }
             "-h" | "--help" => {
                 print_help();
                 return;
             }
+            arg if mode == "diff" && !arg.starts_with('-') => {
+                positional.push(arg.to_string());
+                i += 1;
+            }
             _ => {
                 eprintln!("Option inconnue: {}", args[i]);
                 process::exit(1);
@@ -95,26 +180,80 @@ fn main() {
         }
     }
 
+    if mode == "diff" {
+        if positional.len() != 2 {
+            eprintln!("Erreur: diff nécessite deux fichiers");
+            process::exit(1);
+        }
+        diff_files(&positional[0], &positional[1], resync, block_size);
+        return;
+    }
+
     if file_path.is_empty() {
         eprintln!("Erreur: --file est obligatoire");
         process::exit(1);
     }
 
-    if mode == "read" {
+    let symbols = match &annotations_path {
+        Some(path) => match load_annotations(path) {
+            Ok(symbols) => symbols,
+            Err(msg) => {
+                eprintln!("Erreur: {}", msg);
+                process::exit(1);
+            }
+        },
+        None => HashMap::new(),
+    };
+    let offset = match offset_expr.as_deref().map(|e| eval_offset(e, &symbols)) {
+        Some(Ok(v)) => v,
+        Some(Err(msg)) => {
+            eprintln!("Erreur: {}", msg);
+            process::exit(1);
+        }
+        None => 0,
+    };
+
+    if mode == "read" && interactive {
+        goto_prompt(&file_path, offset, size, squeeze, &symbols);
+    } else if mode == "read" {
         if size == 0 {
             eprintln!("Erreur: --size est obligatoire en mode lecture");
             process::exit(1);
         }
-        read_file(&file_path, offset, size);
+        read_file(&file_path, offset, size, squeeze);
     } else if mode == "write" {
         write_file(&file_path, offset, &hex_data);
+    } else if mode == "fixsum" {
+        let Some(range_expr) = range_expr else {
+            eprintln!("Erreur: --range est obligatoire pour fixsum");
+            process::exit(1);
+        };
+        let Some(store_at_expr) = store_at_expr else {
+            eprintln!("Erreur: --store-at est obligatoire pour fixsum");
+            process::exit(1);
+        };
+        let range = match parse_range(&range_expr, &symbols) {
+            Ok(r) => r,
+            Err(msg) => {
+                eprintln!("Erreur: {}", msg);
+                process::exit(1);
+            }
+        };
+        let store_at = match eval_offset(&store_at_expr, &symbols) {
+            Ok(v) => v,
+            Err(msg) => {
+                eprintln!("Erreur: {}", msg);
+                process::exit(1);
+            }
+        };
+        fix_checksum(&file_path, range, store_at, &algo, little_endian);
     } else {
-        eprintln!("Erreur: spécifiez --read ou --write");
+        eprintln!("Erreur: spécifiez --read, --write ou fixsum");
         process::exit(1);
     }
 }
 
-fn read_file(path: &str, offset: usize, size: usize) {
+fn read_file(path: &str, offset: usize, size: usize, squeeze: bool) {
     let mut file = match File::open(path) {
         Ok(f) => f,
         Err(_) => {
@@ -123,37 +262,131 @@ fn read_file(path: &str, offset: usize, size: usize) {
         }
     };
 
-    if let Err(_) = file.seek(SeekFrom::Start(offset as u64)) {
-        eprintln!("Erreur: offset invalide");
+    let len = match file.metadata() {
+        Ok(m) => m.len() as usize,
+        Err(_) => {
+            eprintln!("Erreur: impossible d'ouvrir le fichier");
+            process::exit(1);
+        }
+    };
+    if offset.checked_add(size).is_none_or(|end| end > len) {
+        eprintln!("Erreur: impossible de lire les données");
         process::exit(1);
     }
 
-    let mut buffer = vec![0u8; size];
-    match file.read_exact(&mut buffer) {
-        Ok(_) => {
-            let mut current_offset = offset;
-            for chunk in buffer.chunks(16) {
-                print!("{:08x}: ", current_offset);
-                for byte in chunk {
-                    print!("{:02x} ", byte);
-                }
-                print!(" |");
-                for byte in chunk {
-                    if *byte >= 32 && *byte < 127 {
-                        print!("{}", *byte as char);
-                    } else {
-                        print!(".");
-                    }
-                }
-                println!("|");
-                current_offset += chunk.len();
+    let end = offset + size;
+    let holes = if squeeze {
+        find_holes(&file, offset, end)
+    } else {
+        Vec::new()
+    };
+
+    let mut current_offset = offset;
+    let mut zero_run = 0usize;
+    let mut need_seek = true;
+    let mut line = [0u8; 16];
+
+    while current_offset < end {
+        if let Some(&(_, hole_end)) = holes
+            .iter()
+            .find(|(start, stop)| *start <= current_offset && current_offset < *stop)
+        {
+            let hole_end = hole_end.min(end);
+            zero_run += hole_end - current_offset;
+            current_offset = hole_end;
+            need_seek = true;
+            continue;
+        }
+
+        let next_hole = holes
+            .iter()
+            .map(|(start, _)| *start)
+            .filter(|start| *start > current_offset)
+            .min()
+            .unwrap_or(end);
+        let n = 16.min(end - current_offset).min(next_hole - current_offset);
+
+        if need_seek {
+            if file.seek(SeekFrom::Start(current_offset as u64)).is_err() {
+                eprintln!("Erreur: offset invalide");
+                process::exit(1);
             }
+            need_seek = false;
         }
-        Err(_) => {
+        if file.read_exact(&mut line[..n]).is_err() {
             eprintln!("Erreur: impossible de lire les données");
             process::exit(1);
         }
+
+        if squeeze && n == 16 && line.iter().all(|b| *b == 0) {
+            zero_run += n;
+        } else {
+            print_squeezed(zero_run);
+            zero_run = 0;
+            print_line(current_offset, &line[..n]);
+        }
+        current_offset += n;
     }
+
+    print_squeezed(zero_run);
+}
+
+fn print_line(offset: usize, chunk: &[u8]) {
+    print!("{:08x}: ", offset);
+    for byte in chunk {
+        print!("{:02x} ", byte);
+    }
+    println!(" |{}|", bytes_to_ascii(chunk));
+}
+
+fn print_squeezed(count: usize) {
+    if count > 0 {
+        println!("* ({} bytes of zeros / hole)", count);
+    }
+}
+
+#[cfg(unix)]
+fn find_holes(file: &File, start: usize, end: usize) -> Vec<(usize, usize)> {
+    use std::os::unix::io::AsRawFd;
+
+    let fd = file.as_raw_fd();
+    let mut holes = Vec::new();
+    let mut pos = start;
+
+    while pos < end {
+        // SAFETY: lseek only repositions the descriptor, which stays owned by `file`.
+        let data = unsafe { libc::lseek(fd, pos as libc::off_t, libc::SEEK_DATA) };
+        if data < 0 {
+            // ENXIO means there is no more data past `pos`: the rest is a hole.
+            // Any other error (e.g. EINVAL) means the filesystem can't tell us.
+            if io::Error::last_os_error().raw_os_error() == Some(libc::ENXIO) {
+                holes.push((pos, end));
+            }
+            break;
+        }
+
+        let data = data as usize;
+        if data > pos {
+            holes.push((pos, data.min(end)));
+        }
+        if data >= end {
+            break;
+        }
+
+        // SAFETY: same as above.
+        let hole = unsafe { libc::lseek(fd, data as libc::off_t, libc::SEEK_HOLE) };
+        if hole < 0 {
+            break;
+        }
+        pos = hole as usize;
+    }
+
+    holes
+}
+
+#[cfg(not(unix))]
+fn find_holes(_file: &File, _start: usize, _end: usize) -> Vec<(usize, usize)> {
+    Vec::new()
 }
 
 fn write_file(path: &str, offset: usize, hex_str: &str) {
@@ -173,7 +406,7 @@ fn write_file(path: &str, offset: usize, hex_str: &str) {
         }
     };
 
-    if let Err(_) = file.seek(SeekFrom::Start(offset as u64)) {
+    if file.seek(SeekFrom::Start(offset as u64)).is_err() {
         eprintln!("Erreur: offset invalide");
         process::exit(1);
     }
@@ -192,9 +425,493 @@ fn write_file(path: &str, offset: usize, hex_str: &str) {
     }
 }
 
+fn fix_checksum(path: &str, range: (usize, usize), store_at: usize, algo: &str, little_endian: bool) {
+    let (start, end) = range;
+    let field_len = match algo {
+        "crc32" => 4,
+        "sum16" => 2,
+        _ => {
+            eprintln!("Erreur: algorithme inconnu (crc32 ou sum16)");
+            process::exit(1);
+        }
+    };
+
+    if store_at < end && start < store_at + field_len {
+        eprintln!("Erreur: le champ de checksum chevauche la plage");
+        process::exit(1);
+    }
+
+    let mut file = match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(f) => f,
+        Err(_) => {
+            eprintln!("Erreur: impossible d'ouvrir le fichier");
+            process::exit(1);
+        }
+    };
+
+    if file.seek(SeekFrom::Start(start as u64)).is_err() {
+        eprintln!("Erreur: offset invalide");
+        process::exit(1);
+    }
+
+    let mut crc = 0xFFFF_FFFFu32;
+    let mut sum = 0u16;
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut remaining = end - start;
+    while remaining > 0 {
+        let n = remaining.min(buffer.len());
+        if file.read_exact(&mut buffer[..n]).is_err() {
+            eprintln!("Erreur: impossible de lire les données");
+            process::exit(1);
+        }
+        crc = crc32_update(crc, &buffer[..n]);
+        sum = buffer[..n]
+            .iter()
+            .fold(sum, |acc, b| acc.wrapping_add(*b as u16));
+        remaining -= n;
+    }
+
+    let bytes = match (algo, little_endian) {
+        ("crc32", true) => (!crc).to_le_bytes().to_vec(),
+        ("crc32", false) => (!crc).to_be_bytes().to_vec(),
+        (_, true) => sum.to_le_bytes().to_vec(),
+        (_, false) => sum.to_be_bytes().to_vec(),
+    };
+
+    if file.seek(SeekFrom::Start(store_at as u64)).is_err() {
+        eprintln!("Erreur: offset invalide");
+        process::exit(1);
+    }
+
+    match file.write_all(&bytes) {
+        Ok(_) => {
+            println!("{} over 0x{:08x}..0x{:08x}", algo, start, end);
+            println!("Writing {} bytes at offset 0x{:08x}", bytes.len(), store_at);
+            println!("Hex: {}", hex_to_display(&bytes));
+            println!("✓ Checksum fixed");
+        }
+        Err(_) => {
+            eprintln!("Erreur: impossible d'écrire les données");
+            process::exit(1);
+        }
+    }
+}
+
+// CRC-32/ISO-HDLC (zlib, PNG, Ethernet), bitwise to keep the tool dependency-free.
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    crc
+}
+
+struct Hunk {
+    a_start: usize,
+    a_len: usize,
+    b_start: usize,
+    b_len: usize,
+}
+
+fn diff_files(path_a: &str, path_b: &str, resync: bool, block_size: usize) {
+    let (a, b) = match (std::fs::read(path_a), std::fs::read(path_b)) {
+        (Ok(a), Ok(b)) => (a, b),
+        _ => {
+            eprintln!("Erreur: impossible d'ouvrir le fichier");
+            process::exit(1);
+        }
+    };
+
+    let hunks = if resync {
+        let matches = resync_matches(&a, &b, block_size);
+        hunks_between_matches(&matches, a.len(), b.len())
+    } else {
+        positional_hunks(&a, &b)
+    };
+
+    println!("--- {} ({} bytes)", path_a, a.len());
+    println!("+++ {} ({} bytes)", path_b, b.len());
+
+    if hunks.is_empty() {
+        println!("✓ Files are identical");
+        return;
+    }
+
+    let mut only_a = 0;
+    let mut only_b = 0;
+    for hunk in &hunks {
+        let kind = match (hunk.a_len, hunk.b_len) {
+            (0, _) => "inserted",
+            (_, 0) => "deleted",
+            _ => "changed",
+        };
+        println!(
+            "@@ a 0x{:08x} ({}) | b 0x{:08x} ({}) {} @@",
+            hunk.a_start, hunk.a_len, hunk.b_start, hunk.b_len, kind
+        );
+        print_hunk_side('-', hunk.a_start, &a[hunk.a_start..hunk.a_start + hunk.a_len]);
+        print_hunk_side('+', hunk.b_start, &b[hunk.b_start..hunk.b_start + hunk.b_len]);
+        only_a += hunk.a_len;
+        only_b += hunk.b_len;
+    }
+
+    println!(
+        "{} hunks, {} bytes only in a, {} bytes only in b",
+        hunks.len(),
+        only_a,
+        only_b
+    );
+}
+
+fn print_hunk_side(sign: char, start: usize, bytes: &[u8]) {
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        println!(
+            "{} {:08x}: {}  |{}|",
+            sign,
+            start + i * 16,
+            hex_to_display(chunk),
+            bytes_to_ascii(chunk)
+        );
+    }
+}
+
+fn positional_hunks(a: &[u8], b: &[u8]) -> Vec<Hunk> {
+    let common = a.len().min(b.len());
+    let mut hunks = Vec::new();
+    let mut i = 0;
+
+    while i < common {
+        if a[i] == b[i] {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < common && a[i] != b[i] {
+            i += 1;
+        }
+        hunks.push(Hunk {
+            a_start: start,
+            a_len: i - start,
+            b_start: start,
+            b_len: i - start,
+        });
+    }
+
+    if a.len() != b.len() {
+        hunks.push(Hunk {
+            a_start: common,
+            a_len: a.len() - common,
+            b_start: common,
+            b_len: b.len() - common,
+        });
+    }
+    hunks
+}
+
+// Weak rolling checksum in the style of rsync: cheap to slide one byte at a
+// time, verified with a byte comparison on every hit.
+struct RollingHash {
+    a: u32,
+    b: u32,
+    len: u32,
+}
+
+impl RollingHash {
+    fn new(window: &[u8]) -> Self {
+        let len = window.len() as u32;
+        let mut a = 0u32;
+        let mut b = 0u32;
+        for (i, byte) in window.iter().enumerate() {
+            a = a.wrapping_add(*byte as u32);
+            b = b.wrapping_add((len - i as u32).wrapping_mul(*byte as u32));
+        }
+        RollingHash { a, b, len }
+    }
+
+    fn roll(&mut self, out: u8, into: u8) {
+        self.a = self.a.wrapping_sub(out as u32).wrapping_add(into as u32);
+        self.b = self
+            .b
+            .wrapping_sub(self.len.wrapping_mul(out as u32))
+            .wrapping_add(self.a);
+    }
+
+    fn digest(&self) -> u32 {
+        (self.a & 0xFFFF) | (self.b << 16)
+    }
+}
+
+/// Returns `(a_start, b_start, len)` runs of identical bytes, in increasing
+/// order on both sides, found by indexing `a` in fixed blocks and sliding a
+/// rolling hash over `b`.
+fn resync_matches(a: &[u8], b: &[u8], block: usize) -> Vec<(usize, usize, usize)> {
+    let mut index: HashMap<u32, Vec<usize>> = HashMap::new();
+    for pos in (0..a.len()).step_by(block) {
+        if pos + block <= a.len() {
+            index
+                .entry(RollingHash::new(&a[pos..pos + block]).digest())
+                .or_default()
+                .push(pos);
+        }
+    }
+
+    let mut matches = Vec::new();
+    let mut next_a = 0;
+    let mut next_b = 0;
+    let mut j = 0;
+    let mut hash = (block <= b.len()).then(|| RollingHash::new(&b[..block]));
+
+    while let Some(h) = hash.as_mut() {
+        let found = index.get(&h.digest()).and_then(|candidates| {
+            candidates
+                .iter()
+                .copied()
+                .find(|&pos| pos >= next_a && a[pos..pos + block] == b[j..j + block])
+        });
+
+        if let Some(mut a_pos) = found {
+            let mut b_pos = j;
+            while a_pos > next_a && b_pos > next_b && a[a_pos - 1] == b[b_pos - 1] {
+                a_pos -= 1;
+                b_pos -= 1;
+            }
+            let mut len = 0;
+            while a_pos + len < a.len() && b_pos + len < b.len() && a[a_pos + len] == b[b_pos + len] {
+                len += 1;
+            }
+
+            matches.push((a_pos, b_pos, len));
+            next_a = a_pos + len;
+            next_b = b_pos + len;
+            j = next_b;
+            hash = (j + block <= b.len()).then(|| RollingHash::new(&b[j..j + block]));
+        } else if j + block < b.len() {
+            h.roll(b[j], b[j + block]);
+            j += 1;
+        } else {
+            hash = None;
+        }
+    }
+
+    matches
+}
+
+fn hunks_between_matches(matches: &[(usize, usize, usize)], a_len: usize, b_len: usize) -> Vec<Hunk> {
+    let mut hunks = Vec::new();
+    let mut a_pos = 0;
+    let mut b_pos = 0;
+
+    for &(a_start, b_start, len) in matches.iter().chain([(a_len, b_len, 0)].iter()) {
+        if a_start > a_pos || b_start > b_pos {
+            hunks.push(Hunk {
+                a_start: a_pos,
+                a_len: a_start - a_pos,
+                b_start: b_pos,
+                b_len: b_start - b_pos,
+            });
+        }
+        a_pos = a_start + len;
+        b_pos = b_start + len;
+    }
+    hunks
+}
+
+fn goto_prompt(path: &str, start: usize, size: usize, squeeze: bool, symbols: &HashMap<String, usize>) {
+    let len = match std::fs::metadata(path) {
+        Ok(m) => m.len() as usize,
+        Err(_) => {
+            eprintln!("Erreur: impossible d'ouvrir le fichier");
+            process::exit(1);
+        }
+    };
+    let size = if size == 0 { 256 } else { size };
+    let mut symbols = symbols.clone();
+    let mut current = start;
+
+    println!("Goto expressions: 0x200 + 4*16, symbol names, '.' for the current offset, q to quit");
+    let stdin = io::stdin();
+    loop {
+        print!("goto 0x{:08x}> ", current);
+        if io::stdout().flush().is_err() {
+            return;
+        }
+
+        let mut line = String::new();
+        match stdin.read_line(&mut line) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+        let line = line.trim();
+        if line == "q" || line == "quit" {
+            return;
+        }
+
+        let target = if line.is_empty() {
+            current + size
+        } else {
+            symbols.insert(".".to_string(), current);
+            match eval_offset(line, &symbols) {
+                Ok(v) => v,
+                Err(msg) => {
+                    eprintln!("Erreur: {}", msg);
+                    continue;
+                }
+            }
+        };
+        if target >= len {
+            eprintln!("Erreur: offset 0x{:x} au-delà de la fin du fichier (0x{:x})", target, len);
+            continue;
+        }
+
+        current = target;
+        read_file(path, current, size.min(len - current), squeeze);
+    }
+}
+
+/// Loads `NAME OFFSET [# comment]` lines; `#` starts a comment, and OFFSET
+/// is the rest of the line, any expression over the names defined above it.
+fn load_annotations(path: &str) -> Result<HashMap<String, usize>, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|_| format!("impossible de lire les annotations {}", path))?;
+    let mut symbols = HashMap::new();
+
+    for (n, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (name, expr) = line
+            .split_once(char::is_whitespace)
+            .map(|(name, expr)| (name, expr.trim()))
+            .unwrap_or((line, ""));
+        if expr.is_empty() {
+            return Err(format!("{}:{}: attendu NAME OFFSET", path, n + 1));
+        }
+        let value = eval_offset(expr, &symbols).map_err(|msg| format!("{}:{}: {}", path, n + 1, msg))?;
+        symbols.insert(name.to_string(), value);
+    }
+
+    Ok(symbols)
+}
+
+/// Evaluates an offset expression: decimal or 0x literals, symbol names,
+/// `+ - * /` with the usual precedence and parentheses.
+fn eval_offset(expr: &str, symbols: &HashMap<String, usize>) -> Result<usize, String> {
+    let tokens = tokenize(expr)?;
+    let mut pos = 0;
+    let value = parse_sum(&tokens, &mut pos, symbols)?;
+    if pos != tokens.len() {
+        return Err("offset invalide".to_string());
+    }
+    Ok(value)
+}
+
+fn tokenize(expr: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = expr.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if "+-*/()".contains(c) {
+            tokens.push(c.to_string());
+            chars.next();
+        } else if c.is_alphanumeric() || c == '_' || c == '.' {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_alphanumeric() || c == '_' || c == '.' {
+                    word.push(c);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            tokens.push(word);
+        } else {
+            return Err(format!("caractère inattendu '{}'", c));
+        }
+    }
+
+    if tokens.is_empty() {
+        return Err("offset invalide".to_string());
+    }
+    Ok(tokens)
+}
+
+fn parse_sum(tokens: &[String], pos: &mut usize, symbols: &HashMap<String, usize>) -> Result<usize, String> {
+    let mut value = parse_product(tokens, pos, symbols)?;
+    while let Some(op) = tokens.get(*pos).map(String::as_str) {
+        let apply: fn(usize, usize) -> Option<usize> = match op {
+            "+" => usize::checked_add,
+            "-" => usize::checked_sub,
+            _ => break,
+        };
+        *pos += 1;
+        let rhs = parse_product(tokens, pos, symbols)?;
+        value = apply(value, rhs).ok_or("offset hors limites")?;
+    }
+    Ok(value)
+}
+
+fn parse_product(tokens: &[String], pos: &mut usize, symbols: &HashMap<String, usize>) -> Result<usize, String> {
+    let mut value = parse_atom(tokens, pos, symbols)?;
+    while let Some(op) = tokens.get(*pos).map(String::as_str) {
+        let apply: fn(usize, usize) -> Option<usize> = match op {
+            "*" => usize::checked_mul,
+            "/" => usize::checked_div,
+            _ => break,
+        };
+        *pos += 1;
+        let rhs = parse_atom(tokens, pos, symbols)?;
+        value = apply(value, rhs).ok_or("offset hors limites ou division par zéro")?;
+    }
+    Ok(value)
+}
+
+fn parse_atom(tokens: &[String], pos: &mut usize, symbols: &HashMap<String, usize>) -> Result<usize, String> {
+    let Some(token) = tokens.get(*pos) else {
+        return Err("expression incomplète".to_string());
+    };
+    *pos += 1;
+
+    if token == "(" {
+        let value = parse_sum(tokens, pos, symbols)?;
+        if tokens.get(*pos).map(String::as_str) != Some(")") {
+            return Err("parenthèse fermante manquante".to_string());
+        }
+        *pos += 1;
+        Ok(value)
+    } else if let Some(hex) = token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")) {
+        usize::from_str_radix(hex, 16).map_err(|_| "offset hexadécimal invalide".to_string())
+    } else if token.starts_with(|c: char| c.is_ascii_digit()) {
+        token.parse().map_err(|_| "offset invalide".to_string())
+    } else if let Some(value) = symbols.get(token) {
+        Ok(*value)
+    } else if token.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '.') {
+        Err(format!("symbole inconnu: {}", token))
+    } else {
+        Err("offset invalide".to_string())
+    }
+}
+
+fn parse_range(s: &str, symbols: &HashMap<String, usize>) -> Result<(usize, usize), String> {
+    let Some((start, end)) = s.split_once("..") else {
+        return Err("plage invalide (format START..END)".to_string());
+    };
+    let start = eval_offset(start, symbols)?;
+    let end = eval_offset(end, symbols)?;
+    if start >= end {
+        return Err("plage vide ou inversée".to_string());
+    }
+    Ok((start, end))
+}
+
 fn hex_to_bytes(hex_str: &str) -> Result<Vec<u8>, ()> {
     let hex_str = hex_str.trim();
-    if hex_str.len() % 2 != 0 {
+    if !hex_str.len().is_multiple_of(2) {
         return Err(());
     }
 
@@ -230,6 +947,8 @@ fn bytes_to_ascii(bytes: &[u8]) -> String {
 
 fn print_help() {
     println!("Usage: hextool [OPTIONS]");
+    println!("       hextool diff FILE_A FILE_B [--resync] [--block N]");
+    println!("       hextool fixsum -f FILE --range START..END --store-at OFFSET [--algo crc32|sum16] [--endian le|be]");
     println!();
     println!("Read and write binary files in hexadecimal");
     println!();
@@ -237,7 +956,20 @@ fn print_help() {
     println!("-f, --file         Target file");
     println!("-r, --read         Read mode (display hex)");
     println!("-w, --write        Write mode (hex string to write)");
-    println!("-o, --offset       Offset in bytes (decimal or 0x hex)");
+    println!("-o, --offset       Offset expression (e.g. 0x200 + 4*16 or header + 8)");
     println!("-s, --size         Number of bytes to read");
+    println!("    --no-squeeze   Print every line, even zero runs and sparse holes");
+    println!("-a, --annotations  Symbol file with NAME OFFSET lines, usable in offsets (# starts a comment)");
+    println!("-i, --interactive  Read mode: prompt for goto expressions");
     println!("-h, --help         Print help");
+    println!();
+    println!("fixsum options:");
+    println!("    --range        Checksummed region START..END (end exclusive)");
+    println!("    --store-at     Offset of the checksum field");
+    println!("    --algo         crc32 (4 bytes) or sum16 (byte sum, 2 bytes) [default: crc32]");
+    println!("    --endian       Byte order of the stored field, le or be [default: le]");
+    println!();
+    println!("diff options:");
+    println!("    --resync       Realign after inserted/deleted blocks (rolling-hash matching)");
+    println!("    --block        Block size used to resynchronize [default: 32]");
 }
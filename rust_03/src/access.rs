@@ -0,0 +1,218 @@
+//! Allow and deny lists. A list file has one entry per line: an IP address,
+//! a network in CIDR notation (`10.0.0.0/8`, `fd00::/8`) or an identity
+//! public key in hex. `#` starts a comment.
+//!
+//! Addresses are checked as soon as a connection is accepted; keys once
+//! the peer has proven its identity in the handshake. The deny list wins
+//! over the allow list, and with an allow list a peer must match one of
+//! its entries, by address or by key.
+
+use crate::device;
+use crate::identity::{self, fingerprint};
+use ed25519_dalek::VerifyingKey;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+enum Entry {
+    Network(IpAddr, u8),
+    Key(VerifyingKey),
+}
+
+impl Entry {
+    fn parse(text: &str) -> Result<Self, String> {
+        if text.len() == 64 && !text.contains(['.', ':']) {
+            return identity::parse_public_key(text).map(Entry::Key);
+        }
+        let (address, prefix) = match text.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (text, None),
+        };
+        let address: IpAddr = address
+            .parse()
+            .map_err(|_| format!("{} is neither an IP address nor a key", text))?;
+        let bits = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse()
+                .ok()
+                .filter(|prefix| *prefix <= bits)
+                .ok_or_else(|| format!("bad prefix length in {}", text))?,
+            None => bits,
+        };
+        Ok(Entry::Network(address, prefix))
+    }
+
+    fn matches_addr(&self, ip: IpAddr) -> bool {
+        let Entry::Network(network, prefix) = *self else {
+            return false;
+        };
+        match (network.to_canonical(), ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+
+    fn matches_key(&self, key: &VerifyingKey) -> bool {
+        matches!(self, Entry::Key(entry) if entry == key)
+    }
+}
+
+#[derive(Default)]
+struct List {
+    entries: Vec<Entry>,
+}
+
+impl List {
+    fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut entries = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let entry = line.split('#').next().unwrap_or("").trim();
+            if entry.is_empty() {
+                continue;
+            }
+            let entry = Entry::parse(entry).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: {}", path.display(), number + 1, e),
+                )
+            })?;
+            entries.push(entry);
+        }
+        Ok(List { entries })
+    }
+
+    fn matches_addr(&self, ip: IpAddr) -> bool {
+        self.entries.iter().any(|entry| entry.matches_addr(ip))
+    }
+
+    fn matches_key(&self, key: &VerifyingKey) -> bool {
+        self.entries.iter().any(|entry| entry.matches_key(key))
+    }
+
+    fn has_keys(&self) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| matches!(entry, Entry::Key(_)))
+    }
+}
+
+/// Where the lists are read from, kept to reload them.
+#[derive(Clone)]
+pub struct AccessFiles {
+    pub allow: Option<PathBuf>,
+    /// A missing deny file is an empty list.
+    pub deny: PathBuf,
+}
+
+pub struct Access {
+    allow: Option<List>,
+    deny: List,
+}
+
+impl Access {
+    pub fn load(files: &AccessFiles) -> io::Result<Self> {
+        let allow = files.allow.as_deref().map(List::load).transpose()?;
+        let deny = match List::load(&files.deny) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => List::default(),
+            result => result?,
+        };
+        Ok(Access { allow, deny })
+    }
+
+    /// Entries loaded, for the startup and reload messages.
+    pub fn describe(&self) -> String {
+        match &self.allow {
+            Some(allow) => format!(
+                "{} allowed, {} denied",
+                allow.entries.len(),
+                self.deny.entries.len()
+            ),
+            None => format!("{} denied", self.deny.entries.len()),
+        }
+    }
+
+    /// Before the handshake, when only the address is known. A peer that
+    /// could still be allowed by its key is let through to the handshake.
+    pub fn check_addr(&self, ip: IpAddr) -> Result<(), String> {
+        if self.deny.matches_addr(ip) {
+            return Err(format!("{} is on the deny list", ip));
+        }
+        match &self.allow {
+            Some(allow) if !allow.has_keys() && !allow.matches_addr(ip) => {
+                Err(format!("{} is not on the allow list", ip))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Once the peer has proven its identity. A linked device is let in
+    /// when the allow list names it or its primary (see `device`).
+    pub fn check(&self, ip: IpAddr, key: &VerifyingKey) -> Result<(), String> {
+        self.check_key(key)?;
+        self.check_addr(ip)?;
+        let account = device::account(key);
+        match &self.allow {
+            Some(allow)
+                if !allow.matches_addr(ip)
+                    && !allow.matches_key(key)
+                    && !allow.matches_key(&account) =>
+            {
+                Err(format!(
+                    "neither {} nor {} is on the allow list",
+                    ip,
+                    fingerprint(key)
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the allow list names `key`, or its primary, by key: the
+    /// operator vouched for that identity, so the hub needs no code from it.
+    pub fn vouches_for(&self, key: &VerifyingKey) -> bool {
+        self.allow
+            .as_ref()
+            .is_some_and(|allow| allow.matches_key(key) || allow.matches_key(&device::account(key)))
+    }
+
+    /// Only the deny list's keys; used by clients, whose view of the peer's
+    /// address may be a proxy or relay. Denying the primary of a linked
+    /// device denies the device too.
+    pub fn check_key(&self, key: &VerifyingKey) -> Result<(), String> {
+        for key in [*key, device::account(key)] {
+            if self.deny.matches_key(&key) {
+                return Err(format!("{} is on the deny list", fingerprint(&key)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `~/.config/streamchat/deny.list` (or the platform equivalent).
+pub fn default_deny_path() -> PathBuf {
+    identity::default_path().with_file_name("deny.list")
+}
+
+/// Appends `key` to the deny list at `path`.
+pub fn block(path: &Path, key: &VerifyingKey) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(
+        file,
+        "{}  # {}, blocked with /block",
+        hex::encode(key.as_bytes()),
+        fingerprint(key)
+    )
+}
@@ -0,0 +1,235 @@
+//! Small attachments (`/attach PATH`): the whole file travels in a single
+//! frame and lands in the downloads directory, like a `/send` without the
+//! chunking. Images get an inline preview on terminals that speak the kitty
+//! graphics protocol or sixel. Anything bigger than `MAX_SIZE` goes through
+//! `/send`.
+
+use crate::transfer::{sanitize_name, unique_path};
+use crate::ui::{self, say};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use clap::ValueEnum;
+use image::{ImageFormat, ImageReader, Limits, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Cursor, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+pub const MAX_SIZE: usize = 512 * 1024;
+/// Previews are scaled down to fit this box, in pixels.
+const PREVIEW_WIDTH: u32 = 320;
+const PREVIEW_HEIGHT: u32 = 240;
+/// An image is not decoded past these, whatever its header claims.
+const MAX_DIMENSION: u32 = 8192;
+const MAX_DECODE_ALLOC: u64 = 64 * 1024 * 1024;
+/// Base64 bytes per kitty graphics escape.
+const KITTY_CHUNK: usize = 4096;
+
+#[derive(Serialize, Deserialize)]
+pub struct Attachment {
+    pub nick: String,
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImagePreview {
+    /// Kitty graphics or sixel, whichever the terminal seems to support.
+    Auto,
+    Kitty,
+    Sixel,
+    Off,
+}
+
+impl ImagePreview {
+    /// The protocol to draw with, if any.
+    fn resolve(self) -> Option<ImagePreview> {
+        let env = |name| std::env::var(name).unwrap_or_default();
+        match self {
+            ImagePreview::Auto => {
+                let term = env("TERM");
+                if std::env::var_os("KITTY_WINDOW_ID").is_some()
+                    || term.contains("kitty")
+                    || matches!(env("TERM_PROGRAM").as_str(), "WezTerm" | "ghostty")
+                {
+                    Some(ImagePreview::Kitty)
+                } else if term.contains("sixel")
+                    || term.starts_with("foot")
+                    || term.starts_with("mlterm")
+                {
+                    Some(ImagePreview::Sixel)
+                } else {
+                    None
+                }
+            }
+            ImagePreview::Off => None,
+            protocol => Some(protocol),
+        }
+    }
+}
+
+/// Reads `path` into an attachment from `nick`.
+pub fn load(path: &Path, nick: &str) -> io::Result<Attachment> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file name"))?
+        .to_string();
+    let data = fs::read(path)?;
+    if data.len() > MAX_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} bytes is too big to attach (max {}), use /send",
+                data.len(),
+                MAX_SIZE
+            ),
+        ));
+    }
+    Ok(Attachment {
+        nick: nick.to_string(),
+        name,
+        data,
+    })
+}
+
+/// The format of `data`, if it is an image we can preview.
+fn image_format(data: &[u8]) -> Option<ImageFormat> {
+    image::guess_format(data).ok().filter(|format| {
+        matches!(
+            format,
+            ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::Gif
+        )
+    })
+}
+
+/// Saves a received attachment in `dir` and previews it if it is an image
+/// and the terminal can show one.
+pub fn receive(attachment: Attachment, dir: &Path, preview: ImagePreview) -> io::Result<PathBuf> {
+    let path = unique_path(dir, &sanitize_name(&attachment.name))?;
+    fs::write(&path, &attachment.data)?;
+    let format = image_format(&attachment.data);
+    say!(
+        "\n{} {} sent {} ({} bytes) -> {}",
+        if format.is_some() { "🖼️ " } else { "📎" },
+        attachment.nick,
+        attachment.name,
+        attachment.data.len(),
+        path.display()
+    );
+    // The TUI redraws over anything written behind its back.
+    let terminal = !ui::active() && !ui::piped() && io::stdout().is_terminal();
+    if let (Some(format), Some(protocol), true) = (format, preview.resolve(), terminal) {
+        match thumbnail(&attachment.data, format) {
+            Ok(image) => {
+                let escape = match protocol {
+                    ImagePreview::Kitty => kitty(&image)?,
+                    _ => sixel(&image),
+                };
+                let mut stdout = io::stdout().lock();
+                writeln!(stdout, "{}", escape)?;
+                stdout.flush()?;
+            }
+            Err(e) => say!(" (no preview: {})", e),
+        }
+    }
+    Ok(path)
+}
+
+/// Decodes `data` and scales it down to the preview size; smaller images
+/// are left as they are.
+fn thumbnail(data: &[u8], format: ImageFormat) -> Result<RgbaImage, image::ImageError> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_ALLOC);
+    let mut reader = ImageReader::with_format(Cursor::new(data), format);
+    reader.limits(limits);
+    let image = reader.decode()?;
+    if image.width() <= PREVIEW_WIDTH && image.height() <= PREVIEW_HEIGHT {
+        return Ok(image.to_rgba8());
+    }
+    Ok(image.thumbnail(PREVIEW_WIDTH, PREVIEW_HEIGHT).to_rgba8())
+}
+
+/// Kitty graphics protocol: the image as PNG, base64 in chunks.
+fn kitty(image: &RgbaImage) -> io::Result<String> {
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(io::Error::other)?;
+    let encoded = BASE64.encode(png);
+    let chunks: Vec<_> = encoded.as_bytes().chunks(KITTY_CHUNK).collect();
+    let mut escape = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        let chunk = std::str::from_utf8(chunk).expect("base64 is ASCII");
+        if i == 0 {
+            let _ = write!(escape, "\x1b_Gf=100,a=T,m={};{}\x1b\\", more, chunk);
+        } else {
+            let _ = write!(escape, "\x1b_Gm={};{}\x1b\\", more, chunk);
+        }
+    }
+    Ok(escape)
+}
+
+/// Sixel: the image quantized to a 6×6×6 color cube, six rows per band.
+/// Mostly transparent pixels are left out.
+fn sixel(image: &RgbaImage) -> String {
+    let (width, height) = image.dimensions();
+    let level = |value: u8| (u32::from(value) * 5 + 127) / 255;
+    let color = |x, y| {
+        let pixel = image.get_pixel(x, y);
+        (pixel[3] >= 128).then(|| level(pixel[0]) * 36 + level(pixel[1]) * 6 + level(pixel[2]))
+    };
+
+    let mut out = format!("\x1bPq\"1;1;{};{}", width, height);
+    for index in 0..216 {
+        let (r, g, b) = (index / 36, index / 6 % 6, index % 6);
+        let _ = write!(out, "#{};2;{};{};{}", index, r * 20, g * 20, b * 20);
+    }
+    for top in (0..height).step_by(6) {
+        let rows = top..(top + 6).min(height);
+        let colors: BTreeSet<u32> = rows
+            .clone()
+            .flat_map(|y| (0..width).filter_map(move |x| color(x, y)))
+            .collect();
+        for &band_color in &colors {
+            let _ = write!(out, "#{}", band_color);
+            let mut run: Option<(char, usize)> = None;
+            for x in 0..width {
+                let bits = rows
+                    .clone()
+                    .filter(|&y| color(x, y) == Some(band_color))
+                    .fold(0u8, |bits, y| bits | 1 << (y - top));
+                let sixel = char::from(63 + bits);
+                run = match run {
+                    Some((previous, count)) if previous == sixel => Some((sixel, count + 1)),
+                    Some((previous, count)) => {
+                        push_run(&mut out, previous, count);
+                        Some((sixel, 1))
+                    }
+                    None => Some((sixel, 1)),
+                };
+            }
+            if let Some((sixel, count)) = run {
+                push_run(&mut out, sixel, count);
+            }
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+fn push_run(out: &mut String, sixel: char, count: usize) {
+    if count > 3 {
+        let _ = write!(out, "!{}{}", count, sixel);
+    } else {
+        out.extend(std::iter::repeat_n(sixel, count));
+    }
+}
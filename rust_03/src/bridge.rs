@@ -0,0 +1,155 @@
+//! `bridge`: a client that relays between a streamchat room (or the lobby)
+//! and an IRC channel. The two message models meet in plain text:
+//!
+//! - a message from IRC is sent to streamchat by the bridge, signed with
+//!   its identity, as `<nick> text` (`* nick text` for `/me`);
+//! - a streamchat message is said in the channel as `<nick> text`, and an
+//!   edit or deletion of one as a `* nick edited: text` notice, IRC having
+//!   no way to change what was said.
+//!
+//! Relayed lines never start with `/`, so IRC users can't run commands on
+//! the bridge. Nobody watches the bridge to compare verification codes:
+//! it requires `--peer-key` and verifies the session on its own.
+
+use crate::history::{Amendment, Change};
+use crate::irc::{self, Client};
+use crate::ui::say;
+use crate::{Authorship, Event, TextMessage};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use tracing::warn;
+
+/// Relayed messages remembered so their edits can follow.
+const REMEMBERED: usize = 200;
+
+struct Bridge {
+    irc: irc::Sender,
+    /// The streamchat room bridged, `None` for the lobby.
+    room: Option<String>,
+    /// Taken by `attach` to read the channel.
+    client: Mutex<Option<Client>>,
+    events: Mutex<Option<mpsc::Sender<Event>>>,
+    joined: AtomicBool,
+    relayed: Mutex<VecDeque<u64>>,
+}
+
+static BRIDGE: OnceLock<Bridge> = OnceLock::new();
+
+/// Makes this client a bridge to the channel `client` joined.
+pub fn install(client: Client, room: Option<String>) {
+    let _ = BRIDGE.set(Bridge {
+        irc: client.sender(),
+        room,
+        client: Mutex::new(Some(client)),
+        events: Mutex::new(None),
+        joined: AtomicBool::new(false),
+        relayed: Mutex::default(),
+    });
+}
+
+pub fn active() -> bool {
+    BRIDGE.get().is_some()
+}
+
+fn send(bridge: &Bridge, line: String) {
+    if let Some(events) = &*bridge.events.lock().unwrap() {
+        let _ = events.send(Event::Line(line));
+    }
+}
+
+/// Starts relaying the channel into the input loop behind `events`. The
+/// first line is `/verify`, the hub's key having been checked against
+/// `--peer-key`. Losing IRC ends the bridge.
+pub fn attach(events: mpsc::Sender<Event>) {
+    let Some(bridge) = BRIDGE.get() else {
+        return;
+    };
+    let Some(mut client) = bridge.client.lock().unwrap().take() else {
+        return;
+    };
+    *bridge.events.lock().unwrap() = Some(events.clone());
+    send(bridge, "/verify".to_string());
+    thread::spawn(move || {
+        loop {
+            let said = match client.next_said() {
+                Ok(said) => said,
+                Err(e) => {
+                    say!("\n❌ IRC: {}", e);
+                    let _ = events.send(Event::Quit);
+                    return;
+                }
+            };
+            let line = if said.action {
+                format!("* {} {}", said.nick, said.text)
+            } else {
+                format!("<{}> {}", said.nick, said.text)
+            };
+            if events.send(Event::Line(line)).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+/// Both sides verified the session: join the bridged room, once.
+pub fn verified() {
+    let Some(bridge) = BRIDGE.get() else {
+        return;
+    };
+    if let Some(room) = &bridge.room
+        && !bridge.joined.swap(true, Ordering::SeqCst)
+    {
+        send(bridge, format!("/join {}", room));
+    }
+}
+
+fn say_in_channel(bridge: &Bridge, text: &str) {
+    if let Err(e) = bridge.irc.say(text) {
+        warn!("could not relay to IRC: {}", e);
+    }
+}
+
+/// A chat message arrived in `room` (`None`: the lobby). Forgeries are
+/// not relayed.
+pub fn message(message: &TextMessage, authorship: Authorship, room: Option<&str>) {
+    let Some(bridge) = BRIDGE.get() else {
+        return;
+    };
+    if room != bridge.room.as_deref()
+        || matches!(authorship, Authorship::Impostor | Authorship::BadSignature)
+    {
+        return;
+    }
+    let mut relayed = bridge.relayed.lock().unwrap();
+    if relayed.len() == REMEMBERED {
+        relayed.pop_front();
+    }
+    relayed.push_back(message.id);
+    drop(relayed);
+    say_in_channel(bridge, &format!("<{}> {}", message.nick, message.text));
+}
+
+/// An authentic edit or deletion arrived for a message we know.
+pub fn amended(amendment: &Amendment) {
+    let Some(bridge) = BRIDGE.get() else {
+        return;
+    };
+    if !bridge.relayed.lock().unwrap().contains(&amendment.id) {
+        return;
+    }
+    let text = match &amendment.change {
+        Change::Edit(text) => format!("* {} edited: {}", amendment.nick, text),
+        Change::Delete => format!("* {} deleted a message", amendment.nick),
+    };
+    say_in_channel(bridge, &text);
+}
+
+/// Leaves IRC as the bridge stops.
+pub fn shutdown() {
+    if let Some(bridge) = BRIDGE.get() {
+        bridge.irc.quit("bridge stopped");
+    }
+}
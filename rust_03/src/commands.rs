@@ -0,0 +1,314 @@
+//! Slash commands typed at the prompt. Every line goes through `parse`
+//! before anything is encrypted, so a mistyped command is reported locally
+//! instead of reaching the peer as text. A line starting with `//` sends
+//! the rest literally, e.g. `//shrug` sends `/shrug`.
+
+use crate::hub::PeerId;
+use crate::parse_nick;
+use crate::room;
+use std::path::PathBuf;
+
+/// Which prompt the line was typed at; some commands only make sense in
+/// one of them.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Context {
+    Client,
+    Hub,
+}
+
+pub enum Command {
+    /// Confirm the verification code (the hub names the peer).
+    Verify(Option<PeerId>),
+    /// Reject the verification code (the hub names the peer).
+    Reject(Option<PeerId>),
+    Send(PathBuf),
+    /// Send a small file (an image) in one message.
+    Attach(PathBuf),
+    /// Save the file offered under this number.
+    Accept(u32),
+    /// Refuse the file offered under this number.
+    Decline(u32),
+    /// Replace the text of message N (the number after 📤).
+    Edit(u32, String),
+    /// Take back message N.
+    Delete(u32),
+    Nick(String),
+    /// Enter a room (name without the `#`).
+    Join(String),
+    /// Open another session, with the peer at this address.
+    Connect(String),
+    /// Make another open session the current one (number or peer name).
+    Switch(String),
+    Leave,
+    /// Show the last N messages the hub kept for the room.
+    History(u32),
+    /// Accept a peer's changed identity key and pin it (the hub names the
+    /// peer).
+    Trust(Option<PeerId>),
+    /// Add the peer's identity to the deny list and disconnect.
+    Block,
+    /// Re-read the allow and deny lists.
+    Reload,
+    Who,
+    Fingerprint,
+    Clear,
+    Help,
+    Quit,
+}
+
+pub enum Input {
+    Empty,
+    Text(String),
+    Command(Command),
+}
+
+struct Spec {
+    name: &'static str,
+    client_usage: Option<&'static str>,
+    hub_usage: Option<&'static str>,
+    help: &'static str,
+}
+
+impl Spec {
+    fn usage(&self, context: Context) -> Option<&'static str> {
+        match context {
+            Context::Client => self.client_usage,
+            Context::Hub => self.hub_usage,
+        }
+    }
+}
+
+const SPECS: &[Spec] = &[
+    Spec {
+        name: "/verify",
+        client_usage: Some("/verify"),
+        hub_usage: Some("/verify [ID]"),
+        help: "confirm that the verification code matches",
+    },
+    Spec {
+        name: "/reject",
+        client_usage: Some("/reject"),
+        hub_usage: Some("/reject ID"),
+        help: "the code does not match: drop the connection",
+    },
+    Spec {
+        name: "/send",
+        client_usage: Some("/send PATH"),
+        hub_usage: Some("/send PATH"),
+        help: "send a file",
+    },
+    Spec {
+        name: "/attach",
+        client_usage: Some("/attach PATH"),
+        hub_usage: Some("/attach PATH"),
+        help: "send a small file or image, previewed on arrival",
+    },
+    Spec {
+        name: "/accept",
+        client_usage: Some("/accept N"),
+        hub_usage: None,
+        help: "save the file offered as number N",
+    },
+    Spec {
+        name: "/decline",
+        client_usage: Some("/decline N"),
+        hub_usage: None,
+        help: "refuse the file offered as number N",
+    },
+    Spec {
+        name: "/edit",
+        client_usage: Some("/edit N TEXT"),
+        hub_usage: None,
+        help: "change your message number N (shown after 📤)",
+    },
+    Spec {
+        name: "/delete",
+        client_usage: Some("/delete N"),
+        hub_usage: None,
+        help: "delete your message number N",
+    },
+    Spec {
+        name: "/nick",
+        client_usage: Some("/nick NAME"),
+        hub_usage: Some("/nick NAME"),
+        help: "change the name shown to others",
+    },
+    Spec {
+        name: "/join",
+        client_usage: Some("/join #ROOM"),
+        hub_usage: None,
+        help: "enter a room (created if it doesn't exist)",
+    },
+    Spec {
+        name: "/leave",
+        client_usage: Some("/leave"),
+        hub_usage: None,
+        help: "leave the room, back to the lobby",
+    },
+    Spec {
+        name: "/history",
+        client_usage: Some("/history [N]"),
+        hub_usage: None,
+        help: "show the room's last N messages, if the hub keeps them",
+    },
+    Spec {
+        name: "/connect",
+        client_usage: Some("/connect ADDR"),
+        hub_usage: None,
+        help: "open another session, with the peer at ADDR",
+    },
+    Spec {
+        name: "/switch",
+        client_usage: Some("/switch N"),
+        hub_usage: None,
+        help: "talk to another open session (number or address, see /who)",
+    },
+    Spec {
+        name: "/trust",
+        client_usage: Some("/trust"),
+        hub_usage: Some("/trust ID"),
+        help: "accept a changed identity key and pin it",
+    },
+    Spec {
+        name: "/block",
+        client_usage: Some("/block"),
+        hub_usage: None,
+        help: "never talk to this peer's identity again, and disconnect",
+    },
+    Spec {
+        name: "/reload",
+        client_usage: None,
+        hub_usage: Some("/reload"),
+        help: "re-read the allow and deny lists",
+    },
+    Spec {
+        name: "/who",
+        client_usage: Some("/who"),
+        hub_usage: Some("/who"),
+        help: "show who is connected, or who is in the room",
+    },
+    Spec {
+        name: "/peers",
+        client_usage: None,
+        hub_usage: Some("/peers"),
+        help: "same as /who",
+    },
+    Spec {
+        name: "/fingerprint",
+        client_usage: Some("/fingerprint"),
+        hub_usage: Some("/fingerprint"),
+        help: "show identity fingerprints",
+    },
+    Spec {
+        name: "/clear",
+        client_usage: Some("/clear"),
+        hub_usage: Some("/clear"),
+        help: "clear the screen",
+    },
+    Spec {
+        name: "/help",
+        client_usage: Some("/help"),
+        hub_usage: Some("/help"),
+        help: "list commands",
+    },
+    Spec {
+        name: "/quit",
+        client_usage: Some("/quit"),
+        hub_usage: Some("/quit"),
+        help: "leave the chat",
+    },
+];
+
+pub fn parse(line: &str, context: Context) -> Result<Input, String> {
+    let trimmed = line.trim();
+    if trimmed.is_empty() {
+        return Ok(Input::Empty);
+    }
+    if let Some(literal) = line.strip_prefix("//") {
+        return Ok(Input::Text(format!("/{}", literal)));
+    }
+    if !trimmed.starts_with('/') {
+        return Ok(Input::Text(line.to_string()));
+    }
+
+    let (name, argument) = match trimmed.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (trimmed, ""),
+    };
+    let Some(usage) = SPECS
+        .iter()
+        .find(|spec| spec.name == name)
+        .and_then(|spec| spec.usage(context))
+    else {
+        return Err(format!(
+            "Unknown command {}, type /help for the list (// sends a literal /).",
+            name
+        ));
+    };
+    let usage_error = || format!("Usage: {}", usage);
+
+    let command = match (name, context, argument) {
+        ("/verify", Context::Client, "") => Command::Verify(None),
+        ("/verify", Context::Hub, "") => Command::Verify(None),
+        ("/verify", Context::Hub, id) => {
+            Command::Verify(Some(parse_id(id).ok_or_else(usage_error)?))
+        }
+        ("/reject", Context::Client, "") => Command::Reject(None),
+        ("/reject", Context::Hub, id) if !id.is_empty() => {
+            Command::Reject(Some(parse_id(id).ok_or_else(usage_error)?))
+        }
+        ("/trust", Context::Client, "") => Command::Trust(None),
+        ("/trust", Context::Hub, id) if !id.is_empty() => {
+            Command::Trust(Some(parse_id(id).ok_or_else(usage_error)?))
+        }
+        ("/send", _, path) if !path.is_empty() => Command::Send(PathBuf::from(path)),
+        ("/attach", _, path) if !path.is_empty() => Command::Attach(PathBuf::from(path)),
+        ("/accept", _, number) => Command::Accept(number.parse().map_err(|_| usage_error())?),
+        ("/decline", _, number) => Command::Decline(number.parse().map_err(|_| usage_error())?),
+        ("/edit", _, argument) => {
+            let (number, text) = argument
+                .split_once(char::is_whitespace)
+                .ok_or_else(usage_error)?;
+            let number = number.parse().map_err(|_| usage_error())?;
+            Command::Edit(number, text.trim().to_string())
+        }
+        ("/delete", _, number) => Command::Delete(number.parse().map_err(|_| usage_error())?),
+        ("/nick", _, nick) if !nick.is_empty() => Command::Nick(parse_nick(nick)?),
+        ("/join", _, name) if !name.is_empty() => Command::Join(room::parse_room(name)?),
+        ("/leave", _, "") => Command::Leave,
+        ("/history", _, "") => Command::History(room::JOIN_HISTORY),
+        ("/history", _, count) => Command::History(
+            count
+                .parse()
+                .ok()
+                .filter(|count| *count > 0)
+                .ok_or_else(usage_error)?,
+        ),
+        ("/connect", _, address) if !address.is_empty() => Command::Connect(address.to_string()),
+        ("/switch", _, target) if !target.is_empty() => Command::Switch(target.to_string()),
+        ("/block", _, "") => Command::Block,
+        ("/reload", _, "") => Command::Reload,
+        ("/who" | "/peers", _, "") => Command::Who,
+        ("/fingerprint", _, "") => Command::Fingerprint,
+        ("/clear", _, "") => Command::Clear,
+        ("/help", _, "") => Command::Help,
+        ("/quit", _, "") => Command::Quit,
+        _ => return Err(usage_error()),
+    };
+    Ok(Input::Command(command))
+}
+
+fn parse_id(text: &str) -> Option<PeerId> {
+    text.trim_start_matches('#').parse().ok()
+}
+
+pub fn help(context: Context) -> String {
+    let mut text = String::from("Commands:");
+    for spec in SPECS {
+        if let Some(usage) = spec.usage(context) {
+            text.push_str(&format!("\n {:<14} {}", usage, spec.help));
+        }
+    }
+    text.push_str("\n Anything else is sent as a message; start with // to send a literal /.");
+    text
+}
@@ -0,0 +1,236 @@
+//! Defaults read from `~/.config/streamchat.toml` (or `--config`), so the
+//! same flags don't have to be typed every session. Anything given on the
+//! command line or through the environment wins over the file.
+//!
+//! ```toml
+//! nick = "ada"
+//! identity = "/home/ada/keys/streamchat.key"
+//! transport = "quic"
+//! padding = "max"
+//! notify = "unfocused"
+//!
+//! [server]
+//! port = 7000
+//! bind = "::"
+//! message-rate = 10
+//! ```
+//!
+//! Keys are the long option names without the dashes; `[server]` holds the
+//! options of `streamchat server`.
+
+use crate::attachment::ImagePreview;
+use crate::notify::NotifyMode;
+use crate::transport::TransportKind;
+use crate::{Cli, Commands, parse_nick};
+use clap::ArgMatches;
+use clap::parser::ValueSource;
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use streamchat_proto::{Group, Padding};
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    nick: Option<String>,
+    identity: Option<PathBuf>,
+    insecure_plain_identity: Option<bool>,
+    downloads: Option<PathBuf>,
+    max_download: Option<u64>,
+    deny_file: Option<PathBuf>,
+    known_peers: Option<PathBuf>,
+    heartbeat: Option<u64>,
+    transport: Option<TransportKind>,
+    group: Option<Group>,
+    padding: Option<Padding>,
+    max_frame: Option<u64>,
+    send_rate: Option<u32>,
+    notify: Option<NotifyMode>,
+    notify_preview: Option<bool>,
+    image_preview: Option<ImagePreview>,
+    plain: Option<bool>,
+    server: ServerConfig,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct ServerConfig {
+    port: Option<u16>,
+    bind: Option<IpAddr>,
+    allow_file: Option<PathBuf>,
+    queue_ttl: Option<u64>,
+    queue_limit: Option<usize>,
+    message_rate: Option<u32>,
+    byte_rate: Option<u32>,
+    ticket_ttl: Option<u64>,
+    room_history: Option<PathBuf>,
+    room_history_len: Option<u64>,
+    address_file: Option<PathBuf>,
+    onion: Option<bool>,
+    tor_control: Option<String>,
+}
+
+/// `~/.config/streamchat.toml` (or the platform equivalent).
+pub fn default_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("streamchat.toml")
+}
+
+fn invalid(path: &Path, message: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", path.display(), message),
+    )
+}
+
+/// Reads the file at `path`; a missing file is an empty configuration
+/// unless it was asked for explicitly.
+pub fn load(path: &Path, explicit: bool) -> io::Result<Config> {
+    let text = match fs::read_to_string(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound && !explicit => return Ok(Config::default()),
+        result => result?,
+    };
+    toml::from_str(&text).map_err(|e| invalid(path, e))
+}
+
+/// Whether the option `id` was left for the configuration to fill.
+fn unset(matches: &ArgMatches, id: &str) -> bool {
+    !matches!(
+        matches.value_source(id),
+        Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+    )
+}
+
+impl Config {
+    /// Fills in the options of `cli` that `matches` shows were not given.
+    pub fn apply(self, cli: &mut Cli, matches: &ArgMatches, path: &Path) -> io::Result<()> {
+        macro_rules! fill {
+            ($matches:expr, $target:expr, $value:expr, $id:literal) => {
+                if let Some(value) = $value
+                    && unset($matches, $id)
+                {
+                    $target = value;
+                }
+            };
+        }
+
+        let nick = self
+            .nick
+            .map(|nick| parse_nick(&nick).map_err(|e| invalid(path, e)))
+            .transpose()?;
+        if self.heartbeat == Some(0) {
+            return Err(invalid(path, "heartbeat must be at least 1 second"));
+        }
+        fill!(matches, cli.nick, nick.map(Some), "nick");
+        fill!(matches, cli.identity, self.identity.map(Some), "identity");
+        fill!(
+            matches,
+            cli.insecure_plain_identity,
+            self.insecure_plain_identity,
+            "insecure_plain_identity"
+        );
+        fill!(matches, cli.downloads, self.downloads, "downloads");
+        fill!(matches, cli.max_download, self.max_download, "max_download");
+        fill!(
+            matches,
+            cli.deny_file,
+            self.deny_file.map(Some),
+            "deny_file"
+        );
+        fill!(
+            matches,
+            cli.known_peers,
+            self.known_peers.map(Some),
+            "known_peers"
+        );
+        fill!(matches, cli.heartbeat, self.heartbeat, "heartbeat");
+        fill!(matches, cli.transport, self.transport, "transport");
+        fill!(matches, cli.padding, self.padding, "padding");
+        if self.max_frame.is_some_and(|bytes| bytes < 1024) {
+            return Err(invalid(path, "max-frame must be at least 1024 bytes"));
+        }
+        fill!(matches, cli.max_frame, self.max_frame, "max_frame");
+        fill!(matches, cli.send_rate, self.send_rate, "send_rate");
+        fill!(matches, cli.notify, self.notify, "notify");
+        fill!(
+            matches,
+            cli.notify_preview,
+            self.notify_preview,
+            "notify_preview"
+        );
+        fill!(
+            matches,
+            cli.image_preview,
+            self.image_preview,
+            "image_preview"
+        );
+        fill!(matches, cli.plain, self.plain, "plain");
+        // --insecure-demo on the command line overrides a configured group.
+        if !cli.insecure_demo {
+            fill!(matches, cli.group, self.group, "group");
+        }
+
+        if let Commands::Server {
+            port,
+            bind,
+            allow_file,
+            queue_ttl,
+            queue_limit,
+            message_rate,
+            byte_rate,
+            ticket_ttl,
+            room_history,
+            room_history_len,
+            address_file,
+            onion,
+            tor_control,
+            ..
+        } = &mut cli.command
+        {
+            let server = self.server;
+            if server.room_history_len == Some(0) {
+                return Err(invalid(path, "room-history-len must be at least 1"));
+            }
+            let matches = matches
+                .subcommand_matches("server")
+                .expect("the server subcommand was parsed");
+            fill!(matches, *port, server.port.map(Some), "port");
+            fill!(matches, *bind, server.bind, "bind");
+            fill!(
+                matches,
+                *allow_file,
+                server.allow_file.map(Some),
+                "allow_file"
+            );
+            fill!(matches, *queue_ttl, server.queue_ttl, "queue_ttl");
+            fill!(matches, *queue_limit, server.queue_limit, "queue_limit");
+            fill!(matches, *message_rate, server.message_rate, "message_rate");
+            fill!(matches, *byte_rate, server.byte_rate, "byte_rate");
+            fill!(matches, *ticket_ttl, server.ticket_ttl, "ticket_ttl");
+            fill!(
+                matches,
+                *room_history,
+                server.room_history.map(Some),
+                "room_history"
+            );
+            fill!(
+                matches,
+                *room_history_len,
+                server.room_history_len,
+                "room_history_len"
+            );
+            fill!(
+                matches,
+                *address_file,
+                server.address_file.map(Some),
+                "address_file"
+            );
+            fill!(matches, *onion, server.onion, "onion");
+            fill!(matches, *tor_control, server.tor_control, "tor_control");
+        }
+        Ok(())
+    }
+}
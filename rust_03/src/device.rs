@@ -0,0 +1,233 @@
+//! One identity on several devices. Each device keeps its own key; a
+//! second device joins the identity of the first (the primary) through a
+//! link both keys signed:
+//!
+//! 1. on the new device, `streamchat identity` shows its public key;
+//! 2. on the primary, `identity --link-device KEY` signs it and prints an
+//!    invitation;
+//! 3. on the new device, `identity --accept-link INVITATION` checks it and
+//!    countersigns, and the finished link is kept in `device.link` next to
+//!    the identity key.
+//!
+//! A linked device sends its link in the hello (extension 2), so the other
+//! side of the session learns it, and a hub passes on the links of the
+//! peers it has to every client (`Frame::DeviceLink`). Whoever knows the
+//! link treats the device as its primary: nickname pins, the known peers
+//! file and the access lists see one identity, whichever device spoke.
+//!
+//! Neither key can make a link alone: a primary can't claim someone
+//! else's key as its device, nor a device a primary that didn't invite
+//! it. Since a link proves itself, it doesn't matter who passed it on.
+//!
+//! The hub needs nothing else to reach every device: each one connects
+//! with its own key and has its own mailbox.
+
+use crate::frame;
+use crate::identity::{self, Identity};
+use ed25519_dalek::{Signature, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use streamchat_proto::hello::{self, Negotiated};
+
+/// First protocol version that understands `Frame::DeviceLink`.
+pub const VERSION: u8 = 7;
+const EXTENSION: u8 = 2;
+
+/// The primary's half: its signature over the device key.
+#[derive(Serialize, Deserialize)]
+struct Invitation {
+    primary: VerifyingKey,
+    device: VerifyingKey,
+    signature: Signature,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DeviceLink {
+    pub primary: VerifyingKey,
+    pub device: VerifyingKey,
+    /// By the primary, over both keys.
+    invitation: Signature,
+    /// By the device, over both keys and the invitation.
+    acceptance: Signature,
+}
+
+fn invitation_bytes(primary: &VerifyingKey, device: &VerifyingKey) -> Vec<u8> {
+    let mut bytes = b"streamchat v1 device invitation".to_vec();
+    bytes.extend_from_slice(primary.as_bytes());
+    bytes.extend_from_slice(device.as_bytes());
+    bytes
+}
+
+fn acceptance_bytes(
+    primary: &VerifyingKey,
+    device: &VerifyingKey,
+    invitation: &Signature,
+) -> Vec<u8> {
+    let mut bytes = b"streamchat v1 device acceptance".to_vec();
+    bytes.extend_from_slice(primary.as_bytes());
+    bytes.extend_from_slice(device.as_bytes());
+    bytes.extend_from_slice(&invitation.to_bytes());
+    bytes
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// The invitation `primary` gives the device whose public key is `device`,
+/// in hex to paste on that device.
+pub fn invite(primary: &Identity, device: &VerifyingKey) -> String {
+    let invitation = Invitation {
+        primary: primary.public_key(),
+        device: *device,
+        signature: primary.sign(&invitation_bytes(&primary.public_key(), device)),
+    };
+    hex::encode(frame::encode(&invitation))
+}
+
+/// Countersigns an invitation made for `device`.
+pub fn accept(text: &str, device: &Identity) -> io::Result<DeviceLink> {
+    let invitation: Invitation = hex::decode(text.trim())
+        .ok()
+        .and_then(|bytes| frame::decode(&bytes))
+        .ok_or_else(|| invalid("not a device invitation"))?;
+    if invitation.device != device.public_key() {
+        return Err(invalid(format!(
+            "the invitation is for {}, this device is {}",
+            identity::fingerprint(&invitation.device),
+            identity::fingerprint(&device.public_key())
+        )));
+    }
+    if invitation.primary == invitation.device {
+        return Err(invalid("a device can't be linked to itself"));
+    }
+    let bytes = invitation_bytes(&invitation.primary, &invitation.device);
+    if invitation
+        .primary
+        .verify_strict(&bytes, &invitation.signature)
+        .is_err()
+    {
+        return Err(invalid("the invitation's signature is not valid"));
+    }
+    Ok(DeviceLink {
+        primary: invitation.primary,
+        device: invitation.device,
+        invitation: invitation.signature,
+        acceptance: device.sign(&acceptance_bytes(
+            &invitation.primary,
+            &invitation.device,
+            &invitation.signature,
+        )),
+    })
+}
+
+impl DeviceLink {
+    /// Whether both keys signed it.
+    pub fn is_valid(&self) -> bool {
+        let invitation = invitation_bytes(&self.primary, &self.device);
+        let acceptance = acceptance_bytes(&self.primary, &self.device, &self.invitation);
+        self.primary != self.device
+            && self
+                .primary
+                .verify_strict(&invitation, &self.invitation)
+                .is_ok()
+            && self
+                .device
+                .verify_strict(&acceptance, &self.acceptance)
+                .is_ok()
+    }
+
+    /// `device.link` next to the identity key at `identity_path`.
+    pub fn path(identity_path: &Path) -> PathBuf {
+        identity_path.with_file_name("device.link")
+    }
+
+    /// The link kept for `identity`, if it is a linked device.
+    pub fn load(identity_path: &Path, identity: &Identity) -> io::Result<Option<Self>> {
+        let path = Self::path(identity_path);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let link = hex::decode(text.trim())
+            .ok()
+            .and_then(|bytes| frame::decode::<DeviceLink>(&bytes))
+            .filter(|link| link.is_valid() && link.device == identity.public_key())
+            .ok_or_else(|| {
+                invalid(format!(
+                    "{}: not a device link for this identity",
+                    path.display()
+                ))
+            })?;
+        Ok(Some(link))
+    }
+
+    pub fn save(&self, identity_path: &Path) -> io::Result<()> {
+        fs::write(
+            Self::path(identity_path),
+            hex::encode(frame::encode(self)) + "\n",
+        )
+    }
+
+    /// The hello extension carrying the link.
+    pub fn extension(&self) -> Vec<u8> {
+        hello::extension(EXTENSION, &frame::encode(self))
+    }
+
+    /// The link in the peer's hello, if it sent a valid one for the key
+    /// it then proved to hold (`peer`).
+    pub fn from_hello(hello: &Negotiated, peer: &VerifyingKey) -> Option<Self> {
+        let link: DeviceLink = frame::decode(hello.peer_extension(EXTENSION)?)?;
+        (link.is_valid() && link.device == *peer).then_some(link)
+    }
+}
+
+/// Device key to primary, for every valid link seen.
+static LINKS: OnceLock<Mutex<HashMap<[u8; 32], VerifyingKey>>> = OnceLock::new();
+
+fn links() -> &'static Mutex<HashMap<[u8; 32], VerifyingKey>> {
+    LINKS.get_or_init(Mutex::default)
+}
+
+/// Remembers `link`, unless it isn't valid. `true` if it was new.
+pub fn learn(link: &DeviceLink) -> bool {
+    if !link.is_valid() {
+        return false;
+    }
+    links()
+        .lock()
+        .unwrap()
+        .insert(link.device.to_bytes(), link.primary)
+        != Some(link.primary)
+}
+
+/// The identity `key` speaks for: its primary if it is a linked device,
+/// itself otherwise.
+pub fn account(key: &VerifyingKey) -> VerifyingKey {
+    links()
+        .lock()
+        .unwrap()
+        .get(key.as_bytes())
+        .copied()
+        .unwrap_or(*key)
+}
+
+/// The fingerprint of `key`'s identity, with the device's when they differ,
+/// e.g. `3f2a ... (device 91c0 ...)`.
+pub fn describe(key: &VerifyingKey) -> String {
+    let account = account(key);
+    if account == *key {
+        identity::fingerprint(key)
+    } else {
+        format!(
+            "{} (device {})",
+            identity::fingerprint(&account),
+            identity::fingerprint(key)
+        )
+    }
+}
@@ -0,0 +1,146 @@
+//! Flood protection on the hub. Each connection gets two token buckets:
+//! one for chat frames (text, file offers, room traffic) and one for raw
+//! bytes. Running out of chat tokens is a strike: the frame is dropped and
+//! the sender muted, twice as long on each strike, then disconnected once
+//! it runs out of strikes. Running out of bytes only pauses reading from
+//! that connection, so TCP pushes back on the sender and a file transfer
+//! slows down instead of failing.
+
+use crate::frame::Frame;
+use std::time::{Duration, Instant};
+
+/// Strikes before the hub drops the connection.
+const MAX_STRIKES: u32 = 3;
+/// Mute after the first strike; each further strike doubles it.
+const FIRST_MUTE: Duration = Duration::from_secs(10);
+/// Bursts of up to this many seconds' worth of traffic go through.
+const BURST_SECS: f64 = 2.0;
+
+/// Per-connection limits; zero disables a limit.
+#[derive(Clone, Copy)]
+pub struct RateLimits {
+    /// Chat frames per second.
+    pub messages: u32,
+    /// Bytes per second, counted on the encrypted frames.
+    pub bytes: u32,
+}
+
+/// A token bucket, also used to pace what a connection sends (see
+/// `shaping`).
+pub struct Bucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Bucket {
+    /// `None` when `rate` is zero, i.e. unlimited.
+    pub fn new(rate: u32) -> Option<Self> {
+        let rate = rate as f64;
+        let capacity = (rate * BURST_SECS).max(1.0);
+        (rate > 0.0).then(|| Bucket {
+            rate,
+            capacity,
+            tokens: capacity,
+            refilled: Instant::now(),
+        })
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.refilled = now;
+    }
+
+    fn take(&mut self, amount: f64) -> bool {
+        self.refill();
+        let enough = self.tokens >= amount;
+        if enough {
+            self.tokens -= amount;
+        }
+        enough
+    }
+
+    /// Takes `amount` even if that runs the bucket into debt, and returns
+    /// how long until the debt is paid off.
+    pub fn borrow(&mut self, amount: f64) -> Duration {
+        self.refill();
+        self.tokens -= amount;
+        Duration::from_secs_f64((-self.tokens / self.rate).max(0.0))
+    }
+}
+
+pub enum Verdict {
+    Accept,
+    /// Dropped because the sender is muted.
+    Muted,
+    /// Dropped, and the sender is now muted for `mute`.
+    Strike {
+        mute: Duration,
+        strikes_left: u32,
+    },
+    /// Out of strikes: drop the connection.
+    Disconnect,
+}
+
+/// The buckets and strikes of one connection.
+pub struct FloodGuard {
+    messages: Option<Bucket>,
+    bytes: Option<Bucket>,
+    muted_until: Option<Instant>,
+    strikes: u32,
+}
+
+impl FloodGuard {
+    pub fn new(limits: RateLimits) -> Self {
+        FloodGuard {
+            messages: Bucket::new(limits.messages),
+            bytes: Bucket::new(limits.bytes),
+            muted_until: None,
+            strikes: 0,
+        }
+    }
+
+    /// Charges a frame of `size` bytes; returns how long to wait before
+    /// reading the next one.
+    pub fn throttle(&mut self, size: usize) -> Duration {
+        self.bytes
+            .as_mut()
+            .map_or(Duration::ZERO, |bytes| bytes.borrow(size as f64))
+    }
+
+    /// Decides what happens to `frame`. Keepalives, acks and control frames
+    /// always go through so a muted peer stays connected.
+    pub fn check(&mut self, frame: &Frame) -> Verdict {
+        let chat = matches!(
+            frame,
+            Frame::Text(_)
+                | Frame::Amend(_)
+                | Frame::FileOffer(_)
+                | Frame::Attachment(_)
+                | Frame::Room(_)
+        );
+        if !chat {
+            return Verdict::Accept;
+        }
+        if self.muted_until.is_some_and(|until| Instant::now() < until) {
+            return Verdict::Muted;
+        }
+        if self.messages.as_mut().is_none_or(|bucket| bucket.take(1.0)) {
+            return Verdict::Accept;
+        }
+
+        self.strikes += 1;
+        if self.strikes >= MAX_STRIKES {
+            return Verdict::Disconnect;
+        }
+        let mute = FIRST_MUTE * 2u32.pow(self.strikes - 1);
+        self.muted_until = Some(Instant::now() + mute);
+        Verdict::Strike {
+            mute,
+            strikes_left: MAX_STRIKES - self.strikes,
+        }
+    }
+}
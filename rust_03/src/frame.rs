@@ -0,0 +1,223 @@
+//! Everything sent after the handshake is a `Frame`, serialized with
+//! bincode and then encrypted, so the type of a frame is hidden on the wire
+//! along with its content. New kinds of messages are added here.
+
+use crate::attachment::Attachment;
+use crate::device::DeviceLink;
+use crate::history::Amendment;
+use crate::room::{self, RoomMessage};
+use crate::transfer::FileOffer;
+use crate::{TextMessage, parse_nick};
+use bincode::config::{Config, standard};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use streamchat_proto::MAX_FRAME_LEN;
+
+#[derive(Serialize, Deserialize)]
+pub enum Frame {
+    /// Boxed, like `Room` and `Queued`, for the author's key and signature.
+    Text(Box<TextMessage>),
+    /// Receipt for the `Text` frame with this sequence number.
+    Ack(u64),
+    /// Keepalive, answered with a `Pong`.
+    Ping,
+    Pong,
+    /// Announces a file: transfer id, size, SHA-256, sender and name.
+    FileOffer(FileOffer),
+    /// A piece of an announced file, tagged with its transfer id.
+    FileChunk {
+        id: u32,
+        data: Vec<u8>,
+    },
+    /// A small file sent whole, e.g. an image.
+    Attachment(Attachment),
+    /// An edit or deletion of an earlier `Text`, by its author.
+    Amend(Box<Amendment>),
+    /// Room membership, key distribution and room text (see `room`). Boxed
+    /// because key requests and grants carry keys and signatures.
+    Room(Box<RoomMessage>),
+    /// A lobby message the hub kept while we were offline.
+    Queued(Box<TextMessage>),
+    Control(Control),
+    /// The sender is leaving on purpose; the connection closes right after.
+    Goodbye,
+    /// Hub to client: a peer's device is linked to another identity (see
+    /// `device`).
+    DeviceLink(Box<DeviceLink>),
+    /// Answer to a `FileOffer`; chunks only follow an accepted one. From
+    /// the hub, a refusal calls off a transfer accepted too late.
+    FileAnswer {
+        id: u32,
+        accepted: bool,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum Control {
+    /// The sender confirmed the short authentication string.
+    Verified,
+    /// Hub to client: chat frames are dropped for `seconds` because the
+    /// client sent too fast.
+    Muted { seconds: u64, strikes_left: u32 },
+    /// Hub to client: sent right before the hub drops the connection.
+    Kicked(String),
+    /// Hub to client, once the session is verified: a ticket to resume it
+    /// on the next connection, valid for `lifetime` seconds.
+    Ticket { ticket: Vec<u8>, lifetime: u64 },
+}
+
+/// A forged length prefix can't make the decoder allocate more than a
+/// frame may hold.
+fn config() -> impl Config {
+    standard().with_limit::<MAX_FRAME_LEN>()
+}
+
+pub fn encode<T: Serialize>(value: &T) -> Vec<u8> {
+    bincode::serde::encode_to_vec(value, config()).expect("frames always serialize")
+}
+
+/// Decodes `bytes`, which must hold exactly one value.
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Option<T> {
+    let (value, read) = bincode::serde::decode_from_slice(bytes, config()).ok()?;
+    (read == bytes.len()).then_some(value)
+}
+
+impl Frame {
+    pub fn encode(&self) -> Vec<u8> {
+        encode(self)
+    }
+
+    /// Decodes a frame and checks what bincode can't: names that end up on
+    /// screen must be valid nicknames and room names.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let frame: Frame = decode(bytes)?;
+        let valid_room = |name: &String| room::parse_room(name).is_ok_and(|parsed| parsed == *name);
+        let valid = match &frame {
+            Frame::Text(message) | Frame::Queued(message) => message.is_valid(),
+            Frame::FileOffer(offer) => parse_nick(&offer.nick).is_ok(),
+            Frame::Attachment(attachment) => parse_nick(&attachment.nick).is_ok(),
+            Frame::Amend(amendment) => parse_nick(&amendment.nick).is_ok(),
+            Frame::Room(message) => match &**message {
+                RoomMessage::Join { room, nick, .. } => {
+                    valid_room(room) && parse_nick(nick).is_ok()
+                }
+                RoomMessage::Joined { room, .. } => valid_room(room),
+                _ => true,
+            },
+            Frame::DeviceLink(link) => link.is_valid(),
+            _ => true,
+        };
+        valid.then_some(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::Identity;
+
+    fn offer(nick: &str) -> FileOffer {
+        FileOffer {
+            id: 7,
+            nick: nick.to_string(),
+            name: "notes.txt".to_string(),
+            size: 1234,
+            sha256: [9; 32],
+        }
+    }
+
+    /// One frame of each kind that needs no room state.
+    fn frames() -> Vec<Frame> {
+        let identity = Identity::from_seed([1; 32]);
+        vec![
+            Frame::Text(Box::new(TextMessage::new("ada", "hello", &identity))),
+            Frame::Ack(42),
+            Frame::Ping,
+            Frame::Pong,
+            Frame::FileOffer(offer("ada")),
+            Frame::FileChunk {
+                id: 7,
+                data: vec![0, 1, 2, 255],
+            },
+            Frame::Attachment(Attachment {
+                nick: "ada".to_string(),
+                name: "cat.png".to_string(),
+                data: vec![137, 80, 78, 71],
+            }),
+            Frame::Queued(Box::new(TextMessage::new("ada", "later", &identity))),
+            Frame::Control(Control::Verified),
+            Frame::Control(Control::Muted {
+                seconds: 30,
+                strikes_left: 2,
+            }),
+            Frame::Control(Control::Kicked("flooding".to_string())),
+            Frame::Control(Control::Ticket {
+                ticket: vec![5; 40],
+                lifetime: 3600,
+            }),
+            Frame::Goodbye,
+            Frame::FileAnswer {
+                id: 7,
+                accepted: true,
+            },
+        ]
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        for frame in frames() {
+            let bytes = frame.encode();
+            let decoded = Frame::decode(&bytes).expect("a frame we encoded decodes");
+            assert_eq!(decoded.encode(), bytes);
+            assert_eq!(
+                std::mem::discriminant(&decoded),
+                std::mem::discriminant(&frame)
+            );
+        }
+    }
+
+    #[test]
+    fn signatures_survive_the_round_trip() {
+        let identity = Identity::from_seed([1; 32]);
+        let frame = Frame::Text(Box::new(TextMessage::new("ada", "hello", &identity)));
+        let Some(Frame::Text(message)) = Frame::decode(&frame.encode()) else {
+            panic!("not a text frame");
+        };
+        assert!(message.signed());
+        assert_eq!(
+            (message.nick.as_str(), message.text.as_str()),
+            ("ada", "hello")
+        );
+    }
+
+    #[test]
+    fn truncated_or_padded_frames_are_refused() {
+        for frame in frames() {
+            let bytes = frame.encode();
+            assert!(Frame::decode(&bytes[..bytes.len() - 1]).is_none());
+            assert!(Frame::decode(&[&bytes[..], &[0]].concat()).is_none());
+        }
+    }
+
+    #[test]
+    fn unknown_kinds_are_refused() {
+        assert!(Frame::decode(&[200]).is_none());
+        assert!(Frame::decode(&[]).is_none());
+    }
+
+    #[test]
+    fn invalid_nicknames_are_refused() {
+        for nick in ["", "two words", "bell\u{7}", &"x".repeat(33)] {
+            let frame = Frame::FileOffer(offer(nick));
+            assert!(Frame::decode(&frame.encode()).is_none(), "{:?}", nick);
+        }
+    }
+
+    #[test]
+    fn forged_lengths_are_refused() {
+        // A file chunk (variant 5) of id 0 claiming u64::MAX bytes of data.
+        let mut bytes = vec![5, 0, 253];
+        bytes.extend_from_slice(&u64::MAX.to_le_bytes());
+        assert!(Frame::decode(&bytes).is_none());
+    }
+}
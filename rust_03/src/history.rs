@@ -0,0 +1,263 @@
+//! Editing and deleting sent messages. Every message carries a random id,
+//! and `/edit N TEXT` or `/delete N` sends an `Amendment` naming it, signed
+//! like the message itself. Each session remembers the recent messages it
+//! sent (numbered for the commands) and received (to check that an
+//! amendment comes from the message's author, and to show what changed).
+
+use crate::bridge;
+use crate::identity::Identity;
+use crate::ui::{self, say};
+use crate::{TextMessage, format_time, now_millis};
+use ed25519_dalek::{Signature, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+/// Messages remembered per session, in each direction.
+const REMEMBERED: usize = 500;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Change {
+    Edit(String),
+    Delete,
+}
+
+/// A change to the message `id`, by its author.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Amendment {
+    pub id: u64,
+    pub sent_at: u64,
+    pub nick: String,
+    pub change: Change,
+    pub author: VerifyingKey,
+    pub signature: Signature,
+}
+
+impl Amendment {
+    pub fn new(id: u64, nick: &str, change: Change, identity: &Identity) -> Self {
+        let sent_at = now_millis();
+        let signature = identity.sign(&Self::signed_bytes(id, sent_at, nick, &change));
+        Amendment {
+            id,
+            sent_at,
+            nick: nick.to_string(),
+            change,
+            author: identity.public_key(),
+            signature,
+        }
+    }
+
+    fn signed_bytes(id: u64, sent_at: u64, nick: &str, change: &Change) -> Vec<u8> {
+        let mut bytes = b"streamchat v1 amendment".to_vec();
+        bytes.extend_from_slice(&id.to_be_bytes());
+        bytes.extend_from_slice(&sent_at.to_be_bytes());
+        bytes.extend_from_slice(&(nick.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(nick.as_bytes());
+        match change {
+            Change::Edit(text) => {
+                bytes.push(0);
+                bytes.extend_from_slice(text.as_bytes());
+            }
+            Change::Delete => bytes.push(1),
+        }
+        bytes
+    }
+
+    /// Whether `author` really wrote this amendment.
+    pub fn signed(&self) -> bool {
+        let bytes = Self::signed_bytes(self.id, self.sent_at, &self.nick, &self.change);
+        self.author.verify_strict(&bytes, &self.signature).is_ok()
+    }
+
+    pub fn describe(&self) -> String {
+        match &self.change {
+            Change::Edit(text) => format!("edited a message: {}", text),
+            Change::Delete => "deleted a message".to_string(),
+        }
+    }
+}
+
+/// The line shown for a message, split around its text so an edit can
+/// put new text in.
+pub struct Line {
+    pub head: String,
+    pub text: String,
+    pub tail: String,
+}
+
+impl Line {
+    pub fn render(&self) -> String {
+        format!("{}: {}{}", self.head, self.text, self.tail)
+    }
+
+    /// Replaces the line shown for `id`, or without the TUI, which can't
+    /// change what was printed, reports the change under it.
+    fn amend(&mut self, id: u64, change: &Change, by: &str) {
+        let struck = matches!(change, Change::Delete);
+        let notice = match change {
+            Change::Edit(text) => {
+                let notice = format!("✏️  {} edited: {} (was: {})", by, text, self.text);
+                self.text = text.clone();
+                self.tail = " (edited)".to_string();
+                notice
+            }
+            Change::Delete => {
+                self.tail = " (deleted)".to_string();
+                format!("🗑️  {} deleted: {}", by, ui::strike(&self.text))
+            }
+        };
+        ui::amend(id, self.render(), struck, notice);
+    }
+}
+
+struct SentMessage {
+    number: u32,
+    id: u64,
+    /// The room it went to, if any.
+    room: Option<String>,
+    line: Line,
+}
+
+/// What this side sent on a session, numbered from 1 for `/edit N`.
+#[derive(Default)]
+pub struct Sent {
+    messages: VecDeque<SentMessage>,
+    last_number: u32,
+}
+
+impl Sent {
+    /// Remembers `message` and shows it; `frame` is its sequence number in
+    /// the lobby.
+    pub fn record(&mut self, message: &TextMessage, room: Option<&str>, frame: Option<u64>) {
+        self.last_number += 1;
+        let number = self.last_number;
+        let head = match (room, frame) {
+            (Some(room), _) => {
+                format!("📤 {} [{}] #{}", number, format_time(message.sent_at), room)
+            }
+            (None, Some(frame)) => format!(
+                "📤 {} [{}] Sending (frame {})",
+                number,
+                format_time(message.sent_at),
+                frame
+            ),
+            (None, None) => format!("📤 {} [{}]", number, format_time(message.sent_at)),
+        };
+        let line = Line {
+            head,
+            text: message.text.clone(),
+            tail: String::new(),
+        };
+        ui::show_keyed(message.id, line.render());
+        if self.messages.len() == REMEMBERED {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(SentMessage {
+            number,
+            id: message.id,
+            room: room.map(str::to_string),
+            line,
+        });
+    }
+
+    /// Id and room of message `number`.
+    pub fn find(&self, number: u32) -> Option<(u64, Option<&str>)> {
+        self.messages
+            .iter()
+            .find(|message| message.number == number)
+            .map(|message| (message.id, message.room.as_deref()))
+    }
+
+    /// Shows that message `id` was changed, once the amendment went out.
+    pub fn amended(&mut self, id: u64, change: &Change) {
+        if let Some(message) = self.messages.iter_mut().find(|message| message.id == id) {
+            message.line.amend(id, change, "you");
+        }
+    }
+}
+
+struct ReceivedMessage {
+    author: VerifyingKey,
+    line: Line,
+    deleted: bool,
+}
+
+/// Messages received on a session (lobby and room), kept by the reader.
+#[derive(Default)]
+pub struct Received {
+    messages: HashMap<u64, ReceivedMessage>,
+    order: VecDeque<u64>,
+}
+
+impl Received {
+    pub fn record(&mut self, message: &TextMessage, line: Line) {
+        if self.order.len() == REMEMBERED
+            && let Some(oldest) = self.order.pop_front()
+        {
+            self.messages.remove(&oldest);
+        }
+        self.order.push_back(message.id);
+        self.messages.insert(
+            message.id,
+            ReceivedMessage {
+                author: message.author,
+                line,
+                deleted: false,
+            },
+        );
+    }
+
+    /// Checks `amendment` against the message it names and shows it.
+    pub fn apply(&mut self, amendment: &Amendment) {
+        if !amendment.signed() {
+            say!("\n⚠️  Dropped an edit with a bad signature.");
+            return;
+        }
+        let message = self.messages.get_mut(&amendment.id);
+        if message
+            .as_ref()
+            .is_some_and(|message| message.author != amendment.author)
+        {
+            say!(
+                "\n⚠️  Dropped an edit by {} of a message they did not write.",
+                amendment.nick
+            );
+            return;
+        }
+        if message.as_ref().is_some_and(|message| message.deleted) {
+            return;
+        }
+        if ui::piped() {
+            let (kind, text) = match &amendment.change {
+                Change::Edit(text) => ("edit", Some(text)),
+                Change::Delete => ("delete", None),
+            };
+            let line = serde_json::json!({
+                "type": kind,
+                "id": amendment.id,
+                "sender": amendment.nick,
+                "timestamp": amendment.sent_at,
+                "text": text,
+                "peer": ui::source(),
+                "known": message.is_some(),
+            });
+            println!("{}", line);
+        }
+        match message {
+            Some(message) => {
+                bridge::amended(amendment);
+                message.deleted = matches!(amendment.change, Change::Delete);
+                if !ui::piped() {
+                    message
+                        .line
+                        .amend(amendment.id, &amendment.change, &amendment.nick);
+                }
+            }
+            None if ui::piped() => {}
+            None => say!(
+                "\n✏️  {} {} (one we never saw)",
+                amendment.nick,
+                amendment.describe()
+            ),
+        }
+    }
+}
@@ -0,0 +1,1562 @@
+//! Multi-client server: one handler thread per connection, plus a shared
+//! registry used to relay each message to every other verified peer. Every
+//! connection has its own handshake and keys, so the hub decrypts and
+//! re-encrypts each message per recipient.
+//!
+//! Peers start in the lobby, which the server console talks to. A peer in
+//! a room only exchanges messages with the room's other members, under the
+//! room's group key (see `room`); the hub routes those without reading them.
+//!
+//! Lobby text for a peer the hub has verified before but that is offline
+//! now is kept in a bounded mailbox keyed by its identity, and delivered as
+//! `Queued` frames once it reconnects and verifies again. Room text can be
+//! kept too, as the hub relays it (`--room-history`, see `storage`).
+
+use crate::access::{Access, AccessFiles};
+use crate::attachment;
+use crate::commands::{self, Command, Context, Input};
+use crate::device::{self, DeviceLink};
+use crate::flood::{FloodGuard, RateLimits, Verdict};
+use crate::frame::{Control, Frame};
+use crate::identity::{Identity, fingerprint};
+use crate::notify;
+use crate::pins::{self, Pin};
+use crate::room::{KeyRequest, RoomMessage, Sealed};
+use crate::shaping::Shaping;
+use crate::storage::RoomHistory;
+use crate::transfer::FileOffer;
+use crate::transport::Listener;
+use crate::{
+    Event, Inbox, Incoming, MISSED_HEARTBEATS, TextMessage, Transport, Writer, format_time,
+    goodbye, input_events, is_timeout, key_exchange, prompt, receive_bulk, start_heartbeat,
+    transfer, ui,
+};
+use ed25519_dalek::VerifyingKey;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io::{self, BufReader, IsTerminal};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use streamchat_proto::hello::{
+    self, FEATURE_OFFLINE_QUEUE, FEATURE_PASSWORD, FEATURE_ROOM_HISTORY, FEATURE_ROOMS,
+};
+use streamchat_proto::resume::{self, TicketKey};
+use streamchat_proto::{Encoder, Group, HEADER_LEN, MAX_PLAINTEXT, Padding, Role, sas};
+
+pub type PeerId = u32;
+
+/// Upper bound on the bytes queued for one offline peer.
+const MAX_QUEUED_BYTES: usize = 1024 * 1024;
+
+/// How much the hub keeps for offline peers.
+#[derive(Clone, Copy)]
+pub struct QueueLimits {
+    /// Queued messages older than this are dropped; zero disables queueing.
+    pub ttl: Duration,
+    /// Messages kept per peer; the oldest go first.
+    pub max_messages: usize,
+}
+
+struct Queued {
+    at: Instant,
+    message: Box<TextMessage>,
+}
+
+impl Queued {
+    fn size(&self) -> usize {
+        self.message.nick.len() + self.message.text.len()
+    }
+}
+
+/// Messages waiting for one identity.
+#[derive(Default)]
+struct Mailbox {
+    messages: VecDeque<Queued>,
+    bytes: usize,
+}
+
+impl Mailbox {
+    fn push(&mut self, message: Queued, limits: QueueLimits) {
+        self.bytes += message.size();
+        self.messages.push_back(message);
+        while self.messages.len() > limits.max_messages || self.bytes > MAX_QUEUED_BYTES {
+            let Some(dropped) = self.messages.pop_front() else {
+                break;
+            };
+            self.bytes -= dropped.size();
+        }
+    }
+
+    /// Takes every message still within the TTL.
+    fn drain(&mut self, limits: QueueLimits) -> Vec<Queued> {
+        self.bytes = 0;
+        self.messages
+            .drain(..)
+            .filter(|message| message.at.elapsed() < limits.ttl)
+            .collect()
+    }
+}
+
+struct Peer {
+    addr: SocketAddr,
+    identity: VerifyingKey,
+    /// Nickname from the peer's latest message, if it sent any.
+    nick: Option<String>,
+    /// `nick` is pinned to another identity and the operator has not
+    /// `/trust`ed this one yet.
+    nick_conflict: bool,
+    sas: String,
+    writer: Arc<Mutex<Writer>>,
+    /// The server operator confirmed this peer's verification code.
+    local_verified: bool,
+    /// The peer confirmed the verification code on its side.
+    peer_verified: bool,
+    room: Option<String>,
+    /// What a resumption ticket for this session is bound to, when the
+    /// client can resume (protocol v6 and later).
+    resumption: Option<[u8; 32]>,
+    /// The link from the hello, when the peer is a linked device.
+    link: Option<DeviceLink>,
+    /// The client takes `DeviceLink` frames (protocol v7 and later).
+    hears_links: bool,
+}
+
+impl Peer {
+    fn verified(&self) -> bool {
+        self.local_verified && self.peer_verified
+    }
+}
+
+struct Room {
+    /// In join order.
+    members: Vec<PeerId>,
+    /// Members known to have the group key.
+    keyed: BTreeSet<PeerId>,
+    /// The member key requests are sent to.
+    holder: PeerId,
+    /// Members still waiting for the key, with their requests.
+    pending: Vec<(PeerId, KeyRequest)>,
+}
+
+/// A file offered in the lobby, relayed to the peers that accept it.
+struct Transfer {
+    /// `None` for the hub's own `/send`.
+    from: Option<PeerId>,
+    size: u64,
+    offered: Instant,
+    accepted: BTreeSet<PeerId>,
+    /// Bytes relayed so far; once it is more than 0, it is too late to
+    /// accept.
+    relayed: u64,
+}
+
+/// Room messages decided under the registry lock and sent once it is
+/// released.
+type Outbox = Vec<(PeerId, Arc<Mutex<Writer>>, RoomMessage)>;
+
+#[derive(Default)]
+struct Registry {
+    next_id: PeerId,
+    peers: BTreeMap<PeerId, Peer>,
+    rooms: BTreeMap<String, Room>,
+    /// One per identity that was ever verified on both sides.
+    mailboxes: BTreeMap<[u8; 32], Mailbox>,
+    /// By transfer id.
+    transfers: BTreeMap<u32, Transfer>,
+}
+
+impl Registry {
+    /// Records an offer, dropping those nobody accepted in time. False if
+    /// another transfer has the id.
+    fn open_transfer(&mut self, from: Option<PeerId>, offer: &FileOffer) -> bool {
+        self.transfers.retain(|_, transfer| {
+            transfer.relayed > 0 || transfer.offered.elapsed() < transfer::ANSWER_TIMEOUT
+        });
+        if self.transfers.contains_key(&offer.id) {
+            return false;
+        }
+        let transfer = Transfer {
+            from,
+            size: offer.size,
+            offered: Instant::now(),
+            accepted: BTreeSet::new(),
+            relayed: 0,
+        };
+        self.transfers.insert(offer.id, transfer);
+        true
+    }
+
+    /// Counts `bytes` more of transfer `id` as relayed; returns the peers
+    /// that accepted it, none if `from` did not offer it.
+    fn relay_chunk(&mut self, from: Option<PeerId>, id: u32, bytes: usize) -> BTreeSet<PeerId> {
+        let Some(transfer) = self
+            .transfers
+            .get_mut(&id)
+            .filter(|transfer| transfer.from == from)
+        else {
+            return BTreeSet::new();
+        };
+        transfer.relayed += bytes as u64;
+        let accepted = transfer.accepted.clone();
+        if transfer.relayed >= transfer.size {
+            self.transfers.remove(&id);
+        }
+        accepted
+    }
+
+    fn post(&self, outbox: &mut Outbox, to: PeerId, message: &RoomMessage) {
+        if let Some(peer) = self.peers.get(&to) {
+            outbox.push((to, Arc::clone(&peer.writer), message.clone()));
+        }
+    }
+
+    fn name(&self, id: PeerId) -> String {
+        match self.peers.get(&id).and_then(|peer| peer.nick.as_deref()) {
+            Some(nick) => format!("#{} {}", id, nick),
+            None => format!("#{}", id),
+        }
+    }
+
+    /// Sends a notice to every member of `room` except `except`.
+    fn notify(&self, outbox: &mut Outbox, room: &str, except: PeerId, text: &str) {
+        let Some(members) = self.rooms.get(room).map(|room| &room.members) else {
+            return;
+        };
+        for &member in members.iter().filter(|&&member| member != except) {
+            self.post(outbox, member, &RoomMessage::Notice(text.to_string()));
+        }
+    }
+
+    fn occupants(&self, room: &str) -> String {
+        let members = self
+            .rooms
+            .get(room)
+            .map(|room| room.members.as_slice())
+            .unwrap_or_default();
+        let names: Vec<_> = members.iter().map(|&id| self.name(id)).collect();
+        format!("In #{}: {}", room, names.join(", "))
+    }
+
+    fn join_room(&mut self, outbox: &mut Outbox, id: PeerId, name: String, request: KeyRequest) {
+        self.leave_room(outbox, id);
+        let Some(peer) = self.peers.get_mut(&id) else {
+            return;
+        };
+        peer.room = Some(name.clone());
+
+        let creator = match self.rooms.get_mut(&name) {
+            Some(room) => {
+                room.members.push(id);
+                room.pending.push((id, request.clone()));
+                let holder = room.holder;
+                self.post(
+                    outbox,
+                    holder,
+                    &RoomMessage::KeyRequest {
+                        requester: id,
+                        request,
+                    },
+                );
+                false
+            }
+            None => {
+                self.rooms.insert(
+                    name.clone(),
+                    Room {
+                        members: vec![id],
+                        keyed: BTreeSet::from([id]),
+                        holder: id,
+                        pending: Vec::new(),
+                    },
+                );
+                true
+            }
+        };
+        println!(
+            "\n🏠 {} joined #{}{}",
+            self.name(id),
+            name,
+            if creator { " (new room)" } else { "" }
+        );
+        self.post(
+            outbox,
+            id,
+            &RoomMessage::Joined {
+                room: name.clone(),
+                creator,
+            },
+        );
+        self.notify(
+            outbox,
+            &name,
+            id,
+            &format!("{} joined #{}.", self.name(id), name),
+        );
+        self.post(outbox, id, &RoomMessage::Notice(self.occupants(&name)));
+    }
+
+    fn leave_room(&mut self, outbox: &mut Outbox, id: PeerId) {
+        let Some(name) = self.peers.get_mut(&id).and_then(|peer| peer.room.take()) else {
+            return;
+        };
+        let who = self.name(id);
+        println!("\n🏠 {} left #{}", who, name);
+        let Some(room) = self.rooms.get_mut(&name) else {
+            return;
+        };
+        room.members.retain(|&member| member != id);
+        room.keyed.remove(&id);
+        room.pending.retain(|(member, _)| *member != id);
+        if room.members.is_empty() {
+            self.rooms.remove(&name);
+            return;
+        }
+
+        if room.holder == id {
+            // Hand the role to a member that has the key, or have one
+            // create a new key if nobody has it yet.
+            let keyed = room
+                .members
+                .iter()
+                .copied()
+                .find(|member| room.keyed.contains(member));
+            let holder = keyed.unwrap_or(room.members[0]);
+            room.holder = holder;
+            if keyed.is_none() {
+                room.keyed.insert(holder);
+                room.pending.retain(|(member, _)| *member != holder);
+            }
+            let pending = room.pending.clone();
+            if keyed.is_none() {
+                self.post(
+                    outbox,
+                    holder,
+                    &RoomMessage::Joined {
+                        room: name.clone(),
+                        creator: true,
+                    },
+                );
+            }
+            for (requester, request) in pending {
+                self.post(
+                    outbox,
+                    holder,
+                    &RoomMessage::KeyRequest { requester, request },
+                );
+            }
+        }
+        self.notify(outbox, &name, id, &format!("{} left #{}.", who, name));
+    }
+}
+
+#[derive(Clone)]
+pub struct Hub {
+    registry: Arc<Mutex<Registry>>,
+    limits: QueueLimits,
+    rates: RateLimits,
+    access: Arc<Mutex<Access>>,
+    access_files: Arc<AccessFiles>,
+    known_peers: Arc<PathBuf>,
+    password: Option<Arc<str>>,
+    group: Group,
+    padding: Padding,
+    /// `None` when tickets are off.
+    tickets: Option<Arc<TicketKey>>,
+    ticket_ttl: Duration,
+    history: Option<Arc<RoomHistory>>,
+    shaping: Shaping,
+    /// Answers to the hub's own `/send` offers.
+    answers: Arc<transfer::Answers>,
+}
+
+impl Hub {
+    fn new(config: HubConfig, access: Access) -> Self {
+        Hub {
+            registry: Arc::default(),
+            limits: config.limits,
+            rates: config.rates,
+            access: Arc::new(Mutex::new(access)),
+            access_files: Arc::new(config.access),
+            known_peers: Arc::new(config.known_peers),
+            password: config.password.map(Arc::from),
+            group: config.group,
+            padding: config.padding,
+            tickets: (!config.ticket_ttl.is_zero()).then(|| Arc::new(TicketKey::generate())),
+            ticket_ttl: config.ticket_ttl,
+            history: config.room_history.map(Arc::new),
+            shaping: config.shaping,
+            answers: Arc::default(),
+        }
+    }
+
+    /// Re-reads the allow and deny lists and drops connected peers that
+    /// they no longer admit. On error the old lists stay in force.
+    fn reload_access(&self) {
+        let access = match Access::load(&self.access_files) {
+            Ok(access) => access,
+            Err(e) => {
+                println!("\n⚠️  Could not reload the access lists: {}", e);
+                return;
+            }
+        };
+        println!("\n🔄 Reloaded the access lists ({}).", access.describe());
+        let refused: Vec<_> = self
+            .registry
+            .lock()
+            .unwrap()
+            .peers
+            .iter()
+            .filter_map(|(id, peer)| {
+                let reason = access.check(peer.addr.ip(), &peer.identity).err()?;
+                Some((*id, reason))
+            })
+            .collect();
+        *self.access.lock().unwrap() = access;
+        for (id, reason) in refused {
+            if self.remove(id).is_some() {
+                println!("🚫 Disconnected peer #{}: {}.", id, reason);
+            }
+        }
+    }
+
+    /// Adds a connected peer; `verified` when it resumed a session that
+    /// was verified on both sides.
+    fn register(
+        &self,
+        addr: SocketAddr,
+        identity: VerifyingKey,
+        sas: String,
+        writer: Arc<Mutex<Writer>>,
+        resumption: Option<[u8; 32]>,
+        verified: bool,
+    ) -> PeerId {
+        let mut registry = self.registry.lock().unwrap();
+        registry.next_id += 1;
+        let id = registry.next_id;
+        registry.peers.insert(
+            id,
+            Peer {
+                addr,
+                identity,
+                nick: None,
+                nick_conflict: false,
+                sas,
+                writer,
+                local_verified: verified,
+                peer_verified: verified,
+                room: None,
+                resumption,
+                link: None,
+                hears_links: false,
+            },
+        );
+        id
+    }
+
+    /// Records what the hello of peer `id` said about devices.
+    fn set_link(&self, id: PeerId, link: Option<DeviceLink>, hears_links: bool) {
+        if let Some(peer) = self.registry.lock().unwrap().peers.get_mut(&id) {
+            peer.link = link;
+            peer.hears_links = hears_links;
+        }
+    }
+
+    fn remove(&self, id: PeerId) -> Option<SocketAddr> {
+        let mut outbox = Vec::new();
+        let peer = {
+            let mut registry = self.registry.lock().unwrap();
+            registry.leave_room(&mut outbox, id);
+            registry
+                .transfers
+                .retain(|_, transfer| transfer.from != Some(id));
+            registry.peers.remove(&id)?
+        };
+        send_all(outbox);
+        let _ = peer.writer.lock().unwrap().stream.shutdown();
+        Some(peer.addr)
+    }
+
+    /// Handles a room message from peer `id`.
+    fn room_message(&self, id: PeerId, message: RoomMessage) {
+        let mut outbox = Vec::new();
+        {
+            let mut registry = self.registry.lock().unwrap();
+            let Some(peer) = registry.peers.get_mut(&id) else {
+                return;
+            };
+            let room = peer.room.clone();
+            match message {
+                RoomMessage::Join {
+                    room,
+                    nick,
+                    request,
+                } => {
+                    if request.identity != peer.identity {
+                        let notice = "Your key request is not signed by your identity.";
+                        registry.post(&mut outbox, id, &RoomMessage::Notice(notice.to_string()));
+                    } else {
+                        peer.nick = Some(nick);
+                        registry.join_room(&mut outbox, id, room, request);
+                    }
+                }
+                RoomMessage::Leave => registry.leave_room(&mut outbox, id),
+                RoomMessage::Members => {
+                    let notice = match room {
+                        Some(room) => registry.occupants(&room),
+                        None => "You are in the lobby.".to_string(),
+                    };
+                    registry.post(&mut outbox, id, &RoomMessage::Notice(notice));
+                }
+                RoomMessage::KeyGrant(grant) => {
+                    // Only a member with the key can hand it to another
+                    // member of the same room.
+                    let requester = grant.requester;
+                    let same_room = room.is_some()
+                        && registry.peers.get(&requester).map(|peer| &peer.room) == Some(&room);
+                    let Some(state) = room
+                        .filter(|_| same_room)
+                        .and_then(|room| registry.rooms.get_mut(&room))
+                        .filter(|state| state.keyed.contains(&id))
+                    else {
+                        return;
+                    };
+                    state.keyed.insert(requester);
+                    state.pending.retain(|(member, _)| *member != requester);
+                    registry.post(&mut outbox, requester, &RoomMessage::KeyGrant(grant));
+                }
+                RoomMessage::Text { nonce, ciphertext } => {
+                    let Some(room) = room else {
+                        return;
+                    };
+                    if let Some(history) = &self.history {
+                        let sealed = Sealed {
+                            nonce,
+                            ciphertext: ciphertext.clone(),
+                        };
+                        if let Err(e) = history.append(&room, sealed) {
+                            println!("\n⚠️  Could not keep a #{} message: {}", room, e);
+                        }
+                    }
+                    let message = RoomMessage::Text { nonce, ciphertext };
+                    let members = registry.rooms[&room].members.clone();
+                    for member in members.into_iter().filter(|&member| member != id) {
+                        registry.post(&mut outbox, member, &message);
+                    }
+                }
+                RoomMessage::HistoryRequest(count) => {
+                    let answer = match (&self.history, room) {
+                        (None, _) => {
+                            RoomMessage::Notice("This hub keeps no room history.".to_string())
+                        }
+                        (Some(_), None) => RoomMessage::Notice("You are in the lobby.".to_string()),
+                        (Some(history), Some(room)) => match history.last(&room, count as usize) {
+                            Ok(messages) => RoomMessage::History(messages),
+                            Err(e) => {
+                                println!("\n⚠️  Could not read the #{} history: {}", room, e);
+                                RoomMessage::Notice(format!(
+                                    "The #{} history can't be read right now.",
+                                    room
+                                ))
+                            }
+                        },
+                    };
+                    registry.post(&mut outbox, id, &answer);
+                }
+                RoomMessage::Joined { .. }
+                | RoomMessage::KeyRequest { .. }
+                | RoomMessage::Notice(_)
+                | RoomMessage::History(_) => {
+                    println!("\n⚠️  Ignored a hub-only room message from #{}.", id);
+                }
+            }
+        }
+        send_all(outbox);
+    }
+
+    /// Records the nickname of a message from peer `id` and checks it
+    /// against the known peers. An error means the nickname is pinned to
+    /// another identity: the message must not be relayed until the operator
+    /// `/trust`s the peer.
+    fn set_nick(&self, id: PeerId, nick: &str) -> Result<(), String> {
+        let identity = {
+            let mut registry = self.registry.lock().unwrap();
+            let Some(peer) = registry.peers.get_mut(&id) else {
+                return Ok(());
+            };
+            if peer.nick.as_deref() == Some(nick) && !peer.nick_conflict {
+                return Ok(());
+            }
+            peer.nick = Some(nick.to_string());
+            peer.identity
+        };
+        // A linked device is pinned as its primary identity.
+        let account = device::account(&identity);
+        let name = format!("nick:{}", nick);
+        let conflict = match pins::check(&self.known_peers, &name, &account) {
+            Ok(Pin::New) => {
+                if let Err(e) = pins::pin(&self.known_peers, &name, &account) {
+                    println!("\n⚠️  Could not pin {}: {}", nick, e);
+                }
+                None
+            }
+            Ok(Pin::Matches) => None,
+            Ok(Pin::Changed(pinned)) => Some(pinned),
+            Err(e) => {
+                println!("\n⚠️  Could not check {}: {}", nick, e);
+                None
+            }
+        };
+        if let Some(peer) = self.registry.lock().unwrap().peers.get_mut(&id) {
+            peer.nick_conflict = conflict.is_some();
+        }
+        match conflict {
+            Some(pinned) => Err(format!(
+                "{} belongs to {}, peer #{} is {}",
+                nick,
+                fingerprint(&pinned),
+                id,
+                device::describe(&identity)
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Pins the nickname of peer `id` to its identity after a conflict.
+    fn trust(&self, id: PeerId) -> Result<String, String> {
+        let mut registry = self.registry.lock().unwrap();
+        let peer = registry
+            .peers
+            .get_mut(&id)
+            .ok_or_else(|| format!("no peer #{}", id))?;
+        let nick = match &peer.nick {
+            Some(nick) if peer.nick_conflict => nick.clone(),
+            _ => return Err(format!("nothing to trust for peer #{}", id)),
+        };
+        let account = device::account(&peer.identity);
+        pins::pin(&self.known_peers, &format!("nick:{}", nick), &account)
+            .map_err(|e| e.to_string())?;
+        peer.nick_conflict = false;
+        Ok(nick)
+    }
+
+    fn is_verified(&self, id: PeerId) -> bool {
+        let registry = self.registry.lock().unwrap();
+        registry.peers.get(&id).is_some_and(Peer::verified)
+    }
+
+    /// Records that the peer confirmed the code; returns whether the session
+    /// is now verified on both sides.
+    fn mark_peer_verified(&self, id: PeerId) -> bool {
+        let mut registry = self.registry.lock().unwrap();
+        match registry.peers.get_mut(&id) {
+            Some(peer) => {
+                peer.peer_verified = true;
+                peer.verified()
+            }
+            None => false,
+        }
+    }
+
+    /// The operator confirmed the code for `id` (or for the only pending
+    /// peer when `id` is `None`).
+    fn verify(&self, id: Option<PeerId>) -> Result<(PeerId, bool), String> {
+        let (id, peer_verified, writer) = {
+            let mut registry = self.registry.lock().unwrap();
+            let id = match id {
+                Some(id) => id,
+                None => {
+                    let pending: Vec<_> = registry
+                        .peers
+                        .iter()
+                        .filter(|(_, peer)| !peer.local_verified)
+                        .map(|(id, _)| *id)
+                        .collect();
+                    match pending[..] {
+                        [id] => id,
+                        [] => return Err("no peer is waiting for verification".to_string()),
+                        _ => return Err("several peers are pending, use /verify ID".to_string()),
+                    }
+                }
+            };
+            let peer = registry
+                .peers
+                .get_mut(&id)
+                .ok_or_else(|| format!("no peer #{}", id))?;
+            if peer.local_verified {
+                return Err(format!("peer #{} is already verified", id));
+            }
+            peer.local_verified = true;
+            (id, peer.peer_verified, Arc::clone(&peer.writer))
+        };
+
+        writer
+            .lock()
+            .unwrap()
+            .send(&Frame::Control(Control::Verified))
+            .map_err(|e| e.to_string())?;
+        if peer_verified {
+            self.welcome(id);
+        }
+        Ok((id, peer_verified))
+    }
+
+    /// Called once `id` is verified on both sides.
+    fn welcome(&self, id: PeerId) {
+        self.issue_ticket(id);
+        self.deliver_queued(id);
+        self.share_links(id);
+    }
+
+    /// Passes the links of the verified peers that are linked devices to
+    /// `id`, and the link of `id` to them, so clients see every device as
+    /// its primary identity.
+    fn share_links(&self, id: PeerId) {
+        let mut outbox = Vec::new();
+        {
+            let registry = self.registry.lock().unwrap();
+            let Some(peer) = registry.peers.get(&id) else {
+                return;
+            };
+            for (other_id, other) in &registry.peers {
+                if *other_id == id || !other.verified() {
+                    continue;
+                }
+                if peer.hears_links
+                    && let Some(link) = &other.link
+                {
+                    outbox.push((Arc::clone(&peer.writer), link.clone()));
+                }
+                if other.hears_links
+                    && let Some(link) = &peer.link
+                {
+                    outbox.push((Arc::clone(&other.writer), link.clone()));
+                }
+            }
+        }
+        for (writer, link) in outbox {
+            let _ = writer
+                .lock()
+                .unwrap()
+                .send(&Frame::DeviceLink(Box::new(link)));
+        }
+    }
+
+    /// Hands `id` a ticket to resume its session later, if tickets are on
+    /// and its client can use them.
+    fn issue_ticket(&self, id: PeerId) {
+        let Some(key) = &self.tickets else {
+            return;
+        };
+        let (writer, ticket) = {
+            let registry = self.registry.lock().unwrap();
+            let Some(peer) = registry.peers.get(&id) else {
+                return;
+            };
+            let Some(resumption) = &peer.resumption else {
+                return;
+            };
+            let ticket = key.issue(
+                resumption,
+                &peer.identity,
+                self.password.is_some(),
+                self.ticket_ttl,
+            );
+            (Arc::clone(&peer.writer), ticket)
+        };
+        let ticket = Control::Ticket {
+            ticket,
+            lifetime: self.ticket_ttl.as_secs(),
+        };
+        let _ = writer.lock().unwrap().send(&Frame::Control(ticket));
+    }
+
+    /// Opens a mailbox for the identity of `id`, verified on both sides,
+    /// and sends whatever was queued there while it was away.
+    fn deliver_queued(&self, id: PeerId) {
+        let (writer, queued) = {
+            let mut registry = self.registry.lock().unwrap();
+            let Some(peer) = registry.peers.get(&id) else {
+                return;
+            };
+            let (writer, identity) = (Arc::clone(&peer.writer), peer.identity.to_bytes());
+            let mailbox = registry.mailboxes.entry(identity).or_default();
+            (writer, mailbox.drain(self.limits))
+        };
+        if queued.is_empty() {
+            return;
+        }
+
+        println!(
+            "\n📬 Delivering {} queued message(s) to #{}.",
+            queued.len(),
+            id
+        );
+        for queued in queued {
+            if writer
+                .lock()
+                .unwrap()
+                .send(&Frame::Queued(queued.message))
+                .is_err()
+            {
+                println!("\n⚠️  Could not deliver to peer #{}.", id);
+                break;
+            }
+        }
+    }
+
+    /// Handles peer `id`'s answer to offer `transfer`. An acceptance
+    /// reaches the sender and gets the peer the chunks, unless they already
+    /// started; a refusal goes no further, as other peers may accept.
+    fn answer_offer(&self, id: PeerId, transfer: u32, accepted: bool) {
+        if !accepted {
+            return;
+        }
+        let (late, from, writer) = {
+            let mut registry = self.registry.lock().unwrap();
+            let Some(offer) = registry.transfers.get_mut(&transfer) else {
+                return;
+            };
+            if offer.from == Some(id) {
+                return;
+            }
+            let late = offer.relayed > 0;
+            if !late {
+                offer.accepted.insert(id);
+            }
+            let from = offer.from;
+            let to = if late { Some(id) } else { from };
+            let writer = to
+                .and_then(|to| registry.peers.get(&to))
+                .map(|peer| Arc::clone(&peer.writer));
+            (late, from, writer)
+        };
+        let answer = Frame::FileAnswer {
+            id: transfer,
+            accepted: !late,
+        };
+        match writer {
+            Some(writer) => {
+                let _ = writer.lock().unwrap().send(&answer);
+            }
+            None if from.is_none() && !late => {
+                self.answers.answer(transfer, true);
+            }
+            None => {}
+        }
+    }
+
+    /// Sends a message to every verified peer in the lobby except `from`.
+    /// Text is also queued for known identities that are offline. File
+    /// chunks only go to the peers that accepted the offer, and after one
+    /// the caller pauses as `--send-rate` asks, which slows the sender down
+    /// in turn.
+    fn broadcast(&self, from: Option<PeerId>, frame: &Frame) {
+        let targets: Vec<_> = {
+            let mut registry = self.registry.lock().unwrap();
+            let accepted = match frame {
+                Frame::FileOffer(offer) => {
+                    if !registry.open_transfer(from, offer) {
+                        println!("\n⚠️  Dropped an offer reusing the id of another transfer.");
+                        return;
+                    }
+                    None
+                }
+                Frame::FileChunk { id, data } => Some(registry.relay_chunk(from, *id, data.len())),
+                _ => None,
+            };
+            if let Frame::Text(text) = frame
+                && !self.limits.ttl.is_zero()
+            {
+                let online: BTreeSet<_> = registry
+                    .peers
+                    .iter()
+                    .filter(|(id, peer)| peer.verified() || Some(**id) == from)
+                    .map(|(_, peer)| peer.identity.to_bytes())
+                    .collect();
+                for (identity, mailbox) in &mut registry.mailboxes {
+                    if !online.contains(identity) {
+                        let message = Queued {
+                            at: Instant::now(),
+                            message: text.clone(),
+                        };
+                        mailbox.push(message, self.limits);
+                    }
+                }
+            }
+            registry
+                .peers
+                .iter()
+                .filter(|(id, peer)| Some(**id) != from && peer.verified() && peer.room.is_none())
+                .filter(|(id, _)| {
+                    accepted
+                        .as_ref()
+                        .is_none_or(|accepted| accepted.contains(id))
+                })
+                .map(|(id, peer)| (*id, Arc::clone(&peer.writer)))
+                .collect()
+        };
+
+        // File chunks go in the bulk lane, paced per recipient.
+        let bulk = matches!(frame, Frame::FileChunk { .. });
+        let mut pause = Duration::ZERO;
+        for (id, writer) in targets {
+            let result = if bulk {
+                Writer::send_bulk(&writer, frame).map(|wait| pause = pause.max(wait))
+            } else {
+                writer.lock().unwrap().send(frame).map(drop)
+            };
+            if result.is_err() {
+                println!("\n⚠️  Could not deliver to peer #{}.", id);
+            }
+        }
+        if !pause.is_zero() {
+            thread::sleep(pause);
+        }
+    }
+
+    fn list_fingerprints(&self) {
+        let registry = self.registry.lock().unwrap();
+        for (id, peer) in &registry.peers {
+            println!(" #{}:     {}", id, device::describe(&peer.identity));
+        }
+    }
+
+    /// Says goodbye to every peer, before the hub shuts down.
+    fn close_all(&self) {
+        let writers: Vec<_> = self
+            .registry
+            .lock()
+            .unwrap()
+            .peers
+            .values()
+            .map(|peer| Arc::clone(&peer.writer))
+            .collect();
+        for writer in writers {
+            goodbye(&writer);
+        }
+    }
+
+    fn list(&self) {
+        let registry = self.registry.lock().unwrap();
+        if registry.peers.is_empty() {
+            println!("No peers connected.");
+        }
+        for (id, peer) in &registry.peers {
+            let state = if peer.verified() {
+                "verified"
+            } else if peer.local_verified {
+                "waiting for peer"
+            } else {
+                "pending"
+            };
+            println!(
+                " #{} {} {} [{}] {} code: {}",
+                id,
+                peer.nick.as_deref().unwrap_or("-"),
+                peer.addr,
+                state,
+                peer.room
+                    .as_ref()
+                    .map_or("lobby".to_string(), |room| format!("#{}", room)),
+                peer.sas
+            );
+        }
+    }
+}
+
+/// Server settings from the command line.
+pub struct HubConfig {
+    pub heartbeat: Duration,
+    pub limits: QueueLimits,
+    pub rates: RateLimits,
+    pub access: AccessFiles,
+    pub known_peers: PathBuf,
+    pub password: Option<String>,
+    pub group: Group,
+    pub padding: Padding,
+    /// How long resumption tickets stay valid; zero issues none.
+    pub ticket_ttl: Duration,
+    /// Where room messages are kept, if they are.
+    pub room_history: Option<RoomHistory>,
+    pub shaping: Shaping,
+}
+
+pub fn run_server(
+    listener: Box<dyn Listener>,
+    identity: Arc<Identity>,
+    peer_key: Option<VerifyingKey>,
+    nick: &str,
+    config: HubConfig,
+) -> io::Result<()> {
+    let access = Access::load(&config.access)?;
+    println!("🚦 Access lists: {}", access.describe());
+    let heartbeat = config.heartbeat;
+    let hub = Hub::new(config, access);
+    #[cfg(unix)]
+    {
+        use signal_hook::consts::SIGHUP;
+        use signal_hook::iterator::Signals;
+        let mut signals = Signals::new([SIGHUP])?;
+        let hub = hub.clone();
+        thread::spawn(move || {
+            for _ in signals.forever() {
+                hub.reload_access();
+                prompt();
+            }
+        });
+    }
+    let own_key = identity.public_key();
+
+    let acceptor_hub = hub.clone();
+    let acceptor_identity = Arc::clone(&identity);
+    thread::spawn(move || {
+        loop {
+            let Ok(stream) = listener.accept() else {
+                continue;
+            };
+            let hub = acceptor_hub.clone();
+            let identity = Arc::clone(&acceptor_identity);
+            thread::spawn(move || {
+                handle_connection(hub, stream, &identity, peer_key.as_ref(), heartbeat)
+            });
+        }
+    });
+
+    if io::stdin().is_terminal() {
+        println!("💬 Type to broadcast. /peers lists clients, /help shows all commands.\n");
+    } else {
+        println!("🤖 No console: serving until Ctrl-C (SIGINT) or /quit on the input.");
+        println!(
+            " With nobody to type /verify, only clients whose identity key is on --allow-file are verified on the server's side, and sessions verified earlier resume.\n"
+        );
+    }
+    prompt();
+
+    let own_fingerprint = fingerprint(&own_key);
+    let mut nick = nick.to_string();
+    let (events, receiver) = mpsc::channel();
+    input_events(None, events)?;
+    for event in receiver {
+        let line = match event {
+            Event::Line(line) => line,
+            // Clients still depend on the hub without a console.
+            Event::InputEnded => {
+                println!("\n⌨️  The console input ended; still serving until Ctrl-C.");
+                continue;
+            }
+            _ => break,
+        };
+        match commands::parse(&line, Context::Hub) {
+            Ok(Input::Empty) => {}
+            Err(e) => println!("⚠️  {}", e),
+            Ok(Input::Command(Command::Help)) => println!("{}", commands::help(Context::Hub)),
+            Ok(Input::Command(Command::Quit)) => break,
+            Ok(Input::Command(Command::Clear)) => ui::clear(),
+            Ok(Input::Command(Command::Nick(new_nick))) => {
+                println!("✓ You are now {}.", new_nick);
+                nick = new_nick;
+            }
+            Ok(Input::Command(Command::Who)) => hub.list(),
+            Ok(Input::Command(
+                Command::Join(_)
+                | Command::Leave
+                | Command::History(_)
+                | Command::Block
+                | Command::Connect(_)
+                | Command::Switch(_)
+                | Command::Edit(..)
+                | Command::Delete(_)
+                | Command::Accept(_)
+                | Command::Decline(_),
+            )) => {
+                unreachable!(
+                    "/join, /leave, /history, /block, /connect, /switch, /edit, /delete, /accept and /decline are client-only"
+                )
+            }
+            Ok(Input::Command(Command::Reload)) => hub.reload_access(),
+            Ok(Input::Command(Command::Trust(id))) => {
+                let id = id.expect("the hub parser requires an id");
+                match hub.trust(id) {
+                    Ok(nick) => println!("📌 {} is now pinned to peer #{}'s identity.", nick, id),
+                    Err(e) => println!("⚠️  {}", e),
+                }
+            }
+            Ok(Input::Command(Command::Fingerprint)) => {
+                println!(" Server: {}", own_fingerprint);
+                hub.list_fingerprints();
+            }
+            Ok(Input::Command(Command::Verify(id))) => report_verify(hub.verify(id)),
+            Ok(Input::Command(Command::Reject(id))) => {
+                let id = id.expect("the hub parser requires an id");
+                match hub.remove(id) {
+                    Some(addr) => println!("❌ Rejected peer #{} ({}).", id, addr),
+                    None => println!("No peer #{}.", id),
+                }
+            }
+            Ok(Input::Command(Command::Attach(path))) => match attachment::load(&path, &nick) {
+                Ok(attachment) => {
+                    let size = attachment.data.len();
+                    hub.broadcast(None, &Frame::Attachment(attachment));
+                    println!("📎 Attached {} ({} bytes).", path.display(), size);
+                }
+                Err(e) => println!("⚠️  Could not attach {}: {}", path.display(), e),
+            },
+            Ok(Input::Command(Command::Send(path))) => {
+                // In the background, while the peers decide.
+                let hub = hub.clone();
+                let nick = nick.clone();
+                thread::spawn(move || {
+                    let chunk_size = hub.shaping.chunk_size(hub.padding);
+                    let result =
+                        transfer::send_file(&path, &nick, chunk_size, &hub.answers, |frame| {
+                            hub.broadcast(None, &frame);
+                            Ok(())
+                        });
+                    if let Err(e) = result {
+                        println!("⚠️  Could not send {}: {}", path.display(), e);
+                    }
+                    prompt();
+                });
+            }
+            Ok(Input::Text(text)) => {
+                let message = TextMessage::new(&nick, &text, &identity);
+                let sent_at = message.sent_at;
+                let frame = Frame::Text(Box::new(message));
+                let length = frame.encode().len();
+                if length > MAX_PLAINTEXT {
+                    println!(
+                        "⚠️  Message too long ({} bytes, max {}), not sent.",
+                        length, MAX_PLAINTEXT
+                    );
+                } else {
+                    hub.broadcast(None, &frame);
+                    println!("📤 [{}] Broadcast: {}", format_time(sent_at), text);
+                }
+            }
+        }
+        prompt();
+    }
+
+    println!("\n👋 Shutting down the server.");
+    hub.close_all();
+    Ok(())
+}
+
+fn send_all(outbox: Outbox) {
+    for (id, writer, message) in outbox {
+        if writer
+            .lock()
+            .unwrap()
+            .send(&Frame::Room(Box::new(message)))
+            .is_err()
+        {
+            println!("\n⚠️  Could not deliver to peer #{}.", id);
+        }
+    }
+}
+
+fn report_verify(result: Result<(PeerId, bool), String>) {
+    match result {
+        Ok((id, true)) => println!("✅ Peer #{} verified on both sides.", id),
+        Ok((id, false)) => println!("⏳ Waiting for peer #{} to verify...", id),
+        Err(e) => println!("⚠️  {}", e),
+    }
+}
+
+fn handle_connection(
+    hub: Hub,
+    mut stream: Box<dyn Transport>,
+    identity: &Identity,
+    peer_key: Option<&VerifyingKey>,
+    heartbeat: Duration,
+) {
+    let Ok(addr) = stream.peer_addr() else {
+        return;
+    };
+    if let Err(reason) = hub.access.lock().unwrap().check_addr(addr.ip()) {
+        println!("\n🚫 Refused {}: {}.", addr, reason);
+        prompt();
+        return;
+    }
+    let timeout = heartbeat * MISSED_HEARTBEATS;
+    if stream.set_read_timeout(Some(timeout)).is_err() {
+        return;
+    }
+    println!("\n🤝 Handshake with {}...", addr);
+
+    let mut features = FEATURE_ROOMS | hub.padding.features();
+    if !hub.limits.ttl.is_zero() {
+        features |= FEATURE_OFFLINE_QUEUE;
+    }
+    if hub.history.is_some() {
+        features |= FEATURE_ROOM_HISTORY;
+    }
+    if hub.password.is_some() {
+        features |= FEATURE_PASSWORD;
+    }
+    let handshake =
+        hello::exchange(&mut stream, Role::Server, features, hub.group, &[]).and_then(|hello| {
+            println!("\n👋 {}", hello.describe());
+            let resumed = resume::server(
+                &mut stream,
+                hub.tickets.as_deref(),
+                &hello,
+                hub.password.is_some(),
+            )?;
+            let (keys, peer_identity, resumed) = match resumed {
+                Some(resumed) => (resumed.keys, resumed.peer_identity, true),
+                None => {
+                    let (keys, peer_identity) = key_exchange(
+                        &mut stream,
+                        Role::Server,
+                        identity,
+                        peer_key,
+                        &hello,
+                        hub.password.as_deref(),
+                    )?;
+                    (keys, peer_identity, false)
+                }
+            };
+            if resumed && peer_key.is_some_and(|expected| *expected != peer_identity) {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "peer identity does not match --peer-key",
+                ));
+            }
+            let resumption = (hello.version >= resume::VERSION).then_some(keys.resumption);
+            let link = DeviceLink::from_hello(&hello, &peer_identity);
+            let hears_links = hello.version >= device::VERSION;
+            Ok((
+                keys,
+                peer_identity,
+                hello.padding,
+                resumption,
+                resumed,
+                link,
+                hears_links,
+            ))
+        });
+    let (keys, peer_identity, padding, resumption, resumed, link, hears_links) = match handshake {
+        Ok(result) => result,
+        Err(e) => {
+            println!("\n⚠️  Handshake with {} failed: {}", addr, e);
+            prompt();
+            return;
+        }
+    };
+    if let Some(link) = &link {
+        device::learn(link);
+        println!(
+            "\n🔗 {} is a device of {}.",
+            addr,
+            fingerprint(&link.primary)
+        );
+    }
+    if let Err(reason) = hub.access.lock().unwrap().check(addr.ip(), &peer_identity) {
+        println!("\n🚫 Refused {}: {}.", addr, reason);
+        prompt();
+        return;
+    }
+    let Ok(read_half) = stream.try_clone() else {
+        return;
+    };
+
+    let (send_key, recv_key) = keys.split(Role::Server);
+    let code = if resumed {
+        "(resumed, verified earlier)".to_string()
+    } else {
+        sas::words(&keys.sas)
+    };
+    let mut writer = Writer::new(stream, Encoder::new(&send_key, padding), hub.shaping);
+    let Ok(bulk) = writer.open_bulk(&send_key, &recv_key) else {
+        return;
+    };
+    let writer = Arc::new(Mutex::new(writer));
+    let id = hub.register(
+        addr,
+        peer_identity,
+        code.clone(),
+        Arc::clone(&writer),
+        resumption,
+        resumed,
+    );
+    hub.set_link(id, link, hears_links);
+    start_heartbeat(Arc::clone(&writer), heartbeat);
+    if resumed {
+        println!(
+            "\n⚡ Peer #{} resumed its verified session from {}",
+            id, addr
+        );
+        hub.welcome(id);
+    } else if hub.access.lock().unwrap().vouches_for(&peer_identity) {
+        println!("\n✓ Peer #{} connected from {}", id, addr);
+        println!(
+            "📋 Its key is on the allow list, so the server takes it as verified; code: {}",
+            code
+        );
+        report_verify(hub.verify(Some(id)));
+    } else {
+        println!("\n✓ Peer #{} connected from {}", id, addr);
+        println!("🔎 Verification code for #{}: {}", id, code);
+        println!(
+            " Type /verify {} if it matches the client's code, /reject {} otherwise.",
+            id, id
+        );
+    }
+    prompt();
+
+    // Chunks on the bulk stream count against the same --byte-rate.
+    let guard = Arc::new(Mutex::new(FloodGuard::new(hub.rates)));
+    if let Some((stream, inbox)) = bulk {
+        let hub = hub.clone();
+        let guard = Arc::clone(&guard);
+        thread::spawn(move || {
+            receive_bulk(stream, inbox, |transfer, data| {
+                let pause = guard.lock().unwrap().throttle(HEADER_LEN + data.len());
+                thread::sleep(pause);
+                if hub.is_verified(id) {
+                    let chunk = Frame::FileChunk {
+                        id: transfer,
+                        data: data.to_vec(),
+                    };
+                    hub.broadcast(Some(id), &chunk);
+                }
+            });
+        });
+    }
+
+    let mut reader = BufReader::new(read_half);
+    let mut inbox = Inbox::new(&recv_key, padding);
+    let mut left = false;
+    loop {
+        let incoming = match inbox.receive(&mut reader) {
+            Ok(incoming) => incoming,
+            Err(e) => {
+                if is_timeout(&e) {
+                    println!(
+                        "\n💀 Peer #{} sent nothing for {}s, dropping it.",
+                        id,
+                        timeout.as_secs()
+                    );
+                }
+                break;
+            }
+        };
+        if let Incoming::Message {
+            frame, ciphertext, ..
+        } = &incoming
+        {
+            let pause = guard
+                .lock()
+                .unwrap()
+                .throttle(HEADER_LEN + ciphertext.len());
+            thread::sleep(pause);
+            let verdict = guard.lock().unwrap().check(frame);
+            match verdict {
+                Verdict::Accept => {}
+                Verdict::Muted => continue,
+                Verdict::Strike { mute, strikes_left } => {
+                    println!(
+                        "\n🔇 Peer #{} is flooding, muted for {}s ({} strike(s) left).",
+                        id,
+                        mute.as_secs(),
+                        strikes_left
+                    );
+                    let muted = Control::Muted {
+                        seconds: mute.as_secs(),
+                        strikes_left,
+                    };
+                    let _ = writer.lock().unwrap().send(&Frame::Control(muted));
+                    prompt();
+                    continue;
+                }
+                Verdict::Disconnect => {
+                    println!("\n👢 Peer #{} kept flooding, disconnecting it.", id);
+                    let kicked = Control::Kicked("too many messages, too fast".to_string());
+                    let _ = writer.lock().unwrap().send(&Frame::Control(kicked));
+                    break;
+                }
+            }
+        }
+        match incoming {
+            Incoming::Message {
+                frame: Frame::Ping, ..
+            } => {
+                let _ = writer.lock().unwrap().send(&Frame::Pong);
+                continue;
+            }
+            Incoming::Message {
+                frame: Frame::Pong | Frame::Ack(_),
+                ..
+            } => continue,
+            Incoming::Message {
+                frame: Frame::Control(Control::Verified),
+                ..
+            } => {
+                if hub.mark_peer_verified(id) {
+                    println!("\n✅ Peer #{} verified on both sides.", id);
+                    hub.welcome(id);
+                } else {
+                    println!("\n✔️  Peer #{} confirmed the verification code.", id);
+                }
+            }
+            Incoming::Message {
+                frame: Frame::Control(_) | Frame::DeviceLink(_),
+                ..
+            } => continue,
+            Incoming::Message {
+                frame: Frame::Goodbye,
+                ..
+            } => {
+                left = true;
+                break;
+            }
+            Incoming::Message { .. } if !hub.is_verified(id) => {
+                println!("\n⚠️  Dropped a message from unverified peer #{}.", id);
+            }
+            Incoming::Message {
+                frame: Frame::Text(message),
+                sequence,
+                ..
+            } => {
+                let _ = writer.lock().unwrap().send(&Frame::Ack(sequence));
+                if message.author != peer_identity || !message.signed() {
+                    println!(
+                        "\n⚠️  Dropped a message from #{} not signed by its identity.",
+                        id
+                    );
+                    prompt();
+                    continue;
+                }
+                if let Err(conflict) = hub.set_nick(id, &message.nick) {
+                    println!(
+                        "\n🚨🚨🚨 PEER #{} USES A NICKNAME PINNED TO ANOTHER IDENTITY 🚨🚨🚨",
+                        id
+                    );
+                    println!(" {}.", conflict);
+                    println!(
+                        " Its messages are not relayed; type /trust {} if the nickname really changed hands.",
+                        id
+                    );
+                    prompt();
+                    continue;
+                }
+                println!(
+                    "\n📨 {} #{} {}: {}",
+                    message.times(),
+                    id,
+                    message.nick,
+                    message.text
+                );
+                notify::message(&message.nick, &message.text);
+                hub.broadcast(Some(id), &Frame::Text(message));
+            }
+            Incoming::Message {
+                frame: Frame::Amend(amendment),
+                ..
+            } => {
+                if amendment.author != peer_identity || !amendment.signed() {
+                    println!(
+                        "\n⚠️  Dropped an edit from #{} not signed by its identity.",
+                        id
+                    );
+                    prompt();
+                    continue;
+                }
+                if let Err(conflict) = hub.set_nick(id, &amendment.nick) {
+                    println!("\n⚠️  Dropped an edit from #{}: {}.", id, conflict);
+                    prompt();
+                    continue;
+                }
+                println!("\n✏️  #{} {} {}", id, amendment.nick, amendment.describe());
+                hub.broadcast(Some(id), &Frame::Amend(amendment));
+            }
+            Incoming::Message {
+                frame: frame @ Frame::FileOffer(_),
+                ..
+            } => {
+                if let Frame::FileOffer(offer) = &frame {
+                    println!(
+                        "\n📎 #{} {} offers {} ({} bytes)",
+                        id, offer.nick, offer.name, offer.size
+                    );
+                }
+                hub.broadcast(Some(id), &frame);
+            }
+            Incoming::Message {
+                frame: frame @ Frame::Attachment(_),
+                ..
+            } => {
+                if let Frame::Attachment(attachment) = &frame {
+                    println!(
+                        "\n📎 #{} {} attached {} ({} bytes)",
+                        id,
+                        attachment.nick,
+                        attachment.name,
+                        attachment.data.len()
+                    );
+                }
+                hub.broadcast(Some(id), &frame);
+            }
+            Incoming::Message {
+                frame: frame @ Frame::FileChunk { .. },
+                ..
+            } => {
+                hub.broadcast(Some(id), &frame);
+                continue;
+            }
+            Incoming::Message {
+                frame:
+                    Frame::FileAnswer {
+                        id: transfer,
+                        accepted,
+                    },
+                ..
+            } => {
+                hub.answer_offer(id, transfer, accepted);
+                continue;
+            }
+            Incoming::Message {
+                frame: Frame::Room(message),
+                ..
+            } => {
+                hub.room_message(id, *message);
+                continue;
+            }
+            Incoming::Message {
+                frame: Frame::Queued(_),
+                ..
+            } => {
+                println!("\n⚠️  Ignored a queued message from #{}.", id);
+            }
+            Incoming::Replayed(sequence) => {
+                println!(
+                    "\n⚠️  Rejected a replayed message from #{} (sequence number {}).",
+                    id, sequence
+                );
+            }
+            Incoming::Gap(missing) => {
+                println!("\n⚠️  {} message(s) from #{} never arrived.", missing, id);
+            }
+            Incoming::Tampered => {
+                println!(
+                    "\n⚠️  Dropped a message from #{} that failed authentication.",
+                    id
+                );
+            }
+            Incoming::Malformed => {
+                println!("\n⚠️  Ignored a malformed message from #{}.", id);
+            }
+        }
+        prompt();
+    }
+
+    if let Some(addr) = hub.remove(id) {
+        let how = if left { "left" } else { "disconnected" };
+        println!("\n👋 Peer #{} ({}) {}.", id, addr, how);
+        prompt();
+    }
+}
@@ -0,0 +1,332 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+/// Read instead of asking on the terminal, for scripts and services.
+pub const PASSPHRASE_ENV: &str = "STREAMCHAT_IDENTITY_PASSPHRASE";
+/// Argon2id cost for new files: 64 MiB, 3 passes. Files record their own
+/// parameters, so raising these later keeps old files readable.
+const KDF_MEMORY_KIB: u32 = 64 * 1024;
+const KDF_PASSES: u32 = 3;
+const KDF_LANES: u32 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+/// Wrong passphrases typed before giving up.
+const PASSPHRASE_TRIES: u32 = 3;
+
+/// How the private key is kept on disk.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Storage {
+    /// Sealed with a key derived from a passphrase:
+    /// `argon2id$m=KIB,t=PASSES,p=LANES$SALT$NONCE$CIPHERTEXT` (hex), the
+    /// part before the salt authenticated along with the key.
+    Encrypted,
+    /// The bare seed in hex. Development only; also lets such a file be
+    /// loaded.
+    Plain,
+}
+
+/// Long-term Ed25519 identity, used to sign the ephemeral key exchange so
+/// peers can tell who they are talking to.
+#[derive(Clone)]
+pub struct Identity {
+    signing_key: SigningKey,
+}
+
+impl Identity {
+    pub fn signing_key(&self) -> &SigningKey {
+        &self.signing_key
+    }
+
+    /// Loads the identity stored at `path`, creating a fresh one on first run
+    /// and saving it as `storage` says. The boolean is `true` when a new key
+    /// was generated.
+    pub fn load_or_generate(path: &Path, storage: Storage) -> io::Result<(Self, bool)> {
+        if path.exists() {
+            return Ok((Self::load(path, storage)?, false));
+        }
+
+        let mut seed = Zeroizing::new([0u8; 32]);
+        rand::rng().fill(&mut seed[..]);
+        let identity = Identity {
+            signing_key: SigningKey::from_bytes(&seed),
+        };
+        let passphrase = match storage {
+            Storage::Encrypted => Some(new_passphrase()?),
+            Storage::Plain => None,
+        };
+        identity.save(path, passphrase.as_deref().map(String::as_str))?;
+        Ok((identity, true))
+    }
+
+    /// Reads the key at `path`, asking for its passphrase. An unencrypted
+    /// file is refused unless `storage` is `Plain`.
+    pub fn load(path: &Path, storage: Storage) -> io::Result<Self> {
+        let content = Zeroizing::new(fs::read_to_string(path)?);
+        let invalid = |message: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), message),
+            )
+        };
+        let content = content.trim();
+        if !content.starts_with("argon2id$") {
+            if storage != Storage::Plain {
+                return Err(invalid(
+                    "the identity key is stored unencrypted; encrypt it with `streamchat identity --set-passphrase`, or pass --insecure-plain-identity",
+                ));
+            }
+            let seed: Zeroizing<[u8; 32]> = hex::decode(content)
+                .ok()
+                .and_then(|bytes| bytes.try_into().ok())
+                .map(Zeroizing::new)
+                .ok_or_else(|| invalid("not a valid identity key"))?;
+            return Ok(Identity {
+                signing_key: SigningKey::from_bytes(&seed),
+            });
+        }
+
+        let sealed = Sealed::parse(content).ok_or_else(|| invalid("not a valid identity key"))?;
+        let from_env = std::env::var_os(PASSPHRASE_ENV).is_some();
+        for _ in 0..PASSPHRASE_TRIES {
+            let passphrase = read_passphrase(&format!("🔐 Passphrase for {}: ", path.display()))?;
+            match sealed.open(&passphrase)? {
+                Some(seed) => {
+                    return Ok(Identity {
+                        signing_key: SigningKey::from_bytes(&seed),
+                    });
+                }
+                None if from_env => break,
+                None => eprintln!("❌ Wrong passphrase, try again."),
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("wrong passphrase for {}", path.display()),
+        ))
+    }
+
+    /// Writes the key to `path`, sealed with `passphrase` unless there is
+    /// none.
+    pub fn save(&self, path: &Path, passphrase: Option<&str>) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let seed = Zeroizing::new(self.signing_key.to_bytes());
+        let content = match passphrase {
+            Some(passphrase) => Sealed::seal(&seed, passphrase)?.to_string(),
+            None => hex::encode(*seed),
+        };
+        write_private(path, &(content + "\n"))
+    }
+
+    pub fn public_key(&self) -> VerifyingKey {
+        self.signing_key.verifying_key()
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Signature {
+        self.signing_key.sign(message)
+    }
+
+    /// The identity of a fixed seed, for tests.
+    #[cfg(test)]
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        Identity {
+            signing_key: SigningKey::from_bytes(&seed),
+        }
+    }
+}
+
+/// An encrypted key file.
+struct Sealed {
+    params: Params,
+    salt: Vec<u8>,
+    nonce: [u8; NONCE_LEN],
+    ciphertext: Vec<u8>,
+}
+
+impl Sealed {
+    fn seal(seed: &[u8; 32], passphrase: &str) -> io::Result<Self> {
+        let params = Params::new(KDF_MEMORY_KIB, KDF_PASSES, KDF_LANES, Some(32))
+            .expect("the default cost is valid");
+        let mut sealed = Sealed {
+            params,
+            salt: rand::random::<[u8; SALT_LEN]>().to_vec(),
+            nonce: rand::random(),
+            ciphertext: Vec::new(),
+        };
+        let key = sealed.key(passphrase)?;
+        sealed.ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key[..]))
+            .encrypt(
+                Nonce::from_slice(&sealed.nonce),
+                Payload {
+                    msg: seed,
+                    aad: sealed.header().as_bytes(),
+                },
+            )
+            .expect("encryption of 32 bytes cannot fail");
+        Ok(sealed)
+    }
+
+    /// The seed, or `None` when the passphrase is wrong.
+    fn open(&self, passphrase: &str) -> io::Result<Option<Zeroizing<[u8; 32]>>> {
+        let key = self.key(passphrase)?;
+        let Ok(seed) = ChaCha20Poly1305::new(Key::from_slice(&key[..])).decrypt(
+            Nonce::from_slice(&self.nonce),
+            Payload {
+                msg: &self.ciphertext,
+                aad: self.header().as_bytes(),
+            },
+        ) else {
+            return Ok(None);
+        };
+        let seed = Zeroizing::new(seed);
+        Ok(seed[..].try_into().ok().map(Zeroizing::new))
+    }
+
+    fn key(&self, passphrase: &str) -> io::Result<Zeroizing<[u8; 32]>> {
+        let mut key = Zeroizing::new([0u8; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+            .hash_password_into(passphrase.as_bytes(), &self.salt, &mut key[..])
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        Ok(key)
+    }
+
+    fn header(&self) -> String {
+        format!(
+            "argon2id$m={},t={},p={}",
+            self.params.m_cost(),
+            self.params.t_cost(),
+            self.params.p_cost()
+        )
+    }
+
+    fn parse(text: &str) -> Option<Self> {
+        let [_, params, salt, nonce, ciphertext] = *text.split('$').collect::<Vec<_>>() else {
+            return None;
+        };
+        let mut cost = [None; 3];
+        for (setting, slot) in params.split(',').zip(&mut cost) {
+            *slot = setting
+                .split_once('=')
+                .and_then(|(_, value)| value.parse().ok());
+        }
+        let [Some(memory), Some(passes), Some(lanes)] = cost else {
+            return None;
+        };
+        Some(Sealed {
+            params: Params::new(memory, passes, lanes, Some(32)).ok()?,
+            salt: hex::decode(salt).ok()?,
+            nonce: hex::decode(nonce).ok()?.try_into().ok()?,
+            ciphertext: hex::decode(ciphertext).ok()?,
+        })
+    }
+}
+
+impl std::fmt::Display for Sealed {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}${}${}${}",
+            self.header(),
+            hex::encode(&self.salt),
+            hex::encode(self.nonce),
+            hex::encode(&self.ciphertext)
+        )
+    }
+}
+
+/// The passphrase from `PASSPHRASE_ENV`, or typed at the terminal.
+fn read_passphrase(prompt: &str) -> io::Result<Zeroizing<String>> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(Zeroizing::new(passphrase));
+    }
+    rpassword::prompt_password(prompt)
+        .map(Zeroizing::new)
+        .map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "can't ask for the identity passphrase ({}); set {}",
+                    e, PASSPHRASE_ENV
+                ),
+            )
+        })
+}
+
+/// A passphrase for a key being saved, typed twice.
+pub fn new_passphrase() -> io::Result<Zeroizing<String>> {
+    let from_env = std::env::var_os(PASSPHRASE_ENV).is_some();
+    loop {
+        let passphrase = read_passphrase("🔐 New passphrase for the identity key: ")?;
+        if passphrase.is_empty() {
+            if from_env {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} is empty", PASSPHRASE_ENV),
+                ));
+            }
+            eprintln!(
+                "⚠️  The passphrase can't be empty (--insecure-plain-identity stores the key unencrypted)."
+            );
+            continue;
+        }
+        if from_env || *read_passphrase("🔐 Same passphrase again: ")? == *passphrase {
+            return Ok(passphrase);
+        }
+        eprintln!("❌ The passphrases differ, try again.");
+    }
+}
+
+/// Writes a secret to `path`, readable by its owner only on unix from the
+/// moment the file exists: a new file is created that way, and one that
+/// was already there is narrowed down before anything is written to it.
+pub fn write_private(path: &Path, content: &str) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(content.as_bytes())
+}
+
+/// `~/.config/streamchat/identity.key` (or the platform equivalent).
+pub fn default_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("streamchat")
+        .join("identity.key")
+}
+
+/// Short, readable digest of a public key, e.g. `3f2a 91c0 ...`.
+pub fn fingerprint(key: &VerifyingKey) -> String {
+    let digest = Sha256::digest(key.as_bytes());
+    digest[..16]
+        .chunks(2)
+        .map(hex::encode)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn parse_public_key(text: &str) -> Result<VerifyingKey, String> {
+    let bytes: [u8; 32] = hex::decode(text.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or("expected 64 hex characters")?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| e.to_string())
+}
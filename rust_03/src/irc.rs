@@ -0,0 +1,352 @@
+//! A minimal IRC client (RFC 2812), as much of it as the bridge needs:
+//! register, join one channel, answer pings, and exchange `PRIVMSG`s with
+//! it. Plain TCP only.
+
+use crate::net;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const DEFAULT_PORT: u16 = 6667;
+/// How long registration and joining may take.
+const REGISTRATION_TIMEOUT: Duration = Duration::from_secs(60);
+/// Text per `PRIVMSG`: lines are limited to 512 bytes, and the server
+/// puts our full `nick!user@host` prefix in front when relaying them.
+const MAX_TEXT: usize = 400;
+
+/// An IRC server and channel, from `irc://host[:port]/#channel`.
+#[derive(Clone, Debug)]
+pub struct Target {
+    address: String,
+    pub channel: String,
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "irc://{}/{}", self.address, self.channel)
+    }
+}
+
+/// Parses `irc://host[:port]/#channel`; the `#` may be left out or
+/// written `%23`.
+pub fn parse_target(text: &str) -> Result<Target, String> {
+    if text.starts_with("ircs://") {
+        return Err("ircs:// (TLS) is not supported, only irc://".to_string());
+    }
+    let rest = text
+        .strip_prefix("irc://")
+        .ok_or("expected irc://server/#channel")?;
+    let (server, channel) = rest
+        .split_once('/')
+        .ok_or("expected a channel after the server, irc://server/#channel")?;
+    if server.is_empty() {
+        return Err("missing IRC server".to_string());
+    }
+    let has_port = server
+        .rsplit_once(':')
+        .is_some_and(|(host, port)| !host.ends_with(':') && port.parse::<u16>().is_ok());
+    let address = if has_port {
+        server.to_string()
+    } else if server.contains(':') && !server.starts_with('[') {
+        format!("[{}]:{}", server, DEFAULT_PORT)
+    } else {
+        format!("{}:{}", server, DEFAULT_PORT)
+    };
+    let channel = channel.strip_prefix("%23").unwrap_or(channel);
+    let channel = channel.strip_prefix('#').unwrap_or(channel);
+    if channel.is_empty()
+        || channel.len() > 49
+        || channel
+            .chars()
+            .any(|c| c == ',' || c == ' ' || c.is_control())
+    {
+        return Err(format!("invalid channel name {:?}", channel));
+    }
+    Ok(Target {
+        address,
+        channel: format!("#{}", channel),
+    })
+}
+
+/// One line from the server: `[:prefix] COMMAND params [:trailing]`.
+struct Message {
+    prefix: Option<String>,
+    command: String,
+    params: Vec<String>,
+}
+
+impl Message {
+    fn parse(line: &str) -> Option<Message> {
+        let mut rest = line;
+        // IRCv3 tags are not asked for, but some servers send them anyway.
+        if let Some(tagged) = rest.strip_prefix('@') {
+            rest = tagged.split_once(' ')?.1;
+        }
+        let prefix = match rest.strip_prefix(':') {
+            Some(prefixed) => {
+                let (prefix, after) = prefixed.split_once(' ')?;
+                rest = after;
+                Some(prefix.to_string())
+            }
+            None => None,
+        };
+        let (middle, trailing) = match rest.split_once(" :") {
+            Some((middle, trailing)) => (middle, Some(trailing)),
+            None => (rest, None),
+        };
+        let mut words = middle.split(' ').filter(|word| !word.is_empty());
+        let command = words.next()?.to_ascii_uppercase();
+        let mut params: Vec<String> = words.map(str::to_string).collect();
+        params.extend(trailing.map(str::to_string));
+        Some(Message {
+            prefix,
+            command,
+            params,
+        })
+    }
+
+    /// The sender's nickname, from a `nick!user@host` prefix.
+    fn nick(&self) -> Option<&str> {
+        let prefix = self.prefix.as_deref()?;
+        Some(prefix.split_once('!').map_or(prefix, |(nick, _)| nick))
+    }
+}
+
+/// A message said in the channel.
+pub struct Said {
+    pub nick: String,
+    pub text: String,
+    /// A `/me` action.
+    pub action: bool,
+}
+
+/// Removes mIRC formatting (bold, colours, ...) and any other control
+/// character, which a terminal could take for an escape sequence.
+fn strip_formatting(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\x03' {
+            // Colour code: up to two digits, optionally ",NN".
+            for _ in 0..2 {
+                chars.next_if(char::is_ascii_digit);
+            }
+            let mut lookahead = chars.clone();
+            if lookahead.next() == Some(',') && lookahead.next().is_some_and(|c| c.is_ascii_digit())
+            {
+                chars.next();
+                for _ in 0..2 {
+                    chars.next_if(char::is_ascii_digit);
+                }
+            }
+        } else if !c.is_control() {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// Splits `text` into pieces of at most `MAX_TEXT` bytes, on character
+/// boundaries.
+fn chunks(text: &str) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    while rest.len() > MAX_TEXT {
+        let mut end = MAX_TEXT;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (piece, after) = rest.split_at(end);
+        pieces.push(piece);
+        rest = after;
+    }
+    if !rest.is_empty() {
+        pieces.push(rest);
+    }
+    pieces
+}
+
+/// Write half of the connection, shared by whoever relays into the channel.
+#[derive(Clone)]
+pub struct Sender {
+    stream: Arc<Mutex<TcpStream>>,
+    channel: String,
+}
+
+impl Sender {
+    fn send_line(&self, line: &str) -> io::Result<()> {
+        let mut stream = self.stream.lock().unwrap();
+        stream.write_all(line.as_bytes())?;
+        stream.write_all(b"\r\n")?;
+        stream.flush()
+    }
+
+    /// Says `text` in the channel: one `PRIVMSG` per line, long lines
+    /// split, control characters (CTCP included) removed.
+    pub fn say(&self, text: &str) -> io::Result<()> {
+        for line in text.lines() {
+            let line: String = line.chars().filter(|c| !c.is_control()).collect();
+            for piece in chunks(&line) {
+                self.send_line(&format!("PRIVMSG {} :{}", self.channel, piece))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn quit(&self, reason: &str) {
+        let _ = self.send_line(&format!("QUIT :{}", reason));
+    }
+}
+
+/// A registered connection that joined its channel.
+pub struct Client {
+    sender: Sender,
+    reader: BufReader<TcpStream>,
+    nick: String,
+}
+
+impl Client {
+    /// Connects to `target`, registers as `nick` (with `_` appended while
+    /// it is taken) and joins the channel.
+    pub fn connect(target: &Target, nick: &str) -> io::Result<Client> {
+        let stream = net::connect(&target.address)?;
+        stream.set_read_timeout(Some(REGISTRATION_TIMEOUT))?;
+        let mut client = Client {
+            reader: BufReader::new(stream.try_clone()?),
+            sender: Sender {
+                stream: Arc::new(Mutex::new(stream)),
+                channel: target.channel.clone(),
+            },
+            nick: nick.to_string(),
+        };
+        client.sender.send_line(&format!("NICK {}", client.nick))?;
+        client
+            .sender
+            .send_line(&format!("USER {} 0 * :streamchat bridge", client.nick))?;
+
+        let mut registered = false;
+        loop {
+            let message = client.read_message()?;
+            match message.command.as_str() {
+                "001" => {
+                    registered = true;
+                    if let Some(nick) = message.params.first() {
+                        client.nick = nick.clone();
+                    }
+                    client
+                        .sender
+                        .send_line(&format!("JOIN {}", target.channel))?;
+                }
+                // Nickname in use.
+                "433" if !registered => {
+                    client.nick.push('_');
+                    client.sender.send_line(&format!("NICK {}", client.nick))?;
+                }
+                "JOIN"
+                    if message.nick() == Some(&client.nick)
+                        && message.params.first().is_some_and(|channel| {
+                            channel.eq_ignore_ascii_case(&target.channel)
+                        }) =>
+                {
+                    break;
+                }
+                // Channel full, invite only, banned, wrong key, ...
+                "403" | "405" | "471" | "473" | "474" | "475" | "477" => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        format!(
+                            "can't join {}: {}",
+                            target.channel,
+                            message.params.last().map_or("", String::as_str)
+                        ),
+                    ));
+                }
+                _ => {}
+            }
+        }
+        client.reader.get_ref().set_read_timeout(None)?;
+        Ok(client)
+    }
+
+    pub fn nick(&self) -> &str {
+        &self.nick
+    }
+
+    pub fn sender(&self) -> Sender {
+        self.sender.clone()
+    }
+
+    /// Reads the next message, answering pings and failing on `ERROR`.
+    fn read_message(&mut self) -> io::Result<Message> {
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "the IRC server closed the connection",
+                ));
+            }
+            let Some(message) = Message::parse(line.trim_end_matches(['\r', '\n'])) else {
+                continue;
+            };
+            match message.command.as_str() {
+                "PING" => {
+                    let token = message.params.first().map_or("", String::as_str);
+                    self.sender.send_line(&format!("PONG :{}", token))?;
+                }
+                "ERROR" => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::ConnectionAborted,
+                        format!(
+                            "IRC server: {}",
+                            message.params.first().map_or("", String::as_str)
+                        ),
+                    ));
+                }
+                _ => return Ok(message),
+            }
+        }
+    }
+
+    /// The next message said in the channel by someone else.
+    pub fn next_said(&mut self) -> io::Result<Said> {
+        loop {
+            let message = self.read_message()?;
+            if message.command == "NICK" && message.nick() == Some(&self.nick) {
+                if let Some(nick) = message.params.first() {
+                    self.nick = nick.clone();
+                }
+                continue;
+            }
+            let [target, text] = message.params.as_slice() else {
+                continue;
+            };
+            if message.command != "PRIVMSG" || !target.eq_ignore_ascii_case(&self.sender.channel) {
+                continue;
+            }
+            let Some(nick) = message.nick().filter(|nick| *nick != self.nick) else {
+                continue;
+            };
+            let (text, action) = match text
+                .strip_prefix("\x01ACTION ")
+                .map(|text| text.trim_end_matches('\x01'))
+            {
+                Some(text) => (text, true),
+                // Other CTCP requests are not chat.
+                None if text.starts_with('\x01') => continue,
+                None => (text.as_str(), false),
+            };
+            let text = strip_formatting(text);
+            if text.trim().is_empty() {
+                continue;
+            }
+            return Ok(Said {
+                nick: strip_formatting(nick),
+                text,
+                action,
+            });
+        }
+    }
+}
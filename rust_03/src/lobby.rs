@@ -0,0 +1,97 @@
+//! The rooms where the rendezvous and relay servers keep a peer waiting for
+//! its counterpart. A peer that disconnects while it waits is forgotten,
+//! and one left waiting longer than `WAIT_TIMEOUT` is sent an `ERROR` and
+//! let go.
+
+use crate::net;
+use std::collections::HashMap;
+use std::io::Write;
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a peer may wait in a room for its counterpart.
+const WAIT_TIMEOUT: Duration = Duration::from_secs(600);
+/// How often the waiting peers are checked on.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A peer waiting in a room, with what the server needs to know about it.
+pub struct Waiting<T> {
+    pub stream: TcpStream,
+    pub addr: SocketAddr,
+    pub info: T,
+    since: Instant,
+}
+
+pub struct Lobby<T> {
+    rooms: Arc<Mutex<HashMap<String, Waiting<T>>>>,
+}
+
+impl<T: Send + 'static> Lobby<T> {
+    /// An empty lobby, and the thread that checks on whoever waits in it.
+    pub fn new() -> Self {
+        let rooms: Arc<Mutex<HashMap<String, Waiting<T>>>> = Arc::default();
+        let swept = Arc::clone(&rooms);
+        thread::spawn(move || {
+            loop {
+                thread::sleep(SWEEP_INTERVAL);
+                swept
+                    .lock()
+                    .unwrap()
+                    .retain(|room, waiting| still_waiting(room, waiting));
+            }
+        });
+        Lobby { rooms }
+    }
+
+    /// The peer waiting in `room`, taken out of it, and the newcomer's
+    /// `stream` back; or `None` after leaving the newcomer there to wait.
+    /// One lock covers both, so two peers arriving at once can't both find
+    /// the room empty.
+    pub fn join(
+        &self,
+        room: &str,
+        stream: TcpStream,
+        addr: SocketAddr,
+        info: T,
+    ) -> Option<(Waiting<T>, TcpStream)> {
+        let mut rooms = self.rooms.lock().unwrap();
+        // Whoever waits may have gone since the last sweep.
+        let waiting = rooms
+            .remove(room)
+            .and_then(|mut first| still_waiting(room, &mut first).then_some(first));
+        if let Some(first) = waiting {
+            return Some((first, stream));
+        }
+        println!("⏳ Room {}: {} is waiting for a peer", room, addr);
+        let newcomer = Waiting {
+            stream,
+            addr,
+            info,
+            since: Instant::now(),
+        };
+        rooms.insert(room.to_string(), newcomer);
+        None
+    }
+}
+
+/// Whether `waiting` is still there and hasn't waited in `room` too long;
+/// one that has is told so.
+fn still_waiting<T>(room: &str, waiting: &mut Waiting<T>) -> bool {
+    if net::closed(&waiting.stream) {
+        println!("👋 Room {}: {} left", room, waiting.addr);
+        return false;
+    }
+    if waiting.since.elapsed() >= WAIT_TIMEOUT {
+        println!("⌛ Room {}: {} gave up waiting", room, waiting.addr);
+        let _ = writeln!(
+            waiting.stream,
+            "ERROR nobody joined room {} within {}s",
+            room,
+            WAIT_TIMEOUT.as_secs()
+        );
+        return false;
+    }
+    true
+}
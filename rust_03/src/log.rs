@@ -0,0 +1,55 @@
+//! Diagnostics behind `-v`: handshake steps at debug level, and key
+//! material and frame hex dumps at trace level (`-vv`). Nothing secret is
+//! shown by default, so a session can be screen-shared safely. Log lines
+//! go through `ui::show` like every other line, so they land in the
+//! message pane instead of garbling the TUI.
+
+use crate::ui;
+use std::io::{self, Write};
+use tracing::Level;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::fmt;
+use tracing_subscriber::prelude::*;
+
+/// Collects one formatted event and shows it when dropped.
+struct UiWriter(Vec<u8>);
+
+impl Write for UiWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for UiWriter {
+    fn drop(&mut self) {
+        let line = String::from_utf8_lossy(&self.0);
+        let line = line.trim_end();
+        if !line.is_empty() {
+            ui::show(line.to_string());
+        }
+    }
+}
+
+/// `verbosity` is the number of `-v` flags. Only this crate's events are
+/// shown; the networking libraries have their own, much noisier, traces.
+pub fn init(verbosity: u8) {
+    let level = match verbosity {
+        0 => Level::WARN,
+        1 => Level::DEBUG,
+        _ => Level::TRACE,
+    };
+    let layer = fmt::layer()
+        .with_writer(|| UiWriter(Vec::new()))
+        .with_ansi(false)
+        .with_target(false)
+        .without_time();
+    tracing_subscriber::registry()
+        .with(layer)
+        .with(Targets::new().with_target(env!("CARGO_CRATE_NAME"), level))
+        .init();
+}
@@ -1,21 +1,175 @@
-use clap::{Parser, Subcommand};
-use rand::Rng;
-use std::io::{self, BufRead, BufReader, Read, Write};
-use std::net::{TcpListener, TcpStream};
+mod access;
+mod attachment;
+mod bridge;
+mod commands;
+mod config;
+mod device;
+mod flood;
+mod frame;
+mod history;
+mod hub;
+mod identity;
+mod irc;
+mod lobby;
+mod log;
+mod mesh;
+mod net;
+mod notify;
+mod onion;
+mod pins;
+mod quic;
+mod relay;
+mod rendezvous;
+mod room;
+mod shaping;
+mod socks;
+mod storage;
+mod transfer;
+mod transport;
+mod ui;
+mod ws;
+
+use attachment::ImagePreview;
+use chrono::{Local, TimeZone};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use commands::{Command, Context, Input};
+use device::DeviceLink;
+use ed25519_dalek::{Signature, VerifyingKey};
+use flood::Bucket;
+use frame::{Control, Frame};
+use history::{Amendment, Change, Line, Received};
+use identity::{Identity, Storage};
+use mesh::{Mesh, Session, SessionId};
+use notify::NotifyMode;
+use pins::Pin;
+use room::{Keyring, Membership, RoomMessage};
+use serde::{Deserialize, Serialize};
+use shaping::Shaping;
+use std::io::{self, IsTerminal, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, Receiver};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use streamchat_proto::hello::{FEATURE_ROOM_HISTORY, FEATURE_ROOMS, Negotiated};
+use streamchat_proto::{Decoder, Encoder, Group, Padding, Record, Role, SessionKeys};
+use tracing::trace;
+use transport::{Transport, TransportKind};
+use ui::say;
 
-const P: u64 = 0xD87FA3E291B4C7F3;
-const G: u64 = 2;
-
-const LCG_A: u64 = 1103515245;
-const LCG_C: u64 = 12345;
-const LCG_M: u64 = 1u64 << 32;
+const MAX_NICK_LEN: usize = 32;
+/// A peer that sent nothing (not even a ping) for this many heartbeat
+/// intervals is considered dead.
+const MISSED_HEARTBEATS: u32 = 3;
+/// Bytes read from the connection at a time.
+const READ_CHUNK: usize = 16 * 1024;
+/// Record stream of the file chunks on a transport with a bulk stream.
+const BULK_STREAM: u32 = 1;
 
 #[derive(Parser, Debug)]
 #[command(name = "streamchat")]
-#[command(about = "P2P encrypted chat using Diffie-Hellman", long_about = None)]
+#[command(about = "P2P encrypted chat using X25519 and ChaCha20-Poly1305", long_about = None)]
 struct Cli {
+    /// Defaults for these options [default: ~/.config/streamchat.toml]
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Identity key file [default: ~/.config/streamchat/identity.key]; its
+    /// passphrase is asked for, or read from STREAMCHAT_IDENTITY_PASSPHRASE
+    #[arg(long, global = true)]
+    identity: Option<PathBuf>,
+    /// Keep a new identity key unencrypted, and accept one that is.
+    /// Development only: anyone who reads the file can impersonate you
+    #[arg(long, global = true)]
+    insecure_plain_identity: bool,
+    /// Name shown to other participants [default: $USER]
+    #[arg(long, global = true, value_parser = parse_nick)]
+    nick: Option<String>,
+    /// Where received files are saved
+    #[arg(long, global = true, default_value = "downloads")]
+    downloads: PathBuf,
+    /// Largest file a peer may offer, in MiB; bigger offers are declined
+    /// without asking
+    #[arg(long, global = true, default_value_t = 1024)]
+    max_download: u64,
+    /// Identities (and, on a server, addresses) never to talk to; /block
+    /// adds to it [default: ~/.config/streamchat/deny.list]
+    #[arg(long, global = true)]
+    deny_file: Option<PathBuf>,
+    /// Identity keys seen for each peer, to detect a key that changed
+    /// [default: ~/.config/streamchat/known_peers]
+    #[arg(long, global = true)]
+    known_peers: Option<PathBuf>,
+    /// Seconds between keepalive pings; a peer silent for three intervals
+    /// is considered dead
+    #[arg(long, global = true, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    heartbeat: u64,
+    /// Network transport; both sides must use the same one
+    #[arg(long, global = true, value_enum, default_value_t = TransportKind::Tcp)]
+    transport: TransportKind,
+    /// Same as --transport ws
+    #[arg(long, global = true, conflicts_with = "transport")]
+    ws: bool,
+    /// Key agreement group; both sides must use the same one
+    #[arg(long, global = true, value_enum, default_value_t = Group::X25519)]
+    group: Group,
+    /// Use the 64-bit Diffie-Hellman group of the first versions, to watch
+    /// the arithmetic. Anyone can break it: never for real conversations
+    #[arg(long, global = true, conflicts_with = "group")]
+    insecure_demo: bool,
+    /// Hide message lengths by padding frames; the stronger setting of the
+    /// two sides wins
+    #[arg(long, global = true, value_enum, default_value_t = Padding::Bucket)]
+    padding: Padding,
+    /// Largest frame a file transfer sends, in bytes once padded: chunks are
+    /// cut to fit. Lower it on slow links so a chunk being sent holds up a
+    /// message for less time (--padding max pads to 64 KiB regardless)
+    #[arg(long, global = true, default_value_t = shaping::DEFAULT_MAX_FRAME, value_parser = clap::value_parser!(u64).range(1024..))]
+    max_frame: u64,
+    /// KiB per second sent on each connection; file transfers slow down to
+    /// stay under it, messages always go first (0: no limit)
+    #[arg(long, global = true, default_value_t = 0)]
+    send_rate: u32,
+    /// Passphrase both sides must share; a wrong one fails the handshake
+    #[arg(
+        long,
+        global = true,
+        env = "STREAMCHAT_PASSWORD",
+        hide_env_values = true
+    )]
+    password: Option<String>,
+    /// Desktop notification for incoming messages: when the terminal seems
+    /// unfocused (a bare --notify), or always (--notify=always)
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_value_t = NotifyMode::Off,
+        default_missing_value = "unfocused"
+    )]
+    notify: NotifyMode,
+    /// Include the message text in notifications, not only the sender
+    #[arg(long, global = true)]
+    notify_preview: bool,
+    /// Inline preview of received images (plain output only, the TUI
+    /// can't show them)
+    #[arg(long, global = true, value_enum, default_value_t = ImagePreview::Auto)]
+    image_preview: ImagePreview,
+    /// For scripts and bots (client and peer): stdin lines are sent without
+    /// prompts, received messages are written to stdout as JSON lines
+    /// (sender, timestamp, text...) and everything else goes to stderr
+    #[arg(long, global = true)]
+    pipe: bool,
+    /// Plain line-by-line output even on a terminal (no full-screen UI)
+    #[arg(long, global = true)]
+    plain: bool,
+    /// Show handshake details (-v), and key material and frame hex dumps
+    /// (-vv); never use -vv while sharing your screen
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
     #[command(subcommand)]
     command: Commands,
 }
@@ -23,175 +177,1455 @@ struct Cli {
 #[derive(Subcommand, Debug)]
 enum Commands {
     Server {
-        port: u16,
+        /// Port to listen on, 0 for any free one; may come from the config
+        /// file instead
+        port: Option<u16>,
+        /// Address to listen on, IPv4 or IPv6 (e.g. 0.0.0.0, ::, [::1])
+        #[arg(long, default_value_t = IpAddr::V4(Ipv4Addr::LOCALHOST), value_parser = net::parse_bind)]
+        bind: IpAddr,
+        /// Only accept a peer whose identity public key matches (hex)
+        #[arg(long, value_parser = identity::parse_public_key)]
+        peer_key: Option<VerifyingKey>,
+        /// Only accept peers whose address or identity key is listed; the
+        /// server takes peers listed by key as verified, without a code
+        #[arg(long)]
+        allow_file: Option<PathBuf>,
+        /// Seconds lobby messages are kept for a known peer that is
+        /// offline (0 disables queueing)
+        #[arg(long, default_value_t = 3600)]
+        queue_ttl: u64,
+        /// Messages kept per offline peer
+        #[arg(long, default_value_t = 100)]
+        queue_limit: usize,
+        /// Chat messages a client may send per second before it is muted
+        /// (0 disables the limit)
+        #[arg(long, default_value_t = 5)]
+        message_rate: u32,
+        /// KiB per second read from each client; faster senders are slowed
+        /// down (0 disables the limit)
+        #[arg(long, default_value_t = 1024)]
+        byte_rate: u32,
+        /// Seconds a client may resume a verified session in one round
+        /// trip, without a new verification code (0 disables resumption)
+        #[arg(long, default_value_t = 3600)]
+        ticket_ttl: u64,
+        /// Keep room messages in this directory, sealed under the room
+        /// keys the server doesn't have, for members to read back
+        #[arg(long)]
+        room_history: Option<PathBuf>,
+        /// Messages kept per room with --room-history
+        #[arg(long, default_value_t = 200, value_parser = clap::value_parser!(u64).range(1..))]
+        room_history_len: u64,
+        /// Write the addresses the server is reachable at to this file, one
+        /// per line, once it listens (removed when it stops)
+        #[arg(long)]
+        address_file: Option<PathBuf>,
+        /// Also publish the server as a Tor onion service, through Tor's
+        /// control port
+        #[arg(long)]
+        onion: bool,
+        /// Tor control port used by --onion
+        #[arg(long, default_value = onion::DEFAULT_CONTROL)]
+        tor_control: String,
+        /// Password for Tor's control port, when it is not set up for
+        /// cookie authentication
+        #[arg(long, env = "STREAMCHAT_TOR_PASSWORD", hide_env_values = true)]
+        tor_password: Option<String>,
     },
     Client {
         address: String,
+        /// Connect through a SOCKS5 proxy, e.g. Tor: socks5://127.0.0.1:9050
+        #[arg(long, value_parser = socks::parse_proxy)]
+        proxy: Option<socks::Proxy>,
+        /// Only accept a peer whose identity public key matches (hex)
+        #[arg(long, value_parser = identity::parse_public_key)]
+        peer_key: Option<VerifyingKey>,
+    },
+    /// Relay the lobby or a room of a server to an IRC channel, and back
+    Bridge {
+        /// IRC channel, irc://server[:port]/#channel
+        #[arg(value_parser = irc::parse_target)]
+        irc: irc::Target,
+        /// streamchat server, host:port
+        address: String,
+        /// Room to bridge instead of the lobby
+        #[arg(long, value_parser = room::parse_room)]
+        room: Option<String>,
+        /// The server's identity public key (hex). Required: nobody is
+        /// there to compare verification codes, so the bridge verifies the
+        /// session itself once the key matches
+        #[arg(long, value_parser = identity::parse_public_key)]
+        peer_key: VerifyingKey,
+        /// Nickname on IRC
+        #[arg(long, default_value = "streamchat")]
+        irc_nick: String,
+    },
+    /// Introduce peers behind NAT to each other so they can connect directly
+    Rendezvous {
+        port: u16,
+        /// Address to listen on, IPv4 or IPv6
+        #[arg(long, default_value_t = IpAddr::V4(Ipv4Addr::UNSPECIFIED), value_parser = net::parse_bind)]
+        bind: IpAddr,
+    },
+    /// Forward encrypted traffic between two peers that can't connect directly
+    Relay {
+        port: u16,
+        /// Address to listen on, IPv4 or IPv6
+        #[arg(long, default_value_t = IpAddr::V4(Ipv4Addr::UNSPECIFIED), value_parser = net::parse_bind)]
+        bind: IpAddr,
+    },
+    /// Meet another peer through a rendezvous server and chat directly
+    Peer {
+        /// Rendezvous server, host:port
+        rendezvous: String,
+        /// Room name shared with the other peer
+        room: String,
+        /// Relay server (host:port) to fall back on if hole punching fails
+        #[arg(long)]
+        relay: Option<String>,
+        /// Only accept a peer whose identity public key matches (hex)
+        #[arg(long, value_parser = identity::parse_public_key)]
+        peer_key: Option<VerifyingKey>,
+    },
+    /// Show this machine's identity public key
+    Identity {
+        /// Also write the public key (hex) to this file
+        #[arg(long)]
+        export: Option<PathBuf>,
+        /// Encrypt the identity key under a new passphrase (also turns an
+        /// unencrypted key into an encrypted one)
+        #[arg(long)]
+        set_passphrase: bool,
+        /// Invite another device into this identity: sign its public key
+        /// (hex, shown by `streamchat identity` there) and print an
+        /// invitation to accept on it
+        #[arg(long, value_name = "KEY", value_parser = identity::parse_public_key)]
+        link_device: Option<VerifyingKey>,
+        /// Join this device to the identity that printed INVITATION with
+        /// --link-device; peers then see that identity when it speaks
+        #[arg(long, value_name = "INVITATION", conflicts_with = "link_device")]
+        accept_link: Option<String>,
     },
 }
 
-fn mod_pow(mut base: u128, mut exp: u128, modulus: u128) -> u64 {
-    let mut result: u128 = 1;
-    base %= modulus;
+/// A chat message: a random id (for `/edit` and `/delete`), when it was
+/// written (Unix milliseconds), the sender's nickname and the text. The
+/// timestamp travels inside the encrypted frame, so it is authenticated
+/// too.
+///
+/// The author signs all four with its identity key. Frames are only
+/// authenticated hop by hop, so without the signature a hub relaying the
+/// message could change it or write one in anybody's name.
+#[derive(Clone, Serialize, Deserialize)]
+struct TextMessage {
+    id: u64,
+    sent_at: u64,
+    nick: String,
+    text: String,
+    author: VerifyingKey,
+    signature: Signature,
+}
 
-    while exp > 0 {
-        if exp % 2 == 1 {
-            result = (result * base) % modulus;
+impl TextMessage {
+    fn new(nick: &str, text: &str, identity: &Identity) -> Self {
+        let id = rand::random();
+        let sent_at = now_millis();
+        let signature = identity.sign(&Self::signed_bytes(id, sent_at, nick, text));
+        TextMessage {
+            id,
+            sent_at,
+            nick: nick.to_string(),
+            text: text.to_string(),
+            author: identity.public_key(),
+            signature,
         }
-        base = (base * base) % modulus;
-        exp /= 2;
     }
 
-    result as u64
+    fn signed_bytes(id: u64, sent_at: u64, nick: &str, text: &str) -> Vec<u8> {
+        let mut bytes = b"streamchat v1 message".to_vec();
+        bytes.extend_from_slice(&id.to_be_bytes());
+        bytes.extend_from_slice(&sent_at.to_be_bytes());
+        bytes.extend_from_slice(&(nick.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(nick.as_bytes());
+        bytes.extend_from_slice(text.as_bytes());
+        bytes
+    }
+
+    fn is_valid(&self) -> bool {
+        parse_nick(&self.nick).is_ok()
+    }
+
+    /// Whether `author` really wrote this message.
+    fn signed(&self) -> bool {
+        let bytes = Self::signed_bytes(self.id, self.sent_at, &self.nick, &self.text);
+        self.author.verify_strict(&bytes, &self.signature).is_ok()
+    }
+
+    /// Checks the signature, and the author against `peer` (the other end
+    /// of the session) and the identity pinned for the nickname. A
+    /// nickname seen for the first time is pinned to its author.
+    fn authorship(&self, peer: Option<&VerifyingKey>, known_peers: &Path) -> Authorship {
+        if !self.signed() {
+            return Authorship::BadSignature;
+        }
+        if peer == Some(&self.author) {
+            return Authorship::Verified;
+        }
+        // A linked device is checked as its primary identity.
+        let account = device::account(&self.author);
+        let name = format!("nick:{}", self.nick);
+        match pins::check(known_peers, &name, &account) {
+            Ok(Pin::Matches) => Authorship::Verified,
+            Ok(Pin::New) if pins::pin(known_peers, &name, &account).is_ok() => {
+                Authorship::FirstSeen
+            }
+            Ok(Pin::Changed(_)) => Authorship::Impostor,
+            _ => Authorship::Unknown,
+        }
+    }
+
+    /// Receive time and the sender's time, e.g. `[14:02:31 | sent 14:02:30]`.
+    fn times(&self) -> String {
+        format!(
+            "[{} | sent {}]",
+            format_time(now_millis()),
+            format_time(self.sent_at)
+        )
+    }
 }
 
-struct StreamCipher {
-    state: u64,
+/// How far the author of a received message can be trusted.
+#[derive(Clone, Copy)]
+enum Authorship {
+    /// The other end of the session, or the identity pinned for the
+    /// nickname.
+    Verified,
+    /// A nickname never seen before, now pinned to its author.
+    FirstSeen,
+    /// The nickname is pinned to another identity.
+    Impostor,
+    BadSignature,
+    /// The known peers file could not be read or written.
+    Unknown,
 }
 
-impl StreamCipher {
-    fn new(seed: u64) -> Self {
-        StreamCipher { state: seed }
+impl Authorship {
+    /// Shown before the nickname.
+    fn badge(self) -> &'static str {
+        match self {
+            Authorship::Verified => "✓",
+            Authorship::FirstSeen => "🆕",
+            Authorship::Impostor => "✗ not the pinned identity,",
+            Authorship::BadSignature => "✗ bad signature,",
+            Authorship::Unknown => "?",
+        }
     }
 
-    fn next_byte(&mut self) -> u8 {
-        self.state =
-            ((self.state as u128 * LCG_A as u128 + LCG_C as u128) % LCG_M as u128) as u64;
-        (self.state & 0xFF) as u8
+    /// Name in `--pipe` output.
+    fn name(self) -> &'static str {
+        match self {
+            Authorship::Verified => "verified",
+            Authorship::FirstSeen => "new",
+            Authorship::Impostor => "impostor",
+            Authorship::BadSignature => "bad-signature",
+            Authorship::Unknown => "unknown",
+        }
     }
+}
 
-    fn encrypt(&mut self, data: &[u8]) -> Vec<u8> {
-        data.iter().map(|&b| b ^ self.next_byte()).collect()
-    }
+/// How a received message reached us.
+#[derive(Clone, Copy, PartialEq)]
+enum Delivery {
+    Live,
+    /// Kept by the hub while we were offline.
+    Queued,
+    /// From a room's history: already seen by the room, so neither
+    /// notified nor bridged.
+    Earlier,
+}
 
-    fn decrypt(&mut self, data: &[u8]) -> Vec<u8> {
-        self.encrypt(data)
+/// Shows a received chat message (sent to `room`, or delivered late as
+/// `delivery` says), notifies about it and remembers it in `received` for
+/// later amendments. With `--pipe` the message goes to stdout as one JSON
+/// line instead.
+fn show_message(
+    message: &TextMessage,
+    authorship: Authorship,
+    room: Option<&str>,
+    delivery: Delivery,
+    received: &mut Received,
+) {
+    let from = match room {
+        Some(room) => format!("{} in #{}", message.nick, room),
+        None => message.nick.clone(),
+    };
+    if delivery != Delivery::Earlier {
+        notify::message(&from, &message.text);
+        bridge::message(message, authorship, room);
+    }
+    let (icon, tail) = match delivery {
+        Delivery::Live => ("📨", ""),
+        Delivery::Queued => ("📬", " (queued while you were offline)"),
+        Delivery::Earlier => ("📜", " (earlier)"),
+    };
+    let line = Line {
+        head: format!(
+            "\n{} {} {}{} {}",
+            icon,
+            message.times(),
+            room.map(|room| format!("#{} ", room)).unwrap_or_default(),
+            authorship.badge(),
+            message.nick,
+        ),
+        text: message.text.clone(),
+        tail: tail.to_string(),
+    };
+    if ui::piped() {
+        let json = serde_json::json!({
+            "type": "message",
+            "id": message.id,
+            "sender": message.nick,
+            "timestamp": message.sent_at,
+            "text": message.text,
+            "peer": ui::source(),
+            "room": room,
+            "queued": delivery == Delivery::Queued,
+            "earlier": delivery == Delivery::Earlier,
+            "authorship": authorship.name(),
+            "fingerprint": identity::fingerprint(&device::account(&message.author)),
+            "device": identity::fingerprint(&message.author),
+        });
+        println!("{}", json);
+    } else {
+        ui::show_keyed(message.id, line.render());
     }
+    received.record(message, line);
 }
 
-fn diffie_hellman_exchange(stream: &mut TcpStream) -> io::Result<u64> {
-    let mut rng = rand::rng();
-    let private_key: u64 = rng.random_range(1000..100000);
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
 
-    println!("\n🔑 Diffie-Hellman Key Exchange");
-    println!(" P (prime): 0x{:016X}", P);
-    println!(" G (generator): {}", G);
-    println!(" Private key: {} (0x{:X})", private_key, private_key);
+/// Local time of day, with the date when it isn't today (or when the
+/// sender's clock is that far off).
+fn format_time(millis: u64) -> String {
+    let Some(time) = Local.timestamp_millis_opt(millis as i64).single() else {
+        return "??:??:??".to_string();
+    };
+    if time.date_naive() == Local::now().date_naive() {
+        time.format("%H:%M:%S").to_string()
+    } else {
+        time.format("%Y-%m-%d %H:%M:%S").to_string()
+    }
+}
 
-    let public_key = mod_pow(G as u128, private_key as u128, P as u128);
-    println!(" Public key: {} (0x{:X})", public_key, public_key);
+fn parse_nick(nick: &str) -> Result<String, String> {
+    if nick.is_empty() || nick.len() > MAX_NICK_LEN {
+        return Err(format!("nickname must be 1 to {} bytes", MAX_NICK_LEN));
+    }
+    if nick.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err("nickname must not contain spaces or control characters".to_string());
+    }
+    Ok(nick.to_string())
+}
 
-    println!("\n📤 Sending public key...");
-    stream.write_all(&public_key.to_be_bytes())?;
-    stream.flush()?;
+fn default_nick() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .ok()
+        .and_then(|user| parse_nick(&user).ok())
+        .unwrap_or_else(|| "anonymous".to_string())
+}
 
-    println!("📥 Receiving peer's public key...");
-    let mut peer_public_key_bytes = [0u8; 8];
-    stream.read_exact(&mut peer_public_key_bytes)?;
-    let peer_public_key = u64::from_be_bytes(peer_public_key_bytes);
-    println!(
-        " Peer's public key: {} (0x{:X})",
-        peer_public_key, peer_public_key
+/// Runs the protocol's key exchange with our identity and reports what it
+/// established.
+fn key_exchange(
+    stream: &mut (impl Read + Write),
+    role: Role,
+    identity: &Identity,
+    expected_peer: Option<&VerifyingKey>,
+    hello: &Negotiated,
+    password: Option<&str>,
+) -> io::Result<(SessionKeys, VerifyingKey)> {
+    let handshake = streamchat_proto::key_exchange(
+        stream,
+        role,
+        identity.signing_key(),
+        expected_peer,
+        hello,
+        password,
+    )?;
+    if handshake.password {
+        say!("\n🔒 The peer knows the password.");
+    }
+    say!(
+        " Peer identity: {}",
+        identity::fingerprint(&handshake.peer_identity)
     );
+    Ok((handshake.keys, handshake.peer_identity))
+}
 
-    let shared_secret = mod_pow(peer_public_key as u128, private_key as u128, P as u128);
-    println!(
-        "\n🔐 Shared secret computed: {} (0x{:X})",
-        shared_secret, shared_secret
-    );
+/// A frame as it went out, for display.
+struct Sent {
+    sequence: u64,
+    plaintext: Vec<u8>,
+    ciphertext: Vec<u8>,
+}
 
-    Ok(shared_secret)
+/// Write half of a connection, shared by every thread that sends on it
+/// (input loop, receiver answering pings, heartbeat, file transfers).
+struct Writer {
+    stream: Box<dyn Transport>,
+    encoder: Encoder,
+    shaping: Shaping,
+    /// What was sent, against `--send-rate`.
+    pacer: Option<Bucket>,
+    /// Where file chunks go when the transport has a bulk stream.
+    bulk: Option<Arc<Mutex<BulkStream>>>,
 }
 
-fn chat_loop(mut stream: TcpStream) -> io::Result<()> {
-    println!("\n🤝 Establishing secure connection...");
-    let shared_secret = diffie_hellman_exchange(&mut stream)?;
-    println!("\n✅ Secure channel established!");
-    println!("💬 You can now send messages (Ctrl+C to quit)\n");
+/// The write half of a bulk stream, with sequence numbers of its own.
+struct BulkStream {
+    stream: Box<dyn Transport>,
+    encoder: Encoder,
+}
 
-    let stream_clone = stream.try_clone()?;
-    let cipher_recv = Arc::new(Mutex::new(StreamCipher::new(shared_secret)));
-    let cipher_send = Arc::new(Mutex::new(StreamCipher::new(shared_secret)));
+impl Writer {
+    fn new(stream: Box<dyn Transport>, encoder: Encoder, shaping: Shaping) -> Self {
+        Writer {
+            stream,
+            encoder,
+            shaping,
+            pacer: shaping.pacer(),
+            bulk: None,
+        }
+    }
+
+    /// Sends file chunks on the transport's bulk stream, if it has one,
+    /// and returns the stream and inbox to read the peer's from.
+    fn open_bulk(
+        &mut self,
+        send_key: &[u8; 32],
+        recv_key: &[u8; 32],
+    ) -> io::Result<Option<(Box<dyn Transport>, Inbox)>> {
+        let Some(stream) = self.stream.bulk()? else {
+            return Ok(None);
+        };
+        let padding = self.encoder.padding();
+        self.bulk = Some(Arc::new(Mutex::new(BulkStream {
+            stream: stream.try_clone()?,
+            encoder: Encoder::on_stream(send_key, padding, BULK_STREAM),
+        })));
+        let inbox = Inbox {
+            decoder: Decoder::on_stream(recv_key, padding, BULK_STREAM),
+        };
+        Ok(Some((stream, inbox)))
+    }
+
+    /// Seals and writes one frame, in the interactive lane (see `shaping`).
+    fn send(&mut self, frame: &Frame) -> io::Result<Sent> {
+        let plaintext = frame.encode();
+        let sealed = self.encoder.encode(&plaintext)?;
+        self.stream.write_all(&sealed.bytes)?;
+        self.stream.flush()?;
+        if let Some(pacer) = &mut self.pacer {
+            pacer.borrow(sealed.bytes.len() as f64);
+        }
+        Ok(Sent {
+            sequence: sealed.sequence,
+            plaintext,
+            ciphertext: sealed.ciphertext().to_vec(),
+        })
+    }
+
+    /// Sends a bulk frame; returns how long the bulk lane must pause
+    /// before the next one. On a bulk stream the writer stays free for
+    /// messages while the frame is written.
+    fn send_bulk(writer: &Mutex<Writer>, frame: &Frame) -> io::Result<Duration> {
+        let bulk = writer.lock().unwrap().bulk.clone();
+        let written = match bulk {
+            Some(bulk) => {
+                let mut bulk = bulk.lock().unwrap();
+                let sealed = bulk.encoder.encode(&frame.encode())?;
+                bulk.stream.write_all(&sealed.bytes)?;
+                bulk.stream.flush()?;
+                sealed.bytes.len()
+            }
+            None => {
+                let mut writer = writer.lock().unwrap();
+                let sealed = writer.encoder.encode(&frame.encode())?;
+                writer.stream.write_all(&sealed.bytes)?;
+                writer.stream.flush()?;
+                sealed.bytes.len()
+            }
+        };
+        let mut writer = writer.lock().unwrap();
+        Ok(writer
+            .pacer
+            .as_mut()
+            .map_or(Duration::ZERO, |pacer| pacer.borrow(written as f64)))
+    }
 
-    let cipher_recv_clone = Arc::clone(&cipher_recv);
+    /// File data per chunk on this connection.
+    fn chunk_size(&self) -> usize {
+        self.shaping.chunk_size(self.encoder.padding())
+    }
+}
+
+/// Tells the peer we are leaving on purpose and closes the connection, so
+/// it can tell a goodbye from a crash.
+fn goodbye(writer: &Mutex<Writer>) {
+    let mut writer = writer.lock().unwrap();
+    let _ = writer.send(&Frame::Goodbye);
+    let _ = writer.stream.shutdown();
+}
+
+/// What the input loop waits for.
+enum Event {
+    Line(String),
+    /// Ctrl-C, or the bridge gave up.
+    Quit,
+    /// The input ended: end of file, or the TUI was closed.
+    InputEnded,
+    /// A `/connect` finished its handshake, or failed.
+    Opened {
+        name: String,
+        session: io::Result<Box<Session>>,
+    },
+    /// A session's connection is gone; `dead` when the peer stopped
+    /// answering rather than closing it.
+    Closed {
+        session: SessionId,
+        dead: bool,
+    },
+}
+
+/// Feeds the lines typed in the TUI (or on stdin without one) and Ctrl-C
+/// into `events`, where the connections report their end too.
+fn input_events(
+    tui_input: Option<Receiver<String>>,
+    events: mpsc::Sender<Event>,
+) -> io::Result<()> {
+    let input = events.clone();
     thread::spawn(move || {
-        let mut reader = BufReader::new(stream_clone);
-        loop {
-            let mut length_bytes = [0u8; 2];
-            if reader.read_exact(&mut length_bytes).is_err() {
-                println!("\n❌ Connection closed by peer.");
-                std::process::exit(0);
+        let lines: Box<dyn Iterator<Item = String>> = match tui_input {
+            Some(tui_input) => Box::new(tui_input.into_iter()),
+            None => Box::new(io::stdin().lines().map_while(Result::ok)),
+        };
+        for line in lines {
+            notify::activity();
+            if input.send(Event::Line(line)).is_err() {
+                return;
             }
+        }
+        let _ = input.send(Event::InputEnded);
+    });
+    ctrlc::set_handler(move || {
+        let _ = events.send(Event::Quit);
+    })
+    .map_err(io::Error::other)
+}
 
-            let length = u16::from_be_bytes(length_bytes) as usize;
-            let mut encrypted_data = vec![0u8; length];
-            if reader.read_exact(&mut encrypted_data).is_err() {
-                println!("\n❌ Error reading message.");
-                continue;
+/// Pings the peer every `interval` until the connection fails, so the other
+/// side's read timeout only fires when we are really gone.
+fn start_heartbeat(writer: Arc<Mutex<Writer>>, interval: Duration) {
+    thread::spawn(move || {
+        loop {
+            thread::sleep(interval);
+            if writer.lock().unwrap().send(&Frame::Ping).is_err() {
+                break;
             }
+        }
+    });
+}
+
+/// Whether a read failed because the peer stayed silent past the timeout.
+fn is_timeout(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
 
-            let mut cipher = cipher_recv_clone.lock().unwrap();
-            let decrypted = cipher.decrypt(&encrypted_data);
+/// A frame received from the peer, after decryption.
+enum Incoming {
+    Message {
+        frame: Frame,
+        sequence: u64,
+        ciphertext: Vec<u8>,
+    },
+    /// The frame failed authentication and was dropped.
+    Tampered,
+    /// An authentic frame whose sequence number was already seen.
+    Replayed(u64),
+    /// This many frames before the next one never arrived.
+    Gap(u64),
+    /// An authentic frame that does not decode, e.g. from a newer build.
+    Malformed,
+}
+
+impl From<Record> for Incoming {
+    fn from(record: Record) -> Self {
+        match record {
+            Record::Frame {
+                sequence,
+                frame,
+                ciphertext,
+            } => match Frame::decode(&frame) {
+                Some(frame) => Incoming::Message {
+                    frame,
+                    sequence,
+                    ciphertext,
+                },
+                None => Incoming::Malformed,
+            },
+            Record::Tampered => Incoming::Tampered,
+            Record::Replayed(sequence) => Incoming::Replayed(sequence),
+            Record::Gap(missing) => Incoming::Gap(missing),
+            Record::Malformed => Incoming::Malformed,
+        }
+    }
+}
+
+/// Receiving half of a session: reads from the connection into the
+/// protocol's `Decoder` until it has something to hand out.
+struct Inbox {
+    decoder: Decoder,
+}
+
+impl Inbox {
+    fn new(key: &[u8; 32], padding: Padding) -> Self {
+        Inbox {
+            decoder: Decoder::new(key, padding),
+        }
+    }
 
-            if let Ok(message) = String::from_utf8(decrypted.clone()) {
-                println!("\n📨 Received: {}", message);
-                println!(" [Encrypted hex: {}]", hex::encode(&encrypted_data));
-                print!(">> ");
-                io::stdout().flush().unwrap();
+    /// The next frame, in sequence order; an `Err` means the connection is
+    /// gone or unusable.
+    fn receive(&mut self, reader: &mut impl Read) -> io::Result<Incoming> {
+        let mut buffer = [0u8; READ_CHUNK];
+        loop {
+            if let Some(record) = self.decoder.poll(Instant::now())? {
+                return Ok(Incoming::from(record));
+            }
+            match reader.read(&mut buffer) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(read) => self.decoder.feed(&buffer[..read]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
             }
         }
-    });
+    }
+}
 
-    let stdin = io::stdin();
-    let mut lines = stdin.lock().lines();
+/// Reads a bulk stream (see `Writer::open_bulk`) until the connection
+/// ends, handing each file chunk to `chunk`. Anything else on it is
+/// reported, as only chunks are sent there.
+fn receive_bulk(stream: Box<dyn Transport>, mut inbox: Inbox, mut chunk: impl FnMut(u32, &[u8])) {
+    let mut reader = io::BufReader::new(stream);
+    while let Ok(incoming) = inbox.receive(&mut reader) {
+        match incoming {
+            Incoming::Message {
+                frame: Frame::FileChunk { id, data },
+                ..
+            } => chunk(id, &data),
+            Incoming::Message { .. } | Incoming::Malformed => {
+                say!("\n⚠️  Ignored a message sent on the file stream.");
+            }
+            Incoming::Tampered => {
+                say!("\n⚠️  Dropped a file chunk that failed authentication.");
+            }
+            Incoming::Replayed(sequence) => {
+                say!(
+                    "\n⚠️  Rejected a replayed file chunk (sequence number {}).",
+                    sequence
+                );
+            }
+            Incoming::Gap(missing) => {
+                say!("\n⚠️  {} file chunk(s) never arrived.", missing);
+            }
+        }
+    }
+}
 
+fn prompt() {
+    if ui::active() || ui::piped() {
+        return;
+    }
     print!(">> ");
-    io::stdout().flush()?;
+    io::stdout().flush().unwrap();
+}
 
-    while let Some(Ok(line)) = lines.next() {
-        if line.trim().is_empty() {
-            print!(">> ");
-            io::stdout().flush()?;
-            continue;
-        }
+/// Client-side settings shared by every way of reaching a peer.
+#[derive(Clone)]
+struct ChatConfig {
+    nick: String,
+    downloads: PathBuf,
+    /// Largest file offer asked about, in bytes.
+    max_download: u64,
+    deny_file: PathBuf,
+    known_peers: PathBuf,
+    password: Option<String>,
+    group: Group,
+    padding: Padding,
+    heartbeat: Duration,
+    image_preview: ImagePreview,
+    /// How `/connect` reaches more peers.
+    transport: TransportKind,
+    proxy: Option<socks::Proxy>,
+    /// Full-screen interface instead of plain line-by-line output.
+    tui: bool,
+    shaping: Shaping,
+    /// This device's link to its primary identity, sent in every hello.
+    device: Option<DeviceLink>,
+}
 
-        let message_bytes = line.as_bytes();
-        let mut cipher = cipher_send.lock().unwrap();
-        let encrypted = cipher.encrypt(message_bytes);
+/// Runs the input loop, starting with a session on `stream`; `/connect`
+/// adds more. `peer_name` is what the peer's key is pinned under.
+fn chat_loop(
+    stream: Box<dyn Transport>,
+    peer_name: &str,
+    role: Role,
+    identity: &Identity,
+    peer_key: Option<&VerifyingKey>,
+    config: &ChatConfig,
+) -> io::Result<()> {
+    let (_tui, input) = if config.tui {
+        let (tui, input) = ui::start();
+        (Some(tui), Some(input))
+    } else {
+        (None, None)
+    };
+    let (events, receiver) = mpsc::channel();
+    let mut mesh = Mesh::new(identity, config, events.clone());
+    mesh.start(stream, peer_name, role, peer_key)?;
+    bridge::attach(events.clone());
+    input_events(input, events)?;
 
-        println!("📤 Sending: {}", line);
-        println!(" [Plaintext hex: {}]", hex::encode(message_bytes));
-        println!(" [Encrypted hex: {}]", hex::encode(&encrypted));
+    prompt();
 
-        let length = encrypted.len() as u16;
-        stream.write_all(&length.to_be_bytes())?;
-        stream.write_all(&encrypted)?;
-        stream.flush()?;
+    let mut nick = config.nick.clone();
+    for event in receiver {
+        let line = match event {
+            Event::Line(line) => line,
+            // A bridge keeps running without a terminal.
+            Event::InputEnded if bridge::active() => continue,
+            Event::Quit | Event::InputEnded => {
+                say!("\n👋 Leaving the chat.");
+                mesh.close_all();
+                return Ok(());
+            }
+            Event::Opened { name, session } => {
+                mesh.opened(&name, session);
+                if mesh.is_empty() && !ui::active() {
+                    return Ok(());
+                }
+                prompt();
+                continue;
+            }
+            Event::Closed { session, dead } => {
+                mesh.remove(session);
+                // The TUI stays up until the user quits.
+                if !mesh.is_empty() || ui::active() {
+                    prompt();
+                    continue;
+                }
+                return if dead {
+                    Err(io::ErrorKind::TimedOut.into())
+                } else {
+                    Ok(())
+                };
+            }
+        };
+        match commands::parse(&line, Context::Client) {
+            Ok(Input::Empty) => {}
+            Err(e) => say!("⚠️  {}", e),
+            Ok(Input::Command(Command::Help)) => say!("{}", commands::help(Context::Client)),
+            Ok(Input::Command(Command::Quit)) => {
+                say!("👋 Leaving the chat.");
+                mesh.close_all();
+                return Ok(());
+            }
+            Ok(Input::Command(Command::Reload)) => unreachable!("/reload is hub-only"),
+            Ok(Input::Command(Command::Clear)) => ui::clear(),
+            Ok(Input::Command(Command::Nick(new_nick))) => {
+                say!("✓ You are now {}.", new_nick);
+                nick = new_nick;
+            }
+            Ok(Input::Command(Command::Connect(address))) => mesh.connect(address),
+            Ok(Input::Command(Command::Switch(target))) => match mesh.find(&target) {
+                Some(id) => mesh.switch(id),
+                None => say!("⚠️  No session {}, /who lists them.", target),
+            },
+            Ok(input) => {
+                let Some(session) = mesh.current() else {
+                    say!("⚠️  Not connected: /connect ADDR, or /quit.");
+                    prompt();
+                    continue;
+                };
+                let id = session.id;
+                let who = matches!(input, Input::Command(Command::Who));
+                if !session_input(session, input, &nick, identity, config)? {
+                    mesh.remove(id);
+                    if mesh.is_empty() {
+                        return Ok(());
+                    }
+                }
+                if who && mesh.len() > 1 {
+                    mesh.list();
+                }
+            }
+        }
 
-        print!(">> ");
-        io::stdout().flush()?;
+        prompt();
     }
 
     Ok(())
 }
 
+/// Handles a line typed for `session`. Returns `false` once the session
+/// is closed (`/block`, `/reject`).
+fn session_input(
+    session: &mut Session,
+    input: Input,
+    nick: &str,
+    identity: &Identity,
+    config: &ChatConfig,
+) -> io::Result<bool> {
+    let writer = &session.writer;
+    let membership = &session.membership;
+    match input {
+        Input::Command(Command::Block) => {
+            access::block(&config.deny_file, &session.peer_identity)?;
+            say!(
+                "🚫 Blocked {}, listed in {}. Leaving the chat.",
+                session.peer_fingerprint,
+                config.deny_file.display()
+            );
+            session.close();
+            return Ok(false);
+        }
+        Input::Command(Command::Who) => {
+            let peer_nick = session.peer_nick.lock().unwrap();
+            say!(
+                " Peer: {} at {}",
+                peer_nick.as_deref().unwrap_or("(no message yet)"),
+                session.addr
+            );
+            say!(
+                " Verification: {}",
+                match (
+                    session.local_verified.load(Ordering::SeqCst),
+                    session.peer_verified.load(Ordering::SeqCst)
+                ) {
+                    (true, true) => "both sides",
+                    (true, false) => "waiting for the peer",
+                    (false, true) => "waiting for you",
+                    (false, false) => "pending",
+                }
+            );
+            if membership.lock().unwrap().is_some() {
+                writer
+                    .lock()
+                    .unwrap()
+                    .send(&Frame::Room(Box::new(RoomMessage::Members)))?;
+            }
+        }
+        Input::Command(Command::Fingerprint) => {
+            say!(" You:  {}", device::describe(&identity.public_key()));
+            say!(" Peer: {}", session.peer_fingerprint);
+        }
+        Input::Command(Command::Trust(_)) if session.key_changed => {
+            let account = device::account(&session.peer_identity);
+            pins::pin(&config.known_peers, &session.name, &account)?;
+            session.key_changed = false;
+            say!(
+                "📌 Pinned the new identity of {}, {}.",
+                session.name,
+                session.peer_fingerprint
+            );
+        }
+        Input::Command(Command::Trust(_)) => {
+            say!("✔️  Nothing to trust: the identity matches the pinned one.");
+        }
+        Input::Command(Command::Verify(_)) if session.key_changed => {
+            say!("🚨 The identity changed: check it and /trust it before /verify.");
+        }
+        Input::Command(Command::Verify(_)) if session.local_verified.load(Ordering::SeqCst) => {
+            say!("✔️  Already verified.");
+        }
+        Input::Command(Command::Verify(_)) => {
+            session.local_verified.store(true, Ordering::SeqCst);
+            writer
+                .lock()
+                .unwrap()
+                .send(&Frame::Control(Control::Verified))?;
+            if session.peer_verified.load(Ordering::SeqCst) {
+                ui::set_state("verified");
+                say!("✅ Both sides verified, messages can flow.");
+                bridge::verified();
+            } else {
+                ui::set_state("waiting for the peer to verify");
+                say!("⏳ Waiting for the peer to verify...");
+            }
+        }
+        Input::Command(Command::Reject(_)) => {
+            say!("❌ Verification code rejected, closing the connection.");
+            session.close();
+            return Ok(false);
+        }
+        Input::Command(
+            Command::Send(_)
+            | Command::Attach(_)
+            | Command::Join(_)
+            | Command::Edit(..)
+            | Command::Delete(_),
+        )
+        | Input::Text(_)
+            if !session.both_verified() =>
+        {
+            say!("⏳ Not sent: both sides must /verify the session first.");
+        }
+        Input::Command(Command::Join(_)) if session.peer_features & FEATURE_ROOMS == 0 => {
+            say!("⚠️  Rooms need a hub (streamchat server), the peer is not one.");
+        }
+        Input::Command(Command::Join(room)) => {
+            let keyring = Keyring::new(&config.known_peers, &session.peer_identity);
+            let history = session.peer_features & FEATURE_ROOM_HISTORY != 0;
+            let (joined, request) = Membership::join(room.clone(), identity, keyring, history);
+            *membership.lock().unwrap() = Some(joined);
+            let join = RoomMessage::Join {
+                room: room.clone(),
+                nick: nick.to_string(),
+                request,
+            };
+            writer.lock().unwrap().send(&Frame::Room(Box::new(join)))?;
+            ui::set_state(format!("in #{}", room));
+            say!("⏳ Joining #{}...", room);
+        }
+        Input::Command(Command::Leave) => {
+            let left = membership.lock().unwrap().take();
+            match left {
+                Some(left) => {
+                    writer
+                        .lock()
+                        .unwrap()
+                        .send(&Frame::Room(Box::new(RoomMessage::Leave)))?;
+                    ui::set_state("verified");
+                    say!("👋 Left #{}, back in the lobby.", left.room);
+                }
+                None => say!("⚠️  You are not in a room."),
+            }
+        }
+        Input::Command(Command::History(_)) if membership.lock().unwrap().is_none() => {
+            say!("⚠️  You are not in a room.");
+        }
+        Input::Command(Command::History(_))
+            if session.peer_features & FEATURE_ROOM_HISTORY == 0 =>
+        {
+            say!("⚠️  This hub keeps no room history.");
+        }
+        Input::Command(Command::History(count)) => {
+            writer
+                .lock()
+                .unwrap()
+                .send(&Frame::Room(Box::new(RoomMessage::HistoryRequest(count))))?;
+        }
+        Input::Command(Command::Send(_) | Command::Attach(_))
+            if membership.lock().unwrap().is_some() =>
+        {
+            say!("⚠️  Files can only be sent in the lobby, /leave the room first.");
+        }
+        Input::Text(text) if membership.lock().unwrap().is_some() => {
+            let message = TextMessage::new(nick, &text, identity);
+            let membership = membership.lock().unwrap();
+            let joined = membership
+                .as_ref()
+                .expect("only the input loop leaves rooms");
+            let Some(sealed) = joined.seal(&Frame::Text(Box::new(message.clone())).encode()) else {
+                say!("⏳ Not sent: still waiting for the #{} key.", joined.room);
+                return Ok(true);
+            };
+            let result = writer.lock().unwrap().send(&Frame::Room(Box::new(sealed)));
+            match result {
+                Ok(_) => session.sent.record(&message, Some(&joined.room), None),
+                Err(e) if e.kind() == io::ErrorKind::InvalidInput => {
+                    say!("⚠️  {}, not sent.", e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Input::Command(Command::Send(path)) => {
+            // In the background, so the chat goes on during the transfer.
+            let writer = Arc::clone(writer);
+            let answers = Arc::clone(&session.answers);
+            let name = session.name.clone();
+            let nick = nick.to_string();
+            thread::spawn(move || {
+                let _source = ui::source_scope(&name);
+                let chunk_size = writer.lock().unwrap().chunk_size();
+                let result =
+                    transfer::send_file(&path, &nick, chunk_size, &answers, |frame| match frame {
+                        Frame::FileChunk { .. } => shaping::send_bulk(&writer, &frame),
+                        frame => writer.lock().unwrap().send(&frame).map(drop),
+                    });
+                if let Err(e) = result {
+                    say!("⚠️  Could not send {}: {}", path.display(), e);
+                }
+                prompt();
+            });
+        }
+        Input::Command(command @ (Command::Accept(number) | Command::Decline(number))) => {
+            let accepted = matches!(command, Command::Accept(_));
+            let mut downloads = session.downloads.lock().unwrap();
+            let answered = if accepted {
+                downloads.accept(number)
+            } else {
+                downloads.decline(number)
+            };
+            match answered {
+                Ok(id) => {
+                    writer
+                        .lock()
+                        .unwrap()
+                        .send(&Frame::FileAnswer { id, accepted })?;
+                }
+                Err(e) => say!("⚠️  {}", e),
+            }
+        }
+        Input::Command(Command::Attach(path)) => {
+            let result = attachment::load(&path, nick).and_then(|attachment| {
+                let size = attachment.data.len();
+                writer
+                    .lock()
+                    .unwrap()
+                    .send(&Frame::Attachment(attachment))?;
+                Ok(size)
+            });
+            match result {
+                Ok(size) => say!("📎 Attached {} ({} bytes).", path.display(), size),
+                Err(e) => say!("⚠️  Could not attach {}: {}", path.display(), e),
+            }
+        }
+        Input::Command(Command::Edit(number, _) | Command::Delete(number))
+            if session.sent.find(number).is_none() =>
+        {
+            say!("⚠️  No message {} to change (the number after 📤).", number);
+        }
+        Input::Command(command @ (Command::Edit(..) | Command::Delete(_))) => {
+            let (number, change) = match command {
+                Command::Edit(number, text) => (number, Change::Edit(text)),
+                Command::Delete(number) => (number, Change::Delete),
+                _ => unreachable!("matched above"),
+            };
+            let (id, room) = session.sent.find(number).expect("checked above");
+            let room = room.map(str::to_string);
+            let amendment =
+                Frame::Amend(Box::new(Amendment::new(id, nick, change.clone(), identity)));
+            // Edits go where the message went: the lobby, or its room.
+            let frame = match room {
+                None if membership.lock().unwrap().is_some() => {
+                    say!(
+                        "⚠️  Message {} went to the lobby, /leave the room to change it.",
+                        number
+                    );
+                    return Ok(true);
+                }
+                None => amendment,
+                Some(room) => {
+                    let membership = membership.lock().unwrap();
+                    let Some(joined) = membership.as_ref().filter(|joined| joined.room == room)
+                    else {
+                        say!(
+                            "⚠️  Message {} went to #{}, /join it to change it.",
+                            number,
+                            room
+                        );
+                        return Ok(true);
+                    };
+                    let Some(sealed) = joined.seal(&amendment.encode()) else {
+                        say!("⏳ Not sent: still waiting for the #{} key.", joined.room);
+                        return Ok(true);
+                    };
+                    Frame::Room(Box::new(sealed))
+                }
+            };
+            match writer.lock().unwrap().send(&frame) {
+                Ok(_) => session.sent.amended(id, &change),
+                Err(e) if e.kind() == io::ErrorKind::InvalidInput => {
+                    say!("⚠️  {}, not sent.", e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Input::Text(text) => {
+            let message = TextMessage::new(nick, &text, identity);
+            let result = writer
+                .lock()
+                .unwrap()
+                .send(&Frame::Text(Box::new(message.clone())));
+            match result {
+                Ok(sent) => {
+                    session.sent.record(&message, None, Some(sent.sequence));
+                    trace!(
+                        "frame {} plaintext: {}",
+                        sent.sequence,
+                        hex::encode(&sent.plaintext)
+                    );
+                    trace!(
+                        "frame {} ciphertext: {}",
+                        sent.sequence,
+                        hex::encode(&sent.ciphertext)
+                    );
+                }
+                Err(e) if e.kind() == io::ErrorKind::InvalidInput => {
+                    say!("⚠️  {}, not sent.", e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Input::Empty
+        | Input::Command(
+            Command::Help
+            | Command::Quit
+            | Command::Reload
+            | Command::Clear
+            | Command::Nick(_)
+            | Command::Connect(_)
+            | Command::Switch(_),
+        ) => unreachable!("handled by the input loop"),
+    }
+    Ok(true)
+}
+
+fn load_identity(path: &Path, storage: Storage) -> io::Result<Identity> {
+    let (identity, created) = Identity::load_or_generate(path, storage)?;
+    if created {
+        say!("🆕 Generated a new identity key at {}", path.display());
+        if storage == Storage::Plain {
+            say!("☠️  --insecure-plain-identity: it is stored unencrypted.");
+        }
+    }
+    Ok(identity)
+}
+
 fn main() -> io::Result<()> {
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let config_path = cli.config.clone().unwrap_or_else(config::default_path);
+    config::load(&config_path, cli.config.is_some())?.apply(&mut cli, &matches, &config_path)?;
+    // Over the configured transport, as --transport is.
+    if cli.ws {
+        cli.transport = TransportKind::Ws;
+    }
+    if cli.pipe {
+        if !matches!(cli.command, Commands::Client { .. } | Commands::Peer { .. }) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--pipe only works with client and peer",
+            ));
+        }
+        ui::set_pipe();
+    }
+    log::init(cli.verbose);
+    notify::init(cli.notify, cli.notify_preview);
+    let identity_path = cli.identity.unwrap_or_else(identity::default_path);
+    // `identity --set-passphrase` is how an unencrypted key gets encrypted.
+    let storage = match cli.command {
+        Commands::Identity {
+            set_passphrase: true,
+            ..
+        } => Storage::Plain,
+        _ if cli.insecure_plain_identity => Storage::Plain,
+        _ => Storage::Encrypted,
+    };
+    let identity = load_identity(&identity_path, storage)?;
+    let device = DeviceLink::load(&identity_path, &identity)?;
+    if let Some(link) = &device {
+        device::learn(link);
+    }
+    let nick = cli.nick.unwrap_or_else(default_nick);
+    let heartbeat = Duration::from_secs(cli.heartbeat);
+    let deny_file = cli.deny_file.unwrap_or_else(access::default_deny_path);
+    let known_peers = cli.known_peers.unwrap_or_else(pins::default_path);
+    let shaping = Shaping {
+        max_frame: cli.max_frame as usize,
+        send_rate: cli.send_rate.saturating_mul(1024),
+    };
+    let group = if cli.insecure_demo {
+        say!("☠️  --insecure-demo: a 64-bit Diffie-Hellman group anyone can break.");
+        say!(" Use it to watch the protocol, never for a real conversation.");
+        Group::Demo64
+    } else {
+        cli.group
+    };
+    let config = ChatConfig {
+        nick: nick.clone(),
+        downloads: cli.downloads,
+        max_download: cli.max_download.saturating_mul(1024 * 1024),
+        deny_file: deny_file.clone(),
+        known_peers: known_peers.clone(),
+        password: cli.password.clone(),
+        group,
+        padding: cli.padding,
+        heartbeat,
+        image_preview: cli.image_preview,
+        transport: cli.transport,
+        proxy: None,
+        tui: !cli.plain && !cli.pipe && io::stdin().is_terminal() && io::stdout().is_terminal(),
+        shaping,
+        device: device.clone(),
+    };
 
     match cli.command {
-        Commands::Server { port } => {
-            let listener = TcpListener::bind(format!("127.0.0.1:{}", port))?;
-            println!("🎧 Server listening on port {}", port);
-            println!("⏳ Waiting for client connection...");
-            let (stream, addr) = listener.accept()?;
-            println!("✓ Client connected from {}", addr);
-            chat_loop(stream)?;
-        }
-        Commands::Client { address } => {
-            println!("🔌 Connecting to {}...", address);
-            let stream = TcpStream::connect(&address)?;
-            println!("✓ Connected to server!");
-            chat_loop(stream)?;
+        Commands::Server {
+            port,
+            bind,
+            peer_key,
+            allow_file,
+            queue_ttl,
+            queue_limit,
+            message_rate,
+            byte_rate,
+            ticket_ttl,
+            room_history,
+            room_history_len,
+            address_file,
+            onion,
+            tor_control,
+            tor_password,
+        } => {
+            let port = port.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "no port: give one, or set port in the [server] section of the config file",
+                )
+            })?;
+            let listener = transport::listen(cli.transport, SocketAddr::new(bind, port))?;
+            let local = listener.local_addr()?;
+            println!("🎧 Server listening on {}", local);
+            let announced = net::announced(local);
+            if local.ip().is_unspecified() {
+                let addrs: Vec<_> = announced.iter().map(SocketAddr::to_string).collect();
+                println!(" Reachable at {}", addrs.join(", "));
+            }
+            // Kept until the server stops: the service goes with it.
+            let onion = if onion {
+                if cli.transport == TransportKind::Quic {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "QUIC runs over UDP and can't be reached through Tor",
+                    ));
+                }
+                let target = if local.ip().is_unspecified() {
+                    announced[0]
+                } else {
+                    local
+                };
+                let service = onion::OnionService::publish(
+                    &tor_control,
+                    tor_password.as_deref(),
+                    local.port(),
+                    target,
+                )?;
+                println!(
+                    "🧅 Onion service at {} (it can take a minute to be reachable)",
+                    service
+                );
+                Some(service)
+            } else {
+                None
+            };
+            if let Some(path) = &address_file {
+                let lines: Vec<String> = announced
+                    .iter()
+                    .map(SocketAddr::to_string)
+                    .chain(onion.iter().map(onion::OnionService::to_string))
+                    .collect();
+                net::write_address_file(path, &lines)?;
+                println!("📝 Addresses written to {}", path.display());
+            }
+            let room_history = room_history
+                .map(|dir| {
+                    println!(
+                        "📜 Keeping the last {} messages of each room in {}",
+                        room_history_len,
+                        dir.display()
+                    );
+                    storage::RoomHistory::open(dir, room_history_len as usize)
+                })
+                .transpose()?;
+            println!("⏳ Waiting for client connections...");
+            let config = hub::HubConfig {
+                heartbeat,
+                limits: hub::QueueLimits {
+                    ttl: Duration::from_secs(queue_ttl),
+                    max_messages: queue_limit,
+                },
+                rates: flood::RateLimits {
+                    messages: message_rate,
+                    bytes: byte_rate.saturating_mul(1024),
+                },
+                access: access::AccessFiles {
+                    allow: allow_file,
+                    deny: deny_file,
+                },
+                known_peers,
+                password: cli.password,
+                group,
+                padding: cli.padding,
+                ticket_ttl: Duration::from_secs(ticket_ttl),
+                room_history,
+                shaping,
+            };
+            let result = hub::run_server(listener, Arc::new(identity), peer_key, &nick, config);
+            if let Some(path) = &address_file {
+                let _ = std::fs::remove_file(path);
+            }
+            drop(onion);
+            result?;
+        }
+        Commands::Client {
+            address,
+            proxy,
+            peer_key,
+        } => {
+            match &proxy {
+                Some(proxy) => say!("🔌 Connecting to {} via {}...", address, proxy),
+                None => say!("🔌 Connecting to {}...", address),
+            }
+            let stream = transport::connect(cli.transport, &address, proxy.as_ref())?;
+            if proxy.is_some() || onion::is_onion(&address) {
+                say!("✓ Connected to server through the proxy!");
+            } else {
+                say!("✓ Connected to server at {}!", stream.peer_addr()?);
+            }
+            let config = ChatConfig { proxy, ..config };
+            chat_loop(
+                stream,
+                &address,
+                Role::Client,
+                &identity,
+                peer_key.as_ref(),
+                &config,
+            )?;
+        }
+        Commands::Bridge {
+            irc,
+            address,
+            room,
+            peer_key,
+            irc_nick,
+        } => {
+            say!("🌉 Connecting to {}...", irc);
+            let client = irc::Client::connect(&irc, &irc_nick)?;
+            say!("✓ Joined {} as {}.", irc.channel, client.nick());
+            bridge::install(client, room);
+            say!("🔌 Connecting to {}...", address);
+            let stream = transport::connect(cli.transport, &address, None)?;
+            let result = chat_loop(
+                stream,
+                &address,
+                Role::Client,
+                &identity,
+                Some(&peer_key),
+                &config,
+            );
+            bridge::shutdown();
+            result?;
+        }
+        Commands::Rendezvous { port, bind } => {
+            let listener = std::net::TcpListener::bind(SocketAddr::new(bind, port))?;
+            println!(
+                "🧭 Rendezvous server listening on {}",
+                listener.local_addr()?
+            );
+            rendezvous::run(listener)?;
+        }
+        Commands::Relay { port, bind } => {
+            let listener = std::net::TcpListener::bind(SocketAddr::new(bind, port))?;
+            println!("🔁 Relay server listening on {}", listener.local_addr()?);
+            relay::run(listener)?;
+        }
+        Commands::Peer {
+            rendezvous,
+            room,
+            relay,
+            peer_key,
+        } => {
+            if cli.transport != TransportKind::Tcp {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "hole punching only works with --transport tcp",
+                ));
+            }
+            say!("🧭 Meeting through {}...", rendezvous);
+            let (stream, role) = match (rendezvous::meet(&rendezvous, &room), relay) {
+                (Ok(direct), _) => direct,
+                (Err(e), Some(relay)) => {
+                    say!("⚠️  {}, falling back to the relay.", e);
+                    relay::join(&relay, &room)?
+                }
+                (Err(e), None) => return Err(e),
+            };
+            chat_loop(
+                Box::new(stream),
+                &format!("{}@{}", room, rendezvous),
+                role,
+                &identity,
+                peer_key.as_ref(),
+                &config,
+            )?;
+        }
+        Commands::Identity {
+            export,
+            set_passphrase,
+            link_device,
+            accept_link,
+        } => {
+            if set_passphrase {
+                let passphrase = identity::new_passphrase()?;
+                identity.save(&identity_path, Some(&passphrase))?;
+                println!("🔐 {} is now encrypted.", identity_path.display());
+            }
+            let public_key = hex::encode(identity.public_key().as_bytes());
+            println!("🪪 Public key: {}", public_key);
+            println!(
+                " Fingerprint: {}",
+                identity::fingerprint(&identity.public_key())
+            );
+            if let Some(path) = export {
+                std::fs::write(&path, public_key + "\n")?;
+                println!("✓ Public key exported to {}", path.display());
+            }
+            if let Some(link) = &device {
+                println!("🔗 A device of {}", identity::fingerprint(&link.primary));
+            }
+            if let Some(key) = link_device {
+                if device.is_some() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "this device is linked to another identity; link new devices from that one",
+                    ));
+                }
+                println!("\n🔗 Invitation for {}:", identity::fingerprint(&key));
+                println!("{}", device::invite(&identity, &key));
+                println!(" On that device, run: streamchat identity --accept-link INVITATION");
+            }
+            if let Some(invitation) = accept_link {
+                let link = device::accept(&invitation, &identity)?;
+                link.save(&identity_path)?;
+                println!(
+                    "\n🔗 This device is now part of {}; peers will see that identity.",
+                    identity::fingerprint(&link.primary)
+                );
+                println!(
+                    " The link is kept in {}",
+                    DeviceLink::path(&identity_path).display()
+                );
+            }
         }
     }
 
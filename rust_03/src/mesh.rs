@@ -0,0 +1,701 @@
+//! Client sessions. A client can talk to several peers at once: the one it
+//! started with, plus one per `/connect ADDR`, each with its own handshake,
+//! keys, verification and room. Typed lines go to the current session
+//! (`/switch` changes it), and while more than one is open, everything a
+//! session prints is tagged with its peer.
+
+use crate::attachment;
+use crate::bridge;
+use crate::device::{self, DeviceLink};
+use crate::frame::{Control, Frame};
+use crate::history::{self, Received};
+use crate::identity::{self, Identity};
+use crate::pins::{self, Pin};
+use crate::room::{self, Membership};
+use crate::transport::{self, Transport};
+use crate::ui::{self, say};
+use crate::{
+    ChatConfig, Delivery, Event, Inbox, Incoming, MISSED_HEARTBEATS, Writer, access, goodbye,
+    is_timeout, key_exchange, prompt, receive_bulk, show_message, start_heartbeat, transfer,
+};
+use ed25519_dalek::VerifyingKey;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufReader};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use streamchat_proto::hello::{self, FEATURE_PASSWORD};
+use streamchat_proto::resume::{self, Offer, Ticket};
+use streamchat_proto::{Encoder, Role, sas};
+use tracing::trace;
+
+pub type SessionId = usize;
+
+/// What opening a session needs, cloned into the threads that do it.
+#[derive(Clone)]
+struct Shared {
+    identity: Identity,
+    config: ChatConfig,
+    events: Sender<Event>,
+    /// The session typed lines go to; only it updates the status bar.
+    current: Arc<AtomicUsize>,
+    /// Resumption tickets from hubs, by session name. They stay in memory:
+    /// on disk, anyone reading the file could resume as us.
+    tickets: Arc<Mutex<HashMap<String, Ticket>>>,
+}
+
+impl Shared {
+    fn is_current(&self, id: SessionId) -> bool {
+        self.current.load(Ordering::SeqCst) == id
+    }
+
+    fn set_state(&self, id: SessionId, state: impl Into<String>) {
+        if self.is_current(id) {
+            ui::set_state(state);
+        }
+    }
+}
+
+/// Read half of a session until its reader threads start.
+struct Reader {
+    stream: Box<dyn Transport>,
+    inbox: Inbox,
+    /// The bulk stream file chunks come in on, if the transport has one.
+    bulk: Option<(Box<dyn Transport>, Inbox)>,
+}
+
+/// One peer, as seen by the input loop; its reader thread holds the rest.
+pub struct Session {
+    pub id: SessionId,
+    /// What the peer's key is pinned under: the address, or
+    /// `ROOM@RENDEZVOUS` for `peer`.
+    pub name: String,
+    pub addr: SocketAddr,
+    pub peer_identity: VerifyingKey,
+    pub peer_fingerprint: String,
+    pub peer_features: u32,
+    /// The peer's key is not the pinned one, and was not `/trust`ed yet.
+    pub key_changed: bool,
+    pub writer: Arc<Mutex<Writer>>,
+    pub local_verified: Arc<AtomicBool>,
+    pub peer_verified: Arc<AtomicBool>,
+    /// Nickname of the peer's last message.
+    pub peer_nick: Arc<Mutex<Option<String>>>,
+    pub membership: Arc<Mutex<Option<Membership>>>,
+    /// Our recent messages, for `/edit` and `/delete`.
+    pub sent: history::Sent,
+    /// Files the peer offered, for `/accept` and `/decline`.
+    pub downloads: Arc<Mutex<transfer::Downloads>>,
+    /// The peer's answers to our `/send` offers.
+    pub answers: Arc<transfer::Answers>,
+    /// Set when we close the session, so the reader goes quietly.
+    leaving: Arc<AtomicBool>,
+    reader: Option<Reader>,
+    /// What a ticket the peer sends us is bound to.
+    resumption: [u8; 32],
+}
+
+impl Session {
+    /// Runs the handshake on `stream`, resuming the session with a ticket
+    /// from an earlier one when we have it. Nothing is read from the peer
+    /// until the session is added to the mesh.
+    fn start(
+        mut stream: Box<dyn Transport>,
+        name: String,
+        role: Role,
+        peer_key: Option<&VerifyingKey>,
+        id: SessionId,
+        shared: &Shared,
+    ) -> io::Result<Self> {
+        let _source = ui::source_scope(&name);
+        let config = &shared.config;
+        stream.set_read_timeout(Some(config.heartbeat * MISSED_HEARTBEATS))?;
+        let addr = stream.peer_addr()?;
+        if shared.is_current(id) {
+            ui::set_peer(addr.to_string());
+        }
+        shared.set_state(id, "handshake");
+
+        say!("\n🤝 Establishing secure connection...");
+        let password = config.password.as_deref();
+        let mut features = config.padding.features();
+        if password.is_some() {
+            features |= FEATURE_PASSWORD;
+        }
+        let offer = shared
+            .tickets
+            .lock()
+            .unwrap()
+            .remove(&name)
+            .filter(|ticket| peer_key.is_none_or(|key| *key == ticket.server))
+            .and_then(|ticket| Offer::new(ticket, config.group));
+        let mut extensions = offer.as_ref().map(Offer::extension).unwrap_or_default();
+        if let Some(link) = &config.device {
+            extensions.extend(link.extension());
+        }
+        let hello = hello::exchange(&mut stream, role, features, config.group, &extensions)?;
+        say!("\n👋 {}", hello.describe());
+        let resumed = match offer {
+            Some(offer) => resume::client(&mut stream, offer, &hello)?,
+            None => None,
+        };
+        let (keys, peer_identity, resumed) = match resumed {
+            Some(resumed) => {
+                say!(
+                    " Peer identity: {}",
+                    identity::fingerprint(&resumed.peer_identity)
+                );
+                (resumed.keys, resumed.peer_identity, true)
+            }
+            None => {
+                let (keys, peer_identity) = key_exchange(
+                    &mut stream,
+                    role,
+                    &shared.identity,
+                    peer_key,
+                    &hello,
+                    password,
+                )?;
+                (keys, peer_identity, false)
+            }
+        };
+        if let Some(link) = DeviceLink::from_hello(&hello, &peer_identity) {
+            device::learn(&link);
+            say!(
+                " 🔗 A device of {}, both keys signed the link.",
+                identity::fingerprint(&link.primary)
+            );
+        }
+        let files = access::AccessFiles {
+            allow: None,
+            deny: config.deny_file.clone(),
+        };
+        access::Access::load(&files)?
+            .check_key(&peer_identity)
+            .map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("refusing the peer: {}", e),
+                )
+            })?;
+        let peer_fingerprint = device::describe(&peer_identity);
+        // A linked device is pinned as its primary identity.
+        let account = device::account(&peer_identity);
+        let mut key_changed = false;
+        match pins::check(&config.known_peers, &name, &account)? {
+            Pin::New => {
+                pins::pin(&config.known_peers, &name, &account)?;
+                say!(
+                    "\n📌 First session with {}, its identity is now pinned.",
+                    name
+                );
+            }
+            Pin::Matches => say!("\n📌 Same identity as in earlier sessions with {}.", name),
+            Pin::Changed(pinned) => {
+                key_changed = true;
+                say!("\n🚨🚨🚨 THE IDENTITY OF {} HAS CHANGED 🚨🚨🚨", name);
+                say!(" Pinned: {}", identity::fingerprint(&pinned));
+                say!(" Now:    {}", peer_fingerprint);
+                say!(" Someone may be intercepting the connection, or the peer reinstalled");
+                say!(" streamchat. Check with them out of band, then type /trust to pin the");
+                say!(" new identity, or /quit. /verify is refused until then.");
+            }
+        }
+        if shared.is_current(id) {
+            ui::set_fingerprint(peer_fingerprint.clone());
+        }
+        say!("\n✅ Secure channel established!");
+        // The ticket's secret comes from a session both sides verified.
+        let verified = resumed && !key_changed;
+        if verified {
+            shared.set_state(id, "verified");
+            say!("\n⚡ Resumed the session verified earlier, no code to compare this time.\n");
+        } else {
+            shared.set_state(id, "waiting for /verify");
+            say!("\n🔎 Verification code: {}", sas::words(&keys.sas));
+            say!(
+                " Compare it with your peer (voice, in person), then type /verify if it matches or /reject if not."
+            );
+            say!(" Messages are held until both sides have verified.\n");
+        }
+
+        let (send_key, recv_key) = keys.split(role);
+        let mut reader = Reader {
+            stream: stream.try_clone()?,
+            inbox: Inbox::new(&recv_key, hello.padding),
+            bulk: None,
+        };
+        let mut writer = Writer::new(
+            stream,
+            Encoder::new(&send_key, hello.padding),
+            config.shaping,
+        );
+        reader.bulk = writer.open_bulk(&send_key, &recv_key)?;
+        let writer = Arc::new(Mutex::new(writer));
+        start_heartbeat(Arc::clone(&writer), config.heartbeat);
+        Ok(Session {
+            id,
+            name,
+            addr,
+            peer_identity,
+            peer_fingerprint,
+            peer_features: hello.peer_features,
+            key_changed,
+            writer,
+            local_verified: Arc::new(AtomicBool::new(verified)),
+            peer_verified: Arc::new(AtomicBool::new(verified)),
+            peer_nick: Arc::default(),
+            membership: Arc::default(),
+            sent: history::Sent::default(),
+            downloads: Arc::new(Mutex::new(transfer::Downloads::new(
+                config.downloads.clone(),
+                config.max_download,
+            ))),
+            answers: Arc::default(),
+            leaving: Arc::default(),
+            reader: Some(reader),
+            resumption: keys.resumption,
+        })
+    }
+
+    pub fn both_verified(&self) -> bool {
+        self.local_verified.load(Ordering::SeqCst) && self.peer_verified.load(Ordering::SeqCst)
+    }
+
+    /// Status bar state.
+    pub fn state(&self) -> String {
+        let local = self.local_verified.load(Ordering::SeqCst);
+        let peer = self.peer_verified.load(Ordering::SeqCst);
+        match (local, peer) {
+            (true, true) => match &*self.membership.lock().unwrap() {
+                Some(joined) => format!("in #{}", joined.room),
+                None => "verified".to_string(),
+            },
+            (true, false) => "waiting for the peer to verify".to_string(),
+            (false, true) => "peer verified, waiting for your /verify".to_string(),
+            (false, false) => "waiting for /verify".to_string(),
+        }
+    }
+
+    /// Says goodbye to the peer and stops the reader without a report.
+    pub fn close(&self) {
+        self.leaving.store(true, Ordering::SeqCst);
+        goodbye(&self.writer);
+    }
+
+    /// Starts the reader thread, which shows what the peer sends and
+    /// reports the end of the connection as `Event::Closed`, and the one
+    /// for the bulk stream if there is one.
+    fn listen(&mut self, shared: Shared) {
+        let Some(Reader {
+            stream,
+            mut inbox,
+            bulk,
+        }) = self.reader.take()
+        else {
+            return;
+        };
+        if let Some((stream, inbox)) = bulk {
+            let name = self.name.clone();
+            let downloads = Arc::clone(&self.downloads);
+            thread::spawn(move || {
+                let _source = ui::source_scope(&name);
+                receive_bulk(stream, inbox, |id, data| {
+                    if let Err(e) = downloads.lock().unwrap().chunk(id, data) {
+                        say!("\n⚠️  File transfer failed: {}", e);
+                    }
+                });
+            });
+        }
+        let id = self.id;
+        let name = self.name.clone();
+        let peer_identity = self.peer_identity;
+        let writer = Arc::clone(&self.writer);
+        let local_verified = Arc::clone(&self.local_verified);
+        let peer_verified = Arc::clone(&self.peer_verified);
+        let peer_nick = Arc::clone(&self.peer_nick);
+        let membership = Arc::clone(&self.membership);
+        let downloads = Arc::clone(&self.downloads);
+        let answers = Arc::clone(&self.answers);
+        let leaving = Arc::clone(&self.leaving);
+        let resumption = self.resumption;
+        let timeout = shared.config.heartbeat * MISSED_HEARTBEATS;
+
+        thread::spawn(move || {
+            let _source = ui::source_scope(&name);
+            let known_peers = &shared.config.known_peers;
+            let mut reader = BufReader::new(stream);
+            let mut received = Received::default();
+            let dead = loop {
+                let incoming = match inbox.receive(&mut reader) {
+                    Ok(incoming) => incoming,
+                    Err(_) if leaving.load(Ordering::SeqCst) => return,
+                    Err(e) if is_timeout(&e) => {
+                        say!(
+                            "\n❌ No response from peer for {}s, connection considered dead.",
+                            timeout.as_secs()
+                        );
+                        break true;
+                    }
+                    Err(_) => {
+                        say!("\n❌ Connection closed by peer.");
+                        break false;
+                    }
+                };
+
+                match incoming {
+                    Incoming::Message {
+                        frame: Frame::Ping, ..
+                    } => {
+                        let _ = writer.lock().unwrap().send(&Frame::Pong);
+                        continue;
+                    }
+                    Incoming::Message {
+                        frame: Frame::Pong, ..
+                    } => continue,
+                    Incoming::Message {
+                        frame: Frame::Control(Control::Verified),
+                        ..
+                    } => {
+                        peer_verified.store(true, Ordering::SeqCst);
+                        say!("\n✔️  Peer confirmed the verification code.");
+                        if local_verified.load(Ordering::SeqCst) {
+                            shared.set_state(id, "verified");
+                            say!("✅ Both sides verified, messages can flow.");
+                            bridge::verified();
+                        } else {
+                            shared.set_state(id, "peer verified, waiting for your /verify");
+                        }
+                    }
+                    Incoming::Message {
+                        frame:
+                            Frame::Control(Control::Muted {
+                                seconds,
+                                strikes_left,
+                            }),
+                        ..
+                    } => say!(
+                        "\n🔇 The hub muted you for {}s for sending too fast; {} more time(s) and it disconnects you.",
+                        seconds,
+                        strikes_left
+                    ),
+                    Incoming::Message {
+                        frame: Frame::Control(Control::Kicked(reason)),
+                        ..
+                    } => say!("\n👢 The hub is disconnecting you: {}", reason),
+                    Incoming::Message {
+                        frame: Frame::Control(Control::Ticket { ticket, lifetime }),
+                        ..
+                    } => {
+                        let ticket = Ticket::new(
+                            ticket,
+                            resumption,
+                            peer_identity,
+                            shared.config.group,
+                            Duration::from_secs(lifetime),
+                        );
+                        shared.tickets.lock().unwrap().insert(name.clone(), ticket);
+                        continue;
+                    }
+                    Incoming::Message {
+                        frame: Frame::Goodbye,
+                        ..
+                    } => {
+                        say!("\n👋 The peer left the chat.");
+                        break false;
+                    }
+                    Incoming::Message {
+                        frame: Frame::DeviceLink(link),
+                        ..
+                    } => {
+                        device::learn(&link);
+                        continue;
+                    }
+                    Incoming::Message { .. } if !local_verified.load(Ordering::SeqCst) => {
+                        say!("\n⚠️  Dropped a message sent before you verified the session.");
+                    }
+                    Incoming::Message {
+                        frame: Frame::FileOffer(offer),
+                        ..
+                    } => {
+                        let id = offer.id;
+                        if !downloads.lock().unwrap().offer(offer) {
+                            let answer = Frame::FileAnswer {
+                                id,
+                                accepted: false,
+                            };
+                            let _ = writer.lock().unwrap().send(&answer);
+                        }
+                    }
+                    Incoming::Message {
+                        frame: Frame::FileAnswer { id, accepted },
+                        ..
+                    } => {
+                        // Either the peer's answer to our offer, or the hub
+                        // calling off one we accepted.
+                        if !answers.answer(id, accepted) && !accepted {
+                            downloads.lock().unwrap().cancel(id);
+                        }
+                        continue;
+                    }
+                    Incoming::Message {
+                        frame: Frame::Attachment(attachment),
+                        ..
+                    } => {
+                        let config = &shared.config;
+                        if let Err(e) =
+                            attachment::receive(attachment, &config.downloads, config.image_preview)
+                        {
+                            say!("⚠️  Could not save an attachment: {}", e);
+                        }
+                    }
+                    Incoming::Message {
+                        frame: Frame::Queued(message),
+                        ..
+                    } => {
+                        let authorship = message.authorship(Some(&peer_identity), known_peers);
+                        show_message(&message, authorship, None, Delivery::Queued, &mut received);
+                    }
+                    Incoming::Message {
+                        frame: Frame::Room(message),
+                        ..
+                    } => room::receive(
+                        *message,
+                        &membership,
+                        &shared.identity,
+                        &writer,
+                        known_peers,
+                        &mut received,
+                    ),
+                    Incoming::Message {
+                        frame: Frame::FileChunk { id, data },
+                        ..
+                    } => {
+                        if let Err(e) = downloads.lock().unwrap().chunk(id, &data) {
+                            say!("\n⚠️  File transfer failed: {}", e);
+                        }
+                        continue;
+                    }
+                    Incoming::Message {
+                        frame: Frame::Amend(amendment),
+                        ..
+                    } => received.apply(&amendment),
+                    Incoming::Message {
+                        frame: Frame::Ack(sequence),
+                        ..
+                    } => say!(" ✓ Delivered (frame {}).", sequence),
+                    Incoming::Message {
+                        frame: Frame::Text(message),
+                        sequence,
+                        ciphertext,
+                    } => {
+                        let _ = writer.lock().unwrap().send(&Frame::Ack(sequence));
+                        let authorship = message.authorship(Some(&peer_identity), known_peers);
+                        show_message(&message, authorship, None, Delivery::Live, &mut received);
+                        *peer_nick.lock().unwrap() = Some(message.nick);
+                        trace!("received frame {}: {}", sequence, hex::encode(&ciphertext));
+                    }
+                    Incoming::Replayed(sequence) => {
+                        say!(
+                            "\n⚠️  Rejected a replayed message (sequence number {}).",
+                            sequence
+                        );
+                    }
+                    Incoming::Gap(missing) => {
+                        say!("\n⚠️  {} message(s) from the peer never arrived.", missing);
+                    }
+                    Incoming::Tampered => {
+                        say!(
+                            "\n⚠️  Dropped a message that failed authentication (tampered or corrupted)."
+                        );
+                    }
+                    Incoming::Malformed => {
+                        say!("\n⚠️  Ignored a message this version does not understand.");
+                    }
+                }
+                prompt();
+            };
+            if shared.is_current(id) {
+                ui::disconnected();
+            }
+            let _ = shared.events.send(Event::Closed { session: id, dead });
+        });
+    }
+}
+
+/// The open sessions, and the one typed lines go to.
+pub struct Mesh {
+    shared: Shared,
+    sessions: BTreeMap<SessionId, Session>,
+    last_id: SessionId,
+    /// `/connect`s still connecting or in their handshake.
+    opening: usize,
+}
+
+impl Mesh {
+    pub fn new(identity: &Identity, config: &ChatConfig, events: Sender<Event>) -> Self {
+        Mesh {
+            shared: Shared {
+                identity: identity.clone(),
+                config: config.clone(),
+                events,
+                current: Arc::default(),
+                tickets: Arc::default(),
+            },
+            sessions: BTreeMap::new(),
+            last_id: 0,
+            opening: 0,
+        }
+    }
+
+    fn next_id(&mut self) -> SessionId {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    /// Opens the first session, on `stream`.
+    pub fn start(
+        &mut self,
+        stream: Box<dyn Transport>,
+        name: &str,
+        role: Role,
+        peer_key: Option<&VerifyingKey>,
+    ) -> io::Result<()> {
+        let id = self.next_id();
+        self.shared.current.store(id, Ordering::SeqCst);
+        let session = Session::start(stream, name.to_string(), role, peer_key, id, &self.shared)?;
+        self.add(session);
+        Ok(())
+    }
+
+    fn add(&mut self, mut session: Session) {
+        session.listen(self.shared.clone());
+        self.sessions.insert(session.id, session);
+        self.update_tagging();
+    }
+
+    fn update_tagging(&self) {
+        ui::tag_sources(self.sessions.len() + self.opening > 1);
+    }
+
+    /// `/connect`: connects to `address` and runs the handshake in the
+    /// background; the session arrives as `Event::Opened`.
+    pub fn connect(&mut self, address: String) {
+        let id = self.next_id();
+        self.opening += 1;
+        self.update_tagging();
+        say!("🔌 Connecting to {} (session {})...", address, id);
+        let shared = self.shared.clone();
+        thread::spawn(move || {
+            let session = {
+                let _source = ui::source_scope(&address);
+                let config = &shared.config;
+                transport::connect(config.transport, &address, config.proxy.as_ref()).and_then(
+                    |stream| {
+                        Session::start(stream, address.clone(), Role::Client, None, id, &shared)
+                    },
+                )
+            };
+            let _ = shared.events.send(Event::Opened {
+                name: address,
+                session: session.map(Box::new),
+            });
+        });
+    }
+
+    /// A `/connect` finished: the new session becomes the current one.
+    pub fn opened(&mut self, name: &str, session: io::Result<Box<Session>>) {
+        self.opening -= 1;
+        match session {
+            Ok(session) => {
+                let id = session.id;
+                self.add(*session);
+                self.switch(id);
+            }
+            Err(e) => {
+                self.update_tagging();
+                say!("⚠️  Could not connect to {}: {}", name, e);
+            }
+        }
+    }
+
+    pub fn current(&mut self) -> Option<&mut Session> {
+        let id = self.shared.current.load(Ordering::SeqCst);
+        self.sessions.get_mut(&id)
+    }
+
+    /// The session `target` names: its number (`2`, `#2`) or peer name.
+    pub fn find(&self, target: &str) -> Option<SessionId> {
+        let by_id = target
+            .trim_start_matches('#')
+            .parse()
+            .ok()
+            .filter(|id| self.sessions.contains_key(id));
+        by_id.or_else(|| {
+            self.sessions
+                .values()
+                .find(|session| session.name == target)
+                .map(|session| session.id)
+        })
+    }
+
+    pub fn switch(&mut self, id: SessionId) {
+        let Some(session) = self.sessions.get(&id) else {
+            return;
+        };
+        self.shared.current.store(id, Ordering::SeqCst);
+        ui::set_peer(session.addr.to_string());
+        ui::set_fingerprint(session.peer_fingerprint.clone());
+        ui::set_state(session.state());
+        say!("🔀 Now talking to {} (session {}).", session.name, id);
+    }
+
+    /// Forgets a session whose connection is gone, moving to another one if
+    /// it was current.
+    pub fn remove(&mut self, id: SessionId) {
+        self.sessions.remove(&id);
+        self.update_tagging();
+        if self.shared.is_current(id)
+            && let Some(&next) = self.sessions.keys().next()
+        {
+            self.switch(next);
+        }
+    }
+
+    /// No session is open or opening.
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty() && self.opening == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// For `/who`.
+    pub fn list(&self) {
+        say!(" Sessions (/switch N):");
+        for session in self.sessions.values() {
+            say!(
+                " {} {} {} ({})",
+                if self.shared.is_current(session.id) {
+                    "*"
+                } else {
+                    " "
+                },
+                session.id,
+                session.name,
+                session.state()
+            );
+        }
+    }
+
+    /// Says goodbye on every session.
+    pub fn close_all(&self) {
+        for session in self.sessions.values() {
+            session.close();
+        }
+    }
+}
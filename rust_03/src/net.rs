@@ -0,0 +1,146 @@
+//! Address handling: the server's bind address and the addresses it
+//! announces, and a simplified happy-eyeballs connect (RFC 8305) for the
+//! client, so a host with both IPv6 and IPv4 addresses still connects
+//! quickly when one family is broken.
+
+use crate::ui::say;
+use std::fs;
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// Head start given to each address before the next one is tried in
+/// parallel.
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Accepts `127.0.0.1`, `::` and the bracketed `[::]` form.
+pub fn parse_bind(text: &str) -> Result<IpAddr, String> {
+    let bare = text
+        .strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+        .unwrap_or(text);
+    bare.parse()
+        .map_err(|_| format!("{} is not an IPv4 or IPv6 address", text))
+}
+
+/// Where peers can reach a server listening on `local`: that address, or
+/// for a wildcard bind, loopback followed by this machine's LAN addresses
+/// (IPv4 only for `0.0.0.0`; `::` usually takes both families).
+pub fn announced(local: SocketAddr) -> Vec<SocketAddr> {
+    if !local.ip().is_unspecified() {
+        return vec![local];
+    }
+    let loopback = match local {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+    };
+    let mut addrs = vec![SocketAddr::new(loopback, local.port())];
+    // Link-local addresses need a scope id to be dialed, so they are left out.
+    let interfaces = if_addrs::get_if_addrs().unwrap_or_default();
+    for interface in interfaces {
+        let ip = interface.ip();
+        if interface.is_loopback() || interface.is_link_local() || (local.is_ipv4() && ip.is_ipv6())
+        {
+            continue;
+        }
+        let addr = SocketAddr::new(ip, local.port());
+        if !addrs.contains(&addr) {
+            addrs.push(addr);
+        }
+    }
+    addrs
+}
+
+/// Writes `addrs` to `path`, one per line, through a temporary file so a
+/// script waiting for it never reads half of it.
+pub fn write_address_file(path: &Path, addrs: &[String]) -> io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let mut file = fs::File::create(&temporary)?;
+    for addr in addrs {
+        writeln!(file, "{}", addr)?;
+    }
+    file.sync_all()?;
+    fs::rename(&temporary, path)
+}
+
+/// Alternates address families, starting with whichever the resolver
+/// listed first.
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let first_is_v6 = addrs.first().is_some_and(SocketAddr::is_ipv6);
+    let (preferred, other): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|addr| addr.is_ipv6() == first_is_v6);
+
+    let mut preferred = preferred.into_iter();
+    let mut other = other.into_iter();
+    let mut ordered = Vec::new();
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => break,
+            (a, b) => ordered.extend(a.into_iter().chain(b)),
+        }
+    }
+    ordered
+}
+
+/// Resolves `address` (`host:port`, `[v6]:port`) and races connection
+/// attempts, starting a new one every `ATTEMPT_DELAY`; the first to succeed
+/// wins.
+pub fn connect(address: &str) -> io::Result<TcpStream> {
+    let addrs = interleave(address.to_socket_addrs()?.collect());
+    if addrs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} did not resolve to any address", address),
+        ));
+    }
+
+    let connected = Arc::new(AtomicBool::new(false));
+    let (sender, receiver) = mpsc::channel();
+    for (i, addr) in addrs.into_iter().enumerate() {
+        let sender = sender.clone();
+        let connected = Arc::clone(&connected);
+        thread::spawn(move || {
+            thread::sleep(ATTEMPT_DELAY * i as u32);
+            if !connected.load(Ordering::SeqCst) {
+                let _ = sender.send((addr, TcpStream::connect(addr)));
+            }
+        });
+    }
+    drop(sender);
+
+    let mut last_error = None;
+    for (addr, result) in receiver {
+        match result {
+            Ok(stream) => {
+                connected.store(true, Ordering::SeqCst);
+                return Ok(stream);
+            }
+            Err(e) => {
+                say!(" {} failed: {}", addr, e);
+                last_error = Some(e);
+            }
+        }
+    }
+    Err(last_error.expect("at least one address was tried"))
+}
+
+/// Whether the other end of `stream`, which is not expected to have sent
+/// anything, has gone: a peek without blocking finds the end of the stream
+/// or an error instead of nothing to read yet.
+pub fn closed(stream: &TcpStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return true;
+    }
+    let closed = match stream.peek(&mut [0u8; 1]) {
+        Ok(read) => read == 0,
+        Err(e) => e.kind() != io::ErrorKind::WouldBlock,
+    };
+    stream.set_nonblocking(false).is_err() || closed
+}
@@ -0,0 +1,95 @@
+//! Desktop notifications for `--notify`. They name the sender but leave the
+//! text out unless `--notify-preview` is given: notifications end up on lock
+//! screens and in notification histories, outside the encrypted session.
+//! They are shown through notify-rust: the freedesktop notification service
+//! over D-Bus on Linux and the BSDs, Notification Center on macOS, toasts on
+//! Windows.
+
+use clap::ValueEnum;
+use notify_rust::Notification;
+use serde::Deserialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Without focus reports from the terminal, a user who typed nothing for
+/// this long is assumed to be looking elsewhere.
+const IDLE_AFTER: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotifyMode {
+    Off,
+    /// When the terminal lost focus, or nothing was typed for a minute.
+    Unfocused,
+    Always,
+}
+
+struct Settings {
+    mode: NotifyMode,
+    preview: bool,
+}
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+/// Set by the TUI on terminals that report focus changes.
+static FOCUS_LOST: AtomicBool = AtomicBool::new(false);
+static LAST_ACTIVITY: Mutex<Option<Instant>> = Mutex::new(None);
+/// Only the first failure is reported.
+static FAILED: AtomicBool = AtomicBool::new(false);
+
+pub fn init(mode: NotifyMode, preview: bool) {
+    let _ = SETTINGS.set(Settings { mode, preview });
+    activity();
+}
+
+/// The user typed something, so they are at the terminal.
+pub fn activity() {
+    *LAST_ACTIVITY.lock().unwrap() = Some(Instant::now());
+}
+
+pub fn set_focused(focused: bool) {
+    FOCUS_LOST.store(!focused, Ordering::Relaxed);
+    if focused {
+        activity();
+    }
+}
+
+fn likely_unfocused() -> bool {
+    FOCUS_LOST.load(Ordering::Relaxed)
+        || LAST_ACTIVITY
+            .lock()
+            .unwrap()
+            .is_none_or(|at| at.elapsed() >= IDLE_AFTER)
+}
+
+/// A message from `from` (a nickname, with the room if any) arrived.
+pub fn message(from: &str, text: &str) {
+    let Some(settings) = SETTINGS.get() else {
+        return;
+    };
+    match settings.mode {
+        NotifyMode::Off => return,
+        NotifyMode::Unfocused if !likely_unfocused() => return,
+        _ => {}
+    }
+    let body = if settings.preview {
+        text.to_string()
+    } else {
+        "New message".to_string()
+    };
+    let title = format!("streamchat: {}", from);
+    thread::spawn(move || {
+        let shown = Notification::new()
+            .appname("streamchat")
+            .summary(&title)
+            .body(&body)
+            .show();
+        if let Err(e) = shown
+            && !FAILED.swap(true, Ordering::Relaxed)
+        {
+            warn!("could not show a desktop notification: {}", e);
+        }
+    });
+}
@@ -0,0 +1,188 @@
+//! Tor integration. The server publishes an ephemeral v3 onion service
+//! through Tor's control port (`ADD_ONION`, control-spec §3.27), which
+//! needs no open port or port forwarding; the client reaches `.onion`
+//! addresses through Tor's SOCKS port.
+//!
+//! The service is not written into Tor's configuration: it lives as long
+//! as the control connection that created it, and its key is discarded,
+//! so every run gets a new address.
+
+use crate::socks::{self, Proxy};
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpStream};
+
+/// Tor's default control and SOCKS ports.
+pub const DEFAULT_CONTROL: &str = "127.0.0.1:9051";
+const DEFAULT_SOCKS: &str = "socks5://127.0.0.1:9050";
+
+/// Whether `address` (`host:port`) names an onion service.
+pub fn is_onion(address: &str) -> bool {
+    address
+        .rsplit_once(':')
+        .is_some_and(|(host, _)| host.to_ascii_lowercase().ends_with(".onion"))
+}
+
+/// The proxy used for `.onion` targets when none was given.
+pub fn tor_proxy() -> Proxy {
+    socks::parse_proxy(DEFAULT_SOCKS).expect("the default Tor SOCKS address parses")
+}
+
+fn tor_error(message: impl Into<String>) -> io::Error {
+    io::Error::other(format!("Tor: {}", message.into()))
+}
+
+/// A control port connection, speaking the line-based control protocol.
+struct Control {
+    writer: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+impl Control {
+    fn connect(address: &str) -> io::Result<Control> {
+        let writer = TcpStream::connect(address).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "can't reach Tor's control port at {} ({}); is Tor running with ControlPort set?",
+                    address, e
+                ),
+            )
+        })?;
+        let reader = BufReader::new(writer.try_clone()?);
+        Ok(Control { writer, reader })
+    }
+
+    /// Sends one command and returns the lines of a `250` reply, without
+    /// their status prefix. Any other status is an error.
+    fn command(&mut self, command: &str) -> io::Result<Vec<String>> {
+        self.writer.write_all(command.as_bytes())?;
+        self.writer.write_all(b"\r\n")?;
+        self.writer.flush()?;
+
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(tor_error("control connection closed"));
+            }
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.len() < 4 || !line.is_char_boundary(4) {
+                return Err(tor_error(format!("malformed reply {:?}", line)));
+            }
+            let (status, separator, text) = (&line[..3], &line[3..4], &line[4..]);
+            if status != "250" {
+                return Err(tor_error(text.to_string()));
+            }
+            lines.push(text.to_string());
+            if separator == " " {
+                return Ok(lines);
+            }
+        }
+    }
+
+    /// Authenticates with whichever method Tor offers: none, the cookie
+    /// file, or the control password.
+    fn authenticate(&mut self, password: Option<&str>) -> io::Result<()> {
+        let info = self.command("PROTOCOLINFO 1")?;
+        let auth = info
+            .iter()
+            .find_map(|line| line.strip_prefix("AUTH "))
+            .ok_or_else(|| tor_error("PROTOCOLINFO lists no authentication methods"))?;
+        let methods: Vec<&str> = auth
+            .split(' ')
+            .find_map(|field| field.strip_prefix("METHODS="))
+            .map(|methods| methods.split(',').collect())
+            .unwrap_or_default();
+
+        let command = if methods.contains(&"NULL") {
+            "AUTHENTICATE".to_string()
+        } else if let Some(password) = password.filter(|_| methods.contains(&"HASHEDPASSWORD")) {
+            format!("AUTHENTICATE {}", quote(password))
+        } else if methods.contains(&"COOKIE") {
+            let path = auth
+                .split_once("COOKIEFILE=")
+                .map(|(_, rest)| unquote(rest))
+                .ok_or_else(|| tor_error("cookie authentication without a COOKIEFILE"))?;
+            let cookie = fs::read(&path).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("can't read Tor's auth cookie {}: {}", path, e),
+                )
+            })?;
+            format!("AUTHENTICATE {}", hex::encode(cookie))
+        } else if methods.contains(&"HASHEDPASSWORD") {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Tor's control port wants a password: give --tor-password",
+            ));
+        } else {
+            return Err(tor_error(format!(
+                "no supported authentication method in {}",
+                methods.join(", ")
+            )));
+        };
+        self.command(&command).map(drop)
+    }
+}
+
+/// Quotes `text` as a control protocol string.
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Reads the quoted string at the start of `text`.
+fn unquote(text: &str) -> String {
+    let mut chars = text.strip_prefix('"').unwrap_or(text).chars();
+    let mut result = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => result.extend(chars.next()),
+            '"' => break,
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// A published onion service. Dropping it closes the control connection,
+/// which takes the service down.
+pub struct OnionService {
+    _control: Control,
+    host: String,
+    port: u16,
+}
+
+impl fmt::Display for OnionService {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.host, self.port)
+    }
+}
+
+impl OnionService {
+    /// Asks the Tor instance behind `control` to forward port `port` of a
+    /// new onion service to `target`.
+    pub fn publish(
+        control: &str,
+        password: Option<&str>,
+        port: u16,
+        target: SocketAddr,
+    ) -> io::Result<OnionService> {
+        let mut connection = Control::connect(control)?;
+        connection.authenticate(password)?;
+        let reply = connection.command(&format!(
+            "ADD_ONION NEW:ED25519-V3 Flags=DiscardPK Port={},{}",
+            port, target
+        ))?;
+        let service_id = reply
+            .iter()
+            .find_map(|line| line.strip_prefix("ServiceID="))
+            .ok_or_else(|| tor_error("ADD_ONION reply has no ServiceID"))?;
+        Ok(OnionService {
+            host: format!("{}.onion", service_id),
+            _control: connection,
+            port,
+        })
+    }
+}
@@ -0,0 +1,81 @@
+//! Trust on first use, like SSH's `known_hosts`. The known peers file maps
+//! a name for the peer to the identity key it had the first time: the
+//! address a client connected to, `ROOM@RENDEZVOUS` for `peer`, and
+//! `nick:NAME` for the nicknames a hub sees. One `NAME KEY` pair (key in
+//! hex) per line, `#` starts a comment.
+//!
+//! A key that changes is either the peer reinstalling streamchat or someone
+//! in the middle, so it is never replaced without the user's `/trust`.
+
+use crate::identity;
+use ed25519_dalek::VerifyingKey;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub enum Pin {
+    /// Never seen under this name.
+    New,
+    Matches,
+    /// The name is pinned to this other key.
+    Changed(VerifyingKey),
+}
+
+/// `~/.config/streamchat/known_peers` (or the platform equivalent).
+pub fn default_path() -> PathBuf {
+    identity::default_path().with_file_name("known_peers")
+}
+
+/// The lines of the file; a missing file has none.
+fn read_lines(path: &Path) -> io::Result<Vec<String>> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(text.lines().map(str::to_string).collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// The name and key on `line`, if it holds an entry.
+fn parse_line(line: &str) -> Option<(&str, &str)> {
+    let entry = line.split('#').next().unwrap_or("").trim();
+    entry.split_once(char::is_whitespace)
+}
+
+pub fn check(path: &Path, name: &str, key: &VerifyingKey) -> io::Result<Pin> {
+    for (number, line) in read_lines(path)?.iter().enumerate() {
+        let Some((pinned_name, pinned_key)) = parse_line(line) else {
+            continue;
+        };
+        if pinned_name != name {
+            continue;
+        }
+        let pinned_key = identity::parse_public_key(pinned_key).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}:{}: {}", path.display(), number + 1, e),
+            )
+        })?;
+        return Ok(if pinned_key == *key {
+            Pin::Matches
+        } else {
+            Pin::Changed(pinned_key)
+        });
+    }
+    Ok(Pin::New)
+}
+
+/// Pins `name` to `key`, replacing what it was pinned to.
+pub fn pin(path: &Path, name: &str, key: &VerifyingKey) -> io::Result<()> {
+    let mut lines = read_lines(path)?;
+    lines.retain(|line| parse_line(line).is_none_or(|(pinned, _)| pinned != name));
+    lines.push(format!(
+        "{} {}  # {}",
+        name,
+        hex::encode(key.as_bytes()),
+        identity::fingerprint(key)
+    ));
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, lines.join("\n") + "\n")
+}
@@ -0,0 +1,300 @@
+//! QUIC transport built on quinn. Quinn is async, so a small shared tokio
+//! runtime drives it and the blocking `Read`/`Write` impls wait on it; the
+//! rest of streamchat stays thread-based.
+//!
+//! A connection carries two bidirectional streams: the first for the
+//! handshake and the chat, the second for file chunks (see
+//! `Transport::bulk`), so a transfer never holds a message up on the wire.
+//! The client writes a byte on the second one as it opens it, since QUIC
+//! only tells the peer about a stream once something is sent on it.
+//!
+//! QUIC always runs TLS, but peers are authenticated by streamchat's own
+//! signed handshake and verification code on top of it. The server
+//! therefore presents a throwaway self-signed certificate and the client
+//! accepts any certificate: the TLS layer only adds transport encryption.
+
+use crate::transport::{Listener, Transport};
+use quinn::crypto::rustls::QuicClientConfig;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature};
+use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+/// Name in the self-signed certificate; never checked.
+const SERVER_NAME: &str = "streamchat";
+
+/// How long the server waits for a new connection to open its streams, so
+/// a silent client can't stall the acceptor.
+const STREAM_TIMEOUT: Duration = Duration::from_secs(10);
+/// The byte that opens the bulk stream.
+const BULK_OPENER: u8 = 0xb1;
+
+type Streams = (quinn::SendStream, quinn::RecvStream);
+
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_all()
+            .build()
+            .expect("failed to start the QUIC runtime")
+    })
+}
+
+/// One bidirectional QUIC stream. Clones share the stream, so the reader
+/// and writer threads can use it at the same time.
+pub struct QuicStream {
+    endpoint: quinn::Endpoint,
+    connection: quinn::Connection,
+    send: Arc<Mutex<quinn::SendStream>>,
+    recv: Arc<Mutex<quinn::RecvStream>>,
+    read_timeout: Arc<Mutex<Option<Duration>>>,
+    /// The connection's bulk stream, on the chat stream's handles.
+    bulk: Option<Arc<QuicStream>>,
+}
+
+impl QuicStream {
+    fn new(
+        endpoint: quinn::Endpoint,
+        connection: quinn::Connection,
+        (send, recv): Streams,
+        bulk: Option<Streams>,
+    ) -> Self {
+        let bulk = bulk.map(|streams| {
+            Arc::new(QuicStream::new(
+                endpoint.clone(),
+                connection.clone(),
+                streams,
+                None,
+            ))
+        });
+        QuicStream {
+            endpoint,
+            connection,
+            send: Arc::new(Mutex::new(send)),
+            recv: Arc::new(Mutex::new(recv)),
+            read_timeout: Arc::new(Mutex::new(None)),
+            bulk,
+        }
+    }
+
+    fn clone_handle(&self) -> QuicStream {
+        QuicStream {
+            endpoint: self.endpoint.clone(),
+            connection: self.connection.clone(),
+            send: Arc::clone(&self.send),
+            recv: Arc::clone(&self.recv),
+            read_timeout: Arc::clone(&self.read_timeout),
+            bulk: self.bulk.clone(),
+        }
+    }
+}
+
+impl Read for QuicStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let timeout = *self.read_timeout.lock().unwrap();
+        let mut recv = self.recv.lock().unwrap();
+        runtime().block_on(async {
+            let read = recv.read(buf);
+            let result = match timeout {
+                Some(timeout) => tokio::time::timeout(timeout, read)
+                    .await
+                    .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))?,
+                None => read.await,
+            };
+            // `None` is the end of the stream.
+            Ok(result?.unwrap_or(0))
+        })
+    }
+}
+
+impl Write for QuicStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut send = self.send.lock().unwrap();
+        Ok(runtime().block_on(send.write(buf))?)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for QuicStream {
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.connection.remote_address())
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        *self.read_timeout.lock().unwrap() = timeout;
+        Ok(())
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(self.clone_handle()))
+    }
+
+    fn bulk(&self) -> io::Result<Option<Box<dyn Transport>>> {
+        Ok(self
+            .bulk
+            .as_ref()
+            .map(|bulk| Box::new(bulk.clone_handle()) as Box<dyn Transport>))
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        self.connection.close(0u32.into(), b"closed");
+        Ok(())
+    }
+}
+
+pub struct QuicListener {
+    endpoint: quinn::Endpoint,
+}
+
+impl QuicListener {
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let certified = rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_string()])
+            .map_err(io::Error::other)?;
+        let key = PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der());
+        let config =
+            quinn::ServerConfig::with_single_cert(vec![certified.cert.der().clone()], key.into())
+                .map_err(io::Error::other)?;
+
+        let _runtime = runtime().enter();
+        let endpoint = quinn::Endpoint::server(config, addr)?;
+        Ok(QuicListener { endpoint })
+    }
+}
+
+impl Listener for QuicListener {
+    fn accept(&self) -> io::Result<Box<dyn Transport>> {
+        runtime().block_on(async {
+            let incoming = self
+                .endpoint
+                .accept()
+                .await
+                .ok_or_else(|| io::Error::other("QUIC endpoint closed"))?;
+            let connection = incoming.await?;
+            let (streams, bulk) = tokio::time::timeout(STREAM_TIMEOUT, async {
+                let streams = connection.accept_bi().await?;
+                let (send, mut recv) = connection.accept_bi().await?;
+                let mut opener = [0u8; 1];
+                recv.read_exact(&mut opener)
+                    .await
+                    .map_err(io::Error::other)?;
+                if opener[0] != BULK_OPENER {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "the second stream is not a bulk stream",
+                    ));
+                }
+                Ok::<_, io::Error>((streams, (send, recv)))
+            })
+            .await
+            .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+            Ok(Box::new(QuicStream::new(
+                self.endpoint.clone(),
+                connection,
+                streams,
+                Some(bulk),
+            )) as Box<dyn Transport>)
+        })
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.endpoint.local_addr()
+    }
+}
+
+pub fn connect(addr: SocketAddr) -> io::Result<QuicStream> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let crypto = rustls::ClientConfig::builder_with_provider(Arc::clone(&provider))
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .map_err(io::Error::other)?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate(provider)))
+        .with_no_client_auth();
+    let config = quinn::ClientConfig::new(Arc::new(
+        QuicClientConfig::try_from(crypto).map_err(io::Error::other)?,
+    ));
+
+    let local: SocketAddr = if addr.is_ipv6() {
+        "[::]:0".parse().unwrap()
+    } else {
+        "0.0.0.0:0".parse().unwrap()
+    };
+    let _runtime = runtime().enter();
+    let mut endpoint = quinn::Endpoint::client(local)?;
+    endpoint.set_default_client_config(config);
+
+    runtime().block_on(async {
+        let connection = endpoint
+            .connect(addr, SERVER_NAME)
+            .map_err(io::Error::other)?
+            .await?;
+        let streams = connection.open_bi().await?;
+        let (mut send, recv) = connection.open_bi().await?;
+        send.write_all(&[BULK_OPENER]).await?;
+        Ok(QuicStream::new(
+            endpoint.clone(),
+            connection,
+            streams,
+            Some((send, recv)),
+        ))
+    })
+}
+
+/// Skips certificate validation (see the module docs) but still checks
+/// handshake signatures so the TLS session itself is sound.
+#[derive(Debug)]
+struct AcceptAnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
@@ -0,0 +1,124 @@
+//! Relay server for peers that can't reach each other directly (symmetric
+//! NATs, strict firewalls). Two peers join the same room and the relay
+//! splices their connections together. The key exchange runs end to end
+//! through it, so the relay only ever forwards ciphertext it can't read;
+//! the identity signatures and verification code still catch a relay that
+//! tries to sit in the middle.
+//!
+//! ```text
+//! -> RELAY <room>
+//! <- PAIRED <server|client>     (then raw streamchat traffic)
+//! <- ERROR <reason>
+//! ```
+//!
+//! Peers wait for each other in a `Lobby`.
+
+use crate::lobby::Lobby;
+use crate::net;
+use crate::ui::say;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use streamchat_proto::Role;
+
+const MAX_LINE_LEN: usize = 128;
+const MAX_ROOM_LEN: usize = 64;
+/// How long the relay waits for the `RELAY` line.
+const LINE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Reads one `\n`-terminated line a byte at a time, so nothing that
+/// follows it (the peer's handshake) is swallowed by a buffer.
+fn read_line(stream: &mut TcpStream) -> io::Result<String> {
+    let mut line = Vec::new();
+    let mut byte = [0u8; 1];
+    loop {
+        stream.read_exact(&mut byte)?;
+        if byte[0] == b'\n' {
+            break;
+        }
+        if line.len() == MAX_LINE_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "line too long"));
+        }
+        line.push(byte[0]);
+    }
+    String::from_utf8(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn run(listener: TcpListener) -> io::Result<()> {
+    let lobby = Arc::new(Lobby::new());
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let lobby = Arc::clone(&lobby);
+        thread::spawn(move || {
+            if let Err(e) = join_room(stream, &lobby) {
+                println!("⚠️  Relay connection failed: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn join_room(mut stream: TcpStream, lobby: &Lobby<()>) -> io::Result<()> {
+    let addr = stream.peer_addr()?;
+    stream.set_read_timeout(Some(LINE_TIMEOUT))?;
+    let line = read_line(&mut stream)?;
+    let room = match line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["RELAY", room] if room.len() <= MAX_ROOM_LEN => room.to_string(),
+        _ => return writeln!(stream, "ERROR expected RELAY <room>"),
+    };
+
+    let Some((first, mut second)) = lobby.join(&room, stream, addr, ()) else {
+        return Ok(());
+    };
+    let mut first_stream = first.stream;
+
+    println!(
+        "🔁 Room {}: relaying between {} and {}",
+        room, first.addr, addr
+    );
+    writeln!(first_stream, "PAIRED server")?;
+    writeln!(second, "PAIRED client")?;
+    // Both set one for their `RELAY` line; the chat may sit idle.
+    first_stream.set_read_timeout(None)?;
+    second.set_read_timeout(None)?;
+    splice(first_stream, second)?;
+    println!("👋 Room {}: relay closed", room);
+    Ok(())
+}
+
+/// Copies bytes both ways until either side closes, then closes both.
+fn splice(a: TcpStream, b: TcpStream) -> io::Result<()> {
+    let (mut a_reader, mut b_writer) = (a.try_clone()?, b.try_clone()?);
+    let forward = thread::spawn(move || {
+        let _ = io::copy(&mut a_reader, &mut b_writer);
+        let _ = a_reader.shutdown(Shutdown::Both);
+        let _ = b_writer.shutdown(Shutdown::Both);
+    });
+    let (mut b_reader, mut a_writer) = (b, a);
+    let _ = io::copy(&mut b_reader, &mut a_writer);
+    let _ = a_writer.shutdown(Shutdown::Both);
+    let _ = b_reader.shutdown(Shutdown::Both);
+    let _ = forward.join();
+    Ok(())
+}
+
+/// Joins `room` on the relay and waits for the other peer. Returns the
+/// relayed connection and the role this side plays in the key exchange.
+pub fn join(relay: &str, room: &str) -> io::Result<(TcpStream, Role)> {
+    let mut stream = net::connect(relay)?;
+    writeln!(stream, "RELAY {}", room)?;
+    say!("⏳ Waiting for the other peer on relay {}...", relay);
+
+    let line = read_line(&mut stream)?;
+    let role = match line.trim() {
+        "PAIRED server" => Role::Server,
+        "PAIRED client" => Role::Client,
+        other => return Err(io::Error::other(format!("relay: {}", other))),
+    };
+    say!("✓ Paired through the relay");
+    Ok((stream, role))
+}
@@ -0,0 +1,241 @@
+//! NAT traversal through a rendezvous server. Two peers register under the
+//! same room name; the server tells each one the other's public endpoint
+//! (as it saw it) and private endpoint (as the peer reported it). Both then
+//! connect to each other from the very port they used to reach the server,
+//! so the SYNs cross in each NAT's existing mapping and the connection
+//! opens directly (TCP simultaneous open), without port forwarding.
+//!
+//! The line protocol is plain text:
+//!
+//! ```text
+//! -> REGISTER <room> <private-addr>
+//! <- PEER <public-addr> <private-addr> <server|client>
+//! <- ERROR <reason>
+//! ```
+//!
+//! The rendezvous server only brokers addresses; the chat itself and its
+//! key exchange go directly between the peers. Peers wait for each other
+//! in a `Lobby`.
+
+use crate::lobby::Lobby;
+use crate::ui::say;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use streamchat_proto::Role;
+
+/// How long both sides keep trying to punch through.
+const PUNCH_TIMEOUT: Duration = Duration::from_secs(15);
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(1);
+const RETRY_DELAY: Duration = Duration::from_millis(200);
+const MAX_ROOM_LEN: usize = 64;
+/// Sent by the client side on the connection it settled on.
+const CHOSEN: u8 = 0x01;
+
+pub fn run(listener: TcpListener) -> io::Result<()> {
+    let lobby = Arc::new(Lobby::new());
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let lobby = Arc::clone(&lobby);
+        thread::spawn(move || {
+            if let Err(e) = register(stream, &lobby) {
+                println!("⚠️  Registration failed: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn register(stream: TcpStream, lobby: &Lobby<String>) -> io::Result<()> {
+    let public = stream.peer_addr()?;
+    stream.set_read_timeout(Some(PUNCH_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+
+    let mut words = line.split_whitespace();
+    let (room, private) = match (words.next(), words.next(), words.next()) {
+        (Some("REGISTER"), Some(room), Some(private)) if room.len() <= MAX_ROOM_LEN => {
+            (room.to_string(), private.to_string())
+        }
+        _ => {
+            let mut stream = stream;
+            return writeln!(stream, "ERROR expected REGISTER <room> <private-addr>");
+        }
+    };
+
+    let Some((mut first, mut second)) = lobby.join(&room, stream, public, private.clone()) else {
+        return Ok(());
+    };
+
+    println!(
+        "🤝 Room {}: introducing {} and {}",
+        room, first.addr, public
+    );
+    writeln!(first.stream, "PEER {} {} server", public, private)?;
+    writeln!(second, "PEER {} {} client", first.addr, first.info)?;
+    Ok(())
+}
+
+/// A socket that can share its local port with the others we open, so the
+/// rendezvous connection, the listener and the punching attempts all use
+/// the same NAT mapping.
+fn reusable_socket(local: SocketAddr) -> io::Result<Socket> {
+    let socket = Socket::new(
+        Domain::for_address(local),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.bind(&local.into())?;
+    Ok(socket)
+}
+
+fn protocol_error(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Registers in `room` on the rendezvous server, waits for the other peer
+/// and punches a direct TCP connection to it. Returns the connection and
+/// the role this side plays in the key exchange.
+pub fn meet(server: &str, room: &str) -> io::Result<(TcpStream, Role)> {
+    let server_addr = server
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "rendezvous did not resolve"))?;
+    let unspecified: SocketAddr = if server_addr.is_ipv6() {
+        "[::]:0".parse().unwrap()
+    } else {
+        "0.0.0.0:0".parse().unwrap()
+    };
+
+    let socket = reusable_socket(unspecified)?;
+    socket.connect(&server_addr.into())?;
+    let mut rendezvous = TcpStream::from(socket);
+    let local = rendezvous.local_addr()?;
+
+    writeln!(rendezvous, "REGISTER {} {}", room, local)?;
+    say!(
+        "⏳ Registered in room {}, waiting for the other peer...",
+        room
+    );
+    let mut line = String::new();
+    BufReader::new(&rendezvous).read_line(&mut line)?;
+
+    let words: Vec<_> = line.split_whitespace().collect();
+    let (public, private, role) = match words[..] {
+        ["PEER", public, private, role] => {
+            let public: SocketAddr = public
+                .parse()
+                .map_err(|_| protocol_error("bad peer address"))?;
+            let private: SocketAddr = private
+                .parse()
+                .map_err(|_| protocol_error("bad peer address"))?;
+            let role = match role {
+                "server" => Role::Server,
+                "client" => Role::Client,
+                _ => return Err(protocol_error("bad role")),
+            };
+            (public, private, role)
+        }
+        ["ERROR", ..] => return Err(io::Error::other(line.trim().to_string())),
+        _ => {
+            return Err(protocol_error(
+                "unexpected reply from the rendezvous server",
+            ));
+        }
+    };
+    drop(rendezvous);
+
+    say!("🕳️  Punching through to {} (LAN: {})...", public, private);
+    let stream = punch(local, public, private, role)?;
+    say!("✓ Direct connection with {}", stream.peer_addr()?);
+    Ok((stream, role))
+}
+
+/// Races a listener on our port against repeated connects to the peer's
+/// public and private endpoints. Several of those can succeed, so the
+/// client side sends `CHOSEN` on the first one it gets and the server side
+/// keeps whichever connection carries that byte.
+fn punch(
+    local: SocketAddr,
+    public: SocketAddr,
+    private: SocketAddr,
+    role: Role,
+) -> io::Result<TcpStream> {
+    let deadline = Instant::now() + PUNCH_TIMEOUT;
+    let (sender, receiver) = mpsc::channel();
+
+    let listener = reusable_socket(local)?;
+    listener.listen(4)?;
+    let accepted = sender.clone();
+    thread::spawn(move || {
+        while let Ok((socket, _)) = listener.accept() {
+            if accepted.send(TcpStream::from(socket)).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut targets = vec![public];
+    if private != public {
+        targets.push(private);
+    }
+    for target in targets {
+        let sender = sender.clone();
+        thread::spawn(move || {
+            while Instant::now() < deadline {
+                let attempt = reusable_socket(local).and_then(|socket| {
+                    socket
+                        .connect_timeout(&target.into(), ATTEMPT_TIMEOUT)
+                        .map(|()| socket)
+                });
+                match attempt {
+                    Ok(socket) => {
+                        let _ = sender.send(TcpStream::from(socket));
+                        return;
+                    }
+                    Err(_) => thread::sleep(RETRY_DELAY),
+                }
+            }
+        });
+    }
+    drop(sender);
+
+    let failed = || io::Error::new(io::ErrorKind::TimedOut, "hole punching failed");
+    match role {
+        Role::Client => {
+            let mut stream = receiver.recv_timeout(PUNCH_TIMEOUT).map_err(|_| failed())?;
+            stream.write_all(&[CHOSEN])?;
+            Ok(stream)
+        }
+        Role::Server => {
+            let (chosen, chosen_receiver) = mpsc::channel();
+            thread::spawn(move || {
+                for mut candidate in receiver {
+                    let chosen = chosen.clone();
+                    thread::spawn(move || {
+                        let mut marker = [0u8; 1];
+                        let picked = candidate.set_read_timeout(Some(PUNCH_TIMEOUT)).is_ok()
+                            && candidate.read_exact(&mut marker).is_ok()
+                            && marker[0] == CHOSEN
+                            && candidate.set_read_timeout(None).is_ok();
+                        if picked {
+                            let _ = chosen.send(candidate);
+                        }
+                    });
+                }
+            });
+            chosen_receiver
+                .recv_timeout(PUNCH_TIMEOUT)
+                .map_err(|_| failed())
+        }
+    }
+}
@@ -0,0 +1,540 @@
+//! Named rooms on a hub. Room traffic travels in `Frame::Room` frames
+//! carrying one of the `RoomMessage` variants below.
+//!
+//! Each room has a group key created by its first member. A newcomer's
+//! `Join` carries a key request: an ephemeral X25519 key signed with its
+//! identity. The hub forwards the request to the current key holder, who
+//! answers with the group key sealed under an X25519 exchange with that
+//! ephemeral key and signed with its own identity. Room text is encrypted
+//! under the group key, so the hub routes it to the members without being
+//! able to read it. When the holder leaves, another member that has the
+//! key takes over. If no member has it, the hub asks one member to create
+//! a fresh key. Members who leave are not rekeyed out.
+//!
+//! A hub started with `--room-history` keeps the sealed room text (see
+//! `storage`), and members ask for the last messages once they have the
+//! key. So that history outlives the members who were there, each client
+//! remembers the room keys it had, per hub, in a `room_keys` file next to
+//! its known peers: it decrypts older messages with them, and when the hub
+//! asks it to create a key for a room it had, it brings the old one back.
+
+use crate::frame::Frame;
+use crate::history::Received;
+use crate::hub::PeerId;
+use crate::identity::{self, Identity};
+use crate::ui::say;
+use crate::{Delivery, Writer, show_message};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use ed25519_dalek::{Signature, VerifyingKey};
+use hkdf::Hkdf;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use x25519_dalek::{EphemeralSecret, PublicKey};
+
+const MAX_ROOM_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+/// Messages asked for on joining a room whose hub keeps history.
+pub const JOIN_HISTORY: u32 = 20;
+
+/// Accepts `#name` or `name`; returns the name without the `#`.
+pub fn parse_room(text: &str) -> Result<String, String> {
+    let name = text.strip_prefix('#').unwrap_or(text);
+    if name.is_empty() || name.len() > MAX_ROOM_LEN {
+        return Err(format!("room name must be 1 to {} bytes", MAX_ROOM_LEN));
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err("room name may only use letters, digits, - and _".to_string());
+    }
+    Ok(name.to_string())
+}
+
+/// A member's signed request for the group key.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct KeyRequest {
+    pub identity: VerifyingKey,
+    pub ephemeral: PublicKey,
+    pub signature: Signature,
+}
+
+/// The group key sealed for one requester by a member that has it.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct KeyGrant {
+    pub requester: PeerId,
+    pub identity: VerifyingKey,
+    pub ephemeral: PublicKey,
+    pub sealed: Vec<u8>,
+    pub signature: Signature,
+}
+
+/// Room text as sealed by its author, kept by the hub for its history.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Sealed {
+    pub nonce: [u8; NONCE_LEN],
+    pub ciphertext: Vec<u8>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum RoomMessage {
+    /// Client to hub: leave the current room, if any, and enter `room`.
+    Join {
+        room: String,
+        nick: String,
+        request: KeyRequest,
+    },
+    /// Client to hub: back to the lobby.
+    Leave,
+    /// Client to hub: list the current room's occupants.
+    Members,
+    /// Hub to client: membership confirmed; `creator` asks the client to
+    /// create the group key (new room, or no member left has it).
+    Joined { room: String, creator: bool },
+    /// Hub to key holder: someone needs the group key.
+    KeyRequest {
+        requester: PeerId,
+        request: KeyRequest,
+    },
+    /// Key holder to hub, then hub to requester.
+    KeyGrant(KeyGrant),
+    /// Text from the hub, shown as is.
+    Notice(String),
+    /// A `Frame::Text`, or a `Frame::Amend` of one, encoded and encrypted
+    /// under the group key.
+    Text {
+        nonce: [u8; NONCE_LEN],
+        ciphertext: Vec<u8>,
+    },
+    /// Client to hub: the last `count` messages kept for the current room.
+    HistoryRequest(u32),
+    /// Hub to client: the messages kept for the room, oldest first.
+    History(Vec<Sealed>),
+}
+
+fn put_string(body: &mut Vec<u8>, text: &str) {
+    body.push(text.len() as u8);
+    body.extend_from_slice(text.as_bytes());
+}
+
+fn request_transcript(room: &str, ephemeral: &PublicKey) -> Vec<u8> {
+    let mut transcript = b"streamchat v1 room key request".to_vec();
+    put_string(&mut transcript, room);
+    transcript.extend_from_slice(ephemeral.as_bytes());
+    transcript
+}
+
+fn grant_transcript(
+    room: &str,
+    requester: &PublicKey,
+    holder: &PublicKey,
+    sealed: &[u8],
+) -> Vec<u8> {
+    let mut transcript = b"streamchat v1 room key grant".to_vec();
+    put_string(&mut transcript, room);
+    transcript.extend_from_slice(requester.as_bytes());
+    transcript.extend_from_slice(holder.as_bytes());
+    transcript.extend_from_slice(sealed);
+    transcript
+}
+
+/// Key that wraps the group key for one requester. It is used once, so a
+/// zero nonce is fine.
+fn wrapping_cipher(
+    shared_secret: &[u8],
+    requester: &PublicKey,
+    holder: &PublicKey,
+) -> ChaCha20Poly1305 {
+    let mut salt = requester.as_bytes().to_vec();
+    salt.extend_from_slice(holder.as_bytes());
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&salt), shared_secret)
+        .expand(b"streamchat v1 room key wrap", &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    ChaCha20Poly1305::new(Key::from_slice(&key))
+}
+
+/// The room keys a client had on one hub, from the `room_keys` file: one
+/// `HUB ROOM KEY` line per key (hub identity and key in hex), oldest
+/// first. `#` starts a comment.
+pub struct Keyring {
+    path: PathBuf,
+    hub: String,
+}
+
+impl Keyring {
+    /// The keys for rooms on the hub `hub`, in the file next to
+    /// `known_peers`.
+    pub fn new(known_peers: &Path, hub: &VerifyingKey) -> Self {
+        Keyring {
+            path: known_peers.with_file_name("room_keys"),
+            hub: hex::encode(hub.as_bytes()),
+        }
+    }
+
+    /// The lines of the file; a missing file has none.
+    fn read_lines(&self) -> io::Result<Vec<String>> {
+        match fs::read_to_string(&self.path) {
+            Ok(text) => Ok(text.lines().map(str::to_string).collect()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The keys `room` had, oldest first. Lines that don't parse are
+    /// skipped.
+    fn keys(&self, room: &str) -> io::Result<Vec<[u8; 32]>> {
+        let lines = self.read_lines()?;
+        Ok(lines
+            .iter()
+            .filter_map(|line| {
+                let entry = line.split('#').next().unwrap_or("");
+                let [hub, name, key] = entry.split_whitespace().collect::<Vec<_>>()[..] else {
+                    return None;
+                };
+                if hub != self.hub || name != room {
+                    return None;
+                }
+                hex::decode(key).ok()?.try_into().ok()
+            })
+            .collect())
+    }
+
+    /// Adds `key` to what `room` had, unless it is there already.
+    fn remember(&self, room: &str, key: &[u8; 32]) -> io::Result<()> {
+        if self.keys(room)?.contains(key) {
+            return Ok(());
+        }
+        let mut lines = self.read_lines()?;
+        lines.push(format!("{} {} {}", self.hub, room, hex::encode(key)));
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        identity::write_private(&self.path, &(lines.join("\n") + "\n"))
+    }
+}
+
+/// This client's room: its name, the group key once we have it, and the
+/// secret half of our pending key request.
+pub struct Membership {
+    pub room: String,
+    key: Option<[u8; 32]>,
+    secret: Option<EphemeralSecret>,
+    keyring: Keyring,
+    /// Keys the room had before, oldest first, for its history.
+    earlier: Vec<[u8; 32]>,
+    /// The hub keeps history: ask for it once we have the key.
+    history: bool,
+}
+
+impl Membership {
+    /// Starts joining `room`; the request goes in the `Join` message.
+    /// `history` when the hub keeps room history.
+    pub fn join(
+        room: String,
+        identity: &Identity,
+        keyring: Keyring,
+        history: bool,
+    ) -> (Self, KeyRequest) {
+        let secret = EphemeralSecret::random();
+        let ephemeral = PublicKey::from(&secret);
+        let request = KeyRequest {
+            identity: identity.public_key(),
+            ephemeral,
+            signature: identity.sign(&request_transcript(&room, &ephemeral)),
+        };
+        let earlier = keyring.keys(&room).unwrap_or_else(|e| {
+            say!("⚠️  Could not read the room keys: {}", e);
+            Vec::new()
+        });
+        let membership = Membership {
+            room,
+            key: None,
+            secret: Some(secret),
+            keyring,
+            earlier,
+            history,
+        };
+        (membership, request)
+    }
+
+    /// Creates the group key, or brings back the last one we had for the
+    /// room so that its history stays readable. Returns whether it did.
+    pub fn create_key(&mut self) -> bool {
+        let remembered = self.earlier.last().copied();
+        let key = remembered.unwrap_or_else(|| {
+            let mut key = [0u8; 32];
+            rand::rng().fill(&mut key);
+            key
+        });
+        self.key = Some(key);
+        self.secret = None;
+        remembered.is_some()
+    }
+
+    /// Seals the group key for `requester` after checking that the request
+    /// was signed for this room.
+    pub fn grant(
+        &self,
+        identity: &Identity,
+        requester: PeerId,
+        request: &KeyRequest,
+    ) -> Result<KeyGrant, String> {
+        let key = self.key.ok_or("we don't have the room key yet")?;
+        request
+            .identity
+            .verify_strict(
+                &request_transcript(&self.room, &request.ephemeral),
+                &request.signature,
+            )
+            .map_err(|_| "the key request's signature does not verify")?;
+
+        let secret = EphemeralSecret::random();
+        let ephemeral = PublicKey::from(&secret);
+        let shared_secret = secret.diffie_hellman(&request.ephemeral);
+        if !shared_secret.was_contributory() {
+            return Err("the key request has a low-order key".to_string());
+        }
+        let sealed = wrapping_cipher(shared_secret.as_bytes(), &request.ephemeral, &ephemeral)
+            .encrypt(
+                &Nonce::default(),
+                Payload {
+                    msg: &key,
+                    aad: self.room.as_bytes(),
+                },
+            )
+            .expect("ChaCha20-Poly1305 encryption cannot fail for in-memory buffers");
+        let signature = identity.sign(&grant_transcript(
+            &self.room,
+            &request.ephemeral,
+            &ephemeral,
+            &sealed,
+        ));
+        Ok(KeyGrant {
+            requester,
+            identity: identity.public_key(),
+            ephemeral,
+            sealed,
+            signature,
+        })
+    }
+
+    /// Unseals the group key from `grant`. Returns the fingerprint of the
+    /// member who sent it.
+    pub fn accept(&mut self, grant: &KeyGrant) -> Result<String, String> {
+        let secret = self.secret.take().ok_or("no key request is pending")?;
+        let requester = PublicKey::from(&secret);
+        grant
+            .identity
+            .verify_strict(
+                &grant_transcript(&self.room, &requester, &grant.ephemeral, &grant.sealed),
+                &grant.signature,
+            )
+            .map_err(|_| "the key grant's signature does not verify")?;
+
+        let shared_secret = secret.diffie_hellman(&grant.ephemeral);
+        let key = wrapping_cipher(shared_secret.as_bytes(), &requester, &grant.ephemeral)
+            .decrypt(
+                &Nonce::default(),
+                Payload {
+                    msg: &grant.sealed,
+                    aad: self.room.as_bytes(),
+                },
+            )
+            .ok()
+            .and_then(|key| <[u8; 32]>::try_from(key).ok())
+            .ok_or("the room key does not decrypt")?;
+        self.key = Some(key);
+        Ok(identity::fingerprint(&grant.identity))
+    }
+
+    /// Encrypts an encoded frame for the room.
+    pub fn seal(&self, plaintext: &[u8]) -> Option<RoomMessage> {
+        let key = self.key?;
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::rng().fill(&mut nonce);
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key))
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext,
+                    aad: self.room.as_bytes(),
+                },
+            )
+            .expect("ChaCha20-Poly1305 encryption cannot fail for in-memory buffers");
+        Some(RoomMessage::Text { nonce, ciphertext })
+    }
+
+    /// Decrypts room text, under the group key or one the room had
+    /// before.
+    pub fn open(&self, nonce: &[u8; NONCE_LEN], ciphertext: &[u8]) -> Option<Vec<u8>> {
+        self.key
+            .iter()
+            .chain(self.earlier.iter().rev())
+            .find_map(|key| {
+                ChaCha20Poly1305::new(Key::from_slice(key))
+                    .decrypt(
+                        Nonce::from_slice(nonce),
+                        Payload {
+                            msg: ciphertext,
+                            aad: self.room.as_bytes(),
+                        },
+                    )
+                    .ok()
+            })
+    }
+}
+
+/// We have the room key now: remember it, and ask for the history if the
+/// hub keeps one.
+fn have_key(joined: &Membership, writer: &Mutex<Writer>) {
+    if let Some(key) = &joined.key
+        && let Err(e) = joined.keyring.remember(&joined.room, key)
+    {
+        say!("⚠️  Could not remember the #{} key: {}", joined.room, e);
+    }
+    if joined.history {
+        let request = RoomMessage::HistoryRequest(JOIN_HISTORY);
+        let _ = writer.lock().unwrap().send(&Frame::Room(Box::new(request)));
+    }
+}
+
+/// Client side of the room protocol, run by the reader thread.
+pub fn receive(
+    message: RoomMessage,
+    membership: &Mutex<Option<Membership>>,
+    identity: &Identity,
+    writer: &Mutex<Writer>,
+    known_peers: &Path,
+    received: &mut Received,
+) {
+    let mut membership = membership.lock().unwrap();
+    match message {
+        RoomMessage::Joined { room, creator } => {
+            // A late answer to a room we already left.
+            let Some(joined) = membership.as_mut().filter(|joined| joined.room == room) else {
+                return;
+            };
+            if creator {
+                if joined.create_key() {
+                    say!(
+                        "\n🔑 You hold the key #{} had before and hand it to members who join.",
+                        room
+                    );
+                } else {
+                    say!(
+                        "\n🔑 You hold the key for #{} and hand it to members who join.",
+                        room
+                    );
+                }
+                have_key(joined, writer);
+            } else {
+                say!(
+                    "\n🏠 Joined #{}, waiting for a member to send the room key...",
+                    room
+                );
+            }
+        }
+        RoomMessage::KeyRequest { requester, request } => {
+            let result = match membership.as_ref() {
+                Some(joined) => joined.grant(identity, requester, &request),
+                None => Err("we are not in a room".to_string()),
+            };
+            match result {
+                Ok(grant) => {
+                    let grant = Frame::Room(Box::new(RoomMessage::KeyGrant(grant)));
+                    if writer.lock().unwrap().send(&grant).is_ok() {
+                        say!(
+                            "\n🔑 Sent the room key to {}.",
+                            identity::fingerprint(&request.identity)
+                        );
+                    }
+                }
+                Err(e) => say!("\n⚠️  Did not hand out the room key: {}", e),
+            }
+        }
+        RoomMessage::KeyGrant(grant) => {
+            let Some(joined) = membership.as_mut() else {
+                return;
+            };
+            match joined.accept(&grant) {
+                Ok(sender) => {
+                    say!(
+                        "\n🔑 Got the #{} key from {}, messages now go to the room.",
+                        joined.room,
+                        sender
+                    );
+                    have_key(joined, writer);
+                }
+                Err(e) => say!("\n⚠️  Rejected the #{} key: {}", joined.room, e),
+            }
+        }
+        RoomMessage::Notice(text) => say!("\n🏠 {}", text),
+        RoomMessage::Text { nonce, ciphertext } => {
+            let frame = membership.as_ref().and_then(|joined| {
+                let plaintext = joined.open(&nonce, &ciphertext)?;
+                Some((&joined.room, Frame::decode(&plaintext)?))
+            });
+            match frame {
+                Some((room, Frame::Text(message))) => {
+                    let authorship = message.authorship(None, known_peers);
+                    show_message(&message, authorship, Some(room), Delivery::Live, received);
+                }
+                Some((_, Frame::Amend(amendment))) => received.apply(&amendment),
+                Some(_) => say!("\n⚠️  Dropped a room frame that is not chat."),
+                None => say!("\n⚠️  Dropped a room message that does not decrypt."),
+            }
+        }
+        RoomMessage::History(messages) => {
+            let Some(joined) = membership.as_ref() else {
+                return;
+            };
+            if messages.is_empty() {
+                say!("\n📜 Nothing kept for #{} yet.", joined.room);
+                return;
+            }
+            say!(
+                "\n📜 The last {} message(s) kept for #{}:",
+                messages.len(),
+                joined.room
+            );
+            let mut unreadable = 0;
+            for sealed in messages {
+                let frame = joined
+                    .open(&sealed.nonce, &sealed.ciphertext)
+                    .and_then(|plaintext| Frame::decode(&plaintext));
+                match frame {
+                    Some(Frame::Text(message)) => {
+                        let authorship = message.authorship(None, known_peers);
+                        let room = Some(joined.room.as_str());
+                        show_message(&message, authorship, room, Delivery::Earlier, received);
+                    }
+                    Some(Frame::Amend(amendment)) => received.apply(&amendment),
+                    _ => unreadable += 1,
+                }
+            }
+            if unreadable > 0 {
+                say!(
+                    "📜 {} of them are under a room key you never had.",
+                    unreadable
+                );
+            }
+        }
+        RoomMessage::Join { .. }
+        | RoomMessage::Leave
+        | RoomMessage::Members
+        | RoomMessage::HistoryRequest(_) => {
+            // Only a hub answers these; the other side is a plain peer.
+            let notice = RoomMessage::Notice(
+                "Rooms need a hub (streamchat server), this peer is not one.".to_string(),
+            );
+            let _ = writer.lock().unwrap().send(&Frame::Room(Box::new(notice)));
+        }
+    }
+}
@@ -0,0 +1,65 @@
+//! Shaping what a connection sends, so that a file transfer doesn't starve
+//! the chat. Frames go out in two lanes:
+//!
+//! - interactive (text, control, keepalives): written as soon as the
+//!   connection is free, by whichever thread sends them;
+//! - bulk (file chunks): written one at a time by the transfer's own
+//!   thread, which lets go of the connection after each chunk and then
+//!   pauses for as long as the send rate (`--send-rate`) asks. On QUIC the
+//!   chunks have a stream of their own, so they don't even share the wire
+//!   with messages.
+//!
+//! Both lanes draw on the same token bucket, but only the bulk lane ever
+//! waits for it. A line typed during a transfer waits for at most the chunk
+//! being written, and `--max-frame` bounds that: chunks are cut so that,
+//! padded, they fit in it. On a bulk stream it doesn't wait at all.
+
+use crate::Writer;
+use crate::flood::Bucket;
+use crate::frame::Frame;
+use std::io;
+use std::sync::Mutex;
+use std::thread;
+use streamchat_proto::Padding;
+
+/// Frame size when `--max-frame` is not given.
+pub const DEFAULT_MAX_FRAME: u64 = 64 * 1024;
+/// Bytes a file chunk frame adds to its data: the variant, the transfer id
+/// and the data length, all varints.
+const CHUNK_OVERHEAD: usize = 16;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Shaping {
+    /// Largest frame, once padded, a file transfer sends.
+    pub max_frame: usize,
+    /// Bytes per second sent on each connection; zero is unlimited.
+    pub send_rate: u32,
+}
+
+impl Shaping {
+    /// File data per chunk for a connection padded with `padding`.
+    pub fn chunk_size(&self, padding: Padding) -> usize {
+        padding
+            .fitting(self.max_frame)
+            .saturating_sub(CHUNK_OVERHEAD)
+            .max(1)
+    }
+
+    /// The bucket each connection charges its frames to.
+    pub fn pacer(&self) -> Option<Bucket> {
+        Bucket::new(self.send_rate)
+    }
+}
+
+/// Sends `frame` in the bulk lane: waits for the connection, writes the
+/// frame, lets go, then pauses as the send rate asks (or at least yields,
+/// so that a waiting interactive frame goes first).
+pub fn send_bulk(writer: &Mutex<Writer>, frame: &Frame) -> io::Result<()> {
+    let pause = Writer::send_bulk(writer, frame)?;
+    if pause.is_zero() {
+        thread::yield_now();
+    } else {
+        thread::sleep(pause);
+    }
+    Ok(())
+}
@@ -0,0 +1,206 @@
+//! SOCKS5 client (RFC 1928, plus RFC 1929 username/password), so the
+//! client can reach the server through Tor or a corporate proxy. Host
+//! names are handed to the proxy unresolved, which keeps DNS lookups off
+//! the local network when going through Tor.
+
+use crate::net;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, TcpStream};
+
+const VERSION: u8 = 5;
+const NO_AUTH: u8 = 0x00;
+const USER_PASSWORD: u8 = 0x02;
+const NO_ACCEPTABLE_METHOD: u8 = 0xFF;
+const CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+#[derive(Clone, Debug)]
+pub struct Proxy {
+    address: String,
+    credentials: Option<(String, String)>,
+}
+
+impl fmt::Display for Proxy {
+    /// Shows the proxy without its password.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.credentials {
+            Some((user, _)) => write!(f, "socks5://{}@{}", user, self.address),
+            None => write!(f, "socks5://{}", self.address),
+        }
+    }
+}
+
+/// Parses `socks5://[user:password@]host:port` (`socks5h://` is accepted
+/// too, names are always resolved by the proxy).
+pub fn parse_proxy(text: &str) -> Result<Proxy, String> {
+    let rest = text
+        .strip_prefix("socks5://")
+        .or_else(|| text.strip_prefix("socks5h://"))
+        .ok_or("expected socks5://host:port")?;
+    let rest = rest.trim_end_matches('/');
+
+    let (credentials, address) = match rest.rsplit_once('@') {
+        Some((user_info, address)) => {
+            let (user, password) = user_info
+                .split_once(':')
+                .ok_or("expected user:password@ before the proxy address")?;
+            if user.len() > 255 || password.len() > 255 {
+                return Err("user name and password are limited to 255 bytes".to_string());
+            }
+            (Some((user.to_string(), password.to_string())), address)
+        }
+        None => (None, rest),
+    };
+    split_host_port(address)?;
+    Ok(Proxy {
+        address: address.to_string(),
+        credentials,
+    })
+}
+
+/// Splits `host:port` or `[v6]:port`.
+fn split_host_port(address: &str) -> Result<(&str, u16), String> {
+    let (host, port) = address
+        .rsplit_once(':')
+        .ok_or_else(|| format!("{} is missing a port", address))?;
+    let port = port
+        .parse()
+        .map_err(|_| format!("invalid port in {}", address))?;
+    let host = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    if host.is_empty() {
+        return Err(format!("{} is missing a host", address));
+    }
+    Ok((host, port))
+}
+
+fn protocol_error(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+impl Proxy {
+    /// Opens a TCP connection to the proxy and asks it to connect to
+    /// `target` (`host:port`).
+    pub fn connect(&self, target: &str) -> io::Result<TcpStream> {
+        let (host, port) =
+            split_host_port(target).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut stream = net::connect(&self.address)?;
+
+        let method = if self.credentials.is_some() {
+            USER_PASSWORD
+        } else {
+            NO_AUTH
+        };
+        stream.write_all(&[VERSION, 1, method])?;
+        let mut reply = [0u8; 2];
+        stream.read_exact(&mut reply)?;
+        if reply[0] != VERSION {
+            return Err(protocol_error("not a SOCKS5 proxy"));
+        }
+        match reply[1] {
+            NO_AUTH => {}
+            USER_PASSWORD => self.authenticate(&mut stream)?,
+            NO_ACCEPTABLE_METHOD => {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "proxy refused our authentication method",
+                ));
+            }
+            other => {
+                return Err(protocol_error(format!(
+                    "proxy chose unsupported method {}",
+                    other
+                )));
+            }
+        }
+
+        let mut request = vec![VERSION, CONNECT, 0];
+        match host.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => {
+                request.push(ATYP_IPV4);
+                request.extend_from_slice(&ip.octets());
+            }
+            Ok(IpAddr::V6(ip)) => {
+                request.push(ATYP_IPV6);
+                request.extend_from_slice(&ip.octets());
+            }
+            Err(_) => {
+                if host.len() > 255 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "host name longer than 255 bytes",
+                    ));
+                }
+                request.push(ATYP_DOMAIN);
+                request.push(host.len() as u8);
+                request.extend_from_slice(host.as_bytes());
+            }
+        }
+        request.extend_from_slice(&port.to_be_bytes());
+        stream.write_all(&request)?;
+
+        let mut header = [0u8; 4];
+        stream.read_exact(&mut header)?;
+        if header[1] != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("proxy: {}", reply_message(header[1])),
+            ));
+        }
+        // Skip the bound address the proxy reports.
+        let address_len = match header[3] {
+            ATYP_IPV4 => 4,
+            ATYP_IPV6 => 16,
+            ATYP_DOMAIN => {
+                let mut len = [0u8; 1];
+                stream.read_exact(&mut len)?;
+                len[0] as usize
+            }
+            other => return Err(protocol_error(format!("unknown address type {}", other))),
+        };
+        let mut bound = vec![0u8; address_len + 2];
+        stream.read_exact(&mut bound)?;
+        Ok(stream)
+    }
+
+    fn authenticate(&self, stream: &mut TcpStream) -> io::Result<()> {
+        let (user, password) = self
+            .credentials
+            .as_ref()
+            .ok_or_else(|| protocol_error("proxy asked for credentials we did not offer"))?;
+        let mut request = vec![1, user.len() as u8];
+        request.extend_from_slice(user.as_bytes());
+        request.push(password.len() as u8);
+        request.extend_from_slice(password.as_bytes());
+        stream.write_all(&request)?;
+
+        let mut reply = [0u8; 2];
+        stream.read_exact(&mut reply)?;
+        if reply[1] != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "proxy rejected the user name or password",
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn reply_message(code: u8) -> &'static str {
+    match code {
+        1 => "general failure",
+        2 => "connection not allowed by ruleset",
+        3 => "network unreachable",
+        4 => "host unreachable",
+        5 => "connection refused",
+        6 => "TTL expired",
+        7 => "command not supported",
+        8 => "address type not supported",
+        _ => "unknown error",
+    }
+}
@@ -0,0 +1,143 @@
+//! Room history kept by a hub (`server --room-history DIR`). What is kept
+//! is the room text as relayed, still sealed under the room's group key,
+//! so the hub stores messages it can't read; members decrypt them with
+//! the keys they remember (see `room`).
+//!
+//! Each room has a file `DIR/NAME.history` of length-prefixed records
+//! (u32 BE length, then the encoded `Sealed`), appended to as messages
+//! arrive. Only the last `limit` are served; the file is rewritten with
+//! just those once it holds twice as many.
+
+use crate::frame;
+use crate::room::Sealed;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// One room's file, as loaded.
+struct Kept {
+    messages: VecDeque<Sealed>,
+    /// Records in the file, including the ones past the limit.
+    on_disk: usize,
+    /// The file ends in a partial record, which an append must not follow.
+    cut_short: bool,
+}
+
+pub struct RoomHistory {
+    dir: PathBuf,
+    limit: usize,
+    /// Rooms whose file was read since the hub started.
+    rooms: Mutex<HashMap<String, Kept>>,
+}
+
+fn record(message: &Sealed) -> Vec<u8> {
+    let body = frame::encode(message);
+    let mut record = (body.len() as u32).to_be_bytes().to_vec();
+    record.extend_from_slice(&body);
+    record
+}
+
+impl RoomHistory {
+    /// Keeps up to `limit` messages per room in `dir`, created if needed.
+    pub fn open(dir: PathBuf, limit: usize) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(RoomHistory {
+            dir,
+            limit,
+            rooms: Mutex::default(),
+        })
+    }
+
+    /// Room names are letters, digits, `-` and `_` (see
+    /// `room::parse_room`), so they are safe file names.
+    fn path(&self, room: &str) -> PathBuf {
+        self.dir.join(format!("{}.history", room))
+    }
+
+    /// Reads a room's file. A record cut short, by a crash in the middle
+    /// of an append, ends it; the next append rewrites the file.
+    fn load(&self, room: &str) -> io::Result<Kept> {
+        let bytes = match fs::read(self.path(room)) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        let mut messages = VecDeque::new();
+        let mut on_disk = 0;
+        let mut rest = bytes.as_slice();
+        while let Some((length, after)) = rest.split_first_chunk::<4>() {
+            let Some((body, after)) = after.split_at_checked(u32::from_be_bytes(*length) as usize)
+            else {
+                break;
+            };
+            rest = after;
+            on_disk += 1;
+            // A record this version can't decode is skipped.
+            if let Some(message) = frame::decode(body) {
+                messages.push_back(message);
+                if messages.len() > self.limit {
+                    messages.pop_front();
+                }
+            }
+        }
+        Ok(Kept {
+            messages,
+            on_disk,
+            cut_short: !rest.is_empty(),
+        })
+    }
+
+    /// Runs `f` on the room's messages, loading them the first time.
+    fn with_room<T>(
+        &self,
+        room: &str,
+        f: impl FnOnce(&mut Kept) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let mut rooms = self.rooms.lock().unwrap();
+        if !rooms.contains_key(room) {
+            let kept = self.load(room)?;
+            rooms.insert(room.to_string(), kept);
+        }
+        f(rooms.get_mut(room).expect("loaded above"))
+    }
+
+    /// Adds a message to the room's history.
+    pub fn append(&self, room: &str, message: Sealed) -> io::Result<()> {
+        self.with_room(room, |kept| {
+            let path = self.path(room);
+            if kept.on_disk >= 2 * self.limit || kept.cut_short {
+                // Rewrite the file with the last `limit - 1`, then append.
+                while kept.messages.len() >= self.limit {
+                    kept.messages.pop_front();
+                }
+                let temporary = path.with_extension("history.tmp");
+                let bytes: Vec<u8> = kept.messages.iter().flat_map(record).collect();
+                fs::write(&temporary, bytes)?;
+                fs::rename(&temporary, &path)?;
+                kept.on_disk = kept.messages.len();
+                kept.cut_short = false;
+            }
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)?
+                .write_all(&record(&message))?;
+            kept.on_disk += 1;
+            kept.messages.push_back(message);
+            if kept.messages.len() > self.limit {
+                kept.messages.pop_front();
+            }
+            Ok(())
+        })
+    }
+
+    /// The room's last `count` messages, oldest first.
+    pub fn last(&self, room: &str, count: usize) -> io::Result<Vec<Sealed>> {
+        self.with_room(room, |kept| {
+            let skip = kept.messages.len().saturating_sub(count);
+            Ok(kept.messages.iter().skip(skip).cloned().collect())
+        })
+    }
+}
@@ -0,0 +1,371 @@
+//! File transfer over the chat connection: an offer announcing name, size
+//! and SHA-256, which the receiver answers with `/accept` or `/decline`,
+//! then chunks that it reassembles into a `.part` file and checks against
+//! the announced hash. Nothing is written before the offer is accepted, and
+//! offers over `--max-download` are declined without asking.
+
+use crate::frame::Frame;
+use crate::ui::say;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+/// How long a sender waits for its offer to be accepted.
+pub const ANSWER_TIMEOUT: Duration = Duration::from_secs(120);
+/// Offers waiting for an answer at once; more are declined.
+const MAX_PENDING: usize = 16;
+
+#[derive(Serialize, Deserialize)]
+pub struct FileOffer {
+    pub id: u32,
+    pub nick: String,
+    pub name: String,
+    pub size: u64,
+    pub sha256: [u8; 32],
+}
+
+/// Progress in tenths, so it is printed every 10%.
+fn progress_step(done: u64, total: u64) -> u64 {
+    (done * 10).checked_div(total).unwrap_or(10)
+}
+
+/// Answers to our offers, filled in by the reader thread and waited for by
+/// the thread sending the file.
+#[derive(Default)]
+pub struct Answers {
+    answers: Mutex<HashMap<u32, Option<bool>>>,
+    arrived: Condvar,
+}
+
+impl Answers {
+    /// Records the answer to offer `id`. Returns false when we are not
+    /// waiting for one, e.g. the offer was someone else's.
+    pub fn answer(&self, id: u32, accepted: bool) -> bool {
+        let mut answers = self.answers.lock().unwrap();
+        let Some(answer) = answers.get_mut(&id) else {
+            return false;
+        };
+        *answer = Some(accepted);
+        self.arrived.notify_all();
+        true
+    }
+
+    fn expect(&self, id: u32) {
+        self.answers.lock().unwrap().insert(id, None);
+    }
+
+    /// The answer to offer `id`, or `None` if none came in time.
+    fn wait(&self, id: u32) -> Option<bool> {
+        let answers = self.answers.lock().unwrap();
+        let (mut answers, _) = self
+            .arrived
+            .wait_timeout_while(answers, ANSWER_TIMEOUT, |answers| {
+                answers.get(&id).is_some_and(Option::is_none)
+            })
+            .unwrap();
+        answers.remove(&id).flatten()
+    }
+}
+
+/// Hashes `path`, sends the offer and waits for it to be accepted, then
+/// streams the content through `send` in chunks of `chunk_size` bytes.
+pub fn send_file(
+    path: &Path,
+    nick: &str,
+    chunk_size: usize,
+    answers: &Answers,
+    mut send: impl FnMut(Frame) -> io::Result<()>,
+) -> io::Result<()> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file name"))?
+        .to_string();
+
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let size = io::copy(&mut file, &mut hasher)?;
+
+    let offer = FileOffer {
+        id: rand::random(),
+        nick: nick.to_string(),
+        name,
+        size,
+        sha256: hasher.finalize().into(),
+    };
+    let (id, name) = (offer.id, offer.name.clone());
+    answers.expect(id);
+    send(Frame::FileOffer(offer))?;
+    say!(
+        "📎 Offered {} ({} bytes), waiting for it to be accepted...",
+        name,
+        size
+    );
+    match answers.wait(id) {
+        Some(true) => say!("📎 Sending {}...", name),
+        Some(false) => return Err(io::Error::other("the offer was declined")),
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("nobody accepted it within {}s", ANSWER_TIMEOUT.as_secs()),
+            ));
+        }
+    }
+
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0u8; chunk_size];
+    let mut sent = 0u64;
+    let mut reported = 0;
+    loop {
+        let n = file.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        send(Frame::FileChunk {
+            id,
+            data: buffer[..n].to_vec(),
+        })?;
+        sent += n as u64;
+
+        let step = progress_step(sent, size);
+        if step > reported {
+            reported = step;
+            say!(" 📤 {}: {}%", name, step * 10);
+        }
+    }
+
+    if sent != size {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "file changed while it was being sent",
+        ));
+    }
+    say!("✓ {} sent", name);
+    Ok(())
+}
+
+struct Download {
+    offer: FileOffer,
+    file: File,
+    part_path: PathBuf,
+    final_path: PathBuf,
+    received: u64,
+    reported: u64,
+    hasher: Sha256,
+}
+
+/// Offers waiting for an answer, and accepted transfers being reassembled
+/// into the downloads directory.
+pub struct Downloads {
+    dir: PathBuf,
+    /// Largest offer asked about, in bytes.
+    max_size: u64,
+    /// By the number `/accept` and `/decline` take.
+    pending: BTreeMap<u32, FileOffer>,
+    next: u32,
+    active: HashMap<u32, Download>,
+}
+
+impl Downloads {
+    pub fn new(dir: PathBuf, max_size: u64) -> Self {
+        Downloads {
+            dir,
+            max_size,
+            pending: BTreeMap::new(),
+            next: 0,
+            active: HashMap::new(),
+        }
+    }
+
+    /// Asks about `offer`. Returns false when it is declined right away:
+    /// too big, or too many offers already waiting.
+    pub fn offer(&mut self, offer: FileOffer) -> bool {
+        if offer.size > self.max_size {
+            say!(
+                "\n📎 Declined {} from {}: {} bytes is over --max-download ({} bytes).",
+                offer.name,
+                offer.nick,
+                offer.size,
+                self.max_size
+            );
+            return false;
+        }
+        if self.pending.len() >= MAX_PENDING {
+            say!(
+                "\n📎 Declined {} from {}: {} offers are already waiting.",
+                offer.name,
+                offer.nick,
+                MAX_PENDING
+            );
+            return false;
+        }
+        self.next += 1;
+        say!(
+            "\n📎 {} offers {} ({} bytes): /accept {} to save it, /decline {} to refuse.",
+            offer.nick,
+            offer.name,
+            offer.size,
+            self.next,
+            self.next
+        );
+        self.pending.insert(self.next, offer);
+        true
+    }
+
+    /// Accepts offer `number` and creates its `.part` file; returns the
+    /// transfer id to answer with.
+    pub fn accept(&mut self, number: u32) -> io::Result<u32> {
+        let offer = self
+            .pending
+            .remove(&number)
+            .ok_or_else(|| no_offer(number))?;
+        let final_path = unique_path(&self.dir, &sanitize_name(&offer.name))?;
+        let mut part_path = final_path.clone().into_os_string();
+        part_path.push(".part");
+        let part_path = PathBuf::from(part_path);
+        let file = File::create(&part_path)?;
+
+        say!(
+            "📎 Accepted {} ({} bytes) -> {}",
+            offer.name,
+            offer.size,
+            final_path.display()
+        );
+
+        let id = offer.id;
+        let download = Download {
+            offer,
+            file,
+            part_path,
+            final_path,
+            received: 0,
+            reported: 0,
+            hasher: Sha256::new(),
+        };
+        if download.offer.size == 0 {
+            finish(download)?;
+            return Ok(id);
+        }
+        self.active.insert(id, download);
+        Ok(id)
+    }
+
+    /// Declines offer `number`; returns the transfer id to answer with.
+    pub fn decline(&mut self, number: u32) -> io::Result<u32> {
+        let offer = self
+            .pending
+            .remove(&number)
+            .ok_or_else(|| no_offer(number))?;
+        say!("📎 Declined {} from {}.", offer.name, offer.nick);
+        Ok(offer.id)
+    }
+
+    /// Drops transfer `id` and its `.part` file, when the other side calls
+    /// it off.
+    pub fn cancel(&mut self, id: u32) {
+        if let Some(download) = self.active.remove(&id) {
+            let _ = fs::remove_file(&download.part_path);
+            say!(
+                "\n⚠️  The transfer of {} was called off.",
+                download.offer.name
+            );
+        }
+    }
+
+    pub fn chunk(&mut self, id: u32, data: &[u8]) -> io::Result<()> {
+        let Some(download) = self.active.get_mut(&id) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "chunk for an unknown transfer",
+            ));
+        };
+
+        if download.received + data.len() as u64 > download.offer.size {
+            let download = self.active.remove(&id).expect("transfer is active");
+            let _ = fs::remove_file(&download.part_path);
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} is larger than announced, discarded",
+                    download.offer.name
+                ),
+            ));
+        }
+
+        download.file.write_all(data)?;
+        download.hasher.update(data);
+        download.received += data.len() as u64;
+
+        let step = progress_step(download.received, download.offer.size);
+        if step > download.reported {
+            download.reported = step;
+            say!(" 📥 {}: {}%", download.offer.name, step * 10);
+        }
+
+        if download.received == download.offer.size {
+            let download = self.active.remove(&id).expect("transfer is active");
+            finish(download)?;
+        }
+        Ok(())
+    }
+}
+
+fn no_offer(number: u32) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("no offer {} waiting", number),
+    )
+}
+
+fn finish(mut download: Download) -> io::Result<()> {
+    download.file.flush()?;
+    let digest: [u8; 32] = download.hasher.finalize().into();
+    if digest != download.offer.sha256 {
+        let _ = fs::remove_file(&download.part_path);
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} failed the SHA-256 check, discarded",
+                download.offer.name
+            ),
+        ));
+    }
+    fs::rename(&download.part_path, &download.final_path)?;
+    say!(
+        "✓ Received {} (SHA-256 verified) -> {}",
+        download.offer.name,
+        download.final_path.display()
+    );
+    Ok(())
+}
+
+/// Keeps only the final path component so a peer can't write outside the
+/// downloads directory.
+pub fn sanitize_name(name: &str) -> String {
+    let name = Path::new(name)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("");
+    if name.is_empty() || name.starts_with('.') {
+        format!("download{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+pub fn unique_path(dir: &Path, name: &str) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let candidate = dir.join(name);
+    if !candidate.exists() {
+        return Ok(candidate);
+    }
+    (1..)
+        .map(|n| dir.join(format!("{} ({})", name, n)))
+        .find(|path| !path.exists())
+        .ok_or_else(|| io::Error::other("no free file name"))
+}
@@ -0,0 +1,143 @@
+//! Byte streams the chat protocol can run over. The handshake and framing
+//! only need a reliable, ordered stream, so the rest of streamchat works
+//! with `Box<dyn Transport>` and never sees whether TCP, QUIC or a
+//! WebSocket carries it.
+
+use crate::socks::Proxy;
+use crate::ui::say;
+use crate::{net, onion, quic, ws};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TransportKind {
+    Tcp,
+    /// QUIC over UDP; survives NAT rebinding and address changes.
+    Quic,
+    /// WebSocket (binary messages), so browser pages can connect.
+    Ws,
+}
+
+/// One connection to a peer.
+pub trait Transport: Read + Write + Send {
+    fn peer_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Reads that wait longer than `timeout` fail with `TimedOut` or
+    /// `WouldBlock`. Applies to every handle on the connection.
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+    /// A second handle on the same connection, so one thread can read while
+    /// another writes.
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>>;
+
+    /// Closes both directions; blocked reads on other handles return.
+    fn shutdown(&self) -> io::Result<()>;
+
+    /// A second stream on the same connection, for file chunks, so they
+    /// don't queue on the wire ahead of messages. `None` when the transport
+    /// has only the one (TCP, WebSocket), and the chunks share it.
+    fn bulk(&self) -> io::Result<Option<Box<dyn Transport>>> {
+        Ok(None)
+    }
+}
+
+impl Transport for TcpStream {
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        TcpStream::peer_addr(self)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(TcpStream::try_clone(self)?))
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        TcpStream::shutdown(self, Shutdown::Both)
+    }
+}
+
+/// Server side: hands out one `Transport` per incoming peer.
+pub trait Listener: Send {
+    fn accept(&self) -> io::Result<Box<dyn Transport>>;
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+}
+
+impl Listener for TcpListener {
+    fn accept(&self) -> io::Result<Box<dyn Transport>> {
+        let (stream, _) = TcpListener::accept(self)?;
+        Ok(Box::new(stream))
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        TcpListener::local_addr(self)
+    }
+}
+
+pub fn listen(kind: TransportKind, addr: SocketAddr) -> io::Result<Box<dyn Listener>> {
+    Ok(match kind {
+        TransportKind::Tcp => Box::new(TcpListener::bind(addr)?),
+        TransportKind::Quic => Box::new(quic::QuicListener::bind(addr)?),
+        TransportKind::Ws => Box::new(ws::WsListener::bind(addr)?),
+    })
+}
+
+/// Connects directly or through `proxy`; `.onion` addresses always go
+/// through a proxy, Tor's own when none was given.
+fn tcp_connect(address: &str, proxy: Option<&Proxy>) -> io::Result<TcpStream> {
+    match proxy {
+        Some(proxy) => proxy.connect(address),
+        None if onion::is_onion(address) => {
+            let tor = onion::tor_proxy();
+            say!(
+                "🧅 {} is an onion service, going through Tor at {}",
+                address,
+                tor
+            );
+            tor.connect(address)
+        }
+        None => net::connect(address),
+    }
+}
+
+pub fn connect(
+    kind: TransportKind,
+    address: &str,
+    proxy: Option<&Proxy>,
+) -> io::Result<Box<dyn Transport>> {
+    match kind {
+        TransportKind::Tcp => Ok(Box::new(tcp_connect(address, proxy)?)),
+        TransportKind::Ws => Ok(Box::new(ws::connect(
+            address,
+            tcp_connect(address, proxy)?,
+        )?)),
+        TransportKind::Quic if proxy.is_some() || onion::is_onion(address) => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "QUIC runs over UDP and can't go through a SOCKS5 proxy",
+        )),
+        TransportKind::Quic => {
+            let mut last_error = None;
+            for addr in address.to_socket_addrs()? {
+                match quic::connect(addr) {
+                    Ok(stream) => return Ok(Box::new(stream)),
+                    Err(e) => {
+                        say!(" {} failed: {}", addr, e);
+                        last_error = Some(e);
+                    }
+                }
+            }
+            Err(last_error.unwrap_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} did not resolve to any address", address),
+                )
+            }))
+        }
+    }
+}
@@ -0,0 +1,441 @@
+//! Terminal UI for the chat client (ratatui): a scrollable message pane, a
+//! persistent input line and a status bar. Output from any thread goes
+//! through `say!`, which feeds the message pane while the TUI runs and
+//! falls back to plain `println!` otherwise (server console, pipes,
+//! `--plain`), so incoming messages never garble what is being typed.
+
+use crate::notify;
+use ratatui::crossterm::event::{
+    self, DisableFocusChange, EnableFocusChange, Event, KeyCode, KeyEventKind, KeyModifiers,
+};
+use ratatui::crossterm::execute;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::cell::RefCell;
+use std::io::IsTerminal;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Messages kept in the pane; older ones are dropped.
+const HISTORY_LIMIT: usize = 5000;
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+enum Update {
+    Line(String),
+    /// A line that can be replaced later by `Amend` with the same key.
+    Keyed(u64, String),
+    Amend {
+        key: u64,
+        text: String,
+        struck: bool,
+    },
+    Peer(String),
+    Fingerprint(String),
+    State(String),
+    Clear,
+    Quit,
+}
+
+/// Where `say!` sends lines while the TUI is running.
+static SINK: Mutex<Option<Sender<Update>>> = Mutex::new(None);
+
+fn send(update: Update) -> bool {
+    match &*SINK.lock().unwrap() {
+        Some(sink) => sink.send(update).is_ok(),
+        None => false,
+    }
+}
+
+/// `--pipe`: stdout only carries received messages, as JSON lines.
+static PIPE: AtomicBool = AtomicBool::new(false);
+/// Whether lines are tagged with the session they come from, once a client
+/// has several.
+static TAG_SOURCES: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// The session the current thread speaks for.
+    static SOURCE: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+pub fn active() -> bool {
+    SINK.lock().unwrap().is_some()
+}
+
+pub fn set_pipe() {
+    PIPE.store(true, Ordering::Relaxed);
+}
+
+pub fn piped() -> bool {
+    PIPE.load(Ordering::Relaxed)
+}
+
+pub fn tag_sources(on: bool) {
+    TAG_SOURCES.store(on, Ordering::Relaxed);
+}
+
+/// The session (peer name) the current thread speaks for.
+pub fn source() -> Option<String> {
+    SOURCE.with_borrow(Clone::clone)
+}
+
+/// Until it is dropped, what this thread shows comes from `source`.
+pub fn source_scope(source: &str) -> SourceScope {
+    SourceScope {
+        previous: SOURCE.replace(Some(source.to_string())),
+    }
+}
+
+pub struct SourceScope {
+    previous: Option<String>,
+}
+
+impl Drop for SourceScope {
+    fn drop(&mut self) {
+        SOURCE.set(self.previous.take());
+    }
+}
+
+/// `text` with `@SOURCE` in front, after the blank lines it starts with.
+fn tagged(text: String) -> String {
+    let Some(source) = source().filter(|_| TAG_SOURCES.load(Ordering::Relaxed)) else {
+        return text;
+    };
+    let body = text.trim_start_matches('\n');
+    let blank = &text[..text.len() - body.len()];
+    format!("{}@{} {}", blank, source, body.trim_start())
+}
+
+/// Shows `text` in the message pane, or prints it when there is no TUI
+/// (to stderr with `--pipe`).
+pub fn show(text: String) {
+    let text = tagged(text);
+    let trimmed = text.trim_matches('\n');
+    if piped() {
+        eprintln!("{}", text);
+        return;
+    }
+    if !active() {
+        println!("{}", text);
+        return;
+    }
+    for line in trimmed.lines() {
+        send(Update::Line(line.to_string()));
+    }
+}
+
+/// Like `show`, for a line `amend` can change later (a chat message,
+/// keyed by its id).
+pub fn show_keyed(key: u64, text: String) {
+    if !active() || piped() {
+        show(text);
+        return;
+    }
+    let text = tagged(text);
+    send(Update::Keyed(key, text.trim_matches('\n').to_string()));
+}
+
+/// Replaces the line shown with `key` by `text`, struck through if
+/// `struck`. Printed lines can't be changed, so without the TUI `notice`
+/// is printed instead.
+pub fn amend(key: u64, text: String, struck: bool, notice: String) {
+    if !active() || piped() {
+        show(notice);
+        return;
+    }
+    let text = tagged(text);
+    send(Update::Amend {
+        key,
+        text: text.trim_matches('\n').to_string(),
+        struck,
+    });
+}
+
+/// `text` struck through, on a terminal.
+pub fn strike(text: &str) -> String {
+    if piped() || !std::io::stdout().is_terminal() {
+        return text.to_string();
+    }
+    format!("\x1b[9m{}\x1b[29m", text)
+}
+
+macro_rules! say {
+    ($($arg:tt)*) => {
+        $crate::ui::show(format!($($arg)*))
+    };
+}
+pub(crate) use say;
+
+pub fn set_peer(peer: impl Into<String>) {
+    send(Update::Peer(peer.into()));
+}
+
+pub fn set_fingerprint(fingerprint: impl Into<String>) {
+    send(Update::Fingerprint(fingerprint.into()));
+}
+
+pub fn set_state(state: impl Into<String>) {
+    send(Update::State(state.into()));
+}
+
+/// Empties the message pane, or the terminal when there is no TUI.
+pub fn clear() {
+    if !send(Update::Clear) && !piped() {
+        print!("\x1b[2J\x1b[H");
+    }
+}
+
+/// The connection is gone. The TUI keeps the last messages on screen
+/// until the user quits.
+pub fn disconnected() {
+    set_state("disconnected, press Esc to quit");
+}
+
+/// Running TUI. Dropping it closes the UI and restores the terminal.
+pub struct Tui {
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        send(Update::Quit);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        *SINK.lock().unwrap() = None;
+    }
+}
+
+/// Takes over the terminal. Returns the UI handle and the lines the user
+/// submits; the receiver ends when the user quits (Esc or Ctrl-C).
+pub fn start() -> (Tui, Receiver<String>) {
+    let (updates, update_receiver) = mpsc::channel();
+    let (input, input_receiver) = mpsc::channel();
+    *SINK.lock().unwrap() = Some(updates);
+
+    let thread = thread::spawn(move || {
+        let mut terminal = ratatui::init();
+        // Terminals without focus reports ignore this.
+        let _ = execute!(std::io::stdout(), EnableFocusChange);
+        let result = App::default().run(&mut terminal, &update_receiver, &input);
+        let _ = execute!(std::io::stdout(), DisableFocusChange);
+        ratatui::restore();
+        *SINK.lock().unwrap() = None;
+        if let Err(e) = result {
+            eprintln!("UI error: {}", e);
+        }
+    });
+    (
+        Tui {
+            thread: Some(thread),
+        },
+        input_receiver,
+    )
+}
+
+/// A message in the pane, possibly several lines.
+struct Entry {
+    key: Option<u64>,
+    text: String,
+    struck: bool,
+}
+
+#[derive(Default)]
+struct App {
+    messages: Vec<Entry>,
+    /// Lines scrolled up from the bottom of the pane.
+    scroll: usize,
+    input: String,
+    /// Cursor position in `input`, in characters.
+    cursor: usize,
+    peer: String,
+    fingerprint: String,
+    state: String,
+}
+
+impl App {
+    fn run(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        updates: &Receiver<Update>,
+        input: &Sender<String>,
+    ) -> std::io::Result<()> {
+        loop {
+            while let Ok(update) = updates.try_recv() {
+                match update {
+                    Update::Line(line) => self.push(None, line, false),
+                    Update::Keyed(key, text) => self.push(Some(key), text, false),
+                    Update::Amend { key, text, struck } => {
+                        match self
+                            .messages
+                            .iter_mut()
+                            .rev()
+                            .find(|entry| entry.key == Some(key))
+                        {
+                            Some(entry) => {
+                                entry.text = text;
+                                entry.struck = struck;
+                            }
+                            None => self.push(Some(key), text, struck),
+                        }
+                    }
+                    Update::Peer(peer) => self.peer = peer,
+                    Update::Fingerprint(fingerprint) => self.fingerprint = fingerprint,
+                    Update::State(state) => self.state = state,
+                    Update::Clear => {
+                        self.messages.clear();
+                        self.scroll = 0;
+                    }
+                    Update::Quit => return Ok(()),
+                }
+            }
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if !event::poll(POLL_INTERVAL)? {
+                continue;
+            }
+            let key = match event::read()? {
+                Event::Key(key) => key,
+                Event::FocusGained => {
+                    notify::set_focused(true);
+                    continue;
+                }
+                Event::FocusLost => {
+                    notify::set_focused(false);
+                    continue;
+                }
+                _ => continue,
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let page = terminal.size()?.height.saturating_sub(4).max(1) as usize;
+            match key.code {
+                KeyCode::Esc => return Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(());
+                }
+                KeyCode::Enter => {
+                    let line = std::mem::take(&mut self.input);
+                    self.cursor = 0;
+                    self.scroll = 0;
+                    if input.send(line).is_err() {
+                        return Ok(());
+                    }
+                }
+                KeyCode::Char(c) => {
+                    let at = self.byte_index();
+                    self.input.insert(at, c);
+                    self.cursor += 1;
+                }
+                KeyCode::Backspace if self.cursor > 0 => {
+                    self.cursor -= 1;
+                    let at = self.byte_index();
+                    self.input.remove(at);
+                }
+                KeyCode::Delete if self.cursor < self.input.chars().count() => {
+                    let at = self.byte_index();
+                    self.input.remove(at);
+                }
+                KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+                KeyCode::Right => self.cursor = (self.cursor + 1).min(self.input.chars().count()),
+                KeyCode::Home => self.cursor = 0,
+                KeyCode::End => self.cursor = self.input.chars().count(),
+                KeyCode::Up => self.scroll_up(1),
+                KeyCode::Down => self.scroll = self.scroll.saturating_sub(1),
+                KeyCode::PageUp => self.scroll_up(page),
+                KeyCode::PageDown => self.scroll = self.scroll.saturating_sub(page),
+                _ => {}
+            }
+        }
+    }
+
+    fn push(&mut self, key: Option<u64>, text: String, struck: bool) {
+        self.messages.push(Entry { key, text, struck });
+        if self.messages.len() > HISTORY_LIMIT {
+            self.messages.remove(0);
+        }
+        if self.scroll > 0 {
+            self.scroll += 1;
+        }
+    }
+
+    fn byte_index(&self) -> usize {
+        self.input
+            .char_indices()
+            .nth(self.cursor)
+            .map_or(self.input.len(), |(i, _)| i)
+    }
+
+    fn scroll_up(&mut self, lines: usize) {
+        self.scroll = (self.scroll + lines).min(self.messages.len().saturating_sub(1));
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [messages_area, input_area, status_area] = Layout::vertical([
+            Constraint::Min(1),
+            Constraint::Length(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let height = messages_area.height as usize;
+        let end = self.messages.len().saturating_sub(self.scroll);
+        let start = end.saturating_sub(height);
+        let lines: Vec<Line> = self.messages[start..end]
+            .iter()
+            .flat_map(|entry| {
+                let style = if entry.struck {
+                    Style::default().add_modifier(Modifier::CROSSED_OUT)
+                } else {
+                    Style::default()
+                };
+                entry
+                    .text
+                    .lines()
+                    .map(move |line| Line::styled(line, style))
+            })
+            .collect();
+        // A multi-line entry may push the top ones out; keep the bottom.
+        let skip = lines.len().saturating_sub(height);
+        frame.render_widget(
+            Paragraph::new(lines).scroll((skip as u16, 0)),
+            messages_area,
+        );
+
+        let title = if self.scroll > 0 {
+            format!(" Message (scrolled up {} lines) ", self.scroll)
+        } else {
+            " Message ".to_string()
+        };
+        frame.render_widget(
+            Paragraph::new(self.input.as_str()).block(Block::bordered().title(title)),
+            input_area,
+        );
+        frame.set_cursor_position((input_area.x + 1 + self.cursor as u16, input_area.y + 1));
+
+        let status = format!(
+            " {} │ peer id: {} │ {} │ Esc quits, PgUp/PgDn scroll",
+            if self.peer.is_empty() {
+                "-"
+            } else {
+                &self.peer
+            },
+            if self.fingerprint.is_empty() {
+                "-"
+            } else {
+                &self.fingerprint
+            },
+            self.state
+        );
+        frame.render_widget(
+            Paragraph::new(status).style(Style::default().add_modifier(Modifier::REVERSED)),
+            status_area,
+        );
+    }
+}
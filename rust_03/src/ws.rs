@@ -0,0 +1,177 @@
+//! WebSocket transport (tungstenite), so a browser page can join the chat.
+//! The streamchat framing is unchanged: every flushed write goes out as one
+//! binary WebSocket message, so a peer receives the handshake keys, then
+//! one `header || ciphertext` message per chat frame.
+
+use crate::is_timeout;
+use crate::transport::{Listener, Transport};
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tungstenite::{Message, WebSocket};
+
+/// tungstenite's socket can't be split, so the reader polls with this
+/// timeout and releases the lock in between to let writers through.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Time allowed for the HTTP upgrade before the acceptor gives up on a
+/// client.
+const UPGRADE_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Shared {
+    socket: WebSocket<TcpStream>,
+    /// Bytes of the last received message not read yet.
+    pending: Vec<u8>,
+}
+
+pub struct WsStream {
+    shared: Arc<Mutex<Shared>>,
+    peer: SocketAddr,
+    read_timeout: Arc<Mutex<Option<Duration>>>,
+    /// Written bytes waiting for `flush`, which sends them as one message.
+    outgoing: Vec<u8>,
+}
+
+impl WsStream {
+    fn new(socket: WebSocket<TcpStream>) -> io::Result<Self> {
+        let stream = socket.get_ref();
+        stream.set_read_timeout(Some(POLL_INTERVAL))?;
+        let peer = stream.peer_addr()?;
+        Ok(WsStream {
+            shared: Arc::new(Mutex::new(Shared {
+                socket,
+                pending: Vec::new(),
+            })),
+            peer,
+            read_timeout: Arc::new(Mutex::new(None)),
+            outgoing: Vec::new(),
+        })
+    }
+}
+
+impl Read for WsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let deadline = self
+            .read_timeout
+            .lock()
+            .unwrap()
+            .map(|timeout| Instant::now() + timeout);
+        loop {
+            {
+                let mut shared = self.shared.lock().unwrap();
+                if !shared.pending.is_empty() {
+                    let n = buf.len().min(shared.pending.len());
+                    buf[..n].copy_from_slice(&shared.pending[..n]);
+                    shared.pending.drain(..n);
+                    return Ok(n);
+                }
+                match shared.socket.read() {
+                    Ok(Message::Binary(data)) => {
+                        shared.pending.extend_from_slice(&data);
+                        continue;
+                    }
+                    Ok(Message::Text(_)) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "expected binary WebSocket messages",
+                        ));
+                    }
+                    Ok(Message::Close(_))
+                    | Err(tungstenite::Error::ConnectionClosed)
+                    | Err(tungstenite::Error::AlreadyClosed) => return Ok(0),
+                    // Ping, pong and raw frames; tungstenite answers pings.
+                    Ok(_) => continue,
+                    Err(tungstenite::Error::Io(e)) if is_timeout(&e) => {}
+                    Err(tungstenite::Error::Io(e)) => return Err(e),
+                    Err(e) => return Err(io::Error::other(e)),
+                }
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(io::Error::from(io::ErrorKind::TimedOut));
+            }
+        }
+    }
+}
+
+impl Write for WsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.outgoing.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.outgoing.is_empty() {
+            return Ok(());
+        }
+        let message = Message::binary(std::mem::take(&mut self.outgoing));
+        match self.shared.lock().unwrap().socket.send(message) {
+            Ok(()) => Ok(()),
+            Err(tungstenite::Error::Io(e)) => Err(e),
+            Err(e) => Err(io::Error::other(e)),
+        }
+    }
+}
+
+impl Transport for WsStream {
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.peer)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        *self.read_timeout.lock().unwrap() = timeout;
+        Ok(())
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(WsStream {
+            shared: Arc::clone(&self.shared),
+            peer: self.peer,
+            read_timeout: Arc::clone(&self.read_timeout),
+            outgoing: Vec::new(),
+        }))
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        self.shared
+            .lock()
+            .unwrap()
+            .socket
+            .get_ref()
+            .shutdown(Shutdown::Both)
+    }
+}
+
+/// Accepts WebSocket upgrades on a plain TCP listener (any path).
+pub struct WsListener {
+    listener: TcpListener,
+}
+
+impl WsListener {
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        Ok(WsListener {
+            listener: TcpListener::bind(addr)?,
+        })
+    }
+}
+
+impl Listener for WsListener {
+    fn accept(&self) -> io::Result<Box<dyn Transport>> {
+        let (stream, _) = self.listener.accept()?;
+        stream.set_read_timeout(Some(UPGRADE_TIMEOUT))?;
+        let socket = tungstenite::accept(stream).map_err(io::Error::other)?;
+        Ok(Box::new(WsStream::new(socket)?))
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+}
+
+/// Upgrades `stream`, already connected to `address`, to a WebSocket at
+/// `ws://address/`.
+pub fn connect(address: &str, stream: TcpStream) -> io::Result<WsStream> {
+    let (socket, _) = tungstenite::client(format!("ws://{}/", address), stream)
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    WsStream::new(socket)
+}
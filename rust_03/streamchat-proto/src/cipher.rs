@@ -0,0 +1,160 @@
+use crate::padding::Padding;
+use crate::{HEADER_LEN, TAG_LEN};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+/// Sliding window over the last 64 sequence numbers (as in IPsec and
+/// DTLS): anything already seen or older than the window is a replay.
+#[derive(Default)]
+struct ReplayWindow {
+    highest: Option<u64>,
+    /// Bit `i` set means `highest - i` was accepted.
+    seen: u64,
+}
+
+impl ReplayWindow {
+    fn accept(&mut self, seq: u64) -> bool {
+        match self.highest {
+            Some(highest) if seq <= highest => {
+                let offset = highest - seq;
+                if offset >= 64 || self.seen & (1 << offset) != 0 {
+                    return false;
+                }
+                self.seen |= 1 << offset;
+            }
+            Some(highest) => {
+                let shift = seq - highest;
+                self.seen = if shift >= 64 { 0 } else { self.seen << shift };
+                self.seen |= 1;
+                self.highest = Some(seq);
+            }
+            None => {
+                self.seen = 1;
+                self.highest = Some(seq);
+            }
+        }
+        true
+    }
+}
+
+/// Why a received record was not returned by [`SessionCipher::open`].
+pub enum OpenError {
+    Tampered,
+    Replayed(u64),
+}
+
+/// One direction of one stream of the session: ChaCha20-Poly1305 under
+/// that direction's key, with the 96-bit nonce built from the stream number
+/// and the record's sequence number. Each stream counts its own sequence
+/// numbers, and a record moved to another stream fails authentication.
+///
+/// The Poly1305 tag is the per-message MAC: it covers the body and the
+/// record header (including the sequence number), and `open` refuses to
+/// return anything for a record that was modified in transit, so no
+/// separate HMAC is layered on top.
+pub struct SessionCipher {
+    aead: ChaCha20Poly1305,
+    pub padding: Padding,
+    stream: u32,
+    next_sequence: u64,
+    window: ReplayWindow,
+}
+
+impl SessionCipher {
+    pub fn new(key: &[u8; 32], padding: Padding, stream: u32) -> Self {
+        SessionCipher {
+            aead: ChaCha20Poly1305::new(Key::from_slice(key)),
+            padding,
+            stream,
+            next_sequence: 0,
+            window: ReplayWindow::default(),
+        }
+    }
+
+    fn nonce(&self, sequence: u64) -> Nonce {
+        let mut nonce = [0u8; 12];
+        nonce[..4].copy_from_slice(&self.stream.to_be_bytes());
+        nonce[4..].copy_from_slice(&sequence.to_be_bytes());
+        *Nonce::from_slice(&nonce)
+    }
+
+    /// Encrypts `body`, already padded, under the next sequence number and
+    /// returns the record header together with the ciphertext.
+    pub fn seal(&mut self, body: &[u8]) -> ([u8; HEADER_LEN], Vec<u8>) {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+
+        let header = header(sequence, body.len() + TAG_LEN);
+        let ciphertext = self
+            .aead
+            .encrypt(
+                &self.nonce(sequence),
+                Payload {
+                    msg: body,
+                    aad: &header,
+                },
+            )
+            .expect("ChaCha20-Poly1305 encryption cannot fail for in-memory buffers");
+        (header, ciphertext)
+    }
+
+    /// Authenticates and decrypts a record, then rejects it if its sequence
+    /// number was already used. The replay check runs only on authentic
+    /// records so forged numbers can't poison the window.
+    pub fn open(
+        &mut self,
+        header: &[u8; HEADER_LEN],
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, OpenError> {
+        let sequence = sequence(header);
+        let body = self
+            .aead
+            .decrypt(
+                &self.nonce(sequence),
+                Payload {
+                    msg: ciphertext,
+                    aad: header,
+                },
+            )
+            .map_err(|_| OpenError::Tampered)?;
+        if !self.window.accept(sequence) {
+            return Err(OpenError::Replayed(sequence));
+        }
+        Ok(body)
+    }
+}
+
+fn header(sequence: u64, length: usize) -> [u8; HEADER_LEN] {
+    let mut header = [0u8; HEADER_LEN];
+    header[..8].copy_from_slice(&sequence.to_be_bytes());
+    header[8..].copy_from_slice(&(length as u32).to_be_bytes());
+    header
+}
+
+pub fn sequence(header: &[u8; HEADER_LEN]) -> u64 {
+    u64::from_be_bytes(header[..8].try_into().unwrap())
+}
+
+pub fn length(header: &[u8; HEADER_LEN]) -> usize {
+    u32::from_be_bytes(header[8..].try_into().unwrap()) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay_window_remembers_the_last_64() {
+        let mut window = ReplayWindow::default();
+        assert!(window.accept(5));
+        assert!(window.accept(3), "older but unseen");
+        assert!(!window.accept(3));
+        assert!(!window.accept(5));
+        assert!(window.accept(100));
+        assert!(!window.accept(36), "64 behind the highest");
+        assert!(window.accept(37));
+        assert!(!window.accept(37));
+        assert!(window.accept(1000), "far ahead");
+        assert!(!window.accept(100));
+    }
+}
@@ -0,0 +1,454 @@
+//! The record layer, sans I/O. On the wire every frame is a record:
+//!
+//! ```text
+//! sequence u64 BE | ciphertext length u32 BE | ciphertext (padded frame + tag)
+//! ```
+//!
+//! the header in clear but authenticated as associated data. An `Encoder`
+//! seals frames into records for the caller to write; a `Decoder` is fed
+//! whatever bytes the caller read, in pieces of any size, and hands back
+//! decrypted frames in sequence order.
+//!
+//! A connection may carry more than one stream of records (QUIC gives file
+//! data a stream of its own). Each stream has its own `Encoder` and
+//! `Decoder`, made with `on_stream`: its own sequence numbers and replay
+//! window, and its number in the nonce, so a record can't be moved from one
+//! stream to another. `new` is stream 0.
+
+use crate::cipher::{self, OpenError, SessionCipher};
+use crate::padding::Padding;
+use crate::{HEADER_LEN, MAX_FRAME_LEN, MAX_PLAINTEXT, TAG_LEN};
+use std::collections::BTreeMap;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Records held back waiting for a missing earlier one, and for how long,
+/// before the gap is given up on.
+const REORDER_DEPTH: usize = 16;
+const REORDER_DELAY: Duration = Duration::from_millis(500);
+
+/// A frame sealed for sending.
+pub struct Sealed {
+    pub sequence: u64,
+    /// Header and ciphertext, to be written as they are.
+    pub bytes: Vec<u8>,
+}
+
+impl Sealed {
+    pub fn ciphertext(&self) -> &[u8] {
+        &self.bytes[HEADER_LEN..]
+    }
+}
+
+/// Sending half of a session.
+pub struct Encoder {
+    cipher: SessionCipher,
+}
+
+impl Encoder {
+    pub fn new(key: &[u8; 32], padding: Padding) -> Self {
+        Self::on_stream(key, padding, 0)
+    }
+
+    pub fn on_stream(key: &[u8; 32], padding: Padding, stream: u32) -> Self {
+        Encoder {
+            cipher: SessionCipher::new(key, padding, stream),
+        }
+    }
+
+    pub fn padding(&self) -> Padding {
+        self.cipher.padding
+    }
+
+    /// Pads and encrypts `frame` under the next sequence number. A frame
+    /// too big for one record is an `InvalidInput` error, and uses up no
+    /// sequence number.
+    pub fn encode(&mut self, frame: &[u8]) -> io::Result<Sealed> {
+        let body = self.cipher.padding.pad(frame.to_vec());
+        if body.len() > MAX_PLAINTEXT {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "message too long ({} bytes, max {})",
+                    frame.len(),
+                    MAX_PLAINTEXT
+                ),
+            ));
+        }
+        let (header, ciphertext) = self.cipher.seal(&body);
+        let mut bytes = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+        bytes.extend_from_slice(&header);
+        bytes.extend_from_slice(&ciphertext);
+        Ok(Sealed {
+            sequence: cipher::sequence(&header),
+            bytes,
+        })
+    }
+}
+
+/// What a `Decoder` hands back.
+pub enum Record {
+    /// An authentic frame, unpadded.
+    Frame {
+        sequence: u64,
+        frame: Vec<u8>,
+        ciphertext: Vec<u8>,
+    },
+    /// The record failed authentication and was dropped.
+    Tampered,
+    /// An authentic record whose sequence number was already seen.
+    Replayed(u64),
+    /// This many records before the next one never arrived.
+    Gap(u64),
+    /// An authentic record whose padding does not hold together.
+    Malformed,
+}
+
+/// Receiving half of a session. Decrypts records and hands them out in
+/// sequence order: a record that arrives ahead of a missing one is held
+/// until the gap fills, for at most `REORDER_DEPTH` records or
+/// `REORDER_DELAY`, after which the gap is reported and skipped. The delay
+/// is only checked when `poll` is called. A record older than the skipped
+/// gap is still delivered (the replay window vouches it is not a
+/// duplicate), late.
+pub struct Decoder {
+    cipher: SessionCipher,
+    /// Bytes fed but not yet part of a complete record.
+    buffer: Vec<u8>,
+    next_sequence: Option<u64>,
+    held: BTreeMap<u64, Record>,
+    gap_since: Option<Instant>,
+}
+
+impl Decoder {
+    pub fn new(key: &[u8; 32], padding: Padding) -> Self {
+        Self::on_stream(key, padding, 0)
+    }
+
+    pub fn on_stream(key: &[u8; 32], padding: Padding, stream: u32) -> Self {
+        Decoder {
+            cipher: SessionCipher::new(key, padding, stream),
+            buffer: Vec::new(),
+            next_sequence: None,
+            held: BTreeMap::new(),
+            gap_since: None,
+        }
+    }
+
+    /// Adds bytes read from the peer.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// The next record to hand out, or `None` until more bytes are fed.
+    /// An `Err` means the stream can't be decoded any further.
+    pub fn poll(&mut self, now: Instant) -> io::Result<Option<Record>> {
+        loop {
+            if let Some(record) = self.pop_ready(now) {
+                return Ok(Some(record));
+            }
+            let Some((sequence, record)) = self.open_next()? else {
+                return Ok(None);
+            };
+            let Some(sequence) = sequence else {
+                return Ok(Some(record));
+            };
+            let next = *self.next_sequence.get_or_insert(sequence);
+            if sequence < next {
+                return Ok(Some(record));
+            }
+            self.held.insert(sequence, record);
+            if sequence > next && self.gap_since.is_none() {
+                self.gap_since = Some(now);
+            }
+        }
+    }
+
+    /// Takes one complete record off the buffer and opens it, returning
+    /// its sequence number when it is authentic.
+    fn open_next(&mut self) -> io::Result<Option<(Option<u64>, Record)>> {
+        let Some(header) = self.buffer.get(..HEADER_LEN) else {
+            return Ok(None);
+        };
+        let header: [u8; HEADER_LEN] = header.try_into().unwrap();
+        let length = cipher::length(&header);
+        if !(TAG_LEN..=MAX_FRAME_LEN).contains(&length) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("frame length {} out of range", length),
+            ));
+        }
+        if self.buffer.len() < HEADER_LEN + length {
+            return Ok(None);
+        }
+        let ciphertext = self.buffer[HEADER_LEN..HEADER_LEN + length].to_vec();
+        self.buffer.drain(..HEADER_LEN + length);
+
+        let body = match self.cipher.open(&header, &ciphertext) {
+            Ok(body) => body,
+            Err(OpenError::Tampered) => return Ok(Some((None, Record::Tampered))),
+            Err(OpenError::Replayed(sequence)) => {
+                return Ok(Some((None, Record::Replayed(sequence))));
+            }
+        };
+        let sequence = cipher::sequence(&header);
+        let record = match self.cipher.padding.unpad(body) {
+            Some(frame) => Record::Frame {
+                sequence,
+                frame,
+                ciphertext,
+            },
+            None => Record::Malformed,
+        };
+        Ok(Some((Some(sequence), record)))
+    }
+
+    fn pop_ready(&mut self, now: Instant) -> Option<Record> {
+        let next = self.next_sequence?;
+        let (&first, _) = self.held.first_key_value()?;
+        let give_up = self.held.len() > REORDER_DEPTH
+            || self
+                .gap_since
+                .is_some_and(|since| now.duration_since(since) >= REORDER_DELAY);
+        if first > next && !give_up {
+            return None;
+        }
+        if first > next {
+            self.next_sequence = Some(first);
+            self.gap_since = None;
+            return Some(Record::Gap(first - next));
+        }
+        self.next_sequence = Some(first + 1);
+        let record = self.held.remove(&first);
+        self.gap_since = match self.held.keys().next() {
+            Some(&waiting) if waiting > first + 1 => self.gap_since.or(Some(now)),
+            _ => None,
+        };
+        record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const KEY: [u8; 32] = [7; 32];
+
+    /// Frames 0 to `count - 1`, each holding its own number.
+    fn sealed(count: u64) -> Vec<Sealed> {
+        let mut encoder = Encoder::new(&KEY, Padding::Bucket);
+        (0..count)
+            .map(|n| encoder.encode(&n.to_be_bytes()).unwrap())
+            .collect()
+    }
+
+    /// What `records` say, in short: the number in each frame, or the
+    /// other kinds of record.
+    fn summary(records: &[Record]) -> Vec<String> {
+        records
+            .iter()
+            .map(|record| match record {
+                Record::Frame { frame, .. } => {
+                    u64::from_be_bytes(frame[..].try_into().unwrap()).to_string()
+                }
+                Record::Gap(missing) => format!("gap {}", missing),
+                Record::Replayed(sequence) => format!("replayed {}", sequence),
+                Record::Tampered => "tampered".to_string(),
+                Record::Malformed => "malformed".to_string(),
+            })
+            .collect()
+    }
+
+    /// Everything `decoder` hands out for `bytes`, at `now`.
+    fn decode(decoder: &mut Decoder, bytes: &[u8], now: Instant) -> Vec<Record> {
+        decoder.feed(bytes);
+        let mut records = Vec::new();
+        while let Some(record) = decoder.poll(now).unwrap() {
+            records.push(record);
+        }
+        records
+    }
+
+    fn frame(record: &Record) -> Option<(u64, &[u8])> {
+        match record {
+            Record::Frame {
+                sequence, frame, ..
+            } => Some((*sequence, frame)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn streams_keep_their_records_apart() {
+        let now = Instant::now();
+        let mut chat = Encoder::new(&KEY, Padding::Bucket);
+        let mut bulk = Encoder::on_stream(&KEY, Padding::Bucket, 1);
+        let message = chat.encode(b"hello").unwrap();
+        let chunk = bulk.encode(b"chunk").unwrap();
+        // Each stream counts from 0.
+        assert_eq!((message.sequence, chunk.sequence), (0, 0));
+
+        let mut chat_in = Decoder::new(&KEY, Padding::Bucket);
+        let mut bulk_in = Decoder::on_stream(&KEY, Padding::Bucket, 1);
+        let records = decode(&mut bulk_in, &chunk.bytes, now);
+        assert_eq!(
+            records.iter().map(frame).collect::<Vec<_>>(),
+            [Some((0, &b"chunk"[..]))]
+        );
+        let records = decode(&mut chat_in, &message.bytes, now);
+        assert_eq!(
+            records.iter().map(frame).collect::<Vec<_>>(),
+            [Some((0, &b"hello"[..]))]
+        );
+
+        // Moved to the other stream, a record fails authentication.
+        let moved = bulk.encode(b"moved").unwrap();
+        let records = decode(&mut chat_in, &moved.bytes, now);
+        assert!(matches!(records[..], [Record::Tampered]));
+    }
+
+    #[test]
+    fn round_trips_fed_in_pieces() {
+        let mut rng = StdRng::seed_from_u64(1174);
+        for padding in [Padding::Off, Padding::Bucket, Padding::Max] {
+            let mut encoder = Encoder::new(&KEY, padding);
+            let frames: Vec<Vec<u8>> = [0, 1, 3, 251, 252, 253, 4096, 70_000]
+                .iter()
+                .map(|&len| (0..len).map(|_| rng.random()).collect())
+                .collect();
+            let wire: Vec<u8> = frames
+                .iter()
+                .flat_map(|frame| encoder.encode(frame).unwrap().bytes)
+                .collect();
+
+            let mut decoder = Decoder::new(&KEY, padding);
+            let mut received = Vec::new();
+            let mut rest = &wire[..];
+            while !rest.is_empty() {
+                let (piece, after) = rest.split_at(rng.random_range(1..=rest.len().min(5000)));
+                rest = after;
+                received.extend(decode(&mut decoder, piece, Instant::now()));
+            }
+            let received: Vec<_> = received.iter().map(frame).collect();
+            let expected: Vec<_> = frames
+                .iter()
+                .enumerate()
+                .map(|(sequence, frame)| Some((sequence as u64, &frame[..])))
+                .collect();
+            assert_eq!(received, expected, "{:?}", padding);
+        }
+    }
+
+    #[test]
+    fn padding_hides_lengths_on_the_wire() {
+        let mut encoder = Encoder::new(&KEY, Padding::Bucket);
+        let short = encoder.encode(b"y").unwrap();
+        let long = encoder.encode(&[b'x'; 200]).unwrap();
+        assert_eq!(short.bytes.len(), long.bytes.len());
+
+        let mut encoder = Encoder::new(&KEY, Padding::Max);
+        let chunk = encoder.encode(&[0; 60_000]).unwrap();
+        assert_eq!(chunk.ciphertext().len(), 64 * 1024 + TAG_LEN);
+    }
+
+    #[test]
+    fn reordered_records_come_out_in_order() {
+        let records = sealed(4);
+        let mut decoder = Decoder::new(&KEY, Padding::Bucket);
+        let now = Instant::now();
+        let mut received = Vec::new();
+        for n in [0, 2, 3, 1] {
+            received.extend(decode(&mut decoder, &records[n].bytes, now));
+        }
+        assert_eq!(summary(&received), ["0", "1", "2", "3"]);
+    }
+
+    #[test]
+    fn gaps_are_given_up_on_after_a_delay() {
+        let records = sealed(4);
+        let mut decoder = Decoder::new(&KEY, Padding::Bucket);
+        let now = Instant::now();
+        assert_eq!(
+            summary(&decode(&mut decoder, &records[0].bytes, now)),
+            ["0"]
+        );
+        assert!(decode(&mut decoder, &records[2].bytes, now).is_empty());
+        assert!(decode(&mut decoder, &records[3].bytes, now + REORDER_DELAY / 2).is_empty());
+
+        let later = decode(&mut decoder, &[], now + REORDER_DELAY);
+        assert_eq!(summary(&later), ["gap 1", "2", "3"]);
+        // The missing one still counts if it turns up late.
+        let late = decode(&mut decoder, &records[1].bytes, now + REORDER_DELAY);
+        assert_eq!(summary(&late), ["1"]);
+    }
+
+    #[test]
+    fn gaps_are_given_up_on_when_too_much_is_held() {
+        let records = sealed(REORDER_DEPTH as u64 + 3);
+        let mut decoder = Decoder::new(&KEY, Padding::Bucket);
+        let now = Instant::now();
+        let mut received = decode(&mut decoder, &records[0].bytes, now);
+        for record in &records[2..] {
+            received.extend(decode(&mut decoder, &record.bytes, now));
+        }
+        let mut expected = vec!["0".to_string(), "gap 1".to_string()];
+        expected.extend((2..records.len()).map(|n| n.to_string()));
+        assert_eq!(summary(&received), expected);
+    }
+
+    #[test]
+    fn replays_are_rejected() {
+        let records = sealed(3);
+        let mut decoder = Decoder::new(&KEY, Padding::Bucket);
+        let now = Instant::now();
+        let mut received = Vec::new();
+        for n in [0, 1, 0, 2, 1] {
+            received.extend(decode(&mut decoder, &records[n].bytes, now));
+        }
+        assert_eq!(
+            summary(&received),
+            ["0", "1", "replayed 0", "2", "replayed 1"]
+        );
+    }
+
+    #[test]
+    fn tampered_records_are_dropped() {
+        let records = sealed(2);
+        let mut decoder = Decoder::new(&KEY, Padding::Bucket);
+        let now = Instant::now();
+        for byte in [0, HEADER_LEN, records[0].bytes.len() - 1] {
+            let mut tampered = records[0].bytes.clone();
+            tampered[byte] ^= 1;
+            assert_eq!(summary(&decode(&mut decoder, &tampered, now)), ["tampered"]);
+        }
+        // Under another key, as from someone else's session.
+        let mut stranger = Encoder::new(&[8; 32], Padding::Bucket);
+        let forged = stranger.encode(&0u64.to_be_bytes()).unwrap();
+        assert_eq!(
+            summary(&decode(&mut decoder, &forged.bytes, now)),
+            ["tampered"]
+        );
+
+        let received = decode(&mut decoder, &records[0].bytes, now);
+        assert_eq!(summary(&received), ["0"]);
+    }
+
+    #[test]
+    fn impossible_lengths_end_the_stream() {
+        let mut header = [0u8; HEADER_LEN];
+        header[8..].copy_from_slice(&(MAX_FRAME_LEN as u32 + 1).to_be_bytes());
+        let mut decoder = Decoder::new(&KEY, Padding::Bucket);
+        decoder.feed(&header);
+        assert!(decoder.poll(Instant::now()).is_err());
+    }
+
+    #[test]
+    fn frames_too_big_use_no_sequence_number() {
+        let mut encoder = Encoder::new(&KEY, Padding::Off);
+        let error = encoder.encode(&vec![0; MAX_PLAINTEXT + 1]).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(encoder.encode(b"next").unwrap().sequence, 0);
+    }
+}
@@ -0,0 +1,191 @@
+//! Key agreement groups, offered as the cipher suite in the hello. X25519
+//! is the default; the RFC 3526 MODP groups are there for peers that need
+//! finite-field Diffie-Hellman. The 64-bit group of the first versions is
+//! only available with `--insecure-demo`, to show the arithmetic on small
+//! numbers: anyone can compute its discrete logs in seconds.
+
+use num_bigint::BigUint;
+use std::io;
+use x25519_dalek::{EphemeralSecret, PublicKey};
+
+/// RFC 3526 group 14, generator 2.
+const MODP_2048: &str = concat!(
+    "FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74",
+    "020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F1437",
+    "4FE1356D6D51C245E485B576625E7EC6F44C42E9A637ED6B0BFF5CB6F406B7ED",
+    "EE386BFB5A899FA5AE9F24117C4B1FE649286651ECE45B3DC2007CB8A163BF05",
+    "98DA48361C55D39A69163FA8FD24CF5F83655D23DCA3AD961C62F356208552BB",
+    "9ED529077096966D670C354E4ABC9804F1746C08CA18217C32905E462E36CE3B",
+    "E39E772C180E86039B2783A2EC07A28FB5C55DF06F4C52C9DE2BCBF695581718",
+    "3995497CEA956AE515D2261898FA051015728E5A8AACAA68FFFFFFFFFFFFFFFF",
+);
+
+/// RFC 3526 group 15, generator 2.
+const MODP_3072: &str = concat!(
+    "FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74",
+    "020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F1437",
+    "4FE1356D6D51C245E485B576625E7EC6F44C42E9A637ED6B0BFF5CB6F406B7ED",
+    "EE386BFB5A899FA5AE9F24117C4B1FE649286651ECE45B3DC2007CB8A163BF05",
+    "98DA48361C55D39A69163FA8FD24CF5F83655D23DCA3AD961C62F356208552BB",
+    "9ED529077096966D670C354E4ABC9804F1746C08CA18217C32905E462E36CE3B",
+    "E39E772C180E86039B2783A2EC07A28FB5C55DF06F4C52C9DE2BCBF695581718",
+    "3995497CEA956AE515D2261898FA051015728E5A8AAAC42DAD33170D04507A33",
+    "A85521ABDF1CBA64ECFB850458DBEF0A8AEA71575D060C7DB3970F85A6E1E4C7",
+    "ABF5AE8CDB0933D71E8C94E04A25619DCEE3D2261AD2EE6BF12FFA06D98A0864",
+    "D87602733EC86A64521F2B18177B200CBBE117577A615D6C770988C0BAD946E2",
+    "08E24FA074E5AB3143DB5BFCE0FD108E4B82D120A93AD2CAFFFFFFFFFFFFFFFF",
+);
+
+const DEMO_64: &str = "D87FA3E291B4C7F3";
+
+/// Bytes of randomness in a MODP private exponent, twice the groups'
+/// security level with some margin.
+const EXPONENT_LEN: usize = 40;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum Group {
+    X25519,
+    Modp2048,
+    Modp3072,
+    /// Selected with `--insecure-demo`, never with `--group`.
+    #[cfg_attr(feature = "clap", value(skip))]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Demo64,
+}
+
+impl Group {
+    const ALL: [Group; 4] = [
+        Group::X25519,
+        Group::Modp2048,
+        Group::Modp3072,
+        Group::Demo64,
+    ];
+
+    /// Suite id in the hello; each suite pairs the group with HKDF-SHA256
+    /// and ChaCha20-Poly1305.
+    pub fn suite(self) -> u8 {
+        match self {
+            Group::X25519 => 1,
+            Group::Modp2048 => 2,
+            Group::Modp3072 => 3,
+            Group::Demo64 => 0x7f,
+        }
+    }
+
+    pub fn from_suite(suite: u8) -> Option<Self> {
+        Group::ALL.into_iter().find(|group| group.suite() == suite)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Group::X25519 => "X25519",
+            Group::Modp2048 => "2048-bit MODP",
+            Group::Modp3072 => "3072-bit MODP",
+            Group::Demo64 => "64-bit demo DH (insecure)",
+        }
+    }
+
+    /// Bytes in a public key and in the shared secret.
+    pub fn key_len(self) -> usize {
+        match self {
+            Group::X25519 => 32,
+            Group::Modp2048 => 256,
+            Group::Modp3072 => 384,
+            Group::Demo64 => 8,
+        }
+    }
+
+    fn modulus(self) -> Option<BigUint> {
+        let hex = match self {
+            Group::X25519 => return None,
+            Group::Modp2048 => MODP_2048,
+            Group::Modp3072 => MODP_3072,
+            Group::Demo64 => DEMO_64,
+        };
+        Some(BigUint::parse_bytes(hex.as_bytes(), 16).expect("the group moduli are valid hex"))
+    }
+
+    pub fn generate(self) -> Ephemeral {
+        match self.modulus() {
+            None => Ephemeral::X25519(EphemeralSecret::random()),
+            Some(modulus) => {
+                let exponent = match self {
+                    Group::Demo64 => BigUint::from(rand::random::<u64>()),
+                    _ => BigUint::from_bytes_be(&rand::random::<[u8; EXPONENT_LEN]>()),
+                };
+                Ephemeral::FiniteField {
+                    group: self,
+                    modulus,
+                    exponent,
+                }
+            }
+        }
+    }
+}
+
+/// Our half of one key agreement.
+pub enum Ephemeral {
+    X25519(EphemeralSecret),
+    FiniteField {
+        group: Group,
+        modulus: BigUint,
+        exponent: BigUint,
+    },
+}
+
+/// Big-endian, left-padded to `len` bytes.
+fn to_fixed_bytes(value: &BigUint, len: usize) -> Vec<u8> {
+    let bytes = value.to_bytes_be();
+    let mut padded = vec![0u8; len - bytes.len()];
+    padded.extend_from_slice(&bytes);
+    padded
+}
+
+impl Ephemeral {
+    pub fn public_key(&self) -> Vec<u8> {
+        match self {
+            Ephemeral::X25519(secret) => PublicKey::from(secret).as_bytes().to_vec(),
+            Ephemeral::FiniteField {
+                group,
+                modulus,
+                exponent,
+            } => {
+                let public = BigUint::from(2u32).modpow(exponent, modulus);
+                to_fixed_bytes(&public, group.key_len())
+            }
+        }
+    }
+
+    /// Computes the shared secret from the peer's public key, which is
+    /// `key_len` bytes long.
+    pub fn agree(self, peer: &[u8]) -> io::Result<Vec<u8>> {
+        let weak = || io::Error::new(io::ErrorKind::InvalidData, "peer sent a weak public key");
+        match self {
+            Ephemeral::X25519(secret) => {
+                let peer: [u8; 32] = peer.try_into().map_err(|_| weak())?;
+                let shared_secret = secret.diffie_hellman(&PublicKey::from(peer));
+                if !shared_secret.was_contributory() {
+                    return Err(weak());
+                }
+                Ok(shared_secret.as_bytes().to_vec())
+            }
+            Ephemeral::FiniteField {
+                group,
+                modulus,
+                exponent,
+            } => {
+                // 0, 1 and p - 1 would fix the shared secret.
+                let peer = BigUint::from_bytes_be(peer);
+                let one = BigUint::from(1u32);
+                if peer <= one || peer >= &modulus - &one {
+                    return Err(weak());
+                }
+                let shared_secret = peer.modpow(&exponent, &modulus);
+                Ok(to_fixed_bytes(&shared_secret, group.key_len()))
+            }
+        }
+    }
+}
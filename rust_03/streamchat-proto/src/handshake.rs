@@ -0,0 +1,125 @@
+//! The signed ephemeral key exchange that follows the hello (and the
+//! password exchange, with one): each side sends a public key in the
+//! negotiated group, then its identity key and a signature over both
+//! public keys and the hellos.
+
+use crate::hello::Negotiated;
+use crate::keys::SessionKeys;
+use crate::{Role, pake};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use std::io::{self, Read, Write};
+use tracing::{debug, trace};
+
+/// What the exchange established.
+pub struct Handshake {
+    pub keys: SessionKeys,
+    pub peer_identity: VerifyingKey,
+    /// The peer proved it knows the password.
+    pub password: bool,
+}
+
+/// What each side signs with its identity key: its own role and ephemeral
+/// key followed by the peer's, so a signature can't be replayed into
+/// another session or reflected back at its author, then both hellos.
+fn identity_transcript(
+    signer: Role,
+    signer_public: &[u8],
+    other_public: &[u8],
+    hellos: &[u8],
+) -> Vec<u8> {
+    let mut transcript = b"streamchat v1 identity".to_vec();
+    transcript.push(match signer {
+        Role::Server => 0,
+        Role::Client => 1,
+    });
+    transcript.extend_from_slice(signer_public);
+    transcript.extend_from_slice(other_public);
+    transcript.extend_from_slice(hellos);
+    transcript
+}
+
+/// Runs the exchange as `role`, signing with `identity`. With
+/// `expected_peer`, any other peer identity is refused.
+pub fn key_exchange(
+    stream: &mut (impl Read + Write),
+    role: Role,
+    identity: &SigningKey,
+    expected_peer: Option<&VerifyingKey>,
+    hello: &Negotiated,
+    password: Option<&str>,
+) -> io::Result<Handshake> {
+    let hellos = &hello.transcript;
+    let password_key = password
+        .map(|password| pake::exchange(stream, role, password, hellos))
+        .transpose()?;
+
+    let group = hello.group;
+    let ephemeral = group.generate();
+    let public_key = ephemeral.public_key();
+    debug!(
+        "{} key exchange, our public key: {}",
+        group.name(),
+        hex::encode(&public_key)
+    );
+    stream.write_all(&public_key)?;
+    stream.flush()?;
+
+    let mut peer_public_key = vec![0u8; group.key_len()];
+    stream.read_exact(&mut peer_public_key)?;
+    debug!("peer's public key: {}", hex::encode(&peer_public_key));
+
+    let shared_secret = ephemeral.agree(&peer_public_key)?;
+    trace!("shared secret: {}", hex::encode(&shared_secret));
+
+    debug!("exchanging signed identities");
+    let signature = identity.sign(&identity_transcript(
+        role,
+        &public_key,
+        &peer_public_key,
+        hellos,
+    ));
+    stream.write_all(identity.verifying_key().as_bytes())?;
+    stream.write_all(&signature.to_bytes())?;
+    stream.flush()?;
+
+    let mut peer_identity_bytes = [0u8; 32];
+    let mut peer_signature_bytes = [0u8; 64];
+    stream.read_exact(&mut peer_identity_bytes)?;
+    stream.read_exact(&mut peer_signature_bytes)?;
+
+    let peer_identity = VerifyingKey::from_bytes(&peer_identity_bytes)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let peer_signature = Signature::from_bytes(&peer_signature_bytes);
+    peer_identity
+        .verify_strict(
+            &identity_transcript(role.peer(), &peer_public_key, &public_key, hellos),
+            &peer_signature,
+        )
+        .map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "peer's identity signature does not verify (possible man-in-the-middle)",
+            )
+        })?;
+
+    if expected_peer.is_some_and(|expected| *expected != peer_identity) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "peer identity does not match --peer-key",
+        ));
+    }
+
+    let (client_public, server_public) = match role {
+        Role::Client => (public_key, peer_public_key),
+        Role::Server => (peer_public_key, public_key),
+    };
+    // With a password, someone in the middle without it can't derive the
+    // keys even if the identity checks were skipped.
+    let mut secret = shared_secret;
+    secret.extend(password_key.iter().flatten());
+    Ok(Handshake {
+        keys: SessionKeys::derive(&secret, &client_public, &server_public),
+        peer_identity,
+        password: password_key.is_some(),
+    })
+}
@@ -0,0 +1,243 @@
+//! Hello exchange, sent by both sides before the key exchange so that
+//! mismatched builds stop with a clear message instead of desynchronizing
+//! the cipher state:
+//!
+//! ```text
+//! magic "STCH" | version u8 | suite count u8 | suites | features u32 BE
+//!   | extension length u16 BE | extensions
+//! ```
+//!
+//! Extensions are `type u8 | length u16 BE | value` records; a side skips
+//! the types it does not know. There are two so far: the resumption offer
+//! a client makes (1, see `resume`), and the link of a device to its
+//! primary identity (2, defined by the application).
+//!
+//! Both hellos are folded into the signed identity transcript, so a man in
+//! the middle can't quietly downgrade what they announce.
+
+use crate::Role;
+use crate::group::Group;
+use crate::padding::Padding;
+use std::io::{self, Read, Write};
+
+const MAGIC: &[u8; 4] = b"STCH";
+const PROTOCOL_VERSION: u8 = 7;
+/// Oldest version this build can still talk to.
+const MIN_VERSION: u8 = 5;
+
+/// The side is a hub: `/join` and `/leave` work.
+pub const FEATURE_ROOMS: u32 = 1 << 0;
+/// The side is a hub that queues lobby messages while we are offline.
+pub const FEATURE_OFFLINE_QUEUE: u32 = 1 << 1;
+/// The side runs the password exchange (`--password`); both must.
+pub const FEATURE_PASSWORD: u32 = 1 << 2;
+/// The side pads frames to power-of-two buckets (`--padding bucket`).
+pub const FEATURE_PADDING_BUCKET: u32 = 1 << 3;
+/// The side pads every frame to the largest bucket (`--padding max`).
+pub const FEATURE_PADDING_MAX: u32 = 1 << 4;
+/// The side is a hub that keeps room history (`--room-history`).
+pub const FEATURE_ROOM_HISTORY: u32 = 1 << 5;
+
+/// What both sides agreed on.
+pub struct Negotiated {
+    /// The protocol version both sides speak.
+    pub version: u8,
+    /// The key agreement group both sides use.
+    pub group: Group,
+    /// Padding both directions use: the stronger of the two announced.
+    pub padding: Padding,
+    /// Features the peer offers.
+    pub peer_features: u32,
+    /// Client hello then server hello, as sent.
+    pub transcript: Vec<u8>,
+    /// The extension records of the peer's hello.
+    peer_extensions: Vec<u8>,
+}
+
+/// One extension record, to pass to `exchange`.
+pub fn extension(kind: u8, value: &[u8]) -> Vec<u8> {
+    let mut record = vec![kind];
+    record.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    record.extend_from_slice(value);
+    record
+}
+
+fn encode(suites: &[u8], features: u32, extensions: &[u8]) -> Vec<u8> {
+    let mut hello = MAGIC.to_vec();
+    hello.push(PROTOCOL_VERSION);
+    hello.push(suites.len() as u8);
+    hello.extend_from_slice(suites);
+    hello.extend_from_slice(&features.to_be_bytes());
+    hello.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+    hello.extend_from_slice(extensions);
+    hello
+}
+
+fn mismatch(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// The peer's hello, parsed, and as it was sent.
+struct PeerHello {
+    version: u8,
+    suites: Vec<u8>,
+    features: u32,
+    extensions: Vec<u8>,
+    raw: Vec<u8>,
+}
+
+fn read_hello(stream: &mut impl Read) -> io::Result<PeerHello> {
+    let mut magic = [0u8; 4];
+    stream.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(mismatch(
+            "the peer did not send a streamchat hello: it is either not streamchat \
+             or a build from before protocol versioning, upgrade it"
+                .to_string(),
+        ));
+    }
+    let mut fixed = [0u8; 2];
+    stream.read_exact(&mut fixed)?;
+    let [version, suite_count] = fixed;
+    let mut suites = vec![0u8; suite_count as usize];
+    stream.read_exact(&mut suites)?;
+    let mut features = [0u8; 4];
+    stream.read_exact(&mut features)?;
+    let mut extension_len = [0u8; 2];
+    stream.read_exact(&mut extension_len)?;
+    let mut extensions = vec![0u8; u16::from_be_bytes(extension_len) as usize];
+    stream.read_exact(&mut extensions)?;
+
+    let mut raw = magic.to_vec();
+    raw.extend_from_slice(&fixed);
+    raw.extend_from_slice(&suites);
+    raw.extend_from_slice(&features);
+    raw.extend_from_slice(&extension_len);
+    raw.extend_from_slice(&extensions);
+    Ok(PeerHello {
+        version,
+        suites,
+        features: u32::from_be_bytes(features),
+        extensions,
+        raw,
+    })
+}
+
+/// Sends our hello, with `extensions` (records made by `extension`), reads
+/// the peer's and checks that we can talk. Each side offers the one group
+/// it was started with.
+pub fn exchange(
+    stream: &mut (impl Read + Write),
+    role: Role,
+    features: u32,
+    group: Group,
+    extensions: &[u8],
+) -> io::Result<Negotiated> {
+    let hello = encode(&[group.suite()], features, extensions);
+    stream.write_all(&hello)?;
+    stream.flush()?;
+    let PeerHello {
+        version: peer_version,
+        suites: peer_suites,
+        features: peer_features,
+        extensions: peer_extensions,
+        raw: peer_hello,
+    } = read_hello(stream)?;
+
+    if peer_version < MIN_VERSION {
+        return Err(mismatch(format!(
+            "the peer speaks protocol v{}, this build needs at least v{}: upgrade the peer",
+            peer_version, MIN_VERSION
+        )));
+    }
+    let version = peer_version.min(PROTOCOL_VERSION);
+
+    if !peer_suites.contains(&group.suite()) {
+        let peer_groups: Vec<_> = peer_suites
+            .iter()
+            .map(|suite| Group::from_suite(*suite).map_or("an unknown group", Group::name))
+            .collect();
+        return Err(mismatch(format!(
+            "the peer uses {}, we use {}: start both sides with the same --group",
+            peer_groups.join(" or "),
+            group.name()
+        )));
+    }
+
+    match (
+        features & FEATURE_PASSWORD != 0,
+        peer_features & FEATURE_PASSWORD != 0,
+    ) {
+        (true, false) => {
+            return Err(mismatch(
+                "we need a password but the peer was started without --password".to_string(),
+            ));
+        }
+        (false, true) => {
+            return Err(mismatch(
+                "the peer needs a password: start with --password".to_string(),
+            ));
+        }
+        _ => {}
+    }
+
+    let transcript = match role {
+        Role::Client => [hello, peer_hello].concat(),
+        Role::Server => [peer_hello, hello].concat(),
+    };
+    let padding = Padding::from_features(features).max(Padding::from_features(peer_features));
+    Ok(Negotiated {
+        version,
+        group,
+        padding,
+        peer_features,
+        transcript,
+        peer_extensions,
+    })
+}
+
+impl Negotiated {
+    /// The value of the peer's extension of type `kind`, if it sent one.
+    pub fn peer_extension(&self, kind: u8) -> Option<&[u8]> {
+        let mut rest = &self.peer_extensions[..];
+        while let [record_kind, high, low, after @ ..] = rest {
+            let len = u16::from_be_bytes([*high, *low]) as usize;
+            let value = after.get(..len)?;
+            if *record_kind == kind {
+                return Some(value);
+            }
+            rest = &after[len..];
+        }
+        None
+    }
+
+    /// E.g. `Protocol v5, X25519 + ChaCha20-Poly1305, no padding, peer
+    /// offers: rooms`.
+    pub fn describe(&self) -> String {
+        let mut names = Vec::new();
+        if self.peer_features & FEATURE_ROOMS != 0 {
+            names.push("rooms");
+        }
+        if self.peer_features & FEATURE_OFFLINE_QUEUE != 0 {
+            names.push("offline queue");
+        }
+        if self.peer_features & FEATURE_ROOM_HISTORY != 0 {
+            names.push("room history");
+        }
+        if self.peer_features & FEATURE_PASSWORD != 0 {
+            names.push("password");
+        }
+        let offers = if names.is_empty() {
+            String::new()
+        } else {
+            format!(", peer offers: {}", names.join(", "))
+        };
+        format!(
+            "Protocol v{}, {} + ChaCha20-Poly1305, {}{}",
+            self.version,
+            self.group.name(),
+            self.padding.describe(),
+            offers
+        )
+    }
+}
@@ -0,0 +1,48 @@
+use crate::Role;
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+/// Independent keys for each direction, derived from the shared secret
+/// with HKDF-SHA256 over the handshake transcript.
+pub struct SessionKeys {
+    client_to_server: [u8; 32],
+    server_to_client: [u8; 32],
+    /// Short authentication string material, identical on both sides only
+    /// if no one tampered with the exchange.
+    pub sas: [u8; 8],
+    /// What a ticket for a later session is built on (see `resume`).
+    pub resumption: [u8; 32],
+}
+
+impl SessionKeys {
+    pub fn derive(shared_secret: &[u8], client_public: &[u8], server_public: &[u8]) -> Self {
+        let mut salt = Vec::with_capacity(64);
+        salt.extend_from_slice(client_public);
+        salt.extend_from_slice(server_public);
+        let hkdf = Hkdf::<Sha256>::new(Some(&salt), shared_secret);
+
+        let mut keys = SessionKeys {
+            client_to_server: [0u8; 32],
+            server_to_client: [0u8; 32],
+            sas: [0u8; 8],
+            resumption: [0u8; 32],
+        };
+        hkdf.expand(b"streamchat v1 client->server", &mut keys.client_to_server)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+        hkdf.expand(b"streamchat v1 server->client", &mut keys.server_to_client)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+        hkdf.expand(b"streamchat v1 sas", &mut keys.sas)
+            .expect("8 bytes is a valid HKDF-SHA256 output length");
+        hkdf.expand(b"streamchat v1 resumption", &mut keys.resumption)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+        keys
+    }
+
+    /// Returns `(send_key, recv_key)` for the given side of the connection.
+    pub fn split(&self, role: Role) -> ([u8; 32], [u8; 32]) {
+        match role {
+            Role::Client => (self.client_to_server, self.server_to_client),
+            Role::Server => (self.server_to_client, self.client_to_server),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! The streamchat protocol, apart from any transport or UI:
+//!
+//! - `hello`: version, group and feature negotiation;
+//! - `pake` and `handshake`: the optional password exchange, then the
+//!   signed ephemeral key exchange that yields the `SessionKeys` and the
+//!   short authentication string (`sas`);
+//! - `resume`: tickets that let a client resume a verified session in one
+//!   round trip instead of the full exchange;
+//! - `codec`: the record layer. An `Encoder` turns frames into bytes to
+//!   write and a `Decoder` is fed the bytes read and hands frames back, in
+//!   order, authenticated and with replays dropped.
+//!
+//! The handshake is a fixed sequence of small messages and runs over any
+//! `Read + Write`, an in-memory pipe included. The codec does no I/O at
+//! all: the caller moves the bytes and passes the time in, so the same
+//! code serves a blocking socket, an event loop or a test.
+//!
+//! What a frame contains (chat text, files, rooms) is up to the
+//! application; the protocol only carries opaque plaintext.
+
+mod cipher;
+pub mod codec;
+pub mod group;
+pub mod handshake;
+pub mod hello;
+pub mod keys;
+pub mod padding;
+pub mod pake;
+pub mod resume;
+pub mod sas;
+
+pub use codec::{Decoder, Encoder, Record, Sealed};
+pub use group::Group;
+pub use handshake::key_exchange;
+pub use hello::Negotiated;
+pub use keys::SessionKeys;
+pub use padding::Padding;
+
+pub const TAG_LEN: usize = 16;
+/// Record header: sequence number (u64 BE) and ciphertext length (u32 BE).
+/// The frame's type is inside the ciphertext.
+pub const HEADER_LEN: usize = 12;
+/// Upper bound on a record's ciphertext, so a peer can't make us allocate
+/// arbitrary amounts of memory with a forged length.
+pub const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+pub const MAX_PLAINTEXT: usize = MAX_FRAME_LEN - TAG_LEN;
+
+/// Which end of the connection this is. The server is the side that
+/// accepted it (a hub, or the listening peer).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Role {
+    Server,
+    Client,
+}
+
+impl Role {
+    pub fn peer(self) -> Role {
+        match self {
+            Role::Server => Role::Client,
+            Role::Client => Role::Server,
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! Length hiding. The frame header carries the ciphertext length in clear,
+//! so without padding an observer can tell a "yes" from a paragraph, or a
+//! keepalive from a message. Padded frames are laid out as
+//!
+//! ```text
+//! frame length u32 BE | frame | zeros
+//! ```
+//!
+//! and encrypted as a whole. Each side announces its `--padding` in the
+//! hello and both use the stronger of the two, so one careful peer is
+//! enough to pad both directions.
+
+use crate::MAX_PLAINTEXT;
+use crate::hello::{FEATURE_PADDING_BUCKET, FEATURE_PADDING_MAX};
+
+/// Smallest bucket; a typical chat line fits in it.
+const MIN_BUCKET: usize = 256;
+/// With `max`, every frame up to this size (a file chunk included) looks
+/// the same; bigger ones are padded to a multiple of it.
+const MAX_BUCKET: usize = 64 * 1024;
+const LENGTH_LEN: usize = 4;
+
+/// Ordered from weakest to strongest.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum Padding {
+    /// Frames are sent at their real length.
+    Off,
+    /// Frames are padded to the next power of two, from 256 bytes.
+    Bucket,
+    /// Every frame is padded to 64 KiB.
+    Max,
+}
+
+impl Padding {
+    /// Hello feature bits announcing this mode.
+    pub fn features(self) -> u32 {
+        match self {
+            Padding::Off => 0,
+            Padding::Bucket => FEATURE_PADDING_BUCKET,
+            Padding::Max => FEATURE_PADDING_MAX,
+        }
+    }
+
+    /// The strongest mode announced in `features`.
+    pub fn from_features(features: u32) -> Self {
+        if features & FEATURE_PADDING_MAX != 0 {
+            Padding::Max
+        } else if features & FEATURE_PADDING_BUCKET != 0 {
+            Padding::Bucket
+        } else {
+            Padding::Off
+        }
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            Padding::Off => "no padding",
+            Padding::Bucket => "padded to buckets",
+            Padding::Max => "padded to 64 KiB",
+        }
+    }
+
+    /// Size `length` bytes of frame end up as on the wire, before the tag.
+    fn padded_len(self, length: usize) -> usize {
+        let needed = length + LENGTH_LEN;
+        let padded = match self {
+            Padding::Off => return length,
+            Padding::Bucket => needed.next_power_of_two().max(MIN_BUCKET),
+            Padding::Max => needed.div_ceil(MAX_BUCKET) * MAX_BUCKET,
+        };
+        padded.min(MAX_PLAINTEXT).max(needed)
+    }
+
+    /// The longest frame that is at most `limit` bytes once padded. When
+    /// the padding makes every frame bigger than that (`max` below 64 KiB),
+    /// the longest one padded to the smallest size.
+    pub fn fitting(self, limit: usize) -> usize {
+        let limit = limit.min(MAX_PLAINTEXT);
+        let padded = match self {
+            Padding::Off => return limit,
+            Padding::Bucket => (1 << limit.max(1).ilog2()).max(MIN_BUCKET),
+            Padding::Max => (limit / MAX_BUCKET).max(1) * MAX_BUCKET,
+        };
+        padded - LENGTH_LEN
+    }
+
+    pub fn pad(self, frame: Vec<u8>) -> Vec<u8> {
+        if self == Padding::Off {
+            return frame;
+        }
+        let padded_len = self.padded_len(frame.len());
+        let mut body = Vec::with_capacity(padded_len);
+        body.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+        body.extend_from_slice(&frame);
+        body.resize(padded_len, 0);
+        body
+    }
+
+    /// The frame inside a padded body, or `None` if the length prefix
+    /// doesn't fit.
+    pub fn unpad(self, mut body: Vec<u8>) -> Option<Vec<u8>> {
+        if self == Padding::Off {
+            return Some(body);
+        }
+        let prefix = body.get(..LENGTH_LEN)?;
+        let length = u32::from_be_bytes(prefix.try_into().unwrap()) as usize;
+        if length > body.len() - LENGTH_LEN {
+            return None;
+        }
+        body.truncate(LENGTH_LEN + length);
+        body.drain(..LENGTH_LEN);
+        Some(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LENGTHS: [usize; 9] = [0, 1, 251, 252, 253, 1000, 65_531, 65_532, 200_000];
+
+    #[test]
+    fn round_trips() {
+        for padding in [Padding::Off, Padding::Bucket, Padding::Max] {
+            for length in LENGTHS {
+                let frame: Vec<u8> = (0..length).map(|n| n as u8).collect();
+                let body = padding.pad(frame.clone());
+                assert_eq!(body.len(), padding.padded_len(length));
+                assert_eq!(padding.unpad(body), Some(frame), "{:?} {}", padding, length);
+            }
+        }
+    }
+
+    #[test]
+    fn sizes() {
+        for length in LENGTHS {
+            let bucket = Padding::Bucket.padded_len(length);
+            assert!(bucket.is_power_of_two() && bucket >= MIN_BUCKET);
+            assert!(Padding::Max.padded_len(length).is_multiple_of(MAX_BUCKET));
+            assert_eq!(Padding::Off.padded_len(length), length);
+        }
+        assert_eq!(Padding::Bucket.padded_len(252), 256);
+        assert_eq!(Padding::Bucket.padded_len(253), 512);
+    }
+
+    #[test]
+    fn fitting_frames_fit() {
+        for limit in [1024, 1500, 64 * 1024, 100_000] {
+            for padding in [Padding::Off, Padding::Bucket] {
+                let fitting = padding.fitting(limit);
+                assert!(
+                    padding.padded_len(fitting) <= limit,
+                    "{:?} {}",
+                    padding,
+                    limit
+                );
+                assert!(
+                    padding.padded_len(fitting + 1) > limit,
+                    "{:?} {}",
+                    padding,
+                    limit
+                );
+            }
+            // Nothing is padded to less than 64 KiB with `max`.
+            let fitting = Padding::Max.fitting(limit);
+            let smallest = (limit / MAX_BUCKET).max(1) * MAX_BUCKET;
+            assert_eq!(Padding::Max.padded_len(fitting), smallest);
+        }
+    }
+
+    #[test]
+    fn unpad_refuses_a_length_past_the_body() {
+        let mut body = Padding::Bucket.pad(b"hello".to_vec());
+        let past = body.len() as u32;
+        body[..LENGTH_LEN].copy_from_slice(&past.to_be_bytes());
+        assert_eq!(Padding::Bucket.unpad(body), None);
+        assert_eq!(Padding::Bucket.unpad(vec![0, 0]), None);
+    }
+}
@@ -0,0 +1,102 @@
+//! Password-authenticated key exchange for `--password`: SPAKE2 over
+//! Ristretto255. Each side blinds an ephemeral key with a point derived
+//! from the password, so someone in the middle who does not know it gets
+//! one online guess per connection and learns nothing to test guesses
+//! against offline. Both sides prove they got the same key before anything
+//! else is sent, and that key is mixed into the session keys.
+
+use crate::Role;
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use hkdf::Hkdf;
+use sha2::{Digest, Sha256, Sha512};
+use std::io::{self, Read, Write};
+
+const DOMAIN: &[u8] = b"streamchat v1 spake2";
+
+/// A point nobody knows the discrete log of, from hashing `label`.
+fn blinding_point(label: &[u8]) -> RistrettoPoint {
+    let digest: [u8; 64] = Sha512::digest(label).into();
+    RistrettoPoint::from_uniform_bytes(&digest)
+}
+
+fn password_scalar(password: &str) -> Scalar {
+    let mut wide = [0u8; 64];
+    Hkdf::<Sha256>::new(Some(DOMAIN), password.as_bytes())
+        .expand(b"password", &mut wide)
+        .expect("64 bytes is a valid HKDF-SHA256 output length");
+    Scalar::from_bytes_mod_order_wide(&wide)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Runs the exchange and returns a key that only someone who knows the
+/// password can have. `hellos` binds it to this connection's hellos.
+pub fn exchange(
+    stream: &mut (impl Read + Write),
+    role: Role,
+    password: &str,
+    hellos: &[u8],
+) -> io::Result<[u8; 32]> {
+    let w = password_scalar(password);
+    let client_point = blinding_point(b"streamchat v1 spake2 M");
+    let server_point = blinding_point(b"streamchat v1 spake2 N");
+    let (own_point, peer_point) = match role {
+        Role::Client => (client_point, server_point),
+        Role::Server => (server_point, client_point),
+    };
+
+    let secret = Scalar::from_bytes_mod_order_wide(&rand::random());
+    let element = (RISTRETTO_BASEPOINT_POINT * secret + own_point * w).compress();
+    stream.write_all(element.as_bytes())?;
+    stream.flush()?;
+
+    let mut peer_bytes = [0u8; 32];
+    stream.read_exact(&mut peer_bytes)?;
+    let peer_element = CompressedRistretto(peer_bytes)
+        .decompress()
+        .ok_or_else(|| invalid("the peer's password exchange message is not a valid point"))?;
+    let shared = (peer_element - peer_point * w) * secret;
+
+    let (client_element, server_element) = match role {
+        Role::Client => (element, CompressedRistretto(peer_bytes)),
+        Role::Server => (CompressedRistretto(peer_bytes), element),
+    };
+    let mut transcript = hellos.to_vec();
+    transcript.extend_from_slice(client_element.as_bytes());
+    transcript.extend_from_slice(server_element.as_bytes());
+    transcript.extend_from_slice(shared.compress().as_bytes());
+    transcript.extend_from_slice(w.as_bytes());
+    let hkdf = Hkdf::<Sha256>::new(Some(DOMAIN), &transcript);
+
+    let mut client_confirmation = [0u8; 32];
+    let mut server_confirmation = [0u8; 32];
+    let mut key = [0u8; 32];
+    for (label, output) in [
+        (&b"client confirmation"[..], &mut client_confirmation),
+        (b"server confirmation", &mut server_confirmation),
+        (b"session key", &mut key),
+    ] {
+        hkdf.expand(label, output)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+    }
+
+    let (own_confirmation, peer_confirmation) = match role {
+        Role::Client => (client_confirmation, server_confirmation),
+        Role::Server => (server_confirmation, client_confirmation),
+    };
+    stream.write_all(&own_confirmation)?;
+    stream.flush()?;
+    let mut received = [0u8; 32];
+    stream.read_exact(&mut received)?;
+    if received != peer_confirmation {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "wrong password: the peer does not share our passphrase",
+        ));
+    }
+    Ok(key)
+}
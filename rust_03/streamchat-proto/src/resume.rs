@@ -0,0 +1,446 @@
+//! Session resumption. Once a session is verified on both sides, the
+//! server hands the client a ticket: the session's resumption secret and
+//! the client's identity, sealed under a key only the server holds. The
+//! next connection offers it in the client hello along with a fresh
+//! ephemeral key:
+//!
+//! ```text
+//! client hello extension 1: ticket length u16 BE | ticket | ephemeral public key
+//! server, after its hello:  0 (declined, the full exchange follows)
+//!                         | 1 | ephemeral public key | server confirmation
+//! client, when accepted:    client confirmation
+//! ```
+//!
+//! Both sides then derive the session keys from the resumption secret, the
+//! new Diffie-Hellman result and the hellos: one round trip instead of the
+//! password, key and identity exchanges, with forward secrecy kept. The
+//! secret only exists at both ends of a verified session, so the
+//! verification carries over to the resumed one.
+//!
+//! The ticket key is made when the server starts and never stored, so
+//! restarting the server voids every ticket.
+
+use crate::group::{Ephemeral, Group};
+use crate::hello::Negotiated;
+use crate::keys::SessionKeys;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use ed25519_dalek::VerifyingKey;
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::io::{self, Read, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+/// First protocol version that answers a resumption offer.
+pub const VERSION: u8 = 6;
+const EXTENSION: u8 = 1;
+const DECLINED: u8 = 0;
+const ACCEPTED: u8 = 1;
+const DOMAIN: &[u8] = b"streamchat v1 resume";
+const TICKET_AAD: &[u8] = b"streamchat v1 ticket";
+const NONCE_LEN: usize = 12;
+/// Resumption secret, client identity, expiry (Unix seconds) and whether
+/// the session had a password.
+const CONTENTS_LEN: usize = 32 + 32 + 8 + 1;
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// What a ticket holds, readable only by the server that sealed it.
+struct Contents {
+    secret: [u8; 32],
+    client: VerifyingKey,
+    expires: u64,
+    password: bool,
+}
+
+/// The server's key for sealing tickets.
+pub struct TicketKey {
+    aead: ChaCha20Poly1305,
+}
+
+impl TicketKey {
+    pub fn generate() -> Self {
+        let key: [u8; 32] = rand::random();
+        TicketKey {
+            aead: ChaCha20Poly1305::new(Key::from_slice(&key)),
+        }
+    }
+
+    /// A ticket for the session whose keys had this `resumption` secret
+    /// and whose client is `client`, valid for `lifetime`.
+    pub fn issue(
+        &self,
+        resumption: &[u8; 32],
+        client: &VerifyingKey,
+        password: bool,
+        lifetime: Duration,
+    ) -> Vec<u8> {
+        let expires = unix_seconds(SystemTime::now() + lifetime);
+        let mut contents = Vec::with_capacity(CONTENTS_LEN);
+        contents.extend_from_slice(resumption);
+        contents.extend_from_slice(client.as_bytes());
+        contents.extend_from_slice(&expires.to_be_bytes());
+        contents.push(password as u8);
+
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let sealed = self
+            .aead
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &contents,
+                    aad: TICKET_AAD,
+                },
+            )
+            .expect("ChaCha20-Poly1305 encryption cannot fail for in-memory buffers");
+        [&nonce[..], &sealed].concat()
+    }
+
+    /// Opens a ticket this key sealed, if it has not expired.
+    fn open(&self, ticket: &[u8], now: SystemTime) -> Option<Contents> {
+        let (nonce, sealed) = ticket.split_at_checked(NONCE_LEN)?;
+        let contents = self
+            .aead
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: sealed,
+                    aad: TICKET_AAD,
+                },
+            )
+            .ok()?;
+        if contents.len() != CONTENTS_LEN {
+            return None;
+        }
+        let expires = u64::from_be_bytes(contents[64..72].try_into().unwrap());
+        if unix_seconds(now) > expires {
+            return None;
+        }
+        Some(Contents {
+            secret: contents[..32].try_into().unwrap(),
+            client: VerifyingKey::from_bytes(&contents[32..64].try_into().unwrap()).ok()?,
+            expires,
+            password: contents[72] == 1,
+        })
+    }
+}
+
+/// The client's side of a ticket: the opaque ticket, and what it needs
+/// to use it.
+#[derive(Clone)]
+pub struct Ticket {
+    ticket: Vec<u8>,
+    secret: [u8; 32],
+    /// The server identity of the session it came from.
+    pub server: VerifyingKey,
+    group: Group,
+    expires: SystemTime,
+}
+
+impl Ticket {
+    /// Keeps `ticket`, received on the session whose keys had this
+    /// `resumption` secret.
+    pub fn new(
+        ticket: Vec<u8>,
+        resumption: [u8; 32],
+        server: VerifyingKey,
+        group: Group,
+        lifetime: Duration,
+    ) -> Self {
+        Ticket {
+            ticket,
+            secret: resumption,
+            server,
+            group,
+            expires: SystemTime::now() + lifetime,
+        }
+    }
+
+    pub fn expired(&self) -> bool {
+        SystemTime::now() >= self.expires
+    }
+}
+
+/// A ticket offered on a new connection, with the ephemeral key sent
+/// along.
+pub struct Offer {
+    ticket: Ticket,
+    ephemeral: Ephemeral,
+    public: Vec<u8>,
+}
+
+impl Offer {
+    /// Offers `ticket` on a connection using `group`; `None` if it expired
+    /// or came from a session in another group.
+    pub fn new(ticket: Ticket, group: Group) -> Option<Offer> {
+        if ticket.expired() || ticket.group != group {
+            return None;
+        }
+        let ephemeral = group.generate();
+        let public = ephemeral.public_key();
+        Some(Offer {
+            ticket,
+            ephemeral,
+            public,
+        })
+    }
+
+    /// The hello extension carrying the offer.
+    pub fn extension(&self) -> Vec<u8> {
+        let mut value = (self.ticket.ticket.len() as u16).to_be_bytes().to_vec();
+        value.extend_from_slice(&self.ticket.ticket);
+        value.extend_from_slice(&self.public);
+        crate::hello::extension(EXTENSION, &value)
+    }
+}
+
+/// A resumed session.
+pub struct Resumed {
+    pub keys: SessionKeys,
+    pub peer_identity: VerifyingKey,
+}
+
+/// Session keys and both confirmations, from the resumption secret, the
+/// new shared secret, the hellos and both ephemeral keys.
+fn derive(
+    secret: &[u8; 32],
+    shared: &[u8],
+    hello: &Negotiated,
+    client_public: &[u8],
+    server_public: &[u8],
+) -> (SessionKeys, [u8; 32], [u8; 32]) {
+    let mut input = secret.to_vec();
+    input.extend_from_slice(shared);
+    input.extend_from_slice(&hello.transcript);
+    let keys = SessionKeys::derive(&input, client_public, server_public);
+
+    input.extend_from_slice(client_public);
+    input.extend_from_slice(server_public);
+    let hkdf = Hkdf::<Sha256>::new(Some(DOMAIN), &input);
+    let mut client_confirmation = [0u8; 32];
+    let mut server_confirmation = [0u8; 32];
+    hkdf.expand(b"client confirmation", &mut client_confirmation)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    hkdf.expand(b"server confirmation", &mut server_confirmation)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    (keys, client_confirmation, server_confirmation)
+}
+
+/// Client side, right after the hello exchange that carried `offer`.
+/// `None` when the server declined (or predates resumption): the full
+/// exchange follows.
+pub fn client(
+    stream: &mut (impl Read + Write),
+    offer: Offer,
+    hello: &Negotiated,
+) -> io::Result<Option<Resumed>> {
+    if hello.version < VERSION {
+        return Ok(None);
+    }
+    let mut answer = [0u8; 1];
+    stream.read_exact(&mut answer)?;
+    if answer[0] != ACCEPTED {
+        debug!("the server declined the resumption ticket");
+        return Ok(None);
+    }
+    let mut server_public = vec![0u8; hello.group.key_len()];
+    stream.read_exact(&mut server_public)?;
+    let mut received = [0u8; 32];
+    stream.read_exact(&mut received)?;
+
+    let shared = offer.ephemeral.agree(&server_public)?;
+    let (keys, client_confirmation, server_confirmation) = derive(
+        &offer.ticket.secret,
+        &shared,
+        hello,
+        &offer.public,
+        &server_public,
+    );
+    if received != server_confirmation {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the server's resumption confirmation does not match (possible man-in-the-middle)",
+        ));
+    }
+    stream.write_all(&client_confirmation)?;
+    stream.flush()?;
+    Ok(Some(Resumed {
+        keys,
+        peer_identity: offer.ticket.server,
+    }))
+}
+
+/// Server side, right after the hello exchange: answers a resumption offer
+/// if the client made one. `None` means the full exchange follows, either
+/// because there was no offer or because it was declined (`key` is `None`,
+/// or the ticket is not ours, expired, or from a session with a different
+/// password setting).
+pub fn server(
+    stream: &mut (impl Read + Write),
+    key: Option<&TicketKey>,
+    hello: &Negotiated,
+    password: bool,
+) -> io::Result<Option<Resumed>> {
+    let Some(offer) = hello.peer_extension(EXTENSION) else {
+        return Ok(None);
+    };
+    let key_len = hello.group.key_len();
+    let accepted = offer
+        .split_first_chunk::<2>()
+        .and_then(|(len, rest)| rest.split_at_checked(u16::from_be_bytes(*len) as usize))
+        .filter(|(_, client_public)| client_public.len() == key_len)
+        .and_then(|(ticket, client_public)| {
+            let contents = key?.open(ticket, SystemTime::now())?;
+            (contents.password == password).then_some((contents, client_public))
+        });
+    let Some((contents, client_public)) = accepted else {
+        debug!("declined a resumption ticket");
+        stream.write_all(&[DECLINED])?;
+        stream.flush()?;
+        return Ok(None);
+    };
+    debug!(
+        "resuming a session, ticket valid for {}s more",
+        contents
+            .expires
+            .saturating_sub(unix_seconds(SystemTime::now()))
+    );
+
+    let ephemeral = hello.group.generate();
+    let server_public = ephemeral.public_key();
+    let shared = ephemeral.agree(client_public)?;
+    let (keys, client_confirmation, server_confirmation) = derive(
+        &contents.secret,
+        &shared,
+        hello,
+        client_public,
+        &server_public,
+    );
+    stream.write_all(&[ACCEPTED])?;
+    stream.write_all(&server_public)?;
+    stream.write_all(&server_confirmation)?;
+    stream.flush()?;
+
+    let mut received = [0u8; 32];
+    stream.read_exact(&mut received)?;
+    if received != client_confirmation {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "the client's resumption confirmation does not match",
+        ));
+    }
+    Ok(Some(Resumed {
+        keys,
+        peer_identity: contents.client,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Role;
+    use crate::hello;
+    use ed25519_dalek::SigningKey;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    const GROUP: Group = Group::X25519;
+    const SECRET: [u8; 32] = [3; 32];
+
+    fn identity(seed: u8) -> VerifyingKey {
+        SigningKey::from_bytes(&[seed; 32]).verifying_key()
+    }
+
+    /// A ticket the client keeps, `ticket` being what the server sent.
+    fn keep(ticket: Vec<u8>, secret: [u8; 32]) -> Ticket {
+        Ticket::new(ticket, secret, identity(1), GROUP, Duration::from_secs(60))
+    }
+
+    /// Offers `ticket` to a server holding `key` over a loopback
+    /// connection; returns what the client and the server each got.
+    fn offer(
+        ticket: Ticket,
+        key: TicketKey,
+        password: bool,
+    ) -> (io::Result<Option<Resumed>>, io::Result<Option<Resumed>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+        let server = thread::spawn(move || {
+            let hello = hello::exchange(&mut server, Role::Server, 0, GROUP, &[])?;
+            super::server(&mut server, Some(&key), &hello, password)
+        });
+
+        let offer = Offer::new(ticket, GROUP).unwrap();
+        let client = hello::exchange(&mut stream, Role::Client, 0, GROUP, &offer.extension())
+            .and_then(|hello| super::client(&mut stream, offer, &hello));
+        // Hang up, so a server waiting on a client that gave up returns.
+        drop(stream);
+        (client, server.join().unwrap())
+    }
+
+    #[test]
+    fn a_ticket_resumes_the_session() {
+        let key = TicketKey::generate();
+        let ticket = key.issue(&SECRET, &identity(2), false, Duration::from_secs(60));
+        let (client, server) = offer(keep(ticket, SECRET), key, false);
+        let (client, server) = (client.unwrap().unwrap(), server.unwrap().unwrap());
+        assert_eq!(client.peer_identity, identity(1));
+        assert_eq!(server.peer_identity, identity(2));
+        let (client_send, client_receive) = client.keys.split(Role::Client);
+        let (server_send, server_receive) = server.keys.split(Role::Server);
+        assert_eq!(client_send, server_receive);
+        assert_eq!(client_receive, server_send);
+        assert_ne!(client_send, client_receive);
+    }
+
+    #[test]
+    fn tickets_from_another_server_are_declined() {
+        let ticket =
+            TicketKey::generate().issue(&SECRET, &identity(2), false, Duration::from_secs(60));
+        let (client, server) = offer(keep(ticket, SECRET), TicketKey::generate(), false);
+        assert!(client.unwrap().is_none());
+        assert!(server.unwrap().is_none());
+    }
+
+    #[test]
+    fn tampered_tickets_are_declined() {
+        let key = TicketKey::generate();
+        let mut ticket = key.issue(&SECRET, &identity(2), false, Duration::from_secs(60));
+        ticket[NONCE_LEN] ^= 1;
+        let (client, server) = offer(keep(ticket, SECRET), key, false);
+        assert!(client.unwrap().is_none());
+        assert!(server.unwrap().is_none());
+    }
+
+    #[test]
+    fn tickets_from_a_session_with_another_password_setting_are_declined() {
+        let key = TicketKey::generate();
+        let ticket = key.issue(&SECRET, &identity(2), true, Duration::from_secs(60));
+        let (client, server) = offer(keep(ticket, SECRET), key, false);
+        assert!(client.unwrap().is_none());
+        assert!(server.unwrap().is_none());
+    }
+
+    #[test]
+    fn expired_tickets_do_not_open() {
+        let key = TicketKey::generate();
+        let ticket = key.issue(&SECRET, &identity(2), false, Duration::from_secs(60));
+        assert!(key.open(&ticket, SystemTime::now()).is_some());
+        let later = SystemTime::now() + Duration::from_secs(120);
+        assert!(key.open(&ticket, later).is_none());
+    }
+
+    #[test]
+    fn a_client_without_the_secret_is_caught() {
+        let key = TicketKey::generate();
+        let ticket = key.issue(&SECRET, &identity(2), false, Duration::from_secs(60));
+        let (client, server) = offer(keep(ticket, [4; 32]), key, false);
+        assert_eq!(client.err().unwrap().kind(), io::ErrorKind::InvalidData);
+        assert!(server.is_err());
+    }
+}
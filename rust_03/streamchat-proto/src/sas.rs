@@ -0,0 +1,24 @@
+//! Short authentication string: a few words both users read out to each
+//! other to confirm that no one sits in the middle of the key exchange.
+
+const WORDS: [&str; 64] = [
+    "acorn", "anchor", "apple", "arrow", "badger", "banjo", "beacon", "bison", "cactus", "canoe",
+    "cedar", "comet", "coral", "crane", "dingo", "dragon", "eagle", "ember", "falcon", "fern",
+    "fjord", "galaxy", "garnet", "glacier", "harbor", "hazel", "heron", "igloo", "iris", "jaguar",
+    "jasper", "kayak", "kettle", "koala", "lagoon", "lantern", "lemon", "lotus", "maple", "meteor",
+    "nectar", "nickel", "oasis", "otter", "pepper", "pine", "quartz", "quill", "raven", "rocket",
+    "saffron", "salmon", "tango", "thistle", "tulip", "umber", "valley", "velvet", "walnut",
+    "willow", "yak", "yodel", "zebra", "zephyr",
+];
+
+/// Number of words shown; 6 bits each, 36 bits in total.
+const WORD_COUNT: usize = 6;
+
+/// Turns the SAS bytes derived from the handshake into a word sequence.
+pub fn words(bytes: &[u8; 8]) -> String {
+    let bits = u64::from_be_bytes(*bytes);
+    (0..WORD_COUNT)
+        .map(|i| WORDS[((bits >> (58 - 6 * i)) & 0x3F) as usize])
+        .collect::<Vec<_>>()
+        .join(" ")
+}
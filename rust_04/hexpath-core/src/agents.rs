@@ -0,0 +1,150 @@
+//! Several agents crossing the map at once, for `--agents`, planned with
+//! cooperative A* so no two of them are ever in the same cell at the same
+//! step, nor swap cells in one step.
+//!
+//! Agents are planned in order, each around the moves of those before
+//! it, waiting in place when it has to. Waiting a step costs what entering
+//! the cell again would. Once at its goal an agent stays there, so the
+//! others route around it from then on. Planning in order keeps it fast,
+//! but an agent can find every way blocked by those planned first; it is
+//! then reported as stuck and left out.
+
+use crate::Grid;
+use crate::flow::FlowField;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+/// What is taken, and when, by the agents planned so far.
+#[derive(Default)]
+struct Reservations {
+    /// (cell, step) pairs.
+    cells: HashSet<(usize, usize)>,
+    /// (from, to, step) moves, taken between `step` and `step + 1`.
+    moves: HashSet<(usize, usize, usize)>,
+    /// Cells agents stay in for good, from the given step.
+    parked: HashMap<usize, usize>,
+    /// The last step each cell is taken at before anyone parks.
+    last: HashMap<usize, usize>,
+}
+
+impl Reservations {
+    fn free(&self, cell: usize, step: usize) -> bool {
+        !self.cells.contains(&(cell, step))
+            && self.parked.get(&cell).is_none_or(|&from| step < from)
+    }
+
+    fn reserve(&mut self, path: &[usize]) {
+        for (step, &cell) in path.iter().enumerate() {
+            self.cells.insert((cell, step));
+            let last = self.last.entry(cell).or_default();
+            *last = (*last).max(step);
+        }
+        for (step, pair) in path.windows(2).enumerate() {
+            self.moves.insert((pair[0], pair[1], step));
+        }
+        if let Some(&goal) = path.last() {
+            self.parked.insert(goal, path.len() - 1);
+        }
+    }
+
+    /// The step after which nothing changes any more.
+    fn horizon(&self) -> usize {
+        self.last
+            .values()
+            .chain(self.parked.values())
+            .copied()
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Plans every `(start, goal)` in order. Each plan is the cell the agent is
+/// in at every step, from its start to its goal, and the cost of getting
+/// there, or `None` if it is stuck.
+pub fn plan(grid: &Grid, agents: &[(usize, usize)]) -> Vec<Option<(Vec<usize>, usize)>> {
+    let mut reservations = Reservations::default();
+    agents
+        .iter()
+        .map(|&(start, goal)| {
+            let plan = space_time_astar(grid, start, goal, &reservations);
+            if let Some((path, _)) = &plan {
+                reservations.reserve(path);
+            }
+            plan
+        })
+        .collect()
+}
+
+/// A* over (cell, step) pairs, avoiding what `reservations` holds.
+fn space_time_astar(
+    grid: &Grid,
+    start: usize,
+    goal: usize,
+    reservations: &Reservations,
+) -> Option<(Vec<usize>, usize)> {
+    if !reservations.free(start, 0) {
+        return None;
+    }
+    // The cost to the goal ignoring other agents: a heuristic that is
+    // never too high.
+    let remaining = FlowField::new(grid, goal).cost;
+    remaining[start]?;
+    // Past the last reservation the map stands still, and a path visiting
+    // every cell once more gets anywhere it can.
+    let horizon = reservations.horizon() + grid.len();
+
+    let mut heap = BinaryHeap::from([Reverse((remaining[start]?, 0, start))]);
+    let mut cost = HashMap::from([((start, 0), 0)]);
+    let mut prev: HashMap<(usize, usize), (usize, usize)> = HashMap::new();
+    let mut closed = HashSet::new();
+
+    while let Some(Reverse((_, step, cell))) = heap.pop() {
+        if !closed.insert((cell, step)) {
+            continue;
+        }
+        let so_far = cost[&(cell, step)];
+        // Only stop where nobody will come through afterwards.
+        if cell == goal
+            && reservations
+                .last
+                .get(&goal)
+                .is_none_or(|&last| last <= step)
+        {
+            let mut path = vec![cell];
+            let mut current = (cell, step);
+            while let Some(&before) = prev.get(&current) {
+                path.push(before.0);
+                current = before;
+            }
+            path.reverse();
+            return Some((path, so_far));
+        }
+        if step == horizon {
+            continue;
+        }
+
+        let mut options = grid.neighbors(cell);
+        options.push(cell);
+        for next in options {
+            let Some(left) = remaining[next] else {
+                continue;
+            };
+            if !reservations.free(next, step + 1)
+                || reservations.moves.contains(&(next, cell, step))
+                || closed.contains(&(next, step + 1))
+            {
+                continue;
+            }
+            let new_cost = so_far + grid.step_cost(cell, next);
+            if cost
+                .get(&(next, step + 1))
+                .is_none_or(|&old| new_cost < old)
+            {
+                cost.insert((next, step + 1), new_cost);
+                prev.insert((next, step + 1), (cell, step));
+                heap.push(Reverse((new_cost + left, step + 1, next)));
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,273 @@
+//! Any-angle paths: `--algo theta-star` and `--smooth`. Both look for
+//! straight lines between cells that can see each other, a line being the
+//! cells a straight walk from one center to the other goes through, each a
+//! move from the one before. Its cost is what those moves cost, so a path
+//! made of lines is still a path on the grid, only drawn, and measured,
+//! through its corners.
+
+use crate::Grid;
+use crate::frontier::{Bitset, IndexedHeap};
+use crate::search::{self, Expansion, Pathfinder, Search, Watch};
+use std::collections::HashSet;
+
+/// Theta*: A* that, whenever it reaches a cell, checks whether the cell it
+/// came from could have been skipped, with a straight line from that
+/// cell's own parent, so parents are corners and not just neighbors.
+pub struct ThetaStar;
+
+impl Pathfinder for ThetaStar {
+    fn name(&self) -> &'static str {
+        "Theta*"
+    }
+
+    fn optimal(&self) -> bool {
+        false
+    }
+
+    fn any_angle(&self) -> bool {
+        true
+    }
+
+    fn find(&self, grid: &Grid, start: usize, end: usize, watch: &mut dyn Watch) -> Search {
+        let cheapest = search::cheapest_step(grid);
+        let priority =
+            |cost: usize, cell: usize| (cost + cheapest * search::steps(grid, cell, end)) as i64;
+
+        let mut dist = vec![usize::MAX; grid.len()];
+        let mut parent = vec![None; grid.len()];
+        // The moves from each cell's parent to it, so a line that would go
+        // the long way round a wrapping grid isn't taken for a shortcut.
+        let mut hops = vec![0; grid.len()];
+        // How far each cell is from `start` through its corners, so of two
+        // ways in that cost the same the straighter one wins.
+        let mut reach = vec![0.0; grid.len()];
+        let mut heap = IndexedHeap::new(grid.len());
+        let mut visited = Bitset::new(grid.len());
+        // Only gathered for a `watch` that is watching.
+        let mut shown = HashSet::new();
+
+        dist[start] = 0;
+        heap.push(start, priority(0, start));
+
+        while let Some((position, _)) = heap.pop() {
+            if position == end {
+                return finish(grid, &parent, end, visited.len());
+            }
+            visited.insert(position);
+            let cost = dist[position];
+
+            if watch.watching() {
+                shown.insert(position);
+                let at = Expansion {
+                    cell: position,
+                    cost: Some(cost),
+                    frontier: heap.len(),
+                };
+                watch.expanded(grid, &shown, &at);
+            }
+            if !watch.keep_going(visited.len()) {
+                break;
+            }
+
+            for neighbor in grid.neighbors(position) {
+                if visited.contains(neighbor) {
+                    continue;
+                }
+
+                let far = |from: usize| reach[from] + distance(grid, &[from, neighbor]);
+                let mut best = (
+                    cost + grid.step_cost(position, neighbor),
+                    far(position),
+                    position,
+                    1,
+                );
+                // Straight from the parent instead, if it is no dearer.
+                if let Some(corner) = parent[position] {
+                    let shortcut = line(grid, corner, neighbor)
+                        .filter(|cells| cells.len() - 1 <= hops[position] + 1);
+                    if let Some(cells) = shortcut {
+                        let through = dist[corner] + line_cost(grid, &cells);
+                        if through <= best.0 {
+                            best = (through, far(corner), corner, cells.len() - 1);
+                        }
+                    }
+                }
+
+                let (new_cost, new_reach, from, moves) = best;
+                if (new_cost, new_reach) < (dist[neighbor], reach[neighbor]) {
+                    dist[neighbor] = new_cost;
+                    reach[neighbor] = new_reach;
+                    parent[neighbor] = Some(from);
+                    hops[neighbor] = moves;
+                    heap.push(neighbor, priority(new_cost, neighbor));
+                }
+            }
+        }
+
+        Search {
+            found: None,
+            expanded: visited.len(),
+            bound: None,
+        }
+    }
+}
+
+/// The path through the corners `parent` leads back along from `end`, a
+/// line between each two, and its cost.
+fn finish(grid: &Grid, parent: &[Option<usize>], end: usize, expanded: usize) -> Search {
+    let mut corners = vec![end];
+    while let Some(corner) = parent[corners[corners.len() - 1]] {
+        corners.push(corner);
+    }
+    corners.reverse();
+    let path = trace(grid, &corners);
+    let cost = line_cost(grid, &path);
+    Search {
+        found: Some((path, cost)),
+        expanded,
+        bound: None,
+    }
+}
+
+/// A path drawn through the fewest corners the string pulling of `--smooth`
+/// found, none of its lines dearer than the stretch of path it replaces.
+pub struct Smoothed {
+    pub corners: Vec<usize>,
+    /// The lines between the corners, cell by cell.
+    pub path: Vec<usize>,
+    pub cost: usize,
+}
+
+/// Pulls `path` taut: from each corner, the line goes on along the path
+/// for as long as the corner can see the cell it reaches, with a line no
+/// dearer and no longer in moves than the path there. So the smoothed path
+/// never costs more; a cheapest path stays a cheapest one. `path` has at
+/// least its start.
+pub fn smooth(grid: &Grid, path: &[usize]) -> Smoothed {
+    // What the path costs up to each of its cells.
+    let mut so_far = vec![0; path.len()];
+    for i in 1..path.len() {
+        so_far[i] = so_far[i - 1] + grid.step_cost(path[i - 1], path[i]);
+    }
+
+    let mut corners = vec![path[0]];
+    let mut anchor = 0;
+    while anchor + 1 < path.len() {
+        let mut reach = anchor + 1;
+        while reach + 1 < path.len() {
+            let next = reach + 1;
+            let seen = line(grid, path[anchor], path[next]).is_some_and(|cells| {
+                cells.len() - 1 <= next - anchor
+                    && line_cost(grid, &cells) <= so_far[next] - so_far[anchor]
+            });
+            if !seen {
+                break;
+            }
+            reach = next;
+        }
+        corners.push(path[reach]);
+        anchor = reach;
+    }
+
+    let path = trace(grid, &corners);
+    let cost = line_cost(grid, &path);
+    Smoothed {
+        corners,
+        path,
+        cost,
+    }
+}
+
+/// How far the path through `corners` goes, in cells, center to center:
+/// a straight line between two corners on a floor, one for a staircase.
+pub fn distance(grid: &Grid, corners: &[usize]) -> f64 {
+    corners
+        .windows(2)
+        .map(|pair| match grid.step(pair[0], pair[1]) {
+            // A single move, which may go over a wrapped edge.
+            Some((dx, dy)) if grid.neighbors(pair[0]).contains(&pair[1]) => {
+                (dx as f64).hypot(dy as f64)
+            }
+            Some(_) => {
+                let (from_x, from_y) = grid.index_to_coords(pair[0]);
+                let (to_x, to_y) = grid.index_to_coords(pair[1]);
+                (from_x.abs_diff(to_x) as f64).hypot(from_y.abs_diff(to_y) as f64)
+            }
+            None => 1.0,
+        })
+        .sum()
+}
+
+/// The cells of the lines through `corners`, each corner once.
+fn trace(grid: &Grid, corners: &[usize]) -> Vec<usize> {
+    let mut path = corners[..1].to_vec();
+    for pair in corners.windows(2) {
+        let cells = line(grid, pair[0], pair[1]).expect("corners see each other");
+        path.extend_from_slice(&cells[1..]);
+    }
+    path
+}
+
+fn line_cost(grid: &Grid, cells: &[usize]) -> usize {
+    cells
+        .windows(2)
+        .map(|pair| grid.step_cost(pair[0], pair[1]))
+        .sum()
+}
+
+/// The cells of the straight line from `from` to `to`, both included, if
+/// each is a move from the one before: with diagonals, one cell per column
+/// or row, whichever there are more of; without, a cell for every column
+/// and row crossed. Lines stay on a floor and never go over a wrapped edge,
+/// except a single move, which is a line of its own.
+fn line(grid: &Grid, from: usize, to: usize) -> Option<Vec<usize>> {
+    if grid.neighbors(from).contains(&to) {
+        return Some(vec![from, to]);
+    }
+    let (from_x, from_y, from_z) = grid.floor_coords(from);
+    let (to_x, to_y, to_z) = grid.floor_coords(to);
+    if from_z != to_z {
+        return None;
+    }
+
+    let (dx, dy) = (
+        to_x as isize - from_x as isize,
+        to_y as isize - from_y as isize,
+    );
+    let (across, down) = (dx.unsigned_abs() as isize, dy.unsigned_abs() as isize);
+    let (x, y) = (from_x as isize, from_y as isize);
+    let offsets: Vec<(isize, isize)> = if grid.diagonal.is_some() {
+        // Rounded to the nearest cell along the longer side.
+        let moves = across.max(down);
+        (1..=moves)
+            .map(|i| {
+                let along = |by: isize| (2 * by * i + moves * by.signum()) / (2 * moves);
+                (along(dx), along(dy))
+            })
+            .collect()
+    } else {
+        // Across or down, whichever the line crosses into first.
+        let (mut i, mut j) = (0, 0);
+        let mut offsets = Vec::new();
+        while i < across || j < down {
+            if j == down || (i < across && (1 + 2 * i) * down < (1 + 2 * j) * across) {
+                i += 1;
+            } else {
+                j += 1;
+            }
+            offsets.push((i * dx.signum(), j * dy.signum()));
+        }
+        offsets
+    };
+
+    let top = from_z * grid.floor_height();
+    let mut cells = vec![from];
+    for (ox, oy) in offsets {
+        let cell = grid.coords_to_index((x + ox) as usize, top + (y + oy) as usize);
+        if !grid.neighbors(cells[cells.len() - 1]).contains(&cell) {
+            return None;
+        }
+        cells.push(cell);
+    }
+    Some(cells)
+}
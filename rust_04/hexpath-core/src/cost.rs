@@ -0,0 +1,310 @@
+//! `--cost-fn`: what a move costs, worked out from the value of the cell
+//! it leaves and the value of the cell it enters. Every search costs its
+//! moves through the map's model, by `Grid::step_cost`, and a move the
+//! model refuses is no move at all: the cell isn't a neighbor.
+//!
+//! - `value`, the original: the value of the cell entered;
+//! - `elevation-diff`: how far the values are apart, as if they were
+//!   heights, so climbing and descending cost and flat ground is free;
+//! - `threshold:N`: 1 for a cell below N (hex), none at all for the rest;
+//! - `expr:E`: a formula of `from` and `to`, the two values, with `+ - * /`,
+//!   parentheses, numbers, and `abs`, `min` and `max`. A negative result
+//!   refuses the move.
+
+use crate::Grid;
+use std::fmt;
+use std::sync::Arc;
+
+pub trait CostModel: fmt::Debug + Send + Sync {
+    /// What moving from a cell worth `from` into one worth `to` costs,
+    /// before the diagonal factor; `None` if the move can't be made.
+    fn cost(&self, from: u8, to: u8) -> Option<usize>;
+
+    /// How it is given to `--cost-fn`.
+    fn name(&self) -> String;
+}
+
+/// The original model, the value of the cell entered.
+pub fn by_value() -> Arc<dyn CostModel> {
+    Arc::new(Value)
+}
+
+pub fn parse(text: &str) -> Result<Arc<dyn CostModel>, String> {
+    if let Some(threshold) = text.strip_prefix("threshold:") {
+        return Ok(Arc::new(Threshold(parse_byte(threshold)?)));
+    }
+    if let Some(formula) = text.strip_prefix("expr:") {
+        return Ok(Arc::new(Expr::parse(formula)?));
+    }
+    match text {
+        "value" => Ok(by_value()),
+        "elevation-diff" => Ok(Arc::new(ElevationDiff)),
+        _ => Err(format!(
+            "expected value, elevation-diff, threshold:N or expr:FORMULA, got '{}'",
+            text
+        )),
+    }
+}
+
+/// A byte in hex, with or without `0x`.
+pub fn parse_byte(text: &str) -> Result<u8, String> {
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+    u8::from_str_radix(digits, 16).map_err(|_| format!("expected a hex byte, got '{}'", text))
+}
+
+/// The least a move can cost, diagonal factor included, on `grid` and on
+/// any map like it whose open cells only hold `values`: a lower bound the
+/// guided searches can count on.
+pub fn least(grid: &Grid, values: impl IntoIterator<Item = u8>) -> usize {
+    let mut seen = [false; 256];
+    for value in values {
+        seen[value as usize] = true;
+    }
+    let values: Vec<u8> = (0..=255).filter(|&value| seen[value as usize]).collect();
+    values
+        .iter()
+        .flat_map(|&from| {
+            values
+                .iter()
+                .filter_map(move |&to| grid.cost.cost(from, to))
+        })
+        .map(|cost| match grid.diagonal {
+            Some(factor) => cost.min((cost as f64 * factor).round() as usize),
+            None => cost,
+        })
+        .min()
+        .unwrap_or(0)
+}
+
+#[derive(Debug)]
+struct Value;
+
+impl CostModel for Value {
+    fn cost(&self, _from: u8, to: u8) -> Option<usize> {
+        Some(to as usize)
+    }
+
+    fn name(&self) -> String {
+        "value".to_string()
+    }
+}
+
+#[derive(Debug)]
+struct ElevationDiff;
+
+impl CostModel for ElevationDiff {
+    fn cost(&self, from: u8, to: u8) -> Option<usize> {
+        Some(from.abs_diff(to) as usize)
+    }
+
+    fn name(&self) -> String {
+        "elevation-diff".to_string()
+    }
+}
+
+#[derive(Debug)]
+struct Threshold(u8);
+
+impl CostModel for Threshold {
+    fn cost(&self, _from: u8, to: u8) -> Option<usize> {
+        (to < self.0).then_some(1)
+    }
+
+    fn name(&self) -> String {
+        format!("threshold:{:02X}", self.0)
+    }
+}
+
+/// A formula, worked out once for every pair of values when it is parsed,
+/// so a search looks its costs up instead of evaluating it.
+struct Expr {
+    formula: String,
+    /// By `from * 256 + to`.
+    costs: Vec<Option<usize>>,
+}
+
+impl fmt::Debug for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Expr({:?})", self.formula)
+    }
+}
+
+impl Expr {
+    fn parse(formula: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            text: formula,
+            at: 0,
+        };
+        let root = parser.sum()?;
+        parser.skip_spaces();
+        if parser.at < formula.len() {
+            return Err(parser.unexpected());
+        }
+        let costs = (0..=255u8)
+            .flat_map(|from| (0..=255u8).map(move |to| (from, to)))
+            .map(|(from, to)| {
+                let cost = root.eval(from as f64, to as f64);
+                (cost.is_finite() && cost >= 0.0).then(|| cost.round() as usize)
+            })
+            .collect();
+        Ok(Expr {
+            formula: formula.to_string(),
+            costs,
+        })
+    }
+}
+
+impl CostModel for Expr {
+    fn cost(&self, from: u8, to: u8) -> Option<usize> {
+        self.costs[from as usize * 256 + to as usize]
+    }
+
+    fn name(&self) -> String {
+        format!("expr:{}", self.formula)
+    }
+}
+
+enum Node {
+    Number(f64),
+    From,
+    To,
+    Negate(Box<Node>),
+    /// The operator, `+ - * /`, and its two sides.
+    Binary(char, Box<Node>, Box<Node>),
+    /// `abs`, `min` or `max`, and its arguments.
+    Call(&'static str, Vec<Node>),
+}
+
+impl Node {
+    fn eval(&self, from: f64, to: f64) -> f64 {
+        match self {
+            Node::Number(number) => *number,
+            Node::From => from,
+            Node::To => to,
+            Node::Negate(node) => -node.eval(from, to),
+            Node::Binary(operator, left, right) => {
+                let (left, right) = (left.eval(from, to), right.eval(from, to));
+                match operator {
+                    '+' => left + right,
+                    '-' => left - right,
+                    '*' => left * right,
+                    _ => left / right,
+                }
+            }
+            Node::Call(function, arguments) => {
+                let values = arguments.iter().map(|node| node.eval(from, to));
+                match *function {
+                    "abs" => values.sum::<f64>().abs(),
+                    "min" => values.fold(f64::INFINITY, f64::min),
+                    _ => values.fold(f64::NEG_INFINITY, f64::max),
+                }
+            }
+        }
+    }
+}
+
+/// Reads a formula by recursive descent: sums of products of factors.
+struct Parser<'a> {
+    text: &'a str,
+    /// Byte offset of what is still to read.
+    at: usize,
+}
+
+impl Parser<'_> {
+    fn skip_spaces(&mut self) {
+        let rest = &self.text[self.at..];
+        self.at += rest.len() - rest.trim_start().len();
+    }
+
+    /// The next character, after any spaces, without taking it.
+    fn peek(&mut self) -> Option<char> {
+        self.skip_spaces();
+        self.text[self.at..].chars().next()
+    }
+
+    /// Takes `wanted` if it comes next.
+    fn take(&mut self, wanted: char) -> bool {
+        let found = self.peek() == Some(wanted);
+        if found {
+            self.at += wanted.len_utf8();
+        }
+        found
+    }
+
+    fn unexpected(&mut self) -> String {
+        match self.peek() {
+            Some(found) => format!("unexpected '{}' at {}", found, self.at + 1),
+            None => "the formula ends too soon".to_string(),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Node, String> {
+        let mut node = self.product()?;
+        while let Some(operator) = self.peek().filter(|c| matches!(c, '+' | '-')) {
+            self.take(operator);
+            node = Node::Binary(operator, Box::new(node), Box::new(self.product()?));
+        }
+        Ok(node)
+    }
+
+    fn product(&mut self) -> Result<Node, String> {
+        let mut node = self.factor()?;
+        while let Some(operator) = self.peek().filter(|c| matches!(c, '*' | '/')) {
+            self.take(operator);
+            node = Node::Binary(operator, Box::new(node), Box::new(self.factor()?));
+        }
+        Ok(node)
+    }
+
+    fn factor(&mut self) -> Result<Node, String> {
+        if self.take('-') {
+            return Ok(Node::Negate(Box::new(self.factor()?)));
+        }
+        if self.take('(') {
+            let node = self.sum()?;
+            return if self.take(')') {
+                Ok(node)
+            } else {
+                Err(self.unexpected())
+            };
+        }
+
+        let rest = &self.text[self.at..];
+        let length = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '_'))
+            .unwrap_or(rest.len());
+        let word = &rest[..length];
+        if word.is_empty() {
+            return Err(self.unexpected());
+        }
+        if let Ok(number) = word.parse::<f64>() {
+            self.at += length;
+            return Ok(Node::Number(number));
+        }
+        let (function, arity) = match word {
+            "from" | "to" => {
+                self.at += length;
+                return Ok(if word == "from" { Node::From } else { Node::To });
+            }
+            "abs" => ("abs", 1),
+            "min" => ("min", 2),
+            "max" => ("max", 2),
+            _ => return Err(format!("unknown name '{}' at {}", word, self.at + 1)),
+        };
+        self.at += length;
+        if !self.take('(') {
+            return Err(self.unexpected());
+        }
+        let mut arguments = vec![self.sum()?];
+        while arguments.len() < arity {
+            if !self.take(',') {
+                return Err(self.unexpected());
+            }
+            arguments.push(self.sum()?);
+        }
+        if !self.take(')') {
+            return Err(self.unexpected());
+        }
+        Ok(Node::Call(function, arguments))
+    }
+}
@@ -0,0 +1,151 @@
+//! `--parallel`: Dijkstra's cheapest paths by delta-stepping, for maps of
+//! millions of cells. Instead of a heap, cells wait in buckets `delta` wide
+//! by their cost so far, and the whole lowest bucket is expanded at once:
+//! the steps out of its cells are worked out on every core with rayon, then
+//! applied in order, so the path found doesn't depend on the thread count.
+//!
+//! Steps of at most `delta` can land back in the bucket being expanded, so
+//! those are taken round after round until it empties; the heavier ones
+//! can't, and are taken once, after. No cell of a later bucket can cost
+//! less than one of an earlier bucket, so the end's cost is final as soon as
+//! the buckets left start above it.
+
+use crate::Grid;
+use crate::search::{self, Expansion, Pathfinder, Search, Watch};
+use rayon::prelude::*;
+use std::collections::HashSet;
+
+pub struct DeltaStepping;
+
+impl Pathfinder for DeltaStepping {
+    fn name(&self) -> &'static str {
+        "parallel Dijkstra"
+    }
+
+    fn optimal(&self) -> bool {
+        true
+    }
+
+    fn find(&self, grid: &Grid, start: usize, end: usize, watch: &mut dyn Watch) -> Search {
+        let delta = bucket_width(grid);
+        let mut dist = vec![usize::MAX; grid.len()];
+        let mut prev = vec![None; grid.len()];
+        let mut settled = vec![false; grid.len()];
+        let mut expanded = 0;
+        dist[start] = 0;
+        let mut buckets = vec![vec![start]];
+        // Only gathered for a `watch` that is watching.
+        let mut shown = HashSet::new();
+
+        let mut relaxed = |steps: Vec<(usize, usize, usize)>,
+                           dist: &mut [usize],
+                           buckets: &mut Vec<Vec<usize>>| {
+            for (cell, cost, from) in steps {
+                if cost < dist[cell] {
+                    dist[cell] = cost;
+                    prev[cell] = Some(from);
+                    let bucket = cost / delta;
+                    if buckets.len() <= bucket {
+                        buckets.resize_with(bucket + 1, Vec::new);
+                    }
+                    buckets[bucket].push(cell);
+                }
+            }
+        };
+
+        let mut current = 0;
+        while current < buckets.len() && dist[end] > current * delta {
+            let mut done = Vec::new();
+            loop {
+                // Cells moved to a cheaper bucket since they were put here
+                // are left out, and cells put here twice taken once.
+                let mut ready: Vec<usize> = std::mem::take(&mut buckets[current])
+                    .into_iter()
+                    .filter(|&cell| dist[cell] / delta == current)
+                    .collect();
+                ready.sort_unstable();
+                ready.dedup();
+                if ready.is_empty() {
+                    break;
+                }
+
+                for &cell in &ready {
+                    if !settled[cell] {
+                        settled[cell] = true;
+                        expanded += 1;
+                    }
+                }
+                let steps = steps_from(grid, &ready, &dist, |step| step <= delta);
+                relaxed(steps, &mut dist, &mut buckets);
+
+                if watch.watching() {
+                    shown.extend(&ready);
+                    let cell = *ready.last().expect("checked above");
+                    let at = Expansion {
+                        cell,
+                        cost: Some(dist[cell]),
+                        frontier: buckets[current..].iter().map(Vec::len).sum(),
+                    };
+                    watch.expanded(grid, &shown, &at);
+                }
+                if !watch.keep_going(expanded) {
+                    return Search {
+                        found: None,
+                        expanded,
+                        bound: None,
+                    };
+                }
+                done.extend(ready);
+            }
+
+            done.sort_unstable();
+            done.dedup();
+            let steps = steps_from(grid, &done, &dist, |step| step > delta);
+            relaxed(steps, &mut dist, &mut buckets);
+            current += 1;
+        }
+
+        if dist[end] == usize::MAX {
+            return Search {
+                found: None,
+                expanded,
+                bound: None,
+            };
+        }
+        search::finish(grid, &prev, end, expanded)
+    }
+}
+
+/// The steps out of `cells` whose cost `keep` accepts and that would make
+/// their neighbor cheaper, as (neighbor, its new cost, from), in the order
+/// of `cells`. The work is split across threads.
+fn steps_from(
+    grid: &Grid,
+    cells: &[usize],
+    dist: &[usize],
+    keep: impl Fn(usize) -> bool + Sync,
+) -> Vec<(usize, usize, usize)> {
+    cells
+        .par_iter()
+        .flat_map_iter(|&cell| {
+            grid.neighbors(cell)
+                .into_iter()
+                .map(move |neighbor| (neighbor, grid.step_cost(cell, neighbor), cell))
+        })
+        .filter(|&(neighbor, step, cell)| keep(step) && dist[cell] + step < dist[neighbor])
+        .map(|(neighbor, step, cell)| (neighbor, dist[cell] + step, cell))
+        .collect()
+}
+
+/// How wide the buckets are: about what a step costs, averaged over the
+/// moves between open cells side by side, which keeps buckets full enough
+/// to share out without expanding many cells before their cost is final.
+fn bucket_width(grid: &Grid) -> usize {
+    let (count, total) = grid
+        .values()
+        .zip(grid.values().skip(1))
+        .filter(|&(left, right)| left != grid.wall && right != grid.wall)
+        .filter_map(|(left, right)| grid.cost.cost(left, right))
+        .fold((0, 0), |(count, total), cost| (count + 1, total + cost));
+    (total / count.max(1)).max(1)
+}
@@ -0,0 +1,68 @@
+//! `--flow-field`: one Dijkstra pass backward from the goal gives every
+//! cell its cost to the goal and the neighbor to step to, so the path from
+//! any start is just a walk along the arrows, with no further search.
+
+use crate::Grid;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+pub struct FlowField {
+    pub goal: usize,
+    /// The cheapest cost from each cell to the goal, `None` if walls cut
+    /// it off.
+    pub cost: Vec<Option<usize>>,
+    /// The neighbor each cell steps to on its way to the goal.
+    next: Vec<Option<usize>>,
+}
+
+impl FlowField {
+    pub fn new(grid: &Grid, goal: usize) -> Self {
+        let mut cost = vec![None; grid.len()];
+        let mut next = vec![None; grid.len()];
+        // Each entry remembers the cell it was reached from, which is where
+        // it steps next: following the search tree, zero-cost cells can't
+        // send the walk around in circles.
+        let mut heap = BinaryHeap::from([Reverse((0, goal, None))]);
+        while let Some(Reverse((so_far, cell, via))) = heap.pop() {
+            if cost[cell].is_some() {
+                continue;
+            }
+            cost[cell] = Some(so_far);
+            next[cell] = via;
+            // Steps are walked toward the goal, so each one costs what
+            // entering `cell` from `before` does.
+            for before in grid.predecessors(cell) {
+                if cost[before].is_none() {
+                    heap.push(Reverse((
+                        so_far + grid.step_cost(before, cell),
+                        before,
+                        Some(cell),
+                    )));
+                }
+            }
+        }
+
+        FlowField { goal, cost, next }
+    }
+
+    /// The path from `start` to the goal, and its cost.
+    pub fn path_from(&self, start: usize) -> Option<(Vec<usize>, usize)> {
+        let cost = self.cost[start]?;
+        let mut path = vec![start];
+        let mut current = start;
+        while let Some(next) = self.next[current] {
+            path.push(next);
+            current = next;
+        }
+        Some((path, cost))
+    }
+
+    /// The neighbor `cell` steps to on its way to the goal.
+    pub fn next(&self, cell: usize) -> Option<usize> {
+        self.next[cell]
+    }
+
+    pub fn reachable(&self) -> usize {
+        self.cost.iter().flatten().count()
+    }
+}
@@ -0,0 +1,153 @@
+//! What the cheapest-first searches keep track of while they run. The
+//! frontier is a binary heap that knows where each cell sits in it, so a
+//! cell found cheaper is moved up in place instead of pushed again: it never
+//! holds a cell twice, stays as small as the frontier, and nothing stale is
+//! ever popped. Expanded cells are a bitset, one bit per cell, instead of a
+//! hash set.
+
+/// Cells by key, lowest first.
+pub struct IndexedHeap {
+    /// The cells in it and their keys, as a binary heap on the key.
+    heap: Vec<(i64, usize)>,
+    /// One more than where each cell is in `heap`, 0 when it isn't, so the
+    /// table starts zeroed: a search that meets a few cells of a large map
+    /// doesn't pay to fill all of it.
+    slot: Vec<u32>,
+}
+
+impl IndexedHeap {
+    /// An empty heap for cells numbered below `cells`.
+    pub fn new(cells: usize) -> Self {
+        assert!(cells < u32::MAX as usize, "too many cells to index");
+        IndexedHeap {
+            heap: Vec::new(),
+            slot: vec![0; cells],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    /// The lowest key, if any cell is in.
+    pub fn peek_key(&self) -> Option<i64> {
+        self.heap.first().map(|&(key, _)| key)
+    }
+
+    /// Adds `cell` with `key`, or lowers its key to `key` if it is already
+    /// in with a higher one.
+    pub fn push(&mut self, cell: usize, key: i64) {
+        let at = match self.slot[cell] {
+            0 => {
+                self.heap.push((key, cell));
+                self.heap.len() - 1
+            }
+            slot if key < self.heap[slot as usize - 1].0 => {
+                let at = slot as usize - 1;
+                self.heap[at].0 = key;
+                at
+            }
+            _ => return,
+        };
+        self.sift_up(at);
+    }
+
+    /// Takes out the cell with the lowest key, and its key.
+    pub fn pop(&mut self) -> Option<(usize, i64)> {
+        let (key, cell) = *self.heap.first()?;
+        let last = self.heap.pop().expect("not empty");
+        self.slot[cell] = 0;
+        if !self.heap.is_empty() {
+            self.heap[0] = last;
+            self.sift_down(0);
+        }
+        Some((cell, key))
+    }
+
+    fn sift_up(&mut self, mut at: usize) {
+        while at > 0 {
+            let parent = (at - 1) / 2;
+            if self.heap[parent].0 <= self.heap[at].0 {
+                break;
+            }
+            self.swap(at, parent);
+            at = parent;
+        }
+        self.place(at);
+    }
+
+    fn sift_down(&mut self, mut at: usize) {
+        loop {
+            let mut lowest = at;
+            for child in [2 * at + 1, 2 * at + 2] {
+                if child < self.heap.len() && self.heap[child].0 < self.heap[lowest].0 {
+                    lowest = child;
+                }
+            }
+            if lowest == at {
+                break;
+            }
+            self.swap(at, lowest);
+            at = lowest;
+        }
+        self.place(at);
+    }
+
+    /// Swaps the entry being sifted, at `from`, with the one at `to`,
+    /// noting where the other one went; the sifted one is noted by `place`
+    /// where it stops.
+    fn swap(&mut self, from: usize, to: usize) {
+        self.heap.swap(from, to);
+        self.place(from);
+    }
+
+    /// Notes where the entry at `at` is.
+    fn place(&mut self, at: usize) {
+        self.slot[self.heap[at].1] = at as u32 + 1;
+    }
+}
+
+/// A set of cells, one bit each.
+pub struct Bitset {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl Bitset {
+    /// An empty set for cells numbered below `cells`.
+    pub fn new(cells: usize) -> Self {
+        Bitset {
+            words: vec![0; cells.div_ceil(64)],
+            len: 0,
+        }
+    }
+
+    /// How many cells are in.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains(&self, cell: usize) -> bool {
+        self.words[cell / 64] & (1 << (cell % 64)) != 0
+    }
+
+    /// Adds `cell`, returning whether it wasn't in already.
+    pub fn insert(&mut self, cell: usize) -> bool {
+        let word = &mut self.words[cell / 64];
+        let bit = 1 << (cell % 64);
+        if *word & bit != 0 {
+            return false;
+        }
+        *word |= bit;
+        self.len += 1;
+        true
+    }
+}
@@ -0,0 +1,353 @@
+//! The map every search runs on: cells of a byte each, row by row, some of
+//! them walls, the rest costing what the cost model says to enter.
+
+use crate::cost::{self, CostModel};
+use rand::Rng;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Where a grid keeps the values of its cells, row by row: a `Vec<u8>`, or
+/// whatever a game already holds its map in.
+pub trait Storage: Send + Sync {
+    /// How many cells there are.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The value of cell `index`.
+    fn get(&self, index: usize) -> u8;
+
+    /// Changes the value of cell `index`. Searches never do; a backend that
+    /// can't be written may panic.
+    fn set(&mut self, index: usize, value: u8);
+
+    /// A copy of the cells, for cloning the grid.
+    fn boxed(&self) -> Box<dyn Storage>;
+
+    /// Roughly the memory the cells take, in bytes: a byte a cell, unless
+    /// the backend says otherwise.
+    fn bytes(&self) -> usize {
+        self.len()
+    }
+}
+
+impl Storage for Vec<u8> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn get(&self, index: usize) -> u8 {
+        self[index]
+    }
+
+    fn set(&mut self, index: usize, value: u8) {
+        self[index] = value;
+    }
+
+    fn boxed(&self) -> Box<dyn Storage> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Storage> {
+    fn clone(&self) -> Self {
+        self.boxed()
+    }
+}
+
+#[derive(Clone)]
+pub struct Grid {
+    pub width: usize,
+    pub height: usize,
+    pub(crate) cells: Box<dyn Storage>,
+    /// Cells holding this value are walls.
+    pub wall: u8,
+    /// With 8-connected movement, the cost factor of diagonal steps.
+    pub diagonal: Option<f64>,
+    /// Whether the edges meet: the left one next to the right, the top
+    /// one next to the bottom.
+    pub wrap: bool,
+    /// How many floors the map stacks, top to bottom, each `height /
+    /// layers` rows; 1 for a flat map.
+    pub layers: usize,
+    /// Cells that are staircases: one leads to the cell right above or
+    /// below it on the next floor up or down, if that is a staircase too.
+    pub stairs: HashSet<usize>,
+    /// What moves cost.
+    pub cost: Arc<dyn CostModel>,
+}
+
+impl Grid {
+    /// A flat, 4-connected map of `cells`, which costs what the cell
+    /// entered is worth.
+    ///
+    /// # Panics
+    ///
+    /// If there aren't `width * height` cells.
+    pub fn new(width: usize, height: usize, cells: impl Storage + 'static, wall: u8) -> Self {
+        assert_eq!(
+            cells.len(),
+            width * height,
+            "a {}x{} grid needs {} cells",
+            width,
+            height,
+            width * height
+        );
+        Grid {
+            width,
+            height,
+            cells: Box::new(cells),
+            wall,
+            diagonal: None,
+            wrap: false,
+            layers: 1,
+            stairs: HashSet::new(),
+            cost: cost::by_value(),
+        }
+    }
+
+    /// A map of random costs where about `density` of the cells are walls.
+    pub fn generate_random(
+        width: usize,
+        height: usize,
+        wall: u8,
+        density: f64,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let cells: Vec<u8> = (0..width * height)
+            .map(|_| {
+                if rng.random_bool(density) {
+                    wall
+                } else {
+                    open_value(wall, rng)
+                }
+            })
+            .collect();
+
+        Grid::new(width, height, cells, wall)
+    }
+
+    /// Turns the walls among `cells` into random open cells, as
+    /// `generate_random` makes them; others are left as they are.
+    pub fn open(&mut self, cells: &[usize], rng: &mut impl Rng) {
+        for &cell in cells {
+            if self.is_wall(cell) {
+                let value = open_value(self.wall, rng);
+                self.set_value(cell, value);
+            }
+        }
+    }
+
+    /// How many cells there are, on every floor.
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// The value of cell `index`.
+    pub fn value(&self, index: usize) -> u8 {
+        self.cells.get(index)
+    }
+
+    pub fn set_value(&mut self, index: usize, value: u8) {
+        self.cells.set(index, value);
+    }
+
+    /// Keeps the cells in `cells` from now on, which must hold the same
+    /// number.
+    ///
+    /// # Panics
+    ///
+    /// If it holds another number of cells.
+    pub fn set_storage(&mut self, cells: impl Storage + 'static) {
+        assert_eq!(cells.len(), self.len(), "the grid has {} cells", self.len());
+        self.cells = Box::new(cells);
+    }
+
+    /// Roughly the memory the cells take, in bytes.
+    pub fn storage_bytes(&self) -> usize {
+        self.cells.bytes()
+    }
+
+    /// Every cell's value, row by row.
+    pub fn values(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..self.len()).map(|index| self.value(index))
+    }
+
+    pub fn is_wall(&self, index: usize) -> bool {
+        self.value(index) == self.wall
+    }
+
+    pub fn index_to_coords(&self, index: usize) -> (usize, usize) {
+        (index % self.width, index / self.width)
+    }
+
+    pub fn coords_to_index(&self, x: usize, y: usize) -> usize {
+        y * self.width + x
+    }
+
+    /// Rows in each floor.
+    pub fn floor_height(&self) -> usize {
+        self.height / self.layers
+    }
+
+    /// Where `index` is on its floor, and which floor: (x, y, layer).
+    pub fn floor_coords(&self, index: usize) -> (usize, usize, usize) {
+        let (x, y) = self.index_to_coords(index);
+        let floor = self.floor_height().max(1);
+        (x, y % floor, y / floor)
+    }
+
+    /// `index` as it is shown: (x, y), or (x, y, layer) on a map of
+    /// several layers.
+    pub fn label(&self, index: usize) -> String {
+        let (x, y, z) = self.floor_coords(index);
+        if self.layers > 1 {
+            format!("({}, {}, {})", x, y, z)
+        } else {
+            format!("({}, {})", x, y)
+        }
+    }
+
+    /// The staircases a staircase at `index` leads to, on the floors right
+    /// above and below it, walls included.
+    pub fn stairway(&self, index: usize) -> Vec<usize> {
+        if !self.stairs.contains(&index) {
+            return Vec::new();
+        }
+        let floor = self.width * self.floor_height();
+        [index.checked_sub(floor), Some(index + floor)]
+            .into_iter()
+            .flatten()
+            .filter(|other| self.stairs.contains(other))
+            .collect()
+    }
+
+    /// The index of the cell at `(x, y)`, if a path can start or end there.
+    pub fn endpoint(&self, (x, y): (usize, usize)) -> Result<usize, String> {
+        if x >= self.width || y >= self.height {
+            return Err(format!(
+                "({}, {}) is outside the {}x{} grid",
+                x, y, self.width, self.height
+            ));
+        }
+        let index = self.coords_to_index(x, y);
+        if self.is_wall(index) {
+            return Err(format!("{} is a wall", self.label(index)));
+        }
+        Ok(index)
+    }
+
+    /// The cell `dx` across and `dy` down from `index` on the same floor:
+    /// wrapped around to the opposite edge on a wrapping grid, else `None`
+    /// past an edge.
+    pub fn offset(&self, index: usize, dx: isize, dy: isize) -> Option<usize> {
+        let (x, y, z) = self.floor_coords(index);
+        let along = |at: usize, by: isize, size: usize| {
+            if self.wrap {
+                Some((at as isize + by).rem_euclid(size as isize) as usize)
+            } else {
+                at.checked_add_signed(by).filter(|&at| at < size)
+            }
+        };
+        let floor = self.floor_height();
+        Some(self.coords_to_index(along(x, dx, self.width)?, z * floor + along(y, dy, floor)?))
+    }
+
+    /// The cells a move from `index` can go to.
+    pub fn neighbors(&self, index: usize) -> Vec<usize> {
+        self.adjacent(index, |neighbor| self.can_move(index, neighbor))
+    }
+
+    /// The cells a move into `index` can come from, for searching
+    /// backward: the same as its neighbors, unless the cost model refuses
+    /// moves one way only.
+    pub fn predecessors(&self, index: usize) -> Vec<usize> {
+        self.adjacent(index, |neighbor| self.can_move(neighbor, index))
+    }
+
+    /// Whether the cost model lets a move go from `from` to `to`.
+    pub fn can_move(&self, from: usize, to: usize) -> bool {
+        self.cost.cost(self.value(from), self.value(to)).is_some()
+    }
+
+    /// The open cells next to `index`, on its floor or up and down its
+    /// stairs, that `allowed` keeps.
+    fn adjacent(&self, index: usize, allowed: impl Fn(usize) -> bool) -> Vec<usize> {
+        let mut neighbors = Vec::new();
+        // The cell at offset (dx, dy), if there is one and it isn't a wall.
+        let open = |dx: isize, dy: isize| {
+            self.offset(index, dx, dy)
+                .filter(|&neighbor| !self.is_wall(neighbor))
+        };
+        // A wrapping grid one or two cells wide meets the same cell both
+        // ways, or the cell itself.
+        let mut add = |neighbor: Option<usize>| {
+            let new = |&n: &usize| n != index && !neighbors.contains(&n) && allowed(n);
+            if let Some(neighbor) = neighbor.filter(new) {
+                neighbors.push(neighbor);
+            }
+        };
+
+        for (dx, dy) in [(0, -1), (0, 1), (-1, 0), (1, 0)] {
+            add(open(dx, dy));
+        }
+        if self.diagonal.is_some() {
+            for (dx, dy) in [(-1, -1), (1, -1), (-1, 1), (1, 1)] {
+                // No cutting corners: both cells beside the step must be open.
+                if open(dx, 0).is_some() && open(0, dy).is_some() {
+                    add(open(dx, dy));
+                }
+            }
+        }
+        for other in self.stairway(index) {
+            add(Some(other).filter(|&other| !self.is_wall(other)));
+        }
+
+        neighbors
+    }
+
+    /// Which way the move from `from` to its neighbor `to` goes, along x
+    /// and along y, each -1, 0 or 1: a move over the edge of a wrapping
+    /// grid goes on past it, not back across the whole map. `None` for a
+    /// move up or down a staircase.
+    pub fn step(&self, from: usize, to: usize) -> Option<(isize, isize)> {
+        let (from_x, from_y, from_z) = self.floor_coords(from);
+        let (to_x, to_y, to_z) = self.floor_coords(to);
+        let along = |from: usize, to: usize| match to as isize - from as isize {
+            by if by > 1 => -1,
+            by if by < -1 => 1,
+            by => by,
+        };
+        (from_z == to_z).then(|| (along(from_x, to_x), along(from_y, to_y)))
+    }
+
+    /// What entering `to` from its neighbor `from` costs: what the cost
+    /// model says, times the diagonal factor for a diagonal step.
+    pub fn step_cost(&self, from: usize, to: usize) -> usize {
+        let value = self
+            .cost
+            .cost(self.value(from), self.value(to))
+            .expect("moves are only costed between neighbors");
+        let (from_x, from_y) = self.index_to_coords(from);
+        let (to_x, to_y) = self.index_to_coords(to);
+        match self.diagonal {
+            Some(factor) if from_x != to_x && from_y != to_y => {
+                (value as f64 * factor).round() as usize
+            }
+            _ => value,
+        }
+    }
+}
+
+/// A random value other than `wall`, so walls only come where they are
+/// meant to.
+fn open_value(wall: u8, rng: &mut impl Rng) -> u8 {
+    let value = rng.random_range(0..=254);
+    if value >= wall { value + 1 } else { value }
+}
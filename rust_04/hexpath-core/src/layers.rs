@@ -0,0 +1,132 @@
+//! Maps of several floors, for `--layers`: the floors are kept stacked top
+//! to bottom in one grid, each `height / layers` rows, so searching,
+//! drawing and saving work on them as on any map. Moves never leave a
+//! floor over its top or bottom edge; the only way between floors is a
+//! staircase, a cell that leads to the cell right above or below it on the
+//! next floor when that one is a staircase too.
+
+use crate::Grid;
+use rand::Rng;
+use rand::seq::index;
+
+/// A staircase named on the command line: X, Y and the layer, or, without
+/// one, a shaft of staircases through every layer.
+pub type Stair = (usize, usize, Option<usize>);
+
+pub fn parse_stair(text: &str) -> Result<Stair, String> {
+    let coordinate = |value: &str| {
+        value
+            .trim()
+            .parse::<usize>()
+            .map_err(|_| format!("invalid coordinate '{}'", value))
+    };
+    match text.split(',').collect::<Vec<_>>()[..] {
+        [x, y] => Ok((coordinate(x)?, coordinate(y)?, None)),
+        [x, y, z] => Ok((coordinate(x)?, coordinate(y)?, Some(coordinate(z)?))),
+        _ => Err(format!("expected X,Y or X,Y,LAYER, got '{}'", text)),
+    }
+}
+
+/// `floors` stacked into one map, the first on top. They must all be the
+/// same size.
+pub fn stack(floors: Vec<Grid>) -> Grid {
+    let layers = floors.len();
+    let cells: Vec<u8> = floors.iter().flat_map(Grid::values).collect();
+    let height = floors.iter().map(|floor| floor.height).sum();
+    let first = floors.into_iter().next().expect("at least one floor");
+    Grid {
+        height,
+        cells: Box::new(cells),
+        layers,
+        ..first
+    }
+}
+
+/// Splits a flat map into `layers` floors of its rows, top to bottom.
+pub fn split(grid: &mut Grid, layers: usize) -> Result<(), String> {
+    if grid.layers > 1 {
+        return if grid.layers == layers {
+            Ok(())
+        } else {
+            Err(format!("the map already has {} layers", grid.layers))
+        };
+    }
+    if !grid.height.is_multiple_of(layers) {
+        return Err(format!(
+            "its {} rows don't split into {} layers",
+            grid.height, layers
+        ));
+    }
+    grid.layers = layers;
+    Ok(())
+}
+
+/// Adds the staircases of `stairs` to `grid`.
+pub fn add_stairs(grid: &mut Grid, stairs: &[Stair]) -> Result<(), String> {
+    if !stairs.is_empty() && grid.layers == 1 {
+        return Err("staircases need a map of several layers".to_string());
+    }
+    let floor = grid.floor_height();
+    for &(x, y, z) in stairs {
+        let outside = x >= grid.width || y >= floor || z.is_some_and(|z| z >= grid.layers);
+        if outside {
+            return Err(format!(
+                "({}, {}{}) is outside the {}x{} grid of {} layers",
+                x,
+                y,
+                z.map_or(String::new(), |z| format!(", {}", z)),
+                grid.width,
+                floor,
+                grid.layers
+            ));
+        }
+        let layers = match z {
+            Some(z) => z..z + 1,
+            None => 0..grid.layers,
+        };
+        for z in layers {
+            grid.stairs.insert(grid.coords_to_index(x, z * floor + y));
+        }
+    }
+    Ok(())
+}
+
+/// Puts shafts of staircases through every floor, at random places open
+/// on all of them: about one for every 64 cells of a floor, at least one.
+pub fn add_random_shafts(grid: &mut Grid, rng: &mut impl Rng) {
+    let floor = grid.width * grid.floor_height();
+    let open: Vec<usize> = (0..floor)
+        .filter(|&cell| (0..grid.layers).all(|z| !grid.is_wall(z * floor + cell)))
+        .collect();
+    let count = (floor / 64).max(1).min(open.len());
+    for pick in index::sample(rng, open.len(), count) {
+        for z in 0..grid.layers {
+            grid.stairs.insert(z * floor + open[pick]);
+        }
+    }
+}
+
+/// Floor `z` of `grid` as a map of its own, and where its cells start in
+/// `grid`.
+pub fn floor(grid: &Grid, z: usize) -> (Grid, usize) {
+    let size = grid.width * grid.floor_height();
+    let first = z * size;
+    let cells: Vec<u8> = (first..first + size).map(|cell| grid.value(cell)).collect();
+    let floor = Grid {
+        width: grid.width,
+        height: grid.floor_height(),
+        cells: Box::new(cells),
+        wall: grid.wall,
+        diagonal: grid.diagonal,
+        wrap: grid.wrap,
+        layers: 1,
+        stairs: grid
+            .stairs
+            .iter()
+            .filter(|&&cell| (first..first + size).contains(&cell))
+            .map(|&cell| cell - first)
+            .collect(),
+        cost: grid.cost.clone(),
+    };
+    (floor, first)
+}
@@ -0,0 +1,68 @@
+//! The solver behind `hexpath`: grids of byte-valued cells, the searches
+//! that find paths across them and the generators that make them, with no
+//! terminal or file formats attached.
+//!
+//! A [`Grid`] keeps its cells in any [`Storage`], a `Vec<u8>` or a backend
+//! of your own; every search is a [`Pathfinder`](search::Pathfinder) run on
+//! one, coming back with a [`Search`](search::Search):
+//!
+//! ```
+//! use hexpath_core::search::{Algo, Unwatched};
+//! use hexpath_core::{Grid, Storage};
+//!
+//! // A game's own map, seen as cell values: 1 for grass, walls 0xFF.
+//! struct Tiles(Vec<bool>);
+//!
+//! impl Storage for Tiles {
+//!     fn len(&self) -> usize {
+//!         self.0.len()
+//!     }
+//!
+//!     fn get(&self, index: usize) -> u8 {
+//!         if self.0[index] { 0xFF } else { 1 }
+//!     }
+//!
+//!     fn set(&mut self, index: usize, value: u8) {
+//!         self.0[index] = value == 0xFF;
+//!     }
+//!
+//!     fn boxed(&self) -> Box<dyn Storage> {
+//!         Box::new(Tiles(self.0.clone()))
+//!     }
+//! }
+//!
+//! let mut walls = vec![false; 9];
+//! walls[4] = true;
+//! let grid = Grid::new(3, 3, Tiles(walls), 0xFF);
+//!
+//! let astar = Algo::Astar.pathfinder(false, false).unwrap();
+//! let search = astar.find(&grid, 0, 8, &mut Unwatched);
+//! let (path, cost) = search.found.unwrap();
+//! assert_eq!(path.len(), 5);
+//! assert_eq!(cost, 4);
+//! ```
+//!
+//! For maps too big to hold a byte a cell, [`storage`] has backends that
+//! pack them smaller.
+//!
+//! With the `clap` feature the enums picking algorithms and generators can
+//! be command-line arguments.
+
+pub mod agents;
+pub mod anyangle;
+pub mod cost;
+pub mod delta;
+pub mod flow;
+pub mod frontier;
+mod grid;
+pub mod layers;
+pub mod longest;
+pub mod maze;
+pub mod noise;
+pub mod route;
+pub mod search;
+mod stochastic;
+pub mod storage;
+pub mod weighted;
+
+pub use grid::{Grid, Storage};
@@ -0,0 +1,392 @@
+//! The most expensive path for `--both`, by the method `--max-path` picks.
+//! Unlike the cheapest path, the most expensive simple path is NP-hard in
+//! general, so there are several trade-offs:
+//!
+//! - `greedy` walks to the dearest unvisited neighbor each step; quick, but
+//!   it often paints itself into a corner;
+//! - `monotone` only allows moves toward the end, which makes the map a DAG
+//!   with an exact answer in one pass; it is the best such path, not
+//!   necessarily the best of all;
+//! - `exact` searches every simple path with branch and bound, and says so
+//!   if the time budget ran out before it could prove its best;
+//! - `aco`, `annealing` and `genetic` start from the greedy and monotone
+//!   paths and improve on them at random, for a number of rounds or until
+//!   the time budget runs out; the same generator state gives the same
+//!   path.
+
+use crate::Grid;
+use crate::stochastic::{self, Run};
+use rand::Rng;
+use std::collections::{HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum MaxMethod {
+    /// Always step to the dearest unvisited neighbor
+    Greedy,
+    /// Exact, among paths that only move toward the end
+    Monotone,
+    /// Exact over all simple paths, within --time-budget
+    Exact,
+    /// An ant colony: paths walked at random, dear moves and those of dear
+    /// paths before likelier
+    Aco,
+    /// Simulated annealing: one path, changed a detour at a time
+    Annealing,
+    /// A genetic algorithm: a population of paths crossed and mutated
+    Genetic,
+}
+
+impl MaxMethod {
+    /// Whether the method draws on the random generator.
+    pub fn stochastic(self) -> bool {
+        matches!(
+            self,
+            MaxMethod::Aco | MaxMethod::Annealing | MaxMethod::Genetic
+        )
+    }
+
+    /// The rounds a stochastic method runs if not told.
+    fn rounds(self) -> usize {
+        match self {
+            MaxMethod::Aco => 100,
+            MaxMethod::Annealing => 20_000,
+            _ => 200,
+        }
+    }
+}
+
+/// How long the slower methods may search.
+#[derive(Clone, Copy, Debug)]
+pub struct Budget {
+    /// Before they settle for their best path so far.
+    pub time: Duration,
+    /// Rounds of a stochastic method, if not its own default.
+    pub rounds: Option<usize>,
+}
+
+/// What a longest-path search came back with.
+pub struct Longest {
+    /// The path, start and end included, and its cost.
+    pub found: Option<(Vec<usize>, usize)>,
+    /// How it was found, for the output.
+    pub method: &'static str,
+    /// Why nothing was, if so.
+    pub miss: &'static str,
+    /// For a stochastic method, the rounds it ran and those it was to.
+    pub rounds: Option<(usize, usize)>,
+    /// For a stochastic method, what the greedy path costs, if there is
+    /// one, to compare with.
+    pub greedy: Option<usize>,
+}
+
+/// The most expensive path from `start` to `end` by `method`; the
+/// stochastic ones draw from `rng`.
+pub fn find(
+    grid: &Grid,
+    start: usize,
+    end: usize,
+    method: MaxMethod,
+    budget: Budget,
+    rng: &mut impl Rng,
+) -> Longest {
+    let quick = |found, method, miss| Longest {
+        found,
+        method,
+        miss,
+        rounds: None,
+        greedy: None,
+    };
+    match method {
+        MaxMethod::Greedy => quick(
+            greedy(grid, start, end),
+            "greedy approximation",
+            "the greedy walk ran into a dead end",
+        ),
+        MaxMethod::Monotone => quick(
+            monotone(grid, start, end),
+            "exact among monotone paths",
+            "walls block every path that only heads toward the end",
+        ),
+        MaxMethod::Aco | MaxMethod::Annealing | MaxMethod::Genetic => {
+            let baseline = greedy(grid, start, end);
+            let greedy_cost = baseline.as_ref().map(|&(_, cost)| cost);
+            let seeds: Vec<_> = [baseline, monotone(grid, start, end)]
+                .into_iter()
+                .flatten()
+                .collect();
+            let rounds = budget.rounds.unwrap_or(method.rounds());
+            let deadline = Instant::now() + budget.time;
+            let (run, name): (Run, _) = match method {
+                MaxMethod::Aco => (
+                    stochastic::ant_colony(grid, start, end, seeds, rounds, deadline, rng),
+                    "ant colony",
+                ),
+                MaxMethod::Annealing => (
+                    stochastic::annealing(grid, start, end, seeds, rounds, deadline, rng),
+                    "simulated annealing",
+                ),
+                _ => (
+                    stochastic::genetic(grid, start, end, seeds, rounds, deadline, rng),
+                    "genetic algorithm",
+                ),
+            };
+            Longest {
+                found: run.found,
+                method: name,
+                miss: "walls cut the end off",
+                rounds: Some((run.rounds, rounds)),
+                greedy: greedy_cost,
+            }
+        }
+        MaxMethod::Exact => {
+            let (found, proven) = branch_and_bound(grid, start, end, budget.time);
+            Longest {
+                found,
+                method: if proven {
+                    "exact, branch and bound"
+                } else {
+                    "branch and bound, best found before the time budget ran out"
+                },
+                miss: if proven {
+                    "walls cut the end off"
+                } else {
+                    "none turned up before the time budget ran out"
+                },
+                rounds: None,
+                greedy: None,
+            }
+        }
+    }
+}
+
+fn greedy(grid: &Grid, start: usize, end: usize) -> Option<(Vec<usize>, usize)> {
+    let mut path = vec![start];
+    let mut visited = HashSet::new();
+    let mut current = start;
+    let mut total_cost = 0;
+
+    visited.insert(start);
+
+    while current != end {
+        let neighbors = grid.neighbors(current);
+        let mut best_neighbor = None;
+        let mut best_cost = 0;
+
+        for &neighbor in &neighbors {
+            if !visited.contains(&neighbor) {
+                let cost = grid.step_cost(current, neighbor);
+                if cost > best_cost {
+                    best_cost = cost;
+                    best_neighbor = Some(neighbor);
+                }
+            }
+        }
+
+        if let Some(next) = best_neighbor {
+            total_cost += best_cost;
+            current = next;
+            visited.insert(current);
+            path.push(current);
+        } else {
+            let mut found = false;
+            for &neighbor in &neighbors {
+                if !visited.contains(&neighbor) {
+                    current = neighbor;
+                    visited.insert(current);
+                    path.push(current);
+                    total_cost += grid.step_cost(current, neighbor);
+                    found = true;
+                    break;
+                }
+            }
+            if !found {
+                return None;
+            }
+        }
+
+        if path.len() > grid.len() {
+            return None;
+        }
+    }
+
+    Some((path, total_cost))
+}
+
+/// The dearest path whose every move gets closer to `end` along x, y or
+/// both, never overshooting it.
+fn monotone(grid: &Grid, start: usize, end: usize) -> Option<(Vec<usize>, usize)> {
+    let (start_x, start_y) = grid.index_to_coords(start);
+    let (end_x, end_y) = grid.index_to_coords(end);
+    let inside = |x: usize, y: usize| {
+        x.abs_diff(start_x) + x.abs_diff(end_x) == start_x.abs_diff(end_x)
+            && y.abs_diff(start_y) + y.abs_diff(end_y) == start_y.abs_diff(end_y)
+    };
+    let from_start = |cell: usize| {
+        let (x, y) = grid.index_to_coords(cell);
+        x.abs_diff(start_x) + y.abs_diff(start_y)
+    };
+
+    // Every allowed move gets further from `start`, so cells in order of
+    // that distance are in topological order.
+    let mut order: Vec<usize> = (0..grid.len())
+        .filter(|&cell| {
+            let (x, y) = grid.index_to_coords(cell);
+            inside(x, y) && !grid.is_wall(cell)
+        })
+        .collect();
+    order.sort_by_key(|&cell| from_start(cell));
+
+    let mut best: Vec<Option<usize>> = vec![None; grid.len()];
+    let mut prev = vec![None; grid.len()];
+    best[start] = Some(0);
+    for cell in order {
+        let Some(cost) = best[cell] else {
+            continue;
+        };
+        for neighbor in grid.neighbors(cell) {
+            let (x, y) = grid.index_to_coords(neighbor);
+            if !inside(x, y) || from_start(neighbor) <= from_start(cell) {
+                continue;
+            }
+            let new_cost = cost + grid.step_cost(cell, neighbor);
+            if best[neighbor].is_none_or(|old| new_cost > old) {
+                best[neighbor] = Some(new_cost);
+                prev[neighbor] = Some(cell);
+            }
+        }
+    }
+
+    let cost = best[end]?;
+    let mut path = Vec::new();
+    let mut current = Some(end);
+    while let Some(pos) = current {
+        path.push(pos);
+        current = prev[pos];
+    }
+    path.reverse();
+    Some((path, cost))
+}
+
+/// Depth-first over simple paths, dearest moves first, dropping any branch
+/// that can't beat the best path so far even if it took every cell it can
+/// still reach. `true` with the path if the search finished, so it is the
+/// most expensive one.
+fn branch_and_bound(
+    grid: &Grid,
+    start: usize,
+    end: usize,
+    budget: Duration,
+) -> (Option<(Vec<usize>, usize)>, bool) {
+    if start == end {
+        return (Some((vec![start], 0)), true);
+    }
+    let deadline = Instant::now() + budget;
+
+    // The most entering a cell can cost, by whichever move.
+    let mut dearest = vec![0; grid.len()];
+    for cell in 0..grid.len() {
+        for next in grid.neighbors(cell) {
+            dearest[next] = dearest[next].max(grid.step_cost(cell, next));
+        }
+    }
+
+    // Start from the better of the quick answers, so pruning bites early.
+    let mut best = [greedy(grid, start, end), monotone(grid, start, end)]
+        .into_iter()
+        .flatten()
+        .max_by_key(|&(_, cost)| cost);
+
+    let mut on_path = vec![false; grid.len()];
+    let mut path = vec![start];
+    let mut cost = 0;
+    on_path[start] = true;
+
+    // Each level holds the moves still to try from the cell at that depth.
+    let mut stack = vec![moves(grid, start)];
+    while let Some(options) = stack.last_mut() {
+        if Instant::now() >= deadline {
+            return (best, false);
+        }
+        let Some(next) = options.pop() else {
+            stack.pop();
+            let cell = path.pop().expect("one cell per level");
+            on_path[cell] = false;
+            if let Some(&from) = path.last() {
+                cost -= grid.step_cost(from, cell);
+            }
+            continue;
+        };
+        if on_path[next] {
+            continue;
+        }
+
+        let here = *path.last().expect("start stays on the path");
+        let new_cost = cost + grid.step_cost(here, next);
+        let best_cost = best.as_ref().map(|&(_, cost)| cost);
+        if next == end {
+            if best_cost.is_none_or(|best| new_cost > best) {
+                let mut found = path.clone();
+                found.push(end);
+                best = Some((found, new_cost));
+            }
+            continue;
+        }
+
+        on_path[next] = true;
+        let Some(rest) = reachable_bound(grid, next, end, &on_path, &dearest) else {
+            on_path[next] = false;
+            continue;
+        };
+        if best_cost.is_some_and(|best| new_cost + rest <= best) {
+            on_path[next] = false;
+            continue;
+        }
+        path.push(next);
+        cost = new_cost;
+        stack.push(moves(grid, next));
+    }
+    (best, true)
+}
+
+/// The neighbors of `cell`, dearest last, since they are popped first.
+fn moves(grid: &Grid, cell: usize) -> Vec<usize> {
+    let mut moves = grid.neighbors(cell);
+    moves.sort_by_key(|&next| grid.step_cost(cell, next));
+    moves
+}
+
+/// An upper bound on what a path from `from` to `end` can still add: the
+/// dearest entry of every cell reachable without crossing `on_path`.
+/// `None` if `end` can't be reached at all.
+fn reachable_bound(
+    grid: &Grid,
+    from: usize,
+    end: usize,
+    on_path: &[bool],
+    dearest: &[usize],
+) -> Option<usize> {
+    let mut seen = vec![false; grid.len()];
+    let mut queue = VecDeque::from([from]);
+    let mut bound = 0;
+    let mut reaches_end = false;
+    seen[from] = true;
+
+    while let Some(cell) = queue.pop_front() {
+        for neighbor in grid.neighbors(cell) {
+            if seen[neighbor] || on_path[neighbor] {
+                continue;
+            }
+            seen[neighbor] = true;
+            bound += dearest[neighbor];
+            if neighbor == end {
+                // Nothing is entered after the end.
+                reaches_end = true;
+                continue;
+            }
+            queue.push_back(neighbor);
+        }
+    }
+    reaches_end.then_some(bound)
+}
@@ -0,0 +1,236 @@
+//! Maze maps for `--generate-maze`. Rooms sit on the even coordinates and
+//! everything else starts as wall; the algorithm then knocks down walls
+//! between neighboring rooms until every room is connected, by a single
+//! route in an odd-sized maze. Open cells get small random costs, walls
+//! the `--blocked` value.
+
+use crate::Grid;
+use rand::Rng;
+use rand::seq::{IndexedRandom, SliceRandom};
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum MazeAlgo {
+    /// Depth-first backtracking: long, winding corridors
+    Dfs,
+    /// Randomized Prim: many short dead ends
+    Prim,
+    /// Randomized Kruskal: branches spread evenly
+    Kruskal,
+}
+
+/// The rooms of a maze, numbered row by row.
+struct Rooms {
+    columns: usize,
+    rows: usize,
+}
+
+impl Rooms {
+    fn count(&self) -> usize {
+        self.columns * self.rows
+    }
+
+    fn neighbors(&self, room: usize) -> Vec<usize> {
+        let (column, row) = (room % self.columns, room / self.columns);
+        let mut neighbors = Vec::new();
+        if row > 0 {
+            neighbors.push(room - self.columns);
+        }
+        if row + 1 < self.rows {
+            neighbors.push(room + self.columns);
+        }
+        if column > 0 {
+            neighbors.push(room - 1);
+        }
+        if column + 1 < self.columns {
+            neighbors.push(room + 1);
+        }
+        neighbors
+    }
+
+    /// The grid cell of a room.
+    fn cell(&self, room: usize) -> (usize, usize) {
+        (room % self.columns * 2, room / self.columns * 2)
+    }
+}
+
+/// A `width` x `height` maze, walls holding the value `wall`.
+pub fn generate(width: usize, height: usize, algo: MazeAlgo, wall: u8, rng: &mut impl Rng) -> Grid {
+    let rooms = Rooms {
+        columns: width.div_ceil(2),
+        rows: height.div_ceil(2),
+    };
+    let passages = if rooms.count() == 0 {
+        Vec::new()
+    } else {
+        match algo {
+            MazeAlgo::Dfs => depth_first(&rooms, rng),
+            MazeAlgo::Prim => prim(&rooms, rng),
+            MazeAlgo::Kruskal => kruskal(&rooms, rng),
+        }
+    };
+
+    let mut grid = Grid::new(width, height, vec![wall; width * height], wall);
+    let mut open = |(x, y): (usize, usize)| {
+        grid.set_value(y * width + x, corridor_cost(rng, wall));
+    };
+    for room in 0..rooms.count() {
+        open(rooms.cell(room));
+    }
+    for (from, to) in passages {
+        let (from_x, from_y) = rooms.cell(from);
+        let (to_x, to_y) = rooms.cell(to);
+        open(((from_x + to_x) / 2, (from_y + to_y) / 2));
+    }
+
+    // An even size leaves a last column (or row) with no rooms in it. It
+    // follows the one before, so the far corners are open too.
+    if width >= 2 && width.is_multiple_of(2) {
+        for y in 0..height {
+            if grid.value(y * width + width - 2) != wall {
+                grid.set_value(y * width + width - 1, corridor_cost(rng, wall));
+            }
+        }
+    }
+    if height >= 2 && height.is_multiple_of(2) {
+        for x in 0..width {
+            if grid.value((height - 2) * width + x) != wall {
+                grid.set_value((height - 1) * width + x, corridor_cost(rng, wall));
+            }
+        }
+    }
+    grid
+}
+
+/// A low cost for an open cell, never the wall value.
+fn corridor_cost(rng: &mut impl Rng, wall: u8) -> u8 {
+    loop {
+        let cost = rng.random_range(0x01..=0x1F);
+        if cost != wall {
+            return cost;
+        }
+    }
+}
+
+/// Walks from room to random unvisited neighbor, backing up at dead ends.
+fn depth_first(rooms: &Rooms, rng: &mut impl Rng) -> Vec<(usize, usize)> {
+    let mut visited = vec![false; rooms.count()];
+    let mut passages = Vec::new();
+    let mut stack = vec![0];
+    visited[0] = true;
+
+    while let Some(&room) = stack.last() {
+        let unvisited: Vec<usize> = rooms
+            .neighbors(room)
+            .into_iter()
+            .filter(|&neighbor| !visited[neighbor])
+            .collect();
+        match unvisited.choose(rng) {
+            Some(&next) => {
+                visited[next] = true;
+                passages.push((room, next));
+                stack.push(next);
+            }
+            None => {
+                stack.pop();
+            }
+        }
+    }
+    passages
+}
+
+/// Grows the maze from one room, opening a random wall on its border each
+/// time.
+fn prim(rooms: &Rooms, rng: &mut impl Rng) -> Vec<(usize, usize)> {
+    let mut in_maze = vec![false; rooms.count()];
+    let mut passages = Vec::new();
+    let mut frontier: Vec<(usize, usize)> =
+        rooms.neighbors(0).into_iter().map(|to| (0, to)).collect();
+    in_maze[0] = true;
+
+    while !frontier.is_empty() {
+        let (from, to) = frontier.swap_remove(rng.random_range(0..frontier.len()));
+        if in_maze[to] {
+            continue;
+        }
+        in_maze[to] = true;
+        passages.push((from, to));
+        for next in rooms.neighbors(to) {
+            if !in_maze[next] {
+                frontier.push((to, next));
+            }
+        }
+    }
+    passages
+}
+
+/// Opens walls in random order, skipping those between rooms already
+/// connected.
+fn kruskal(rooms: &Rooms, rng: &mut impl Rng) -> Vec<(usize, usize)> {
+    let mut walls: Vec<(usize, usize)> = (0..rooms.count())
+        .flat_map(|room| {
+            rooms
+                .neighbors(room)
+                .into_iter()
+                .filter(move |&neighbor| neighbor > room)
+                .map(move |neighbor| (room, neighbor))
+        })
+        .collect();
+    walls.shuffle(rng);
+
+    // Union-find over the rooms: `set[room]` leads to its set's root.
+    let mut set: Vec<usize> = (0..rooms.count()).collect();
+    fn root(set: &mut [usize], mut room: usize) -> usize {
+        while set[room] != room {
+            set[room] = set[set[room]];
+            room = set[room];
+        }
+        room
+    }
+
+    let mut passages = Vec::new();
+    for (from, to) in walls {
+        let (from_root, to_root) = (root(&mut set, from), root(&mut set, to));
+        if from_root != to_root {
+            set[from_root] = to_root;
+            passages.push((from, to));
+        }
+    }
+    passages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn every_open_cell_is_reached() {
+        for seed in 0..60 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let (width, height) = (rng.random_range(1..24), rng.random_range(1..24));
+            for algo in [MazeAlgo::Dfs, MazeAlgo::Prim, MazeAlgo::Kruskal] {
+                let grid = generate(width, height, algo, 0xFF, &mut rng);
+                let context = format!("{:?} {}x{} on seed {}", algo, width, height, seed);
+                assert!(!grid.is_wall(0), "{}", context);
+                assert!(!grid.is_wall(grid.len() - 1), "{}", context);
+
+                let mut reached = vec![false; grid.len()];
+                let mut queue = vec![0];
+                reached[0] = true;
+                while let Some(cell) = queue.pop() {
+                    for neighbor in grid.neighbors(cell) {
+                        if !reached[neighbor] {
+                            reached[neighbor] = true;
+                            queue.push(neighbor);
+                        }
+                    }
+                }
+                for (cell, &reached) in reached.iter().enumerate() {
+                    assert_eq!(reached, !grid.is_wall(cell), "{}: {}", context, cell);
+                }
+            }
+        }
+    }
+}
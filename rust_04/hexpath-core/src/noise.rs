@@ -0,0 +1,173 @@
+//! Coherent noise for `--generate-terrain`: values that change smoothly
+//! from cell to cell, like elevation, so cheap valleys and expensive ridges
+//! show up as regions instead of speckle. Both kinds are gradient noise
+//! over a permutation table shuffled by the caller's generator, summed over
+//! a few octaves for detail at several sizes.
+
+use crate::Grid;
+use rand::Rng;
+use rand::seq::SliceRandom;
+use std::f64::consts::FRAC_1_SQRT_2;
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum NoiseKind {
+    /// Perlin noise on a square lattice
+    Perlin,
+    /// Simplex noise on a triangular lattice, with fewer axis-aligned
+    /// artifacts
+    Simplex,
+}
+
+/// Octaves summed, each at twice the frequency and half the weight of the
+/// one before.
+const OCTAVES: u32 = 4;
+
+const GRADIENTS: [(f64, f64); 8] = [
+    (1.0, 0.0),
+    (-1.0, 0.0),
+    (0.0, 1.0),
+    (0.0, -1.0),
+    (FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+    (-FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+    (FRAC_1_SQRT_2, -FRAC_1_SQRT_2),
+    (-FRAC_1_SQRT_2, -FRAC_1_SQRT_2),
+];
+
+struct Noise {
+    kind: NoiseKind,
+    /// A shuffled 0..=255, twice, so two lookups never wrap.
+    permutation: Vec<u8>,
+}
+
+impl Noise {
+    fn new(kind: NoiseKind, rng: &mut impl Rng) -> Self {
+        let mut permutation: Vec<u8> = (0..=255).collect();
+        permutation.shuffle(rng);
+        permutation.extend_from_within(..);
+        Noise { kind, permutation }
+    }
+
+    /// The gradient at lattice point `(x, y)`.
+    fn gradient(&self, x: i64, y: i64) -> (f64, f64) {
+        let hash =
+            self.permutation[self.permutation[(x & 255) as usize] as usize + (y & 255) as usize];
+        GRADIENTS[hash as usize % GRADIENTS.len()]
+    }
+
+    /// Noise at `(x, y)`, roughly from -1 to 1.
+    fn sample(&self, x: f64, y: f64) -> f64 {
+        match self.kind {
+            NoiseKind::Perlin => self.perlin(x, y),
+            NoiseKind::Simplex => self.simplex(x, y),
+        }
+    }
+
+    fn fractal(&self, x: f64, y: f64) -> f64 {
+        let mut total = 0.0;
+        let mut frequency = 1.0;
+        let mut weight = 1.0;
+        for _ in 0..OCTAVES {
+            total += weight * self.sample(x * frequency, y * frequency);
+            frequency *= 2.0;
+            weight /= 2.0;
+        }
+        total
+    }
+
+    fn perlin(&self, x: f64, y: f64) -> f64 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (dx, dy) = (x - x0, y - y0);
+        let (ix, iy) = (x0 as i64, y0 as i64);
+        let corner = |cx: i64, cy: i64, dx: f64, dy: f64| {
+            let (gx, gy) = self.gradient(cx, cy);
+            gx * dx + gy * dy
+        };
+        // Smootherstep, so the slope is continuous across cell borders.
+        let fade = |t: f64| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+        let lerp = |t: f64, a: f64, b: f64| a + t * (b - a);
+        let (u, v) = (fade(dx), fade(dy));
+        let top = lerp(u, corner(ix, iy, dx, dy), corner(ix + 1, iy, dx - 1.0, dy));
+        let bottom = lerp(
+            u,
+            corner(ix, iy + 1, dx, dy - 1.0),
+            corner(ix + 1, iy + 1, dx - 1.0, dy - 1.0),
+        );
+        lerp(v, top, bottom) * std::f64::consts::SQRT_2
+    }
+
+    fn simplex(&self, x: f64, y: f64) -> f64 {
+        let skew = 0.5 * (3f64.sqrt() - 1.0);
+        let unskew = (3.0 - 3f64.sqrt()) / 6.0;
+
+        // The triangle holding the point, and the point's offset from each
+        // of its three corners.
+        let s = (x + y) * skew;
+        let (i, j) = ((x + s).floor(), (y + s).floor());
+        let t = (i + j) * unskew;
+        let (x0, y0) = (x - (i - t), y - (j - t));
+        let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+        let (ix, iy) = (i as i64, j as i64);
+        let corners = [
+            (ix, iy, x0, y0),
+            (
+                ix + i1,
+                iy + j1,
+                x0 - i1 as f64 + unskew,
+                y0 - j1 as f64 + unskew,
+            ),
+            (
+                ix + 1,
+                iy + 1,
+                x0 - 1.0 + 2.0 * unskew,
+                y0 - 1.0 + 2.0 * unskew,
+            ),
+        ];
+
+        let total: f64 = corners
+            .iter()
+            .map(|&(cx, cy, dx, dy)| {
+                let falloff = 0.5 - dx * dx - dy * dy;
+                if falloff <= 0.0 {
+                    return 0.0;
+                }
+                let (gx, gy) = self.gradient(cx, cy);
+                falloff.powi(4) * (gx * dx + gy * dy)
+            })
+            .sum();
+        70.0 * total
+    }
+}
+
+/// A `width` x `height` terrain whose features are about `scale` cells
+/// across. Costs span the whole byte range except `wall`.
+pub fn generate(
+    width: usize,
+    height: usize,
+    kind: NoiseKind,
+    scale: f64,
+    wall: u8,
+    rng: &mut impl Rng,
+) -> Grid {
+    let noise = Noise::new(kind, rng);
+    let samples: Vec<f64> = (0..width * height)
+        .map(|index| {
+            let (x, y) = (index % width, index / width);
+            noise.fractal(x as f64 / scale, y as f64 / scale)
+        })
+        .collect();
+
+    // Stretched to the full range, whatever the octaves added up to.
+    let low = samples.iter().copied().fold(f64::INFINITY, f64::min);
+    let high = samples.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let span = if high > low { high - low } else { 1.0 };
+    let cells: Vec<u8> = samples
+        .iter()
+        .map(|sample| {
+            let value = ((sample - low) / span * 254.0).round() as u8;
+            if value >= wall { value + 1 } else { value }
+        })
+        .collect();
+
+    Grid::new(width, height, cells, wall)
+}
@@ -0,0 +1,161 @@
+//! Routes through `--via` waypoints: one search per leg, stitched into a
+//! single path. With `--optimize-order` the waypoints are visited in
+//! whichever order is cheapest, found exactly (Held-Karp) over the costs of
+//! every leg between them, which is why their number is capped.
+
+use crate::Grid;
+use crate::search::{Pathfinder, Search, Watch};
+use std::collections::HashMap;
+
+/// Most waypoints `--optimize-order` takes: it runs a search per ordered
+/// pair of them and its table grows as 2^n.
+pub const MAX_OPTIMIZED: usize = 12;
+
+pub struct Route {
+    /// Start, the waypoints in the order visited, end.
+    pub stops: Vec<usize>,
+    /// The stitched path and its cost, and the cells all searches expanded.
+    pub search: Search,
+    /// The first leg of `stops` no path joins, if none was found.
+    pub blocked: Option<(usize, usize)>,
+}
+
+/// The path from `start` through `via` to `end`, taking the waypoints in
+/// the order given or, if `optimize`, in the cheapest order.
+pub fn plan(
+    pathfinder: &dyn Pathfinder,
+    grid: &Grid,
+    start: usize,
+    via: &[usize],
+    end: usize,
+    optimize: bool,
+    watch: &mut dyn Watch,
+) -> Route {
+    // Stops by number: 0 is the start, 1..=via.len() the waypoints, and
+    // via.len() + 1 the end.
+    let points: Vec<usize> = [start]
+        .into_iter()
+        .chain(via.iter().copied())
+        .chain([end])
+        .collect();
+    let mut legs = HashMap::new();
+    let mut expanded = 0;
+    let mut leg = |from: usize, to: usize| -> Option<usize> {
+        let search = legs.entry((from, to)).or_insert_with(|| {
+            let search = pathfinder.find(grid, points[from], points[to], watch);
+            expanded += search.expanded;
+            search
+        });
+        search.found.as_ref().map(|&(_, cost)| cost)
+    };
+
+    let given: Vec<usize> = (0..points.len()).collect();
+    let order = if optimize && via.len() > 1 {
+        cheapest_order(via.len(), &mut leg).unwrap_or(given)
+    } else {
+        given
+    };
+    for pair in order.windows(2) {
+        leg(pair[0], pair[1]);
+    }
+
+    let stops = order.iter().map(|&stop| points[stop]).collect();
+    let mut path = Vec::new();
+    let mut cost = 0;
+    // No leg costs more than its bound times the cheapest, so the whole
+    // path costs no more than the loosest of them times it.
+    let mut bound: Option<f64> = None;
+    for pair in order.windows(2) {
+        let leg = &legs[&(pair[0], pair[1])];
+        match &leg.found {
+            Some((leg_path, leg_cost)) => {
+                // Each leg starts where the one before ended.
+                let skip = if path.is_empty() { 0 } else { 1 };
+                path.extend_from_slice(&leg_path[skip..]);
+                cost += leg_cost;
+                if let Some(leg_bound) = leg.bound {
+                    bound = Some(bound.map_or(leg_bound, |bound| bound.max(leg_bound)));
+                }
+            }
+            None => {
+                return Route {
+                    stops,
+                    search: Search {
+                        found: None,
+                        expanded,
+                        bound: None,
+                    },
+                    blocked: Some((points[pair[0]], points[pair[1]])),
+                };
+            }
+        }
+    }
+
+    Route {
+        stops,
+        search: Search {
+            found: Some((path, cost)),
+            expanded,
+            bound,
+        },
+        blocked: None,
+    }
+}
+
+/// The cheapest order of stops 0, a permutation of 1..=waypoints, then
+/// waypoints + 1, by the costs `leg` gives (`None` for no path). `None` if
+/// no order gets through.
+fn cheapest_order(
+    waypoints: usize,
+    leg: &mut impl FnMut(usize, usize) -> Option<usize>,
+) -> Option<Vec<usize>> {
+    let end = waypoints + 1;
+    let full = (1 << waypoints) - 1;
+
+    // best[set][last]: the cheapest way from the start through the
+    // waypoints in `set` (bit i for waypoint i + 1), ending at `last`.
+    let mut best = vec![vec![None::<usize>; waypoints]; 1 << waypoints];
+    let mut prev = vec![vec![None::<usize>; waypoints]; 1 << waypoints];
+    for first in 0..waypoints {
+        best[1 << first][first] = leg(0, first + 1);
+    }
+    for set in 1..=full {
+        for last in 0..waypoints {
+            let Some(cost) = best[set][last] else {
+                continue;
+            };
+            for next in 0..waypoints {
+                if set & (1 << next) != 0 {
+                    continue;
+                }
+                let Some(step) = leg(last + 1, next + 1) else {
+                    continue;
+                };
+                let grown = set | (1 << next);
+                if best[grown][next].is_none_or(|old| cost + step < old) {
+                    best[grown][next] = Some(cost + step);
+                    prev[grown][next] = Some(last);
+                }
+            }
+        }
+    }
+
+    let (mut last, _) = (0..waypoints)
+        .filter_map(|last| Some((last, best[full][last]? + leg(last + 1, end)?)))
+        .min_by_key(|&(_, cost)| cost)?;
+
+    let mut order = vec![end];
+    let mut set = full;
+    loop {
+        order.push(last + 1);
+        let before = prev[set][last];
+        set &= !(1 << last);
+        match before {
+            Some(before) => last = before,
+            None => break,
+        }
+    }
+    order.push(0);
+    order.reverse();
+    Some(order)
+}
@@ -0,0 +1,738 @@
+//! The strategies `--algo` picks from, behind one `Pathfinder` trait so
+//! they can be run on the same map and compared: each one reports the path
+//! it settles on, what that path costs and how many cells it expanded on
+//! the way. Only Dijkstra and A* promise the cheapest path; the others trade
+//! that for fewer steps or less work.
+
+use crate::anyangle::ThetaStar;
+use crate::delta::DeltaStepping;
+use crate::frontier::{Bitset, IndexedHeap};
+use crate::{Grid, cost};
+use std::collections::{HashSet, VecDeque};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum Algo {
+    /// Cheapest path, expanding cells in order of cost so far
+    Dijkstra,
+    /// Fewest steps, whatever they cost
+    Bfs,
+    /// The first path a depth-first walk stumbles on
+    Dfs,
+    /// Heads straight for the end; fast, but often not the cheapest
+    GreedyBfs,
+    /// Cheapest path, like Dijkstra, steered toward the end
+    Astar,
+    /// A* cutting straight across open ground, drawn through its corners
+    ThetaStar,
+}
+
+impl Algo {
+    /// The strategy, searching from both ends at once if `bidirectional`,
+    /// which only Dijkstra and A* can, or on every core if `parallel`,
+    /// which only Dijkstra can.
+    pub fn pathfinder(
+        self,
+        bidirectional: bool,
+        parallel: bool,
+    ) -> Result<Box<dyn Pathfinder>, String> {
+        Ok(match (self, bidirectional, parallel) {
+            (Algo::Dijkstra, false, false) => Box::new(Dijkstra),
+            (Algo::Bfs, false, false) => Box::new(BreadthFirst),
+            (Algo::Dfs, false, false) => Box::new(DepthFirst),
+            (Algo::GreedyBfs, false, false) => Box::new(GreedyBestFirst),
+            (Algo::Astar, false, false) => Box::new(AStar),
+            (Algo::ThetaStar, false, false) => Box::new(ThetaStar),
+            (Algo::Dijkstra, true, false) => Box::new(Bidirectional { guided: false }),
+            (Algo::Astar, true, false) => Box::new(Bidirectional { guided: true }),
+            (Algo::Dijkstra, false, true) => Box::new(DeltaStepping),
+            (algo, true, false) => {
+                return Err(format!(
+                    "{} can't search from both ends; use dijkstra or astar",
+                    algo.name()
+                ));
+            }
+            (_, true, true) => {
+                return Err("a parallel search can't also search from both ends".to_string());
+            }
+            (algo, false, true) => {
+                return Err(format!(
+                    "{} has no parallel version; use dijkstra",
+                    algo.name()
+                ));
+            }
+        })
+    }
+
+    /// As `--algo` names it.
+    pub fn name(self) -> &'static str {
+        match self {
+            Algo::Dijkstra => "dijkstra",
+            Algo::Bfs => "bfs",
+            Algo::Dfs => "dfs",
+            Algo::GreedyBfs => "greedy-bfs",
+            Algo::Astar => "astar",
+            Algo::ThetaStar => "theta-star",
+        }
+    }
+}
+
+/// What a search came back with.
+pub struct Search {
+    /// The path, start and end included, and its cost.
+    pub found: Option<(Vec<usize>, usize)>,
+    /// Cells taken off the frontier and expanded.
+    pub expanded: usize,
+    /// For the searches that settle for good enough: at most how many
+    /// times the cheapest path's cost the one found costs.
+    pub bound: Option<f64>,
+}
+
+/// The cell a search just expanded, as a `Watch` is told.
+pub struct Expansion {
+    pub cell: usize,
+    /// Its cost from where its side of the search began, for the searches
+    /// that keep one.
+    pub cost: Option<usize>,
+    /// Cells still waiting on the frontier: the queue, stack or heap.
+    pub frontier: usize,
+}
+
+/// Sees a search as it runs, as `--animate` and `--export-gif` do.
+pub trait Watch {
+    /// Whether anything is looking, so searches can skip gathering what
+    /// `expanded` would be shown.
+    fn watching(&self) -> bool {
+        true
+    }
+
+    /// Called after each expansion with every cell expanded so far, `at`
+    /// being the latest.
+    fn expanded(&mut self, grid: &Grid, cells: &HashSet<usize>, at: &Expansion);
+
+    /// Called after each expansion, watching or not, with how many cells
+    /// have been expanded: the search gives up, finding nothing, as soon
+    /// as this says no.
+    fn keep_going(&mut self, _expanded: usize) -> bool {
+        true
+    }
+}
+
+/// Watches nothing, for searches nobody looks at.
+pub struct Unwatched;
+
+impl Watch for Unwatched {
+    fn watching(&self) -> bool {
+        false
+    }
+
+    fn expanded(&mut self, _grid: &Grid, _cells: &HashSet<usize>, _at: &Expansion) {}
+}
+
+impl<W: Watch + ?Sized> Watch for &mut W {
+    fn watching(&self) -> bool {
+        (**self).watching()
+    }
+
+    fn expanded(&mut self, grid: &Grid, cells: &HashSet<usize>, at: &Expansion) {
+        (**self).expanded(grid, cells, at);
+    }
+
+    fn keep_going(&mut self, expanded: usize) -> bool {
+        (**self).keep_going(expanded)
+    }
+}
+
+/// A watch that may not be there.
+impl<W: Watch> Watch for Option<W> {
+    fn watching(&self) -> bool {
+        self.as_ref().is_some_and(Watch::watching)
+    }
+
+    fn expanded(&mut self, grid: &Grid, cells: &HashSet<usize>, at: &Expansion) {
+        if let Some(watch) = self {
+            watch.expanded(grid, cells, at);
+        }
+    }
+
+    fn keep_going(&mut self, expanded: usize) -> bool {
+        self.as_mut().is_none_or(|watch| watch.keep_going(expanded))
+    }
+}
+
+/// Two watches on the same search, each told only while it is watching.
+impl<A: Watch, B: Watch> Watch for (A, B) {
+    fn watching(&self) -> bool {
+        self.0.watching() || self.1.watching()
+    }
+
+    fn expanded(&mut self, grid: &Grid, cells: &HashSet<usize>, at: &Expansion) {
+        if self.0.watching() {
+            self.0.expanded(grid, cells, at);
+        }
+        if self.1.watching() {
+            self.1.expanded(grid, cells, at);
+        }
+    }
+
+    /// Both are asked, so both hear how far the search has got.
+    fn keep_going(&mut self, expanded: usize) -> bool {
+        self.0.keep_going(expanded) & self.1.keep_going(expanded)
+    }
+}
+
+pub trait Pathfinder {
+    fn name(&self) -> &'static str;
+
+    /// Whether the path found is always a cheapest one.
+    fn optimal(&self) -> bool;
+
+    /// Whether the path is meant to be drawn through its corners, as
+    /// `--smooth` draws every path.
+    fn any_angle(&self) -> bool {
+        false
+    }
+
+    /// Searches `grid` from `start` to `end`, showing `watch` the expanded
+    /// cells after each expansion.
+    fn find(&self, grid: &Grid, start: usize, end: usize, watch: &mut dyn Watch) -> Search;
+}
+
+pub struct Dijkstra;
+pub struct BreadthFirst;
+pub struct DepthFirst;
+pub struct GreedyBestFirst;
+pub struct AStar;
+
+/// Dijkstra, or A* if `guided`, from both ends at once until the two
+/// searches meet.
+pub struct Bidirectional {
+    guided: bool,
+}
+
+impl Pathfinder for Dijkstra {
+    fn name(&self) -> &'static str {
+        "Dijkstra"
+    }
+
+    fn optimal(&self) -> bool {
+        true
+    }
+
+    fn find(&self, grid: &Grid, start: usize, end: usize, watch: &mut dyn Watch) -> Search {
+        best_first(grid, start, end, watch, |cost, _| cost as i64)
+    }
+}
+
+impl Pathfinder for AStar {
+    fn name(&self) -> &'static str {
+        "A*"
+    }
+
+    fn optimal(&self) -> bool {
+        true
+    }
+
+    fn find(&self, grid: &Grid, start: usize, end: usize, watch: &mut dyn Watch) -> Search {
+        // Never more than the real cost left, so the first path to reach
+        // the end is still a cheapest one.
+        let cheapest = cheapest_step(grid);
+        best_first(grid, start, end, watch, |cost, cell| {
+            (cost + cheapest * steps(grid, cell, end)) as i64
+        })
+    }
+}
+
+impl Pathfinder for GreedyBestFirst {
+    fn name(&self) -> &'static str {
+        "greedy best-first"
+    }
+
+    fn optimal(&self) -> bool {
+        false
+    }
+
+    fn find(&self, grid: &Grid, start: usize, end: usize, watch: &mut dyn Watch) -> Search {
+        best_first(grid, start, end, watch, |_, cell| {
+            steps(grid, cell, end) as i64
+        })
+    }
+}
+
+impl Pathfinder for BreadthFirst {
+    fn name(&self) -> &'static str {
+        "BFS"
+    }
+
+    fn optimal(&self) -> bool {
+        false
+    }
+
+    fn find(&self, grid: &Grid, start: usize, end: usize, watch: &mut dyn Watch) -> Search {
+        let mut prev = vec![None; grid.len()];
+        let mut seen = HashSet::from([start]);
+        let mut expanded = HashSet::new();
+        let mut queue = VecDeque::from([start]);
+
+        while let Some(position) = queue.pop_front() {
+            if position == end {
+                return finish(grid, &prev, end, expanded.len());
+            }
+            expanded.insert(position);
+            if watch.watching() {
+                let at = Expansion {
+                    cell: position,
+                    cost: None,
+                    frontier: queue.len(),
+                };
+                watch.expanded(grid, &expanded, &at);
+            }
+            if !watch.keep_going(expanded.len()) {
+                break;
+            }
+            for neighbor in grid.neighbors(position) {
+                if seen.insert(neighbor) {
+                    prev[neighbor] = Some(position);
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+
+        Search {
+            found: None,
+            expanded: expanded.len(),
+            bound: None,
+        }
+    }
+}
+
+impl Pathfinder for DepthFirst {
+    fn name(&self) -> &'static str {
+        "DFS"
+    }
+
+    fn optimal(&self) -> bool {
+        false
+    }
+
+    fn find(&self, grid: &Grid, start: usize, end: usize, watch: &mut dyn Watch) -> Search {
+        let mut prev = vec![None; grid.len()];
+        let mut expanded = HashSet::new();
+        let mut stack = vec![start];
+
+        while let Some(position) = stack.pop() {
+            if position == end {
+                return finish(grid, &prev, end, expanded.len());
+            }
+            if !expanded.insert(position) {
+                continue;
+            }
+            if watch.watching() {
+                let at = Expansion {
+                    cell: position,
+                    cost: None,
+                    frontier: stack.len(),
+                };
+                watch.expanded(grid, &expanded, &at);
+            }
+            if !watch.keep_going(expanded.len()) {
+                break;
+            }
+            // Pushed in reverse, so the first neighbor is tried first. The
+            // last push of a cell wins, as that is the one popped first.
+            for neighbor in grid.neighbors(position).into_iter().rev() {
+                if !expanded.contains(&neighbor) {
+                    prev[neighbor] = Some(position);
+                    stack.push(neighbor);
+                }
+            }
+        }
+
+        Search {
+            found: None,
+            expanded: expanded.len(),
+            bound: None,
+        }
+    }
+}
+
+impl Pathfinder for Bidirectional {
+    fn name(&self) -> &'static str {
+        if self.guided {
+            "bidirectional A*"
+        } else {
+            "bidirectional Dijkstra"
+        }
+    }
+
+    fn optimal(&self) -> bool {
+        true
+    }
+
+    fn find(&self, grid: &Grid, start: usize, end: usize, watch: &mut dyn Watch) -> Search {
+        let cells = grid.len();
+        let cheapest = if self.guided { cheapest_step(grid) } else { 0 };
+
+        // Each side steers by the average of the two heuristics,
+        // (toward its goal - toward its origin) / 2, so that both sides
+        // order cells by the same reduced costs and the usual stopping
+        // rule for bidirectional Dijkstra holds. Keys are doubled to stay
+        // whole numbers.
+        let balance = |cell: usize| {
+            cheapest as i64 * (steps(grid, cell, end) as i64 - steps(grid, cell, start) as i64)
+        };
+        let key = |cost: usize, cell: usize, forward: bool| {
+            let balance = balance(cell);
+            2 * cost as i64 + if forward { balance } else { -balance }
+        };
+
+        // Index 0 searches forward from `start`, 1 backward from `end`.
+        let mut dist = [vec![usize::MAX; cells], vec![usize::MAX; cells]];
+        let mut prev = [vec![None; cells], vec![None; cells]];
+        let mut heap = [IndexedHeap::new(cells), IndexedHeap::new(cells)];
+        let mut closed = [Bitset::new(cells), Bitset::new(cells)];
+        // Only gathered for a `watch` that is watching: both sides' cells.
+        let mut shown = HashSet::new();
+        for (side, origin) in [start, end].into_iter().enumerate() {
+            dist[side][origin] = 0;
+            heap[side].push(origin, key(0, origin, side == 0));
+        }
+
+        // The cheapest path seen so far, through `meeting`.
+        let mut best = if start == end { 0 } else { usize::MAX };
+        let mut meeting = (start == end).then_some(start);
+
+        while let (Some(forward), Some(backward)) = (heap[0].peek_key(), heap[1].peek_key()) {
+            // Nothing left on either side can make a path cheaper than
+            // the best one already found.
+            if best != usize::MAX && forward + backward >= 2 * best as i64 {
+                break;
+            }
+
+            let side = if heap[0].len() <= heap[1].len() { 0 } else { 1 };
+            let (position, _) = heap[side].pop().expect("peeked above");
+            let cost = dist[side][position];
+            closed[side].insert(position);
+
+            if watch.watching() {
+                shown.insert(position);
+                let at = Expansion {
+                    cell: position,
+                    cost: Some(cost),
+                    frontier: heap[0].len() + heap[1].len(),
+                };
+                watch.expanded(grid, &shown, &at);
+            }
+            if !watch.keep_going(closed[0].len() + closed[1].len()) {
+                // Whatever meeting was found may not be the cheapest.
+                meeting = None;
+                break;
+            }
+
+            // Backward, steps are taken the other way round.
+            let neighbors = if side == 0 {
+                grid.neighbors(position)
+            } else {
+                grid.predecessors(position)
+            };
+            for neighbor in neighbors {
+                if closed[side].contains(neighbor) {
+                    continue;
+                }
+
+                let step = if side == 0 {
+                    grid.step_cost(position, neighbor)
+                } else {
+                    grid.step_cost(neighbor, position)
+                };
+                let new_cost = cost + step;
+                if new_cost < dist[side][neighbor] {
+                    dist[side][neighbor] = new_cost;
+                    prev[side][neighbor] = Some(position);
+                    heap[side].push(neighbor, key(new_cost, neighbor, side == 0));
+
+                    let other = dist[1 - side][neighbor];
+                    if other != usize::MAX && new_cost + other < best {
+                        best = new_cost + other;
+                        meeting = Some(neighbor);
+                    }
+                }
+            }
+        }
+
+        let expanded = closed[0].len() + closed[1].len();
+        let Some(meeting) = meeting else {
+            return Search {
+                found: None,
+                expanded,
+                bound: None,
+            };
+        };
+
+        // Back from the meeting point to `start`, then on to `end`.
+        let mut path = Vec::new();
+        let mut current = Some(meeting);
+        while let Some(pos) = current {
+            path.push(pos);
+            current = prev[0][pos];
+        }
+        path.reverse();
+        let mut current = prev[1][meeting];
+        while let Some(pos) = current {
+            path.push(pos);
+            current = prev[1][pos];
+        }
+
+        let cost = path
+            .windows(2)
+            .map(|step| grid.step_cost(step[0], step[1]))
+            .sum();
+        Search {
+            found: Some((path, cost)),
+            expanded,
+            bound: None,
+        }
+    }
+}
+
+/// Expands cells lowest `priority(cost so far, cell)` first, keeping the
+/// cheapest known way into each cell.
+pub(crate) fn best_first(
+    grid: &Grid,
+    start: usize,
+    end: usize,
+    watch: &mut dyn Watch,
+    priority: impl Fn(usize, usize) -> i64,
+) -> Search {
+    let mut dist = vec![usize::MAX; grid.len()];
+    let mut prev = vec![None; grid.len()];
+    let mut heap = IndexedHeap::new(grid.len());
+    let mut visited = Bitset::new(grid.len());
+    // Only gathered for a `watch` that is watching.
+    let mut shown = HashSet::new();
+
+    dist[start] = 0;
+    heap.push(start, priority(0, start));
+
+    while let Some((position, _)) = heap.pop() {
+        if position == end {
+            return finish(grid, &prev, end, visited.len());
+        }
+        visited.insert(position);
+        let cost = dist[position];
+
+        if watch.watching() {
+            shown.insert(position);
+            let at = Expansion {
+                cell: position,
+                cost: Some(cost),
+                frontier: heap.len(),
+            };
+            watch.expanded(grid, &shown, &at);
+        }
+        if !watch.keep_going(visited.len()) {
+            break;
+        }
+
+        for neighbor in grid.neighbors(position) {
+            if visited.contains(neighbor) {
+                continue;
+            }
+
+            let new_cost = cost + grid.step_cost(position, neighbor);
+            if new_cost < dist[neighbor] {
+                dist[neighbor] = new_cost;
+                prev[neighbor] = Some(position);
+                heap.push(neighbor, priority(new_cost, neighbor));
+            }
+        }
+    }
+
+    Search {
+        found: None,
+        expanded: visited.len(),
+        bound: None,
+    }
+}
+
+/// The path `prev` leads back along from `end`, and its cost.
+pub fn finish(grid: &Grid, prev: &[Option<usize>], end: usize, expanded: usize) -> Search {
+    let mut path = Vec::new();
+    let mut current = Some(end);
+
+    while let Some(pos) = current {
+        path.push(pos);
+        current = prev[pos];
+    }
+
+    path.reverse();
+    let cost = path
+        .windows(2)
+        .map(|step| grid.step_cost(step[0], step[1]))
+        .sum();
+    Search {
+        found: Some((path, cost)),
+        expanded,
+        bound: None,
+    }
+}
+
+/// The fewest moves from `from` to `to`, walls aside, the short way round
+/// on a wrapping grid. Each floor up or down is one more move, as if the
+/// stairs were right there.
+pub fn steps(grid: &Grid, from: usize, to: usize) -> usize {
+    let (from_x, from_y, from_z) = grid.floor_coords(from);
+    let (to_x, to_y, to_z) = grid.floor_coords(to);
+    let apart = |from: usize, to: usize, size: usize| {
+        let apart = from.abs_diff(to);
+        if grid.wrap {
+            apart.min(size - apart)
+        } else {
+            apart
+        }
+    };
+    let (dx, dy) = (
+        apart(from_x, to_x, grid.width),
+        apart(from_y, to_y, grid.floor_height()),
+    );
+    let floors = from_z.abs_diff(to_z);
+    if grid.diagonal.is_some() {
+        dx.max(dy) + floors
+    } else {
+        dx + dy + floors
+    }
+}
+
+/// The least any single move on `grid` can cost.
+pub fn cheapest_step(grid: &Grid) -> usize {
+    let open = grid.values().filter(|&value| value != grid.wall);
+    cost::least(grid, open)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weighted::WeightedAStar;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const COST_FNS: [&str; 4] = ["value", "elevation-diff", "threshold:c0", "expr:to / 16"];
+
+    /// A random map for `seed`, some diagonal, some wrapping, each costed
+    /// by one of `COST_FNS`, with its corners as start and end.
+    fn map(seed: u64) -> (Grid, usize, usize) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let (width, height) = (rng.random_range(1..16), rng.random_range(1..12));
+        let density = rng.random_range(0.0..0.45);
+        let mut grid = Grid::generate_random(width, height, 0xFF, density, &mut rng);
+        grid.diagonal = rng.random_bool(0.5).then_some(1.5);
+        grid.wrap = rng.random_bool(0.25);
+        grid.cost = cost::parse(COST_FNS[seed as usize % COST_FNS.len()]).expect("a cost function");
+        let (start, end) = (0, grid.len() - 1);
+        grid.open(&[start, end], &mut rng);
+        (grid, start, end)
+    }
+
+    /// Every search `--algo` and its flags can pick.
+    fn pathfinders() -> Vec<Box<dyn Pathfinder>> {
+        let algos = [
+            Algo::Dijkstra,
+            Algo::Bfs,
+            Algo::Dfs,
+            Algo::GreedyBfs,
+            Algo::Astar,
+            Algo::ThetaStar,
+        ];
+        let mut pathfinders: Vec<_> = algos
+            .iter()
+            .flat_map(|&algo| {
+                [
+                    (algo, false, false),
+                    (algo, true, false),
+                    (algo, false, true),
+                ]
+            })
+            .filter_map(|(algo, bidirectional, parallel)| {
+                algo.pathfinder(bidirectional, parallel).ok()
+            })
+            .collect();
+        pathfinders.push(Box::new(WeightedAStar {
+            weight: 1.0,
+            anytime: None,
+        }));
+        pathfinders
+    }
+
+    /// Checks that `path` goes from `start` to `end` a move at a time, or
+    /// a line at a time if it is drawn through its corners, and costs
+    /// `cost`.
+    fn check_path(grid: &Grid, start: usize, end: usize, path: &[usize], cost: usize, lines: bool) {
+        assert_eq!(path.first(), Some(&start));
+        assert_eq!(path.last(), Some(&end));
+        assert!(path.iter().all(|&cell| !grid.is_wall(cell)), "{:?}", path);
+        if !lines {
+            for step in path.windows(2) {
+                assert!(grid.neighbors(step[0]).contains(&step[1]), "{:?}", path);
+            }
+            let total: usize = path
+                .windows(2)
+                .map(|step| grid.step_cost(step[0], step[1]))
+                .sum();
+            assert_eq!(total, cost, "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn searches_agree_with_dijkstra() {
+        for seed in 0..300 {
+            let (grid, start, end) = map(seed);
+            let cheapest = Dijkstra
+                .find(&grid, start, end, &mut Unwatched)
+                .found
+                .map(|(_, cost)| cost);
+            for pathfinder in pathfinders() {
+                let found = pathfinder.find(&grid, start, end, &mut Unwatched).found;
+                let Some((path, cost)) = found else {
+                    assert_eq!(cheapest, None, "{} on seed {}", pathfinder.name(), seed);
+                    continue;
+                };
+                let context = format!("{} on seed {}", pathfinder.name(), seed);
+                let cheapest = cheapest.unwrap_or_else(|| panic!("{}: no path", context));
+                check_path(&grid, start, end, &path, cost, pathfinder.any_angle());
+                if pathfinder.optimal() {
+                    assert_eq!(cost, cheapest, "{}", context);
+                } else {
+                    assert!(cost >= cheapest, "{}", context);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn weighted_paths_keep_within_their_bound() {
+        for seed in 0..300 {
+            let (grid, start, end) = map(seed);
+            let Some((_, cheapest)) = Dijkstra.find(&grid, start, end, &mut Unwatched).found else {
+                continue;
+            };
+            for weight in [1.5, 2.0, 4.0] {
+                let weighted = WeightedAStar {
+                    weight,
+                    anytime: None,
+                };
+                let search = weighted.find(&grid, start, end, &mut Unwatched);
+                let (path, cost) = search.found.expect("a path where Dijkstra found one");
+                check_path(&grid, start, end, &path, cost, false);
+                assert_eq!(search.bound, Some(weight));
+                assert!(
+                    cost as f64 <= weight * cheapest as f64,
+                    "weight {} on seed {}: {} against {}",
+                    weight,
+                    seed,
+                    cost,
+                    cheapest
+                );
+            }
+        }
+    }
+}
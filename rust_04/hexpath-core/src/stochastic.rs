@@ -0,0 +1,349 @@
+//! The stochastic methods for the most expensive path: an ant colony,
+//! simulated annealing and a genetic algorithm. Each starts from the paths
+//! it is given (the greedy and monotone ones) and improves on them for a
+//! number of rounds, or until the time budget runs out, drawing every
+//! choice from the generator it is handed, so the same seed and rounds
+//! give the same path. None comes back with worse than it started from.
+//!
+//! All of them make paths the same two ways:
+//!
+//! - a walk: a depth-first search from the start that picks each move at
+//!   random, the more likely the more it weighs, and backs out of dead
+//!   ends, so it always gets to the end when it can;
+//! - a detour: a stretch of a few moves of a path swapped for another
+//!   random way between its ends that keeps clear of the rest of it.
+
+use crate::Grid;
+use rand::Rng;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+/// A path, start and end included, and its cost.
+pub type Found = (Vec<usize>, usize);
+
+/// Ants sent out each round.
+const ANTS: usize = 20;
+/// The share of pheromone that evaporates each round.
+const EVAPORATION: f64 = 0.1;
+/// Pheromone kept between these, so no move is ever sure or ruled out.
+const PHEROMONE: (f64, f64) = (0.05, 5.0);
+
+/// Paths kept from one generation to the next.
+const POPULATION: usize = 30;
+/// The best of them, kept as they are.
+const ELITE: usize = 2;
+/// Paths drawn to pick each parent from.
+const TOURNAMENT: usize = 3;
+/// How likely a child is to take a detour.
+const MUTATION: f64 = 0.5;
+
+/// Moves a detour may replace at most.
+const SPAN: usize = 8;
+/// Cells a detour may try before giving up.
+const DETOUR_STEPS: usize = 64;
+
+/// The best path a method found, and the rounds it ran.
+pub struct Run {
+    pub found: Option<Found>,
+    pub rounds: usize,
+}
+
+/// Ants walk from the start, each move the likelier the dearer it is and
+/// the more pheromone its cell has; after each round the pheromone
+/// evaporates a little, and each path walked leaves more of it the dearer
+/// it was, the best so far most.
+pub fn ant_colony(
+    grid: &Grid,
+    start: usize,
+    end: usize,
+    seeds: Vec<Found>,
+    rounds: usize,
+    deadline: Instant,
+    rng: &mut impl Rng,
+) -> Run {
+    let mut best = best_of(seeds);
+    let mut pheromone = vec![1.0; grid.len()];
+    let mut round = 0;
+    while round < rounds && Instant::now() < deadline {
+        round += 1;
+        let mut ants = Vec::with_capacity(ANTS);
+        for _ in 0..ANTS {
+            let weight = |from: usize, to: usize| {
+                pheromone[to] * ((grid.step_cost(from, to) + 1) as f64).powi(2)
+            };
+            let Some(path) = walk(grid, start, end, weight, rng) else {
+                return Run {
+                    found: best,
+                    rounds: round,
+                };
+            };
+            let cost = cost(grid, &path);
+            ants.push((path, cost));
+        }
+        best = best_of(ants.iter().cloned().chain(best));
+
+        let dearest = best.as_ref().map_or(1, |(_, cost)| *cost).max(1) as f64;
+        for amount in &mut pheromone {
+            *amount *= 1.0 - EVAPORATION;
+        }
+        for (path, cost) in ants.iter().chain(&best) {
+            for &cell in path {
+                pheromone[cell] += *cost as f64 / dearest;
+            }
+        }
+        for amount in &mut pheromone {
+            *amount = amount.clamp(PHEROMONE.0, PHEROMONE.1);
+        }
+    }
+    Run {
+        found: best,
+        rounds: round,
+    }
+}
+
+/// One path, changed a detour at a time: a dearer one is always kept, a
+/// cheaper one the likelier the less it loses and the earlier it comes,
+/// as the temperature falls from about a step's cost to none.
+pub fn annealing(
+    grid: &Grid,
+    start: usize,
+    end: usize,
+    seeds: Vec<Found>,
+    rounds: usize,
+    deadline: Instant,
+    rng: &mut impl Rng,
+) -> Run {
+    let found = best_of(seeds).or_else(|| {
+        let path = walk(grid, start, end, |_, _| 1.0, rng)?;
+        let cost = cost(grid, &path);
+        Some((path, cost))
+    });
+    let Some(mut current) = found else {
+        return Run {
+            found: None,
+            rounds: 0,
+        };
+    };
+    let mut best = current.clone();
+    let mut on_path: HashSet<usize> = current.0.iter().copied().collect();
+    let hottest = current.1 as f64 / current.0.len().max(1) as f64;
+
+    let mut round = 0;
+    while round < rounds && Instant::now() < deadline {
+        let temperature = hottest * (1.0 - round as f64 / rounds as f64) + f64::EPSILON;
+        round += 1;
+        let Some(candidate) = detour(grid, &current.0, &on_path, rng) else {
+            continue;
+        };
+        let cost = cost(grid, &candidate);
+        let change = cost as f64 - current.1 as f64;
+        if change >= 0.0 || rng.random::<f64>() < (change / temperature).exp() {
+            on_path = candidate.iter().copied().collect();
+            current = (candidate, cost);
+            if current.1 > best.1 {
+                best = current.clone();
+            }
+        }
+    }
+    Run {
+        found: Some(best),
+        rounds: round,
+    }
+}
+
+/// A population of paths, bred a generation a round: the best kept, the
+/// rest children of parents picked by tournament, each the start of one
+/// up to a cell both share and the end of the other after it, and half of
+/// them sent on a detour.
+pub fn genetic(
+    grid: &Grid,
+    start: usize,
+    end: usize,
+    seeds: Vec<Found>,
+    rounds: usize,
+    deadline: Instant,
+    rng: &mut impl Rng,
+) -> Run {
+    let mut population = seeds;
+    while population.len() < POPULATION {
+        let weight = |from: usize, to: usize| (grid.step_cost(from, to) + 1) as f64;
+        let Some(path) = walk(grid, start, end, weight, rng) else {
+            break;
+        };
+        let cost = cost(grid, &path);
+        population.push((path, cost));
+    }
+    if population.is_empty() {
+        return Run {
+            found: None,
+            rounds: 0,
+        };
+    }
+
+    let mut round = 0;
+    while round < rounds && Instant::now() < deadline {
+        round += 1;
+        population.sort_by_key(|(_, cost)| Reverse(*cost));
+        let mut next: Vec<Found> = population.iter().take(ELITE).cloned().collect();
+        while next.len() < POPULATION {
+            let mother = tournament(&population, rng);
+            let father = tournament(&population, rng);
+            let mut child =
+                crossover(&mother.0, &father.0, rng).unwrap_or_else(|| mother.0.clone());
+            if rng.random_bool(MUTATION) {
+                let on_path = child.iter().copied().collect();
+                if let Some(changed) = detour(grid, &child, &on_path, rng) {
+                    child = changed;
+                }
+            }
+            let cost = cost(grid, &child);
+            next.push((child, cost));
+        }
+        population = next;
+    }
+    Run {
+        found: best_of(population),
+        rounds: round,
+    }
+}
+
+fn cost(grid: &Grid, path: &[usize]) -> usize {
+    path.windows(2)
+        .map(|pair| grid.step_cost(pair[0], pair[1]))
+        .sum()
+}
+
+fn best_of(paths: impl IntoIterator<Item = Found>) -> Option<Found> {
+    paths.into_iter().max_by_key(|(_, cost)| *cost)
+}
+
+/// One of `options`, each as likely as its weight.
+fn pick(options: &[(usize, f64)], rng: &mut impl Rng) -> usize {
+    let total: f64 = options.iter().map(|&(_, weight)| weight).sum();
+    let mut left = rng.random::<f64>() * total;
+    for &(option, weight) in options {
+        if left < weight {
+            return option;
+        }
+        left -= weight;
+    }
+    options.last().expect("something to pick").0
+}
+
+/// A random simple path from `start` to `end`, each move drawn by its
+/// `weight`; `None` if walls cut the end off.
+fn walk(
+    grid: &Grid,
+    start: usize,
+    end: usize,
+    weight: impl Fn(usize, usize) -> f64,
+    rng: &mut impl Rng,
+) -> Option<Vec<usize>> {
+    let mut seen = vec![false; grid.len()];
+    let mut path = vec![start];
+    seen[start] = true;
+    while let Some(&here) = path.last() {
+        if here == end {
+            return Some(path);
+        }
+        let options: Vec<(usize, f64)> = grid
+            .neighbors(here)
+            .into_iter()
+            .filter(|&next| !seen[next])
+            .map(|next| (next, weight(here, next)))
+            .collect();
+        if options.is_empty() {
+            // A dead end: back out, and don't come here again.
+            path.pop();
+            continue;
+        }
+        let next = pick(&options, rng);
+        seen[next] = true;
+        path.push(next);
+    }
+    None
+}
+
+/// `path` with a few of its moves, at random, swapped for another way
+/// between the same two cells that keeps clear of `on_path`, its cells.
+fn detour(
+    grid: &Grid,
+    path: &[usize],
+    on_path: &HashSet<usize>,
+    rng: &mut impl Rng,
+) -> Option<Vec<usize>> {
+    if path.len() < 2 {
+        return None;
+    }
+    let from = rng.random_range(0..path.len() - 1);
+    let to = (from + rng.random_range(1..=SPAN)).min(path.len() - 1);
+    let (start, end) = (path[from], path[to]);
+    let inside: HashSet<usize> = path[from + 1..to].iter().copied().collect();
+
+    let mut seen = HashSet::from([start]);
+    let mut way = vec![start];
+    for _ in 0..DETOUR_STEPS {
+        let &here = way.last()?;
+        let options: Vec<(usize, f64)> = grid
+            .neighbors(here)
+            .into_iter()
+            .filter(|next| {
+                *next == end
+                    || (!seen.contains(next) && (!on_path.contains(next) || inside.contains(next)))
+            })
+            .map(|next| (next, 1.0))
+            .collect();
+        if options.is_empty() {
+            way.pop();
+            continue;
+        }
+        let next = pick(&options, rng);
+        if next == end {
+            if way.len() == 1 {
+                // Straight there: no detour at all.
+                continue;
+            }
+            let mut changed = path[..from].to_vec();
+            changed.extend(way);
+            changed.extend_from_slice(&path[to..]);
+            return Some(changed);
+        }
+        seen.insert(next);
+        way.push(next);
+    }
+    None
+}
+
+fn tournament<'a>(population: &'a [Found], rng: &mut impl Rng) -> &'a Found {
+    (0..TOURNAMENT)
+        .map(|_| &population[rng.random_range(0..population.len())])
+        .max_by_key(|(_, cost)| *cost)
+        .expect("a tournament of some")
+}
+
+/// `mother` up to a cell, other than the ends, both go through, then
+/// `father` from it; `None` if they share none or the child would cross
+/// itself.
+fn crossover(mother: &[usize], father: &[usize], rng: &mut impl Rng) -> Option<Vec<usize>> {
+    let in_father: HashMap<usize, usize> = father
+        .iter()
+        .enumerate()
+        .map(|(index, &cell)| (cell, index))
+        .collect();
+    let shared: Vec<(usize, usize)> = mother
+        .iter()
+        .enumerate()
+        .take(mother.len().saturating_sub(1))
+        .skip(1)
+        .filter_map(|(index, cell)| Some((index, *in_father.get(cell)?)))
+        .collect();
+    if shared.is_empty() {
+        return None;
+    }
+    let (at_mother, at_father) = shared[rng.random_range(0..shared.len())];
+    let mut child = mother[..at_mother].to_vec();
+    child.extend_from_slice(&father[at_father..]);
+    let mut seen = HashSet::new();
+    child.iter().all(|&cell| seen.insert(cell)).then_some(child)
+}
@@ -0,0 +1,319 @@
+//! Smaller ways for a [`Grid`](crate::Grid) to keep its cells than a byte
+//! each, for maps too big to hold that way:
+//!
+//! - [`Packed`]: the values the map uses in a palette, and each cell its
+//!   place there in 1, 2, 4 or 8 bits, as few as the palette needs. A maze
+//!   packs to a bit a cell; any map of at most 16 values, walls included,
+//!   to 4, which [`quantize`] gets a map of many down to;
+//! - [`Runs`]: the cells in chunks, each kept as runs of one value and
+//!   read by a binary search over them, for maps of wide plains and long
+//!   walls.
+//!
+//! Both read slower than bytes, and are written by packing again what they
+//! hold, so are for searching, not for editing.
+
+use crate::Storage;
+use std::mem;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum Backend {
+    /// A byte a cell
+    Bytes,
+    /// A palette of the values, and each cell's place in it in as few bits
+    /// as it takes
+    Packed,
+    /// Runs of one value, in chunks
+    Runs,
+}
+
+/// Cells as places in a palette of their values, `bits` each, packed into
+/// words.
+#[derive(Clone)]
+pub struct Packed {
+    len: usize,
+    bits: usize,
+    palette: Vec<u8>,
+    words: Vec<u64>,
+}
+
+impl Packed {
+    pub fn new(values: impl IntoIterator<Item = u8>) -> Self {
+        let values: Vec<u8> = values.into_iter().collect();
+        let mut used = [false; 256];
+        for &value in &values {
+            used[value as usize] = true;
+        }
+        let palette: Vec<u8> = (0..=255).filter(|&value| used[value as usize]).collect();
+        let bits = [1, 2, 4, 8]
+            .into_iter()
+            .find(|&bits| palette.len() <= 1 << bits)
+            .expect("a byte has 256 values");
+
+        let mut place = [0; 256];
+        for (index, &value) in palette.iter().enumerate() {
+            place[value as usize] = index as u64;
+        }
+        let per_word = 64 / bits;
+        let mut words = vec![0; values.len().div_ceil(per_word)];
+        for (index, &value) in values.iter().enumerate() {
+            words[index / per_word] |= place[value as usize] << (index % per_word * bits);
+        }
+
+        Packed {
+            len: values.len(),
+            bits,
+            palette,
+            words,
+        }
+    }
+
+    /// Bits a cell.
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
+    /// The word cell `index` is in, and how far up it.
+    fn locate(&self, index: usize) -> (usize, usize) {
+        let per_word = 64 / self.bits;
+        (index / per_word, index % per_word * self.bits)
+    }
+}
+
+impl Storage for Packed {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn get(&self, index: usize) -> u8 {
+        assert!(index < self.len, "cell {} of {}", index, self.len);
+        let (word, shift) = self.locate(index);
+        let mask = (1 << self.bits) - 1;
+        self.palette[(self.words[word] >> shift & mask) as usize]
+    }
+
+    /// Packs the cells again when `value` is new and the palette full.
+    fn set(&mut self, index: usize, value: u8) {
+        assert!(index < self.len, "cell {} of {}", index, self.len);
+        let place = match self.palette.iter().position(|&known| known == value) {
+            Some(place) => place,
+            None if self.palette.len() < 1 << self.bits => {
+                self.palette.push(value);
+                self.palette.len() - 1
+            }
+            None => {
+                let mut values: Vec<u8> = (0..self.len).map(|index| self.get(index)).collect();
+                values[index] = value;
+                *self = Packed::new(values);
+                return;
+            }
+        };
+        let (word, shift) = self.locate(index);
+        let mask = (1 << self.bits) - 1;
+        self.words[word] = self.words[word] & !(mask << shift) | (place as u64) << shift;
+    }
+
+    fn boxed(&self) -> Box<dyn Storage> {
+        Box::new(self.clone())
+    }
+
+    fn bytes(&self) -> usize {
+        self.words.len() * mem::size_of::<u64>() + self.palette.len()
+    }
+}
+
+/// Cells in each chunk of [`Runs`].
+const CHUNK: usize = 4096;
+
+/// A chunk of cells as runs of one value: where each run ends in the
+/// chunk, and its value.
+#[derive(Clone)]
+struct Chunk {
+    ends: Box<[u16]>,
+    values: Box<[u8]>,
+}
+
+impl Chunk {
+    fn new(cells: &[u8]) -> Self {
+        let (mut ends, mut values) = (Vec::new(), Vec::new());
+        for (index, &value) in cells.iter().enumerate() {
+            if values.last() == Some(&value) {
+                *ends.last_mut().expect("a run per value") += 1;
+            } else {
+                ends.push(index as u16 + 1);
+                values.push(value);
+            }
+        }
+        Chunk {
+            ends: ends.into(),
+            values: values.into(),
+        }
+    }
+
+    fn get(&self, offset: usize) -> u8 {
+        let run = self.ends.partition_point(|&end| end as usize <= offset);
+        self.values[run]
+    }
+
+    fn cells(&self) -> Vec<u8> {
+        let mut cells = Vec::with_capacity(CHUNK);
+        for (&end, &value) in self.ends.iter().zip(&self.values) {
+            cells.resize(end as usize, value);
+        }
+        cells
+    }
+}
+
+/// Cells as runs of one value, in chunks of a few thousand, so reading one
+/// only searches the runs of its chunk, and writing one only packs its
+/// chunk again.
+#[derive(Clone)]
+pub struct Runs {
+    len: usize,
+    chunks: Vec<Chunk>,
+}
+
+impl Runs {
+    pub fn new(values: impl IntoIterator<Item = u8>) -> Self {
+        let values: Vec<u8> = values.into_iter().collect();
+        Runs {
+            len: values.len(),
+            chunks: values.chunks(CHUNK).map(Chunk::new).collect(),
+        }
+    }
+
+    /// How many runs the cells make.
+    pub fn count(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.values.len()).sum()
+    }
+}
+
+impl Storage for Runs {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn get(&self, index: usize) -> u8 {
+        assert!(index < self.len, "cell {} of {}", index, self.len);
+        self.chunks[index / CHUNK].get(index % CHUNK)
+    }
+
+    fn set(&mut self, index: usize, value: u8) {
+        assert!(index < self.len, "cell {} of {}", index, self.len);
+        let chunk = &mut self.chunks[index / CHUNK];
+        let mut cells = chunk.cells();
+        cells[index % CHUNK] = value;
+        *chunk = Chunk::new(&cells);
+    }
+
+    fn boxed(&self) -> Box<dyn Storage> {
+        Box::new(self.clone())
+    }
+
+    fn bytes(&self) -> usize {
+        let runs = self.count() * (mem::size_of::<u16>() + mem::size_of::<u8>());
+        runs + self.chunks.len() * mem::size_of::<Chunk>()
+    }
+}
+
+/// `value` rounded to the middle of the one of `levels` equal bands of
+/// 0..=255 it is in; walls stay walls, and nothing else becomes one.
+pub fn quantize(value: u8, wall: u8, levels: usize) -> u8 {
+    if value == wall {
+        return wall;
+    }
+    let levels = levels.clamp(1, 256);
+    let band = value as usize * levels / 256;
+    let middle = ((2 * band + 1) * 256 / (2 * levels)) as u8;
+    if middle != wall {
+        middle
+    } else if middle > value {
+        middle - 1
+    } else {
+        middle + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Random cells for `seed`, from a palette of 1 to 256 values, in runs
+    /// of random length, across a few chunks.
+    fn cells(seed: u64) -> Vec<u8> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let palette: Vec<u8> = (0..rng.random_range(1..=256))
+            .map(|_| rng.random())
+            .collect();
+        let len = rng.random_range(0..3 * CHUNK);
+        let mut cells = Vec::with_capacity(len);
+        while cells.len() < len {
+            let value = palette[rng.random_range(0..palette.len())];
+            let run = rng.random_range(1..64).min(len - cells.len());
+            cells.extend(std::iter::repeat_n(value, run));
+        }
+        cells
+    }
+
+    /// Checks that `storage` holds `cells`, and still holds them as they
+    /// are changed at random.
+    fn check(mut storage: Box<dyn Storage>, mut cells: Vec<u8>, seed: u64) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        assert_eq!(storage.len(), cells.len());
+        assert!((0..cells.len()).all(|index| storage.get(index) == cells[index]));
+        if cells.is_empty() {
+            return;
+        }
+        for _ in 0..50 {
+            let (index, value) = (rng.random_range(0..cells.len()), rng.random());
+            storage.set(index, value);
+            cells[index] = value;
+        }
+        assert!((0..cells.len()).all(|index| storage.get(index) == cells[index]));
+        let copy = storage.boxed();
+        assert!((0..cells.len()).all(|index| copy.get(index) == cells[index]));
+    }
+
+    #[test]
+    fn packed_cells_read_back() {
+        for seed in 0..40 {
+            let cells = cells(seed);
+            check(Box::new(Packed::new(cells.iter().copied())), cells, seed);
+        }
+    }
+
+    #[test]
+    fn runs_read_back() {
+        for seed in 0..40 {
+            let cells = cells(seed);
+            check(Box::new(Runs::new(cells.iter().copied())), cells, seed);
+        }
+    }
+
+    #[test]
+    fn quantized_values_keep_to_their_levels() {
+        for levels in 1..=20 {
+            for wall in [0x00, 0x80, 0xFF] {
+                let values: Vec<u8> = (0..=255)
+                    .map(|value| quantize(value, wall, levels))
+                    .collect();
+                let mut distinct = values.clone();
+                distinct.sort_unstable();
+                distinct.dedup();
+                // The wall, and a band whose middle is the wall splits in
+                // two either side of it.
+                assert!(distinct.len() <= levels + 2, "{} levels", levels);
+                for (value, &quantized) in values.iter().enumerate() {
+                    assert_eq!(
+                        value == wall as usize,
+                        quantized == wall,
+                        "{} levels",
+                        levels
+                    );
+                }
+            }
+        }
+    }
+}
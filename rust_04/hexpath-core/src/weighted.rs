@@ -0,0 +1,105 @@
+//! `--weight`: A* with its guess at the cost left made W times bolder, so
+//! it heads for the end harder and expands fewer cells, for a path that
+//! costs at most W times the cheapest: good enough, fast, on big maps.
+//!
+//! `--anytime` makes it keep going: after the first path it searches again
+//! with the weight halfway closer to 1, and again, for as long as the time
+//! budget lasts, keeping the cheapest path found. The bound it reports is
+//! the weight of the last search it finished; reaching 1 means the path is
+//! a cheapest one.
+//!
+//! The guess is the cheapest step times the moves left, so on a map where
+//! a step can cost nothing it is 0, and no weight makes it bolder: there
+//! weighted A* searches as Dijkstra does.
+
+use crate::Grid;
+use crate::search::{self, Expansion, Pathfinder, Search, Watch};
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+/// Weights this close to 1 are taken as 1.
+const CLOSE_ENOUGH: f64 = 0.05;
+
+#[derive(Clone, Copy, Debug)]
+pub struct WeightedAStar {
+    /// What the cost left is guessed at times, at least 1.
+    pub weight: f64,
+    /// For `--anytime`: how long it may keep improving on the first path.
+    pub anytime: Option<Duration>,
+}
+
+impl Pathfinder for WeightedAStar {
+    fn name(&self) -> &'static str {
+        if self.anytime.is_some() {
+            "anytime A*"
+        } else {
+            "weighted A*"
+        }
+    }
+
+    fn optimal(&self) -> bool {
+        self.weight == 1.0
+    }
+
+    fn find(&self, grid: &Grid, start: usize, end: usize, watch: &mut dyn Watch) -> Search {
+        let mut weight = self.weight;
+        let mut search = weighted(grid, start, end, weight, watch);
+        let Some(budget) = self.anytime else {
+            return search;
+        };
+        let deadline = Instant::now() + budget;
+        while search.found.is_some() && weight > 1.0 && Instant::now() < deadline {
+            weight = 1.0 + (weight - 1.0) / 2.0;
+            if weight - 1.0 < CLOSE_ENOUGH {
+                weight = 1.0;
+            }
+            let mut until = Until {
+                watch: &mut *watch,
+                deadline,
+            };
+            let better = weighted(grid, start, end, weight, &mut until);
+            search.expanded += better.expanded;
+            let Some((path, cost)) = better.found else {
+                // Out of time, or told to stop: the last path stands.
+                break;
+            };
+            if search.found.as_ref().is_some_and(|&(_, best)| cost < best) {
+                search.found = Some((path, cost));
+            }
+            search.bound = better.bound;
+        }
+        search
+    }
+}
+
+/// One weighted A* search, bound by `weight` if it finds a path.
+fn weighted(grid: &Grid, start: usize, end: usize, weight: f64, watch: &mut dyn Watch) -> Search {
+    let cheapest = search::cheapest_step(grid) as f64;
+    let mut search = search::best_first(grid, start, end, watch, |cost, cell| {
+        cost as i64 + (weight * cheapest * search::steps(grid, cell, end) as f64).round() as i64
+    });
+    if search.found.is_some() {
+        search.bound = Some(weight);
+    }
+    search
+}
+
+/// A watch that also gives up once `deadline` has passed.
+struct Until<'a> {
+    watch: &'a mut dyn Watch,
+    deadline: Instant,
+}
+
+impl Watch for Until<'_> {
+    fn watching(&self) -> bool {
+        self.watch.watching()
+    }
+
+    fn expanded(&mut self, grid: &Grid, cells: &HashSet<usize>, at: &Expansion) {
+        self.watch.expanded(grid, cells, at);
+    }
+
+    fn keep_going(&mut self, expanded: usize) -> bool {
+        self.watch.keep_going(expanded) && Instant::now() < self.deadline
+    }
+}
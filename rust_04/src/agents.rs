@@ -0,0 +1,84 @@
+//! `--agents FILE`: several agents crossing the map at once, planned by
+//! `hexpath_core::agents` so none ever collide. The file holds one agent
+//! per line, its start and its goal in the forms `--start` takes:
+//!
+//! ```text
+//! # start   goal
+//! 0,0       bottom-right
+//! top-right 0,9
+//! ```
+//!
+//! Agents are planned in file order; one every way is blocked for is
+//! reported as stuck and left out.
+
+use crate::{Anchor, Grid, palette, style, value_to_color};
+use crossterm::{
+    ExecutableCommand, cursor,
+    style::{Color, Print},
+    terminal,
+};
+use std::io::{self, Write};
+use std::thread;
+use std::time::Duration;
+
+/// Starts and goals from the text of an agents file.
+pub fn parse(text: &str) -> Result<Vec<(Anchor, Anchor)>, String> {
+    let mut agents = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [start, goal] = fields[..] else {
+            return Err(format!(
+                "line {}: expected a start and a goal, got '{}'",
+                number + 1,
+                line
+            ));
+        };
+        let anchor = |text| Anchor::parse(text).map_err(|e| format!("line {}: {}", number + 1, e));
+        agents.push((anchor(start)?, anchor(goal)?));
+    }
+    Ok(agents)
+}
+
+/// The label of agent `index` on the map and in the output.
+pub fn label(index: usize) -> char {
+    (b'A' + (index % 26) as u8) as char
+}
+
+/// Draws the map with the agents at `positions` (`None` for those left
+/// out), over the previous frame.
+pub fn draw(grid: &Grid, positions: &[Option<usize>], step: usize) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    // Plain, each frame follows the last.
+    if !style::plain() {
+        stdout.execute(terminal::Clear(terminal::ClearType::All))?;
+        stdout.execute(cursor::MoveTo(0, 0))?;
+    }
+    println!("🤖 Step {}", step);
+
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            let index = grid.coords_to_index(x, y);
+            if let Some(agent) = positions.iter().position(|&cell| cell == Some(index)) {
+                style::color(&mut stdout, Color::White)?;
+                stdout.execute(Print(format!("[{}]", label(agent))))?;
+            } else if grid.is_wall(index) {
+                style::color(&mut stdout, palette::current().blocked())?;
+                stdout.execute(Print("██ "))?;
+            } else {
+                let value = grid.value(index);
+                style::color(&mut stdout, value_to_color(value))?;
+                stdout.execute(Print(format!("{:02X} ", value)))?;
+            }
+        }
+        stdout.execute(Print("\n"))?;
+    }
+
+    style::color(&mut stdout, Color::Reset)?;
+    stdout.flush()?;
+    thread::sleep(Duration::from_millis(250));
+    Ok(())
+}
@@ -0,0 +1,181 @@
+//! `hexpath bench`: times search strategies on seeded random maps of
+//! several sizes and compares them in a table, or as CSV. Every strategy
+//! runs on the same maps, from the top-left corner to the bottom-right, with
+//! nothing watching, so the times are those of the searches alone.
+
+use crate::search::{self, Algo, Pathfinder, Unwatched};
+use crate::{DEFAULT_WALL, Grid};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use std::time::{Duration, Instant};
+
+#[derive(clap::Args, Debug)]
+pub struct BenchArgs {
+    /// Map sizes, comma-separated: N for NxN, or WxH
+    #[arg(long, value_name = "SIZES", value_parser = parse_size, value_delimiter = ',', default_value = "64,128,256,512")]
+    sizes: Vec<(usize, usize)>,
+    /// Strategies to time, comma-separated
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "dijkstra,astar"
+    )]
+    algos: Vec<Algo>,
+    /// Maps per size, each searched once by every strategy
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..), default_value_t = 5)]
+    runs: u32,
+    /// Seed of the maps, so runs can be compared across builds
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// Also search from both ends at once (dijkstra and astar only)
+    #[arg(long)]
+    bidirectional: bool,
+    /// Time the searches on every core (dijkstra only)
+    #[arg(long, conflicts_with = "bidirectional")]
+    parallel: bool,
+    /// Print the results as CSV instead of a table
+    #[arg(long)]
+    csv: bool,
+}
+
+/// A size on the command line: N for NxN, or WxH.
+fn parse_size(text: &str) -> Result<(usize, usize), String> {
+    let size = match text.split_once('x') {
+        Some((width, height)) => width.parse().ok().zip(height.parse().ok()),
+        None => text.parse().ok().map(|side| (side, side)),
+    };
+    match size {
+        Some((width, height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(format!("expected N or WxH, got '{}'", text)),
+    }
+}
+
+/// How one strategy did on the maps of one size.
+struct Row {
+    width: usize,
+    height: usize,
+    name: &'static str,
+    mean: Duration,
+    p95: Duration,
+    /// Cells expanded, on average.
+    expanded: usize,
+    /// Cost of the path, on average over the maps it was found on.
+    cost: Option<usize>,
+}
+
+pub fn run(args: &BenchArgs) {
+    let pathfinders: Vec<_> = args
+        .algos
+        .iter()
+        .map(|algo| {
+            algo.pathfinder(args.bidirectional, args.parallel)
+                .unwrap_or_else(|e| {
+                    eprintln!("Invalid --algos: {}", e);
+                    std::process::exit(1);
+                })
+        })
+        .collect();
+
+    if !args.csv {
+        println!(
+            "\n⏱ Timing {} on {} random maps per size, seed {}...\n",
+            pathfinders
+                .iter()
+                .map(|pathfinder| pathfinder.name())
+                .collect::<Vec<_>>()
+                .join(", "),
+            args.runs,
+            args.seed
+        );
+    }
+
+    let mut rows = Vec::new();
+    // Whether some map has a step that costs nothing, so A* can't tell
+    // which way the end is.
+    let mut free_step = false;
+    for &(width, height) in &args.sizes {
+        let mut rng = ChaCha8Rng::seed_from_u64(args.seed);
+        let maps: Vec<Grid> = (0..args.runs)
+            .map(|_| Grid::generate_random(width, height, DEFAULT_WALL, 0.0, &mut rng))
+            .collect();
+        free_step |= maps.iter().any(|grid| search::cheapest_step(grid) == 0);
+        for pathfinder in &pathfinders {
+            rows.push(time(pathfinder.as_ref(), &maps));
+        }
+    }
+
+    if args.csv {
+        println!("width,height,algorithm,runs,mean_ms,p95_ms,expanded,cost");
+        for row in &rows {
+            println!(
+                "{},{},{},{},{:.3},{:.3},{},{}",
+                row.width,
+                row.height,
+                row.name,
+                args.runs,
+                millis(row.mean),
+                millis(row.p95),
+                row.expanded,
+                row.cost.map_or(String::new(), |cost| cost.to_string())
+            );
+        }
+        return;
+    }
+
+    println!(
+        "{:<10} {:<24} {:>10} {:>10} {:>10} {:>10}",
+        "Size", "Algorithm", "Mean ms", "p95 ms", "Expanded", "Cost"
+    );
+    for row in &rows {
+        println!(
+            "{:<10} {:<24} {:>10.3} {:>10.3} {:>10} {:>10}",
+            format!("{}x{}", row.width, row.height),
+            row.name,
+            millis(row.mean),
+            millis(row.p95),
+            row.expanded,
+            row.cost.map_or("-".to_string(), |cost| cost.to_string())
+        );
+    }
+    if free_step && args.algos.contains(&Algo::Astar) {
+        println!(
+            "\n⚠️ Some maps have cells of 00, which cost nothing to enter: A* guesses 0 for the cost left on them, so it expands what Dijkstra does, only slower"
+        );
+    }
+}
+
+/// Runs `pathfinder` once on each of `maps`, which are all the same size.
+fn time(pathfinder: &dyn Pathfinder, maps: &[Grid]) -> Row {
+    let mut times = Vec::new();
+    let mut expanded = 0;
+    let mut costs = Vec::new();
+    for grid in maps {
+        let end = grid.len() - 1;
+        let began = Instant::now();
+        let search = pathfinder.find(grid, 0, end, &mut Unwatched);
+        times.push(began.elapsed());
+        expanded += search.expanded;
+        if let Some((_, cost)) = search.found {
+            costs.push(cost);
+        }
+    }
+
+    times.sort();
+    let runs = maps.len();
+    // The nearest-rank 95th percentile.
+    let p95 = times[(runs * 95).div_ceil(100) - 1];
+    Row {
+        width: maps[0].width,
+        height: maps[0].height,
+        name: pathfinder.name(),
+        mean: times.iter().sum::<Duration>() / runs as u32,
+        p95,
+        expanded: expanded / runs,
+        cost: (!costs.is_empty()).then(|| costs.iter().sum::<usize>() / costs.len()),
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
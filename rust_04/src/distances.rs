@@ -0,0 +1,150 @@
+//! `--show-distances`: after a search, every cell it expanded colored by
+//! its distance from where the search began, cheap to dear, so how it
+//! spread is plain to see; a search from both ends counts from whichever
+//! end is nearer. Searches that keep no costs, BFS and DFS, are colored by
+//! the order they expanded cells in instead.
+
+use crate::search::{Expansion, Watch};
+use crate::viewport::Viewport;
+use crate::{Grid, layers, palette, style, value_to_color};
+use crossterm::{
+    QueueableCommand,
+    style::{Color, Print},
+    terminal,
+};
+use std::collections::HashSet;
+use std::io::{self, IsTerminal, Write};
+
+/// Steps of the legend, cheapest to dearest.
+const LEGEND_STEPS: usize = 5;
+
+/// What a search expanded, gathered as it runs.
+pub struct DistanceField {
+    /// Each expanded cell's distance, or its place in the order expanded.
+    reached: Vec<Option<usize>>,
+    /// Whether `reached` holds the order, for a search without costs.
+    by_order: bool,
+    expansions: usize,
+}
+
+impl DistanceField {
+    pub fn new(grid: &Grid) -> Self {
+        DistanceField {
+            reached: vec![None; grid.len()],
+            by_order: false,
+            expansions: 0,
+        }
+    }
+
+    /// Prints the field over `grid`, with `path` in white and a legend
+    /// under it. A map of several layers is printed floor by floor.
+    pub fn draw(&self, grid: &Grid, path: &[usize]) -> io::Result<()> {
+        let dearest = self.reached.iter().flatten().copied().max().unwrap_or(0);
+        let path: HashSet<usize> = path.iter().copied().collect();
+        if grid.layers > 1 {
+            for z in 0..grid.layers {
+                let (floor, first) = layers::floor(grid, z);
+                let size = floor.len();
+                let path = path
+                    .iter()
+                    .filter(|&&cell| (first..first + size).contains(&cell))
+                    .map(|&cell| cell - first)
+                    .collect();
+                println!("🏢 Layer {} of {}:", z, grid.layers);
+                draw(&floor, &self.reached[first..first + size], dearest, &path)?;
+            }
+        } else {
+            draw(grid, &self.reached, dearest, &path)?;
+        }
+
+        let mut stdout = io::stdout();
+        stdout.queue(Print("Legend:"))?;
+        for step in 0..LEGEND_STEPS {
+            let value = dearest * step / (LEGEND_STEPS - 1);
+            let swatch = if style::plain() {
+                style::shade(value, dearest)
+            } else {
+                "██ ".to_string()
+            };
+            style::color(&mut stdout, heat(value, dearest))?;
+            stdout.queue(Print(format!(" {}", swatch.trim_end())))?;
+            style::color(&mut stdout, Color::Reset)?;
+            stdout.queue(Print(format!(" {}", value)))?;
+        }
+        let unit = if self.by_order {
+            "order expanded"
+        } else {
+            "cost from the start"
+        };
+        stdout.queue(Print(format!(" ({})\n", unit)))?;
+        stdout.flush()
+    }
+}
+
+impl Watch for DistanceField {
+    fn expanded(&mut self, _grid: &Grid, _cells: &HashSet<usize>, at: &Expansion) {
+        // A later leg of a route, or the other side of a search from both
+        // ends, may come by a cell again; the nearer count stands.
+        let reached = match at.cost {
+            Some(cost) => cost,
+            None => {
+                self.by_order = true;
+                self.expansions
+            }
+        };
+        self.expansions += 1;
+        let cell = &mut self.reached[at.cell];
+        *cell = Some(cell.map_or(reached, |before| before.min(reached)));
+    }
+}
+
+fn heat(value: usize, dearest: usize) -> Color {
+    value_to_color((value * 255 / dearest.max(1)) as u8)
+}
+
+/// Draws one floor, zoomed out to the terminal's width like the map: a
+/// block shows the nearest cell expanded in it.
+fn draw(
+    grid: &Grid,
+    reached: &[Option<usize>],
+    dearest: usize,
+    path: &HashSet<usize>,
+) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    let view = match terminal::size() {
+        Ok((columns, _)) if stdout.is_terminal() => {
+            Viewport::fitted(grid, (columns as usize / 3, grid.height))
+        }
+        _ => Viewport::fitted(grid, (grid.width, grid.height)),
+    };
+    if view.zoom > 1 {
+        println!(
+            "🔍 Zoomed out to fit: each cell shows the nearest of {}x{}",
+            view.zoom, view.zoom
+        );
+    }
+    let (across, down) = view.shown(grid);
+    for y in 0..down {
+        for x in 0..across {
+            let block = view.block(grid, x, y);
+            let nearest = block.iter().filter_map(|&cell| reached[cell]).min();
+            let (color, text) = if block.iter().all(|&cell| grid.is_wall(cell)) {
+                (palette::current().blocked(), "▒▒ ".to_string())
+            } else if block.iter().any(|cell| path.contains(cell)) {
+                let text = if style::plain() { "** " } else { "██ " };
+                (palette::current().path(), text.to_string())
+            } else {
+                match nearest {
+                    Some(value) if style::plain() => (Color::Reset, style::shade(value, dearest)),
+                    Some(value) => (heat(value, dearest), "██ ".to_string()),
+                    None => (Color::DarkGrey, "·· ".to_string()),
+                }
+            };
+            style::color(&mut stdout, color)?;
+            stdout.queue(Print(text))?;
+        }
+        stdout.queue(Print("\n"))?;
+    }
+    style::color(&mut stdout, Color::Reset)?;
+    stdout.flush()
+}
@@ -0,0 +1,508 @@
+//! `--replan`: the map changes while an agent walks it, and D* Lite keeps
+//! its path up to date. Changes come from a script, one per line:
+//!
+//! ```text
+//! # step  cell   new value (hex)
+//! 3       4,5    FF
+//! 3       5,5    02
+//! 10      center 80
+//! ```
+//!
+//! or at random, `--events N` cells every step. A change at step N lands
+//! once the agent has taken N steps. The agent's own cell and its goal
+//! can't turn into walls.
+//!
+//! D* Lite searches backward from the goal, so the costs it knows stay
+//! valid as the agent moves; after a change only the cells whose cost to
+//! the goal actually changed are expanded again. Each repair is compared
+//! with a Dijkstra search from scratch on the same map, for the cells
+//! both expanded and for the cost each found.
+
+use crate::screen::Screen;
+use crate::search::{self, Dijkstra, Pathfinder, Unwatched};
+use crate::{Anchor, Grid, cost, palette, parse_byte};
+use rand::Rng;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+/// One scripted change.
+pub struct Event {
+    pub step: usize,
+    pub cell: Anchor,
+    pub value: u8,
+}
+
+/// Changes from the text of a script, in step order.
+pub fn parse(text: &str) -> Result<Vec<Event>, String> {
+    let mut events = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [step, cell, value] = fields[..] else {
+            return Err(format!(
+                "line {}: expected a step, a cell and a value, got '{}'",
+                number + 1,
+                line
+            ));
+        };
+        let error = |e: String| format!("line {}: {}", number + 1, e);
+        events.push(Event {
+            step: step
+                .parse()
+                .map_err(|_| error(format!("invalid step '{}'", step)))?,
+            cell: Anchor::parse(cell).map_err(error)?,
+            value: parse_byte(value).map_err(error)?,
+        });
+    }
+    events.sort_by_key(|event| event.step);
+    Ok(events)
+}
+
+/// (estimate through the cell, cost to the goal), compared in that order.
+type Key = (usize, usize);
+
+const INFINITE: usize = usize::MAX;
+
+/// Rows under the animated map for what happened on the last step.
+const NOTE_LINES: usize = 4;
+
+struct DStarLite {
+    /// Cost to the goal, as last settled.
+    g: Vec<usize>,
+    /// Cost to the goal, one step ahead of `g`.
+    rhs: Vec<usize>,
+    queue: BinaryHeap<Reverse<(Key, usize)>>,
+    /// The key each queued cell is queued under; older entries are stale.
+    queued: Vec<Option<Key>>,
+    start: usize,
+    goal: usize,
+    /// How far the agent moved since the queue was built, added to new
+    /// keys instead of requeueing every old one.
+    km: usize,
+    /// The least a step can cost, for the heuristic.
+    cheapest: usize,
+    /// What a step's cost is multiplied by before one is added to it, the
+    /// number of cells: see `step`.
+    scale: usize,
+    /// Cells expanded since the counter was last reset.
+    expanded: usize,
+}
+
+impl DStarLite {
+    fn new(grid: &Grid, start: usize, goal: usize, cheapest: usize) -> Self {
+        let cells = grid.len();
+        let mut planner = DStarLite {
+            g: vec![INFINITE; cells],
+            rhs: vec![INFINITE; cells],
+            queue: BinaryHeap::new(),
+            queued: vec![None; cells],
+            start,
+            goal,
+            km: 0,
+            cheapest,
+            scale: cells,
+            expanded: 0,
+        };
+        planner.rhs[goal] = 0;
+        planner.enqueue(grid, goal);
+        planner
+    }
+
+    fn heuristic(&self, grid: &Grid, from: usize, to: usize) -> usize {
+        (self.cheapest * self.scale + 1) * search::steps(grid, from, to)
+    }
+
+    /// What the planner counts a step as costing. D* Lite goes wrong on
+    /// steps that cost nothing: cells joined by them can keep each other's
+    /// old cost after it went up. So every step counts one more, on top of
+    /// its cost scaled past what the steps of any path can add up to, and
+    /// the cheapest path is still the cheapest, the one of fewest steps
+    /// among equals.
+    fn step(&self, grid: &Grid, from: usize, to: usize) -> usize {
+        grid.step_cost(from, to) * self.scale + 1
+    }
+
+    fn key(&self, grid: &Grid, cell: usize) -> Key {
+        let best = self.g[cell].min(self.rhs[cell]);
+        (
+            best.saturating_add(self.heuristic(grid, self.start, cell))
+                .saturating_add(self.km),
+            best,
+        )
+    }
+
+    fn enqueue(&mut self, grid: &Grid, cell: usize) {
+        let key = self.key(grid, cell);
+        self.queued[cell] = Some(key);
+        self.queue.push(Reverse((key, cell)));
+    }
+
+    /// The smallest live key, dropping stale entries on the way.
+    fn top(&mut self) -> Option<(Key, usize)> {
+        while let Some(&Reverse((key, cell))) = self.queue.peek() {
+            if self.queued[cell] == Some(key) {
+                return Some((key, cell));
+            }
+            self.queue.pop();
+        }
+        None
+    }
+
+    /// The cheapest way on from `cell`: its neighbor and the total cost.
+    fn best_step(&self, grid: &Grid, cell: usize) -> Option<(usize, usize)> {
+        grid.neighbors(cell)
+            .into_iter()
+            .map(|next| {
+                (
+                    next,
+                    self.step(grid, cell, next).saturating_add(self.g[next]),
+                )
+            })
+            .min_by_key(|&(_, cost)| cost)
+            .filter(|&(_, cost)| cost != INFINITE)
+    }
+
+    fn update(&mut self, grid: &Grid, cell: usize) {
+        if cell != self.goal {
+            self.rhs[cell] = if grid.is_wall(cell) {
+                INFINITE
+            } else {
+                self.best_step(grid, cell)
+                    .map_or(INFINITE, |(_, cost)| cost)
+            };
+        }
+        self.queued[cell] = None;
+        if self.g[cell] != self.rhs[cell] {
+            self.enqueue(grid, cell);
+        }
+    }
+
+    fn compute(&mut self, grid: &Grid) {
+        while let Some((key, cell)) = self.top() {
+            if key >= self.key(grid, self.start) && self.rhs[self.start] == self.g[self.start] {
+                break;
+            }
+            self.queue.pop();
+            self.queued[cell] = None;
+
+            let fresh = self.key(grid, cell);
+            if key < fresh {
+                self.enqueue(grid, cell);
+            } else if self.g[cell] > self.rhs[cell] {
+                self.expanded += 1;
+                self.g[cell] = self.rhs[cell];
+                for before in grid.predecessors(cell) {
+                    self.update(grid, before);
+                }
+            } else {
+                self.expanded += 1;
+                self.g[cell] = INFINITE;
+                for before in grid.predecessors(cell) {
+                    self.update(grid, before);
+                }
+                self.update(grid, cell);
+            }
+        }
+    }
+
+    /// Updates every cell whose steps a change of `cell`'s value touched:
+    /// its own and its neighbors', diagonals squeezing past it and stairs
+    /// leading to it included.
+    fn changed(&mut self, grid: &Grid, cell: usize) {
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                if let Some(near) = grid.offset(cell, dx, dy) {
+                    self.update(grid, near);
+                }
+            }
+        }
+        for other in grid.stairway(cell) {
+            self.update(grid, other);
+        }
+    }
+
+    /// The agent's cost to the goal, as planned.
+    fn cost(&self) -> Option<usize> {
+        Some(self.g[self.start])
+            .filter(|&cost| cost != INFINITE)
+            .map(|cost| cost / self.scale)
+    }
+
+    /// The planned path from the agent to the goal, if there is one.
+    fn path(&self, grid: &Grid) -> Option<Vec<usize>> {
+        let mut path = vec![self.start];
+        let mut current = self.start;
+        while current != self.goal {
+            current = self.best_step(grid, current)?.0;
+            if path.len() > grid.len() {
+                return None;
+            }
+            path.push(current);
+        }
+        Some(path)
+    }
+}
+
+/// Walks from `start` to `goal` while `scripted` changes, plus `random`
+/// random ones every step, reshape `grid`; each step is drawn with
+/// `animate`'s delay after it, if given.
+pub fn run(
+    grid: &mut Grid,
+    start: usize,
+    goal: usize,
+    mut scripted: Vec<Event>,
+    random: usize,
+    rng: &mut impl Rng,
+    animate: Option<Duration>,
+) -> io::Result<()> {
+    let at = |grid: &Grid, cell: usize| grid.label(cell);
+
+    // The heuristic has to stay a lower bound through every change: the
+    // cheapest move between values on the map or in the script, or
+    // between any values when random changes can set anything.
+    let values = grid
+        .values()
+        .filter(|&value| value != grid.wall)
+        .chain(scripted.iter().map(|event| event.value))
+        .chain((0..=255).filter(|_| random > 0));
+    let cheapest = cost::least(grid, values);
+
+    println!(
+        "\n🔁 Walking from {} to {}, replanning with D* Lite...\n",
+        at(grid, start),
+        at(grid, goal)
+    );
+
+    let mut planner = DStarLite::new(grid, start, goal, cheapest);
+    planner.compute(grid);
+    let first = planner.expanded;
+    let scratch_first = Dijkstra.find(grid, start, goal, &mut Unwatched).expanded;
+    let (mut repaired, mut scratch, mut replans, mut wrong) = (0, 0, 0, 0);
+
+    let mut position = start;
+    let mut last_moved_from = start;
+    let mut walked = vec![start];
+    let mut cost = 0;
+    let mut step = 0;
+    scripted.reverse();
+
+    // Animated, what happened on a step is shown under the map with the
+    // next frame, since printing it would scroll the map out of place.
+    let mut screen = animate.map(|delay| (Screen::new(NOTE_LINES), delay));
+    let mut notes = Vec::new();
+    while position != goal {
+        if let Some((screen, delay)) = &mut screen {
+            let mut shown: HashSet<usize> = walked.iter().copied().collect();
+            shown.extend(planner.path(grid).unwrap_or_default());
+            let _ = screen.draw(grid, Some(&shown), palette::current().path());
+            let _ = screen.lines(&fit_notes(&notes));
+            notes.clear();
+            thread::sleep(*delay);
+        }
+
+        let Some((next, _)) = planner.best_step(grid, position) else {
+            notes.push(format!(
+                "✗ Step {}: walls cut {} off from {}",
+                step,
+                at(grid, position),
+                at(grid, goal)
+            ));
+            break;
+        };
+        cost += grid.step_cost(position, next);
+        position = next;
+        walked.push(position);
+        planner.start = position;
+        step += 1;
+
+        // This step's changes: the script's, then the random ones.
+        let mut changes = Vec::new();
+        while scripted.last().is_some_and(|event| event.step <= step) {
+            let event = scripted.pop().expect("checked above");
+            match event.cell.locate(grid) {
+                Ok((x, y)) => changes.push((grid.coords_to_index(x, y), event.value)),
+                Err(e) => notes.push(format!("⚠ Step {}: {}", step, e)),
+            }
+        }
+        for _ in 0..random {
+            changes.push((rng.random_range(0..grid.len()), rng.random()));
+        }
+
+        let mut changed = false;
+        for (cell, value) in changes {
+            if value == grid.wall && (cell == position || cell == goal) {
+                notes.push(format!(
+                    "⚠ Step {}: {} stays open, the agent needs it",
+                    step,
+                    at(grid, cell)
+                ));
+                continue;
+            }
+            if grid.value(cell) == value {
+                continue;
+            }
+            if !changed {
+                planner.km += planner.heuristic(grid, last_moved_from, position);
+                last_moved_from = position;
+                changed = true;
+            }
+            notes.push(format!(
+                "⚡ Step {}: {} {:02X} → {:02X}",
+                step,
+                at(grid, cell),
+                grid.value(cell),
+                value
+            ));
+            grid.set_value(cell, value);
+            planner.changed(grid, cell);
+        }
+
+        if changed {
+            planner.expanded = 0;
+            planner.compute(grid);
+            repaired += planner.expanded;
+            let from_scratch = Dijkstra.find(grid, position, goal, &mut Unwatched);
+            scratch += from_scratch.expanded;
+            replans += 1;
+            let expected = from_scratch.found.map(|(_, cost)| cost);
+            if planner.cost() != expected {
+                wrong += 1;
+                let cost = |cost: Option<usize>| cost.map_or("none".to_string(), |c| c.to_string());
+                notes.push(format!(
+                    "⚠ Step {}: the repair costs {}, Dijkstra from scratch {}",
+                    step,
+                    cost(planner.cost()),
+                    cost(expected)
+                ));
+            }
+        }
+        if screen.is_none() {
+            for note in notes.drain(..) {
+                println!("{}", note);
+            }
+        }
+    }
+
+    match screen {
+        Some((mut screen, _)) => {
+            let shown: HashSet<usize> = walked.into_iter().collect();
+            screen.draw(grid, Some(&shown), palette::current().path())?;
+            screen.lines(&fit_notes(&notes))?;
+        }
+        None => {
+            for note in notes {
+                println!("{}", note);
+            }
+        }
+    }
+    if position == goal {
+        println!(
+            "✓ Reached {} in {} steps, cost {}",
+            at(grid, goal),
+            step,
+            cost
+        );
+    }
+    println!(" Replans: {}", replans);
+    println!(
+        " Expanded: {} cells for the first plan, {} for repairs",
+        first, repaired
+    );
+    println!(
+        " From scratch: {} cells for the first plan, {} for replans",
+        scratch_first, scratch
+    );
+    if scratch > 0 {
+        println!(
+            " Reuse: repairs expanded {:.0}% of what replanning from scratch did",
+            repaired as f64 * 100.0 / scratch as f64
+        );
+    }
+    if wrong > 0 {
+        println!(
+            "⚠️ {} of {} repairs cost other than Dijkstra from scratch",
+            wrong, replans
+        );
+    } else if replans > 0 {
+        println!(" Checked: every repair costs what Dijkstra from scratch does");
+    }
+
+    Ok(())
+}
+
+/// `notes` cut down to the rows kept for them under the animated map.
+fn fit_notes(notes: &[String]) -> Vec<String> {
+    if notes.len() <= NOTE_LINES {
+        return notes.to_vec();
+    }
+    let mut fitted = notes[..NOTE_LINES - 1].to_vec();
+    fitted.push(format!("… and {} more", notes.len() - (NOTE_LINES - 1)));
+    fitted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    /// Walks random maps while random cells change, zeros and walls
+    /// included, and checks every repair against Dijkstra from scratch.
+    fn check_repairs(cost_fn: &str) {
+        for seed in 0..40 {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            let (width, height) = (12, 8);
+            let values: Vec<u8> = (0..width * height).map(|_| rng.random()).collect();
+            let mut grid = Grid::new(width, height, values, 0xFF);
+            grid.cost = cost::parse(cost_fn).expect("a cost function");
+            let (mut position, goal) = (0, grid.len() - 1);
+            grid.set_value(position, 0);
+            grid.set_value(goal, 0);
+
+            let cheapest = cost::least(&grid, 0..=255);
+            let mut planner = DStarLite::new(&grid, position, goal, cheapest);
+            planner.compute(&grid);
+            let mut last_moved_from = position;
+            while let Some((next, _)) = planner.best_step(&grid, position) {
+                position = next;
+                planner.start = position;
+                if position == goal {
+                    break;
+                }
+                planner.km += planner.heuristic(&grid, last_moved_from, position);
+                last_moved_from = position;
+                for _ in 0..2 {
+                    let cell = rng.random_range(0..grid.len());
+                    let value = rng.random();
+                    if value == grid.wall && (cell == position || cell == goal) {
+                        continue;
+                    }
+                    grid.set_value(cell, value);
+                    planner.changed(&grid, cell);
+                }
+                planner.compute(&grid);
+                let expected = Dijkstra
+                    .find(&grid, position, goal, &mut Unwatched)
+                    .found
+                    .map(|(_, cost)| cost);
+                assert_eq!(planner.cost(), expected, "seed {}", seed);
+            }
+        }
+    }
+
+    #[test]
+    fn repairs_match_dijkstra() {
+        check_repairs("value");
+    }
+
+    #[test]
+    fn repairs_match_dijkstra_with_free_steps() {
+        check_repairs("elevation-diff");
+    }
+}
@@ -0,0 +1,450 @@
+//! `--edit`: the map in a full-screen editor. Cells are painted with the
+//! keyboard or the mouse, start and end placed anywhere, and the selected
+//! search run in place, its expanded cells drawn as it goes and its path
+//! left on the map until the next change.
+//!
+//! Keys: arrows move, space paints the brush, `+`/`-` change the brush by
+//! 10 and `]`/`[` by 1, `p` picks the brush from the cell, `x` toggles a
+//! wall, `s` and `e` place the start and the end, `a` cycles the search,
+//! enter runs it, ctrl-s saves and `q` quits. The left mouse button paints,
+//! the right one toggles walls.
+
+use crate::mapfile::{self, MapFormat, MapInfo};
+use crate::search::{Algo, Expansion, Watch};
+use crate::{FullScreen, Grid, palette, value_to_color};
+use clap::ValueEnum;
+use crossterm::{
+    ExecutableCommand, QueueableCommand, cursor,
+    event::{
+        self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent,
+        MouseEventKind,
+    },
+    style::{Attribute, Color, Print, SetAttribute, SetForegroundColor},
+    terminal,
+};
+use std::collections::HashSet;
+use std::io::{self, Stdout, Write};
+use std::thread;
+use std::time::Duration;
+
+/// Where ctrl-s writes the map.
+pub struct SaveTo {
+    pub path: String,
+    pub format: MapFormat,
+    pub info: MapInfo,
+    pub compress: bool,
+}
+
+/// Lines below the map: the state, then the keys or the last message.
+const STATUS_LINES: u16 = 2;
+
+const HELP: &str = "arrows move · space paint · +/- ]/[ brush · p pick · x wall · s start · e end · a search · enter run · ctrl-s save · q quit";
+
+/// What the map part of the screen shows: which cells, and where the
+/// cursor and the endpoints are.
+#[derive(Clone, Copy)]
+struct View {
+    /// The top-left cell on screen.
+    corner: (usize, usize),
+    /// How many cells fit across and down.
+    size: (usize, usize),
+    cursor: (usize, usize),
+    start: (usize, usize),
+    end: (usize, usize),
+}
+
+impl View {
+    /// Scrolls so the cursor is on screen.
+    fn follow(&mut self) {
+        let (width, height) = terminal::size().unwrap_or((80, 24));
+        self.size = (
+            (width as usize / 3).max(1),
+            (height.saturating_sub(STATUS_LINES) as usize).max(1),
+        );
+        let axis = |corner: usize, cursor: usize, size: usize| {
+            if cursor < corner {
+                cursor
+            } else if cursor >= corner + size {
+                cursor + 1 - size
+            } else {
+                corner
+            }
+        };
+        self.corner = (
+            axis(self.corner.0, self.cursor.0, self.size.0),
+            axis(self.corner.1, self.cursor.1, self.size.1),
+        );
+    }
+
+    /// The cell under the screen position, if it shows one.
+    fn cell_at(&self, grid: &Grid, column: u16, row: u16) -> Option<(usize, usize)> {
+        let (x, y) = (
+            self.corner.0 + column as usize / 3,
+            self.corner.1 + row as usize,
+        );
+        (row < self.size.1 as u16 && x < grid.width && y < grid.height).then_some((x, y))
+    }
+
+    fn draw(
+        &self,
+        out: &mut Stdout,
+        grid: &Grid,
+        path: &HashSet<usize>,
+        expanded: &HashSet<usize>,
+    ) -> io::Result<()> {
+        let bottom = (self.corner.1 + self.size.1).min(grid.height);
+        let right = (self.corner.0 + self.size.0).min(grid.width);
+        let palette = palette::current();
+        for (row, y) in (self.corner.1..bottom).enumerate() {
+            out.queue(cursor::MoveTo(0, row as u16))?;
+            for x in self.corner.0..right {
+                let index = grid.coords_to_index(x, y);
+                let value = grid.value(index);
+                let (color, text) = if (x, y) == self.start {
+                    (Color::White, "S  ".to_string())
+                } else if (x, y) == self.end {
+                    (Color::White, "E  ".to_string())
+                } else if grid.is_wall(index) {
+                    (palette.blocked(), "██ ".to_string())
+                } else if path.contains(&index) {
+                    (palette.path(), format!("{:02X} ", value))
+                } else if expanded.contains(&index) {
+                    (palette.visited(Color::Grey), format!("{:02X} ", value))
+                } else {
+                    (value_to_color(value), format!("{:02X} ", value))
+                };
+                out.queue(SetForegroundColor(color))?;
+                if (x, y) == self.cursor {
+                    // The cursor covers the cell's two characters, not the
+                    // space after them.
+                    let (cell, gap) =
+                        text.split_at(text.char_indices().nth(2).map_or(0, |(at, _)| at));
+                    out.queue(SetAttribute(Attribute::Reverse))?;
+                    out.queue(Print(cell))?;
+                    out.queue(SetAttribute(Attribute::NoReverse))?;
+                    out.queue(Print(gap))?;
+                } else {
+                    out.queue(Print(text))?;
+                }
+            }
+            out.queue(terminal::Clear(terminal::ClearType::UntilNewLine))?;
+        }
+        out.queue(SetForegroundColor(Color::Reset))?;
+        Ok(())
+    }
+}
+
+/// Draws a search run from the editor on the map as it expands cells,
+/// until a key is pressed.
+struct Live<'a> {
+    out: &'a mut Stdout,
+    view: View,
+    skipped: bool,
+}
+
+impl Watch for Live<'_> {
+    fn watching(&self) -> bool {
+        !self.skipped
+    }
+
+    fn expanded(&mut self, grid: &Grid, cells: &HashSet<usize>, _at: &Expansion) {
+        if event::poll(Duration::ZERO).unwrap_or(false) {
+            let _ = event::read();
+            self.skipped = true;
+            return;
+        }
+        let _ = self.view.draw(self.out, grid, &HashSet::new(), cells);
+        let _ = self.out.flush();
+        thread::sleep(Duration::from_millis(15));
+    }
+}
+
+struct Editor {
+    grid: Grid,
+    view: View,
+    brush: u8,
+    /// Index into every `Algo`.
+    algo: usize,
+    bidirectional: bool,
+    path: HashSet<usize>,
+    /// The last search's outcome, or the last message.
+    status: String,
+    /// Whether the map changed since it was last saved.
+    unsaved: bool,
+    /// Whether `q` was just pressed with unsaved changes.
+    quitting: bool,
+    save: Option<SaveTo>,
+    saved: bool,
+}
+
+/// Edits `grid` until `q`, starting with the cursor on `start` and the
+/// search set to `algo`.
+pub fn run(
+    grid: Grid,
+    start: (usize, usize),
+    end: (usize, usize),
+    algo: Algo,
+    bidirectional: bool,
+    save: Option<SaveTo>,
+) -> io::Result<()> {
+    if grid.is_empty() {
+        eprintln!("Can't edit an empty map");
+        std::process::exit(1);
+    }
+    let inside = |(x, y): (usize, usize)| (x.min(grid.width - 1), y.min(grid.height - 1));
+    let (start, end) = (inside(start), inside(end));
+    let mut editor = Editor {
+        view: View {
+            corner: (0, 0),
+            size: (1, 1),
+            cursor: start,
+            start,
+            end,
+        },
+        brush: 0x40,
+        algo: Algo::value_variants()
+            .iter()
+            .position(|&variant| variant == algo)
+            .unwrap_or(0),
+        bidirectional,
+        path: HashSet::new(),
+        status: HELP.to_string(),
+        unsaved: false,
+        quitting: false,
+        save,
+        saved: false,
+        grid,
+    };
+
+    let mut out = io::stdout();
+    {
+        let _screen = FullScreen::enter(&mut out)?;
+        out.execute(terminal::Clear(terminal::ClearType::All))?;
+        loop {
+            editor.view.follow();
+            editor.draw(&mut out)?;
+            let keep_going = match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => editor.key(key, &mut out),
+                Event::Mouse(mouse) => {
+                    editor.mouse(mouse);
+                    true
+                }
+                Event::Resize(..) => {
+                    out.execute(terminal::Clear(terminal::ClearType::All))?;
+                    true
+                }
+                _ => true,
+            };
+            if !keep_going {
+                break;
+            }
+        }
+    }
+
+    if let (true, Some(save)) = (editor.saved, &editor.save) {
+        println!("✓ Map saved to {}", save.path);
+    }
+    if editor.unsaved {
+        println!("⚠ Unsaved changes discarded");
+    }
+    Ok(())
+}
+
+impl Editor {
+    fn algo(&self) -> Algo {
+        Algo::value_variants()[self.algo]
+    }
+
+    fn draw(&self, out: &mut Stdout) -> io::Result<()> {
+        self.view
+            .draw(out, &self.grid, &self.path, &HashSet::new())?;
+
+        let (x, y) = self.view.cursor;
+        let state = format!(
+            "({}, {}) = {:02X} · brush {:02X} · search {}{}",
+            x,
+            y,
+            self.grid.value(self.grid.coords_to_index(x, y)),
+            self.brush,
+            self.algo()
+                .to_possible_value()
+                .expect("no variant is skipped")
+                .get_name(),
+            if self.unsaved { " · unsaved" } else { "" }
+        );
+        let shown = self.view.size.1.min(self.grid.height) as u16;
+        out.queue(cursor::MoveTo(0, shown))?;
+        out.queue(terminal::Clear(terminal::ClearType::FromCursorDown))?;
+        out.queue(Print(state))?;
+        out.queue(cursor::MoveTo(0, shown + 1))?;
+        out.queue(Print(&self.status))?;
+        out.flush()
+    }
+
+    /// Handles a key; `false` to quit.
+    fn key(&mut self, key: KeyEvent, out: &mut Stdout) -> bool {
+        let quitting = std::mem::take(&mut self.quitting);
+        let (x, y) = self.view.cursor;
+        match key.code {
+            KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => self.save(),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Char('q') | KeyCode::Esc => {
+                if !self.unsaved || quitting {
+                    return false;
+                }
+                self.quitting = true;
+                self.status = "Unsaved changes: q again to quit anyway, ctrl-s to save".to_string();
+            }
+            KeyCode::Left | KeyCode::Char('h') => self.view.cursor.0 = x.saturating_sub(1),
+            KeyCode::Right | KeyCode::Char('l') => {
+                self.view.cursor.0 = (x + 1).min(self.grid.width - 1)
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.view.cursor.1 = y.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.view.cursor.1 = (y + 1).min(self.grid.height - 1)
+            }
+            KeyCode::Char(' ') => self.paint((x, y), self.brush),
+            KeyCode::Char('x') => self.toggle_wall((x, y)),
+            KeyCode::Char('+') | KeyCode::Char('=') => self.brush = self.brush.saturating_add(0x10),
+            KeyCode::Char('-') => self.brush = self.brush.saturating_sub(0x10),
+            KeyCode::Char(']') => self.brush = self.brush.saturating_add(1),
+            KeyCode::Char('[') => self.brush = self.brush.saturating_sub(1),
+            KeyCode::Char('p') => {
+                self.brush = self.grid.value(self.grid.coords_to_index(x, y));
+            }
+            KeyCode::Char('s') => {
+                self.view.start = (x, y);
+                self.forget_search();
+            }
+            KeyCode::Char('e') => {
+                self.view.end = (x, y);
+                self.forget_search();
+            }
+            KeyCode::Char('a') => {
+                self.algo = (self.algo + 1) % Algo::value_variants().len();
+                self.forget_search();
+            }
+            KeyCode::Enter | KeyCode::Char('r') => self.search(out),
+            _ => {}
+        }
+        true
+    }
+
+    fn mouse(&mut self, mouse: MouseEvent) {
+        let Some(cell) = self.view.cell_at(&self.grid, mouse.column, mouse.row) else {
+            return;
+        };
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) | MouseEventKind::Drag(MouseButton::Left) => {
+                self.view.cursor = cell;
+                self.paint(cell, self.brush);
+            }
+            MouseEventKind::Down(MouseButton::Right) => {
+                self.view.cursor = cell;
+                self.toggle_wall(cell);
+            }
+            _ => {}
+        }
+    }
+
+    fn paint(&mut self, (x, y): (usize, usize), value: u8) {
+        let index = self.grid.coords_to_index(x, y);
+        if self.grid.value(index) != value {
+            self.grid.set_value(index, value);
+            self.unsaved = true;
+            self.forget_search();
+        }
+    }
+
+    /// Walls the cell, or opens it to the brush value (or 00 when the brush
+    /// is the wall value itself).
+    fn toggle_wall(&mut self, (x, y): (usize, usize)) {
+        let index = self.grid.coords_to_index(x, y);
+        let value = if !self.grid.is_wall(index) {
+            self.grid.wall
+        } else if self.brush != self.grid.wall {
+            self.brush
+        } else {
+            0
+        };
+        self.paint((x, y), value);
+    }
+
+    /// Drops the last search's results, which the map no longer matches.
+    fn forget_search(&mut self) {
+        self.path.clear();
+        self.status = HELP.to_string();
+    }
+
+    fn search(&mut self, out: &mut Stdout) {
+        self.forget_search();
+        let endpoint = |name: &str, cell| {
+            self.grid
+                .endpoint(cell)
+                .map_err(|e| format!("✗ The {}: {}", name, e))
+        };
+        let (start, end) = match (
+            endpoint("start", self.view.start),
+            endpoint("end", self.view.end),
+        ) {
+            (Ok(start), Ok(end)) => (start, end),
+            (Err(e), _) | (_, Err(e)) => {
+                self.status = e;
+                return;
+            }
+        };
+
+        let algo = self.algo();
+        // Searches that can't go both ways just go one.
+        let pathfinder = algo
+            .pathfinder(self.bidirectional, false)
+            .or_else(|_| algo.pathfinder(false, false))
+            .expect("every search can run one way");
+        let mut live = Live {
+            out,
+            view: self.view,
+            skipped: false,
+        };
+        let search = pathfinder.find(&self.grid, start, end, &mut live);
+
+        self.status = match search.found {
+            Some((path, cost)) => {
+                let status = format!(
+                    "✓ {}: cost {}, {} steps, {} cells expanded",
+                    pathfinder.name(),
+                    cost,
+                    path.len(),
+                    search.expanded
+                );
+                self.path = path.into_iter().collect();
+                status
+            }
+            None => format!(
+                "✗ {}: walls cut the start off from the end, {} cells expanded",
+                pathfinder.name(),
+                search.expanded
+            ),
+        };
+    }
+
+    fn save(&mut self) {
+        let Some(save) = &self.save else {
+            self.status = "✗ Nowhere to save: open a map file or pass --output".to_string();
+            return;
+        };
+        match mapfile::save(
+            &self.grid,
+            &save.path,
+            save.format,
+            save.info.clone(),
+            save.compress,
+        ) {
+            Ok(()) => {
+                self.unsaved = false;
+                self.saved = true;
+                self.status = format!("✓ Saved to {}", save.path);
+            }
+            Err(e) => self.status = format!("✗ Can't save to {}: {}", save.path, e),
+        }
+    }
+}
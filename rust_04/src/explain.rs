@@ -0,0 +1,115 @@
+//! `--explain`: a path found as a table of its steps, to check a search
+//! by and to debug a `--cost-fn` with: each cell, its value, what entering
+//! it cost and the cost so far, then the least, most and mean a step
+//! cost. The steps are costed again here, by `Grid::step_cost` as the
+//! searches cost them, so a total other than the one the search reported
+//! is a bug, and said so.
+
+use crate::Grid;
+use crate::report::coords;
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct Step {
+    /// As `[x, y]`, or `[x, y, layer]`.
+    cell: Vec<usize>,
+    value: u8,
+    /// What entering the cell cost: 0 for the start.
+    cost: usize,
+    /// The cost of the path up to and including the cell.
+    total: usize,
+}
+
+/// The cells of `path` as steps, each costed.
+pub fn steps(grid: &Grid, path: &[usize]) -> Vec<Step> {
+    let mut total = 0;
+    path.iter()
+        .enumerate()
+        .map(|(index, &cell)| {
+            let cost = match index {
+                0 => 0,
+                _ => grid.step_cost(path[index - 1], cell),
+            };
+            total += cost;
+            Step {
+                cell: coords(grid, cell),
+                value: grid.value(cell),
+                cost,
+                total,
+            }
+        })
+        .collect()
+}
+
+/// Prints `path`'s steps as a table, what they cost, and whether they add
+/// up to the `reported` cost.
+pub fn print(grid: &Grid, path: &[usize], reported: usize) {
+    let steps = steps(grid, path);
+    let rows: Vec<[String; 5]> = path
+        .iter()
+        .zip(&steps)
+        .enumerate()
+        .map(|(index, (&cell, step))| {
+            [
+                index.to_string(),
+                grid.label(cell),
+                format!("{:02X} ({})", step.value, step.value),
+                if index == 0 {
+                    "-".to_string()
+                } else {
+                    step.cost.to_string()
+                },
+                step.total.to_string(),
+            ]
+        })
+        .collect();
+    let header = ["Step", "Cell", "Value", "Cost", "Total"];
+    let widths: Vec<usize> = (0..header.len())
+        .map(|column| {
+            rows.iter()
+                .map(|row| row[column].chars().count())
+                .chain([header[column].len()])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    // Numbers to the right, the cell and its value to the left.
+    let line = |row: [&str; 5]| {
+        format!(
+            " {:>w0$}  {:<w1$}  {:<w2$}  {:>w3$}  {:>w4$}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            row[4],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3],
+            w4 = widths[4],
+        )
+    };
+    println!("{}", line(header));
+    for row in &rows {
+        println!("{}", line(row.each_ref().map(String::as_str)));
+    }
+
+    let costs: Vec<usize> = steps.iter().skip(1).map(|step| step.cost).collect();
+    match (costs.iter().min(), costs.iter().max()) {
+        (Some(least), Some(most)) => println!(
+            " Step cost: least {}, most {}, mean {:.1} over {} steps",
+            least,
+            most,
+            costs.iter().sum::<usize>() as f64 / costs.len() as f64,
+            costs.len()
+        ),
+        _ => println!(" Step cost: none, the path starts where it ends"),
+    }
+    let total = steps.last().map_or(0, |step| step.total);
+    if total != reported {
+        println!(
+            "⚠️ The steps add up to {}, not the {} the search reported",
+            total, reported
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//! `--export FILE`: the map as an image, PNG or SVG after the file's
+//! extension. Cells are squares of `--cell-size` pixels in the colors the
+//! terminal draws them in, walls dark grey unless the --palette says, with
+//! each path found drawn over them as a line through the centers of its
+//! cells, one color per path, the first the palette's.
+//! Smoothed paths go on top, straight from corner to corner, in gold and,
+//! in SVG, dashed.
+
+use crate::render::{self, Canvas, line_width};
+use crate::{Grid, palette, value_to_rgb};
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+/// Staircases between the floors of a map of several layers.
+const STAIRS: [u8; 3] = [0xC0, 0xC0, 0xC0];
+/// The lines of smoothed paths.
+const SMOOTHED: [u8; 3] = [0xFF, 0xD7, 0x00];
+
+/// Path colors after the palette's own, in the order the paths are given,
+/// then again from the top.
+const PATH_COLORS: [[u8; 3]; 4] = [
+    [0xFF, 0x00, 0xFF],
+    [0x00, 0xFF, 0xFF],
+    [0x00, 0x00, 0x00],
+    [0x80, 0x40, 0xFF],
+];
+
+#[derive(Clone, Copy)]
+pub enum ImageFormat {
+    Png,
+    Svg,
+}
+
+impl ImageFormat {
+    /// The format `path`'s extension names.
+    pub fn pick(path: &str) -> Result<ImageFormat, String> {
+        match Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some(extension) if extension.eq_ignore_ascii_case("png") => Ok(ImageFormat::Png),
+            Some(extension) if extension.eq_ignore_ascii_case("svg") => Ok(ImageFormat::Svg),
+            _ => Err(format!("'{}' must end in .png or .svg", path)),
+        }
+    }
+}
+
+/// Writes `grid` to `path` with `paths` (each with its name, shown as the
+/// line's title in SVG) drawn over it, then the corners of `smoothed`
+/// paths, `cell_size` pixels to a cell.
+pub fn export(
+    grid: &Grid,
+    path: &str,
+    format: ImageFormat,
+    paths: &[(String, Vec<usize>)],
+    smoothed: &[(String, Vec<usize>)],
+    cell_size: usize,
+) -> io::Result<()> {
+    match format {
+        ImageFormat::Png => write_png(grid, path, paths, smoothed, cell_size),
+        ImageFormat::Svg => fs::write(path, to_svg(grid, paths, smoothed, cell_size)),
+    }
+}
+
+fn cell_color(grid: &Grid, cell: usize) -> [u8; 3] {
+    if grid.is_wall(cell) {
+        palette::current().blocked_rgb()
+    } else if grid.stairs.contains(&cell) {
+        STAIRS
+    } else {
+        value_to_rgb(grid.value(cell))
+    }
+}
+
+fn path_color(index: usize) -> [u8; 3] {
+    match index % (PATH_COLORS.len() + 1) {
+        0 => palette::current().path_rgb(),
+        index => PATH_COLORS[index - 1],
+    }
+}
+
+fn write_png(
+    grid: &Grid,
+    path: &str,
+    paths: &[(String, Vec<usize>)],
+    smoothed: &[(String, Vec<usize>)],
+    cell_size: usize,
+) -> io::Result<()> {
+    let mut canvas = Canvas::new(grid, cell_size, |cell| cell_color(grid, cell))?;
+    for (index, (_, cells)) in paths.iter().enumerate() {
+        canvas.draw_path(grid, cells, path_color(index));
+    }
+    for (_, corners) in smoothed {
+        canvas.draw_path(grid, corners, SMOOTHED);
+    }
+
+    let dimension = |value: usize| {
+        u32::try_from(value).map_err(|_| io::Error::other("the image is too large for PNG"))
+    };
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(path)?),
+        dimension(canvas.width)?,
+        dimension(canvas.height)?,
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer
+        .write_image_data(canvas.pixels.as_flattened())
+        .map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)
+}
+
+fn hex([r, g, b]: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn to_svg(
+    grid: &Grid,
+    paths: &[(String, Vec<usize>)],
+    smoothed: &[(String, Vec<usize>)],
+    cell_size: usize,
+) -> String {
+    let width = grid.width * cell_size;
+    let height = grid.height * cell_size;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">\n",
+        width, height, width, height
+    );
+
+    svg.push_str("<g shape-rendering=\"crispEdges\">\n");
+    for cell in 0..grid.len() {
+        let (x, y) = grid.index_to_coords(cell);
+        let _ = writeln!(
+            svg,
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+            x * cell_size,
+            y * cell_size,
+            cell_size,
+            cell_size,
+            hex(cell_color(grid, cell))
+        );
+    }
+    svg.push_str("</g>\n");
+
+    for (index, (name, cells)) in paths.iter().enumerate() {
+        let _ = writeln!(
+            svg,
+            "<g fill=\"none\" stroke=\"{}\" stroke-width=\"{}\" \
+             stroke-linecap=\"round\" stroke-linejoin=\"round\"><title>{}</title>{}</g>",
+            hex(path_color(index)),
+            line_width(cell_size),
+            escape(name),
+            polylines(grid, cells, cell_size)
+        );
+    }
+    for (name, corners) in smoothed {
+        let _ = writeln!(
+            svg,
+            "<g fill=\"none\" stroke=\"{}\" stroke-width=\"{}\" stroke-dasharray=\"{}\" \
+             stroke-linecap=\"round\" stroke-linejoin=\"round\"><title>{}</title>{}</g>",
+            hex(SMOOTHED),
+            line_width(cell_size),
+            cell_size as f64 / 2.0,
+            escape(name),
+            polylines(grid, corners, cell_size)
+        );
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// One polyline per stroke of `cells`, each clipped to the image where it
+/// goes over a wrapped edge; a group holds them as one path.
+fn polylines(grid: &Grid, cells: &[usize], cell_size: usize) -> String {
+    let half = cell_size as f64 / 2.0;
+    render::strokes(grid, cells)
+        .into_iter()
+        .map(|stroke| {
+            let points: Vec<String> = stroke
+                .into_iter()
+                .map(|(x, y)| {
+                    format!(
+                        "{},{}",
+                        (x * cell_size as isize) as f64 + half,
+                        (y * cell_size as isize) as f64 + half
+                    )
+                })
+                .collect();
+            format!("<polyline points=\"{}\"/>", points.join(" "))
+        })
+        .collect()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
@@ -0,0 +1,86 @@
+//! Drawing a `--flow-field`: the cost of every cell to the goal as a
+//! heatmap, or the step each cell takes toward it as an arrow.
+
+use crate::{Grid, palette, style, value_to_color};
+use clap::ValueEnum;
+use crossterm::{
+    ExecutableCommand,
+    style::{Color, Print},
+};
+use hexpath_core::flow::FlowField;
+use std::io::{self, Write};
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum FlowView {
+    /// Cells colored by their cost to the goal
+    Heatmap,
+    /// Each cell's step toward the goal
+    Arrows,
+    Both,
+    /// Only answer the path queries
+    None,
+}
+
+/// Each cell colored by its cost to the goal, cheap to dear.
+pub fn draw_heatmap(field: &FlowField, grid: &Grid) -> io::Result<()> {
+    let dearest = field
+        .cost
+        .iter()
+        .flatten()
+        .copied()
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    draw(grid, |cell| {
+        let cost = field.cost[cell]?;
+        if style::plain() {
+            return Some((Color::Reset, style::shade(cost, dearest)));
+        }
+        let heat = (cost * 255 / dearest) as u8;
+        Some((value_to_color(heat), "██ ".to_string()))
+    })
+}
+
+/// Each cell's step toward the goal as an arrow.
+pub fn draw_arrows(field: &FlowField, grid: &Grid) -> io::Result<()> {
+    draw(grid, |cell| {
+        if cell == field.goal {
+            return Some((Color::White, "@@ ".to_string()));
+        }
+        let next = field.next(cell)?;
+        let arrow = match grid.step(cell, next) {
+            Some((1, 0)) => '→',
+            Some((-1, 0)) => '←',
+            Some((0, -1)) => '↑',
+            Some((0, 1)) => '↓',
+            Some((1, -1)) => '↗',
+            Some((-1, -1)) => '↖',
+            Some((1, 1)) => '↘',
+            Some(_) => '↙',
+            // Up or down the stairs.
+            None => '⇅',
+        };
+        Some((value_to_color(grid.value(cell)), format!("{}  ", arrow)))
+    })
+}
+
+/// Draws the map, each reachable cell as `look` says; walls and cells
+/// cut off from the goal look the same in every view.
+fn draw(grid: &Grid, look: impl Fn(usize) -> Option<(Color, String)>) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            let index = grid.coords_to_index(x, y);
+            let (color, text) = if grid.is_wall(index) {
+                (palette::current().blocked(), "▒▒ ".to_string())
+            } else {
+                look(index).unwrap_or((Color::DarkGrey, "·· ".to_string()))
+            };
+            style::color(&mut stdout, color)?;
+            stdout.execute(Print(text))?;
+        }
+        stdout.execute(Print("\n"))?;
+    }
+    style::color(&mut stdout, Color::Reset)?;
+    stdout.flush()
+}
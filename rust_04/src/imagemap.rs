@@ -0,0 +1,50 @@
+//! `--from-image FILE`: a picture (PNG, JPEG, BMP or PNM) as a map, one
+//! cell per pixel, each costing the pixel's brightness, black 00 to white
+//! FF. `--invert` makes dark pixels the dear ones instead, as for a scanned
+//! maze with ink walls; `--wall-threshold` makes every cell costing at
+//! least that much a wall.
+//!
+//! Only those cells are walls: a pixel that happens to land on the wall
+//! value is nudged one step off it.
+
+use crate::Grid;
+use std::io;
+
+/// The grid `path` makes, walls holding `wall`, and a description of how
+/// it was made for the map's `generator` field.
+pub fn load(
+    path: &str,
+    invert: bool,
+    threshold: Option<u8>,
+    wall: u8,
+) -> io::Result<(Grid, String)> {
+    let image = image::open(path)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?
+        .into_luma8();
+    let (width, height) = (image.width() as usize, image.height() as usize);
+
+    let cells: Vec<u8> = image
+        .into_raw()
+        .into_iter()
+        .map(|luma| {
+            let value = if invert { 255 - luma } else { luma };
+            if threshold.is_some_and(|threshold| value >= threshold) {
+                wall
+            } else if value == wall {
+                if wall == 0 { 1 } else { wall - 1 }
+            } else {
+                value
+            }
+        })
+        .collect();
+
+    let mut generator = format!("image {}", path);
+    if invert {
+        generator += ", inverted";
+    }
+    if let Some(threshold) = threshold {
+        generator += &format!(", walls from {:02X}", threshold);
+    }
+
+    Ok((Grid::new(width, height, cells, wall), generator))
+}
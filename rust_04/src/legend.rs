@@ -0,0 +1,159 @@
+//! `--legend`: what a visualized map is drawn with so places on it can be
+//! read off the screen, large and zoomed out as it may be: the numbers of
+//! its columns above it and of its rows down its left every N cells, S and
+//! E on where paths start and end, and under it the scale of its colors.
+
+use crate::viewport::Viewport;
+use crate::{Grid, palette, style, value_to_color};
+use crossterm::{
+    QueueableCommand,
+    style::{Color, Print},
+};
+use std::io::{self, Write};
+use std::ops::Range;
+
+/// Steps the color scale shows from cheap to dear.
+const SCALE: usize = 16;
+
+#[derive(Clone, Debug)]
+pub struct Legend {
+    /// Cells between the numbers on the rulers.
+    pub every: usize,
+    /// Cells marked S.
+    pub starts: Vec<usize>,
+    /// Cells marked E.
+    pub ends: Vec<usize>,
+}
+
+impl Legend {
+    pub fn new(every: usize) -> Self {
+        Legend {
+            every: every.max(1),
+            starts: Vec::new(),
+            ends: Vec::new(),
+        }
+    }
+
+    /// This legend with where `paths` start and end marked too.
+    pub fn marking(&self, paths: &[&[usize]]) -> Self {
+        let mut legend = self.clone();
+        for path in paths {
+            legend.starts.extend(path.first());
+            legend.ends.extend(path.last());
+        }
+        legend
+    }
+
+    /// The legend of one floor of a map of several layers, whose `size`
+    /// cells start at `first`.
+    pub fn floor(&self, first: usize, size: usize) -> Self {
+        let on_floor = |cells: &[usize]| {
+            cells
+                .iter()
+                .filter(|&&cell| (first..first + size).contains(&cell))
+                .map(|&cell| cell - first)
+                .collect()
+        };
+        Legend {
+            every: self.every,
+            starts: on_floor(&self.starts),
+            ends: on_floor(&self.ends),
+        }
+    }
+
+    /// S if `block` holds a start, else E if it holds an end.
+    pub fn mark(&self, block: &[usize]) -> Option<char> {
+        if block.iter().any(|cell| self.starts.contains(cell)) {
+            Some('S')
+        } else if block.iter().any(|cell| self.ends.contains(cell)) {
+            Some('E')
+        } else {
+            None
+        }
+    }
+
+    /// Characters the row numbers take, a space after them included.
+    pub fn gutter(&self, grid: &Grid) -> usize {
+        grid.height.saturating_sub(1).to_string().len() + 1
+    }
+
+    /// The number of a row the `rows` of cells a line shows, if one is
+    /// numbered, padded to the gutter.
+    pub fn row_label(&self, grid: &Grid, rows: Range<usize>) -> String {
+        let width = self.gutter(grid) - 1;
+        match self.numbered(rows.start..rows.end.min(grid.height)) {
+            Some(row) => format!("{:>1$} ", row, width),
+            None => " ".repeat(width + 1),
+        }
+    }
+
+    /// The numbers of the columns shown, over the block each is in,
+    /// `block_width` characters to a block, those that would run into the
+    /// one before left out.
+    pub fn column_ruler(&self, grid: &Grid, view: &Viewport, block_width: usize) -> String {
+        let (across, _) = view.shown(grid);
+        let mut ruler = String::new();
+        for x in 0..across {
+            let left = view.corner.0 + x * view.zoom;
+            let Some(column) = self.numbered(left..(left + view.zoom).min(grid.width)) else {
+                continue;
+            };
+            let at = x * block_width;
+            // A space between numbers, unless it is the first.
+            let free = if ruler.is_empty() { 0 } else { ruler.len() + 1 };
+            if at >= free {
+                ruler.push_str(&" ".repeat(at - ruler.len()));
+                ruler.push_str(&column.to_string());
+            }
+        }
+        ruler
+    }
+
+    /// The first of `cells` (rows or columns) a number goes on.
+    fn numbered(&self, cells: Range<usize>) -> Option<usize> {
+        let first = cells.start.div_ceil(self.every) * self.every;
+        (first < cells.end).then_some(first)
+    }
+}
+
+/// Prints the colors cells of `grid` are drawn in from cheap to dear,
+/// then those of walls, paths and any staircases; plain, what stands for
+/// them.
+pub fn print_scale(out: &mut impl Write, grid: &Grid) -> io::Result<()> {
+    let stairs = !grid.stairs.is_empty();
+    if style::plain() {
+        out.queue(Print(
+            "🔑 Cells by value, 00 cheap to FF dear · ██ walls · ** paths",
+        ))?;
+        if stairs {
+            out.queue(Print(" · ## staircases"))?;
+        }
+        out.queue(Print(" · S start · E end\n"))?;
+        return Ok(());
+    }
+    let palette = palette::current();
+    out.queue(Print("🔑 00 "))?;
+    for step in 0..SCALE {
+        style::color(out, value_to_color((step * 255 / (SCALE - 1)) as u8))?;
+        out.queue(Print("█"))?;
+    }
+    style::color(out, Color::Reset)?;
+    out.queue(Print(" FF · "))?;
+    style::color(out, palette.blocked())?;
+    out.queue(Print("██"))?;
+    style::color(out, Color::Reset)?;
+    out.queue(Print(" walls · "))?;
+    style::color(out, palette.path())?;
+    out.queue(Print("██"))?;
+    style::color(out, Color::Reset)?;
+    out.queue(Print(" paths"))?;
+    if stairs {
+        out.queue(Print(" · "))?;
+        style::color(out, Color::Magenta)?;
+        out.queue(Print("██"))?;
+        style::color(out, Color::Reset)?;
+        out.queue(Print(" staircases"))?;
+    }
+    out.queue(Print(" · S start · E end\n"))?;
+    Ok(())
+}
@@ -1,332 +1,1043 @@
-use clap::Parser;
+mod agents;
+mod bench;
+mod distances;
+mod dstar;
+mod editor;
+mod explain;
+mod export;
+mod flow;
+mod imagemap;
+mod legend;
+mod mapfile;
+mod overlay;
+mod palette;
+mod progress;
+mod recording;
+mod render;
+mod replay;
+mod report;
+mod screen;
+mod style;
+mod viewer;
+mod viewport;
+
+use clap::{Parser, Subcommand};
+use cost::CostModel;
 use crossterm::{
-    cursor,
-    style::{Color, Print, SetForegroundColor},
-    terminal, ExecutableCommand,
+    ExecutableCommand, QueueableCommand, cursor, event,
+    style::{Color, Print},
+    terminal,
 };
-use rand::Rng;
-use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashSet};
+use distances::DistanceField;
+use export::ImageFormat;
+use flow::FlowView;
+use hexpath_core::cost::parse_byte;
+use hexpath_core::flow::FlowField;
+use hexpath_core::longest::{Budget, MaxMethod};
+use hexpath_core::maze::MazeAlgo;
+use hexpath_core::noise::NoiseKind;
+use hexpath_core::storage::{Backend, Packed, Runs};
+use hexpath_core::weighted::WeightedAStar;
+use hexpath_core::{Grid, anyangle, cost, layers, longest, maze, noise, route, search, storage};
+use legend::Legend;
+use mapfile::{MapFormat, MapInfo};
+use overlay::Compared;
+use palette::Palette;
+use progress::{Interrupts, Progress};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use recording::{Animation, GifRecorder, Player};
+use replay::{SavedRuns, VisitLog};
+use report::OutputFormat;
+use search::Algo;
+use std::collections::HashSet;
 use std::fs;
-use std::io::{self, Write};
-use std::thread;
-use std::time::Duration;
+use std::io::{self, IsTerminal, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use viewport::Viewport;
 
 #[derive(Parser, Debug)]
 #[command(name = "hexpath")]
 #[command(about = "Pathfinding on hexadecimal grid", long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     map_file: Option<String>,
     #[arg(short, long)]
     generate: Option<String>,
+    /// Generate a WxH maze instead of random noise: cheap corridors
+    /// between walls
+    #[arg(long, value_name = "WxH", conflicts_with = "generate")]
+    generate_maze: Option<String>,
+    /// How --generate-maze carves the maze
+    #[arg(long, value_enum, default_value_t = MazeAlgo::Dfs)]
+    maze_algo: MazeAlgo,
+    /// Generate a WxH terrain instead of random noise: smooth slopes,
+    /// like an elevation map
+    #[arg(long, value_name = "WxH", conflicts_with_all = ["generate", "generate_maze"])]
+    generate_terrain: Option<String>,
+    /// The noise --generate-terrain is made of
+    #[arg(long, value_enum, default_value_t = NoiseKind::Perlin)]
+    noise: NoiseKind,
+    /// Size in cells of the hills and valleys of --generate-terrain
+    #[arg(long, value_name = "S", value_parser = parse_scale, default_value_t = 16.0)]
+    scale: f64,
+    /// Make the map from a picture (PNG, JPEG, BMP, PNM): each pixel a
+    /// cell costing its brightness
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["generate", "generate_maze", "generate_terrain"])]
+    from_image: Option<String>,
+    /// With --from-image, make dark pixels dear and bright ones cheap
+    #[arg(long, requires = "from_image")]
+    invert: bool,
+    /// With --from-image, turn every cell costing at least VALUE (hex)
+    /// into a wall
+    #[arg(long, value_name = "VALUE", value_parser = parse_byte, requires = "from_image")]
+    wall_threshold: Option<u8>,
+    /// Seed for the generators: the same seed and options give the same
+    /// map on any machine. Without it a random seed is used, and shown
+    #[arg(long)]
+    seed: Option<u64>,
     #[arg(short, long)]
     output: Option<String>,
+    /// Format of the map file and of --output; by default the one their
+    /// extension names (.json, .csv, .bin, .terrain), else hex. A name ending in .gz is
+    /// gzipped, its format named by the extension before it
+    #[arg(long, value_enum)]
+    map_format: Option<MapFormat>,
+    /// Cells in each row of a hex or CSV map file, for one whose rows are
+    /// wrapped over several lines or all on one; a first line of WxH says
+    /// the same in the file
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..), requires = "map_file")]
+    width: Option<u32>,
+    /// Compress a binary --output with zstd
+    #[arg(long)]
+    compress: bool,
+    /// A note kept in a JSON --output; repeat for several
+    #[arg(long, value_name = "KEY=VALUE", value_parser = parse_annotation)]
+    annotate: Vec<(String, String)>,
+    /// How the cells are held while searching: packed or as runs, a map
+    /// too big for a byte a cell may fit
+    #[arg(long, value_enum, default_value_t = Backend::Bytes)]
+    storage: Backend,
+    /// Round the cells' values to LEVELS evenly spaced ones, walls kept,
+    /// before searching: 15 or fewer pack to 4 bits a cell
+    #[arg(long, value_name = "LEVELS", value_parser = clap::value_parser!(u32).range(2..=255))]
+    quantize: Option<u32>,
     #[arg(short, long)]
     visualize: bool,
+    /// With --visualize, draw two cells to a character, without their
+    /// values, so maps three times as wide fit the terminal
+    #[arg(long, requires = "visualize")]
+    compact: bool,
+    /// With --visualize, number every Nth row and column around the map,
+    /// mark where paths start and end S and E, and show the scale of its
+    /// colors under it; N is 10 unless given as --legend=N
+    #[arg(long, value_name = "N", num_args = 0..=1, require_equals = true, default_missing_value = "10", value_parser = clap::value_parser!(u32).range(1..), requires = "visualize")]
+    legend: Option<u32>,
+    /// Print no colors or terminal codes, the same text on every run, for
+    /// pipes and for comparing with a saved copy: paths drawn as **,
+    /// staircases as ## and distances as digits
+    #[arg(long, visible_alias = "no-color", conflicts_with_all = ["animate", "interactive", "edit", "compact"])]
+    plain: bool,
+    /// The colors cells are drawn in, from cheap to dear, in the terminal
+    /// and in images: classic, viridis, magma, grayscale, or a FILE of
+    /// VALUE COLOR stops, which may also give the path, visited and
+    /// blocked colors
+    #[arg(long, value_name = "NAME|FILE", value_parser = Palette::parse, default_value = "classic")]
+    palette: Palette,
+    /// After each search, color every cell it expanded by its distance
+    /// from the start, with a legend
+    #[arg(long)]
+    show_distances: bool,
+    /// Print each path found step by step: the cell, its value, what the
+    /// step cost and the total so far, then the least, most and mean step
+    /// cost
+    #[arg(long)]
+    explain: bool,
+    /// Save the paths found, and every cell each search expanded in
+    /// order, to a JSON FILE that `hexpath replay` draws or plays again
+    #[arg(long, value_name = "FILE")]
+    save_path: Option<String>,
+    /// With --visualize, stay on the map: click a start and an end to
+    /// find the path between them, as often as wanted
+    #[arg(long, requires = "visualize")]
+    interactive: bool,
+    /// Draw the map and the paths found to an image, PNG or SVG after the
+    /// file's extension
+    #[arg(long, value_name = "FILE")]
+    export: Option<String>,
+    /// Pixels per cell in the --export image
+    #[arg(long, value_name = "PX", value_parser = clap::value_parser!(u16).range(1..), default_value_t = 16)]
+    cell_size: u16,
+    /// Record the search into an animated GIF: the expanded cells growing,
+    /// then the path found; with or without --animate
+    #[arg(long, value_name = "FILE")]
+    export_gif: Option<String>,
+    /// Expansions per frame of the --export-gif
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..), default_value_t = 1)]
+    gif_every: u32,
     #[arg(short, long)]
     both: bool,
+    /// How to print the results: text for people, or one JSON document
+    /// for scripts (searches only)
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = OutputFormat::Text,
+        conflicts_with_all = [
+            "visualize", "animate", "edit", "agents", "replan", "events",
+            "flow_field", "export", "export_gif", "show_distances", "save_path",
+        ]
+    )]
+    format: OutputFormat,
+    /// Search strategies to run, comma-separated, to compare them on the
+    /// same map
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "dijkstra")]
+    algo: Vec<Algo>,
+    /// Search from both ends at once, meeting in the middle (dijkstra and
+    /// astar only)
+    #[arg(long)]
+    bidirectional: bool,
+    /// Search on every core, by delta-stepping, for maps of millions of
+    /// cells (dijkstra only)
+    #[arg(long, conflicts_with = "bidirectional")]
+    parallel: bool,
+    /// Weighted A* (astar only): guess the cost left W times over, to
+    /// expand fewer cells for a path costing at most W times the cheapest
+    #[arg(long, value_name = "W", value_parser = parse_weight, conflicts_with_all = ["bidirectional", "parallel"])]
+    weight: Option<f64>,
+    /// Weighted A* (astar only) that finds a path fast, from --weight or
+    /// 3, then searches again with smaller weights for a cheaper one
+    /// until --time-budget runs out, reporting the bound it got to
+    #[arg(long, conflicts_with_all = ["bidirectional", "parallel"])]
+    anytime: bool,
+    /// Pull each path found taut, into straight lines between corners that
+    /// cost no more, and draw it over the path (theta-star paths always are)
+    #[arg(long)]
+    smooth: bool,
+    /// How --both finds the most expensive path; aco, annealing and
+    /// genetic improve on the greedy path at random, the same --seed
+    /// giving the same path
+    #[arg(long, visible_alias = "max-algo", value_name = "METHOD", value_enum, default_value_t = MaxMethod::Greedy)]
+    max_path: MaxMethod,
+    /// How long --max-path exact, aco, annealing or genetic, or --anytime,
+    /// may search before settling for its best path so far: milliseconds,
+    /// or with a unit, as in 500ms or 2.5s
+    #[arg(long, value_name = "MS", value_parser = parse_budget, default_value = "10s")]
+    time_budget: Duration,
+    /// Rounds --max-path aco, annealing or genetic runs: ant colonies of
+    /// 20, detours tried or generations; 100, 20000 and 200 if not given
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    iterations: Option<u32>,
     #[arg(short, long)]
     animate: bool,
+    /// Milliseconds --animate waits between frames; while it plays, space
+    /// pauses, n steps one expansion, + and - speed up and slow down
+    #[arg(long, value_name = "MS", requires = "animate", default_value_t = 50)]
+    speed: u64,
+    /// Open the map in a full-screen editor: paint cells, move the start
+    /// and end, run the first --algo in place; ctrl-s saves to --output,
+    /// else to the map file
+    #[arg(long)]
+    edit: bool,
+    /// Where paths start: X,Y (from 0) or top-left, top-right, bottom-left,
+    /// bottom-right, center
+    #[arg(long, value_parser = Anchor::parse, default_value = "top-left")]
+    start: Anchor,
+    /// Where paths end, in the same forms as --start
+    #[arg(long, value_parser = Anchor::parse, default_value = "bottom-right")]
+    end: Anchor,
+    /// A cell the minimum path must pass through, in the same forms as
+    /// --start; repeat for several, visited in the order given
+    #[arg(long, value_name = "X,Y", value_parser = Anchor::parse)]
+    via: Vec<Anchor>,
+    /// Visit the --via cells in whichever order is cheapest
+    #[arg(long)]
+    optimize_order: bool,
+    /// Find the paths between many starts and ends on the one map, one
+    /// "X,Y,X,Y" per line of the CSV FILE, and sum them up in a table
+    #[arg(
+        long,
+        value_name = "FILE",
+        required_if_eq("format", "csv"),
+        conflicts_with_all = [
+            "via", "both", "visualize", "animate", "edit", "agents", "replan",
+            "events", "flow_field", "export", "export_gif", "show_distances",
+            "save_path",
+        ]
+    )]
+    pairs: Option<String>,
+    /// Move several agents at once without collisions, one "START GOAL"
+    /// per line of FILE, instead of finding a single path
+    #[arg(long, value_name = "FILE")]
+    agents: Option<String>,
+    /// Walk from --start to --end while the map changes as FILE scripts,
+    /// repairing the path with D* Lite
+    #[arg(long, value_name = "FILE")]
+    replan: Option<String>,
+    /// Random cell changes every step of the walk (implies the D* Lite
+    /// walk, with or without --replan)
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    events: usize,
+    /// Work out every cell's cost to --end in one pass, then answer the
+    /// path from --start and from each --query without searching again
+    #[arg(long)]
+    flow_field: bool,
+    /// How to draw the --flow-field
+    #[arg(long, value_enum, default_value_t = FlowView::Both)]
+    flow_view: FlowView,
+    /// Another start to answer from the --flow-field; repeat for several
+    #[arg(long, value_name = "X,Y", value_parser = Anchor::parse)]
+    query: Vec<Anchor>,
+    /// Cell value (hex) that is a wall, never entered by a path [default:
+    /// the one a JSON map names, else FF]
+    #[arg(long, value_name = "VALUE", value_parser = parse_byte)]
+    blocked: Option<u8>,
+    /// Also move diagonally (8-connected), never squeezing between walls
+    #[arg(long)]
+    diagonals: bool,
+    /// Cost factor of a diagonal step with --diagonals
+    #[arg(long, value_name = "F", value_parser = parse_factor, default_value_t = std::f64::consts::SQRT_2)]
+    diagonal_cost: f64,
+    /// What a move costs: value (of the cell entered), elevation-diff
+    /// (between the two cells), threshold:N (1 below N, no way in from N
+    /// up), or expr:FORMULA of from and to, with + - * / ( ) abs min max
+    /// (a negative result forbids the move)
+    #[arg(long, value_name = "FN", value_parser = cost::parse, default_value = "value")]
+    cost_fn: Arc<dyn CostModel>,
+    /// Make the map a torus: moving off one edge comes back on the
+    /// opposite one, left to right and top to bottom
+    #[arg(long)]
+    wrap: bool,
+    /// Floors of the map, stacked: a generated map gets N of the size
+    /// asked, a loaded one is cut into N of its rows, top to bottom
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..), default_value_t = 1)]
+    layers: u32,
+    /// A staircase between floors at X,Y on every layer, or at X,Y,LAYER
+    /// on one only; repeat for several. Generated floors without any get
+    /// some at random
+    #[arg(long, value_name = "X,Y[,LAYER]", value_parser = layers::parse_stair)]
+    stairs: Vec<layers::Stair>,
+    /// Share of walls in a generated map, from 0 to 1
+    #[arg(long, value_name = "P", value_parser = parse_density, default_value_t = 0.0)]
+    obstacle_density: f64,
 }
 
-#[derive(Clone)]
-struct Grid {
-    width: usize,
-    height: usize,
-    cells: Vec<u8>,
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Time search strategies on seeded random maps of several sizes and
+    /// compare them
+    Bench(bench::BenchArgs),
+    /// Draw or play again the searches --save-path saved, on their map,
+    /// without searching
+    Replay(replay::ReplayArgs),
 }
 
-impl Grid {
-    fn generate_random(width: usize, height: usize) -> Self {
-        let mut rng = rand::rng();
-        let cells: Vec<u8> = (0..width * height)
-            .map(|_| rng.random_range(0..=255))
-            .collect();
+/// The wall value when neither --blocked nor the map names one.
+const DEFAULT_WALL: u8 = 0xFF;
 
-        Grid {
-            width,
-            height,
-            cells,
-        }
-    }
+/// The weight --anytime starts from without --weight.
+const ANYTIME_WEIGHT: f64 = 3.0;
 
-    fn get(&self, x: usize, y: usize) -> Option<u8> {
-        if x < self.width && y < self.height {
-            Some(self.cells[y * self.width + x])
-        } else {
-            None
-        }
-    }
+fn parse_annotation(text: &str) -> Result<(String, String), String> {
+    let (key, value) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got '{}'", text))?;
+    Ok((key.trim().to_string(), value.trim().to_string()))
+}
 
-    fn index_to_coords(&self, index: usize) -> (usize, usize) {
-        (index % self.width, index / self.width)
+fn parse_factor(text: &str) -> Result<f64, String> {
+    match text.parse::<f64>() {
+        Ok(factor) if factor.is_finite() && factor >= 0.0 => Ok(factor),
+        _ => Err(format!("expected a non-negative number, got '{}'", text)),
     }
+}
 
-    fn coords_to_index(&self, x: usize, y: usize) -> usize {
-        y * self.width + x
+fn parse_scale(text: &str) -> Result<f64, String> {
+    match text.parse::<f64>() {
+        Ok(scale) if scale.is_finite() && scale > 0.0 => Ok(scale),
+        _ => Err(format!("expected a positive number, got '{}'", text)),
     }
+}
 
-    fn neighbors(&self, index: usize) -> Vec<usize> {
-        let (x, y) = self.index_to_coords(index);
-        let mut neighbors = Vec::new();
+/// Milliseconds, or a number of `ms` or `s`.
+fn parse_budget(text: &str) -> Result<Duration, String> {
+    let (number, scale) = match text.strip_suffix("ms") {
+        Some(millis) => (millis, 0.001),
+        None => match text.strip_suffix('s') {
+            Some(seconds) => (seconds, 1.0),
+            None => (text, 0.001),
+        },
+    };
+    number
+        .parse::<f64>()
+        .ok()
+        .and_then(|number| Duration::try_from_secs_f64(number * scale).ok())
+        .filter(|budget| !budget.is_zero())
+        .ok_or_else(|| {
+            format!(
+                "expected a positive number of milliseconds, or of seconds ending in s, got '{}'",
+                text
+            )
+        })
+}
 
-        if y > 0 {
-            neighbors.push(self.coords_to_index(x, y - 1));
-        }
-        if y < self.height - 1 {
-            neighbors.push(self.coords_to_index(x, y + 1));
-        }
-        if x > 0 {
-            neighbors.push(self.coords_to_index(x - 1, y));
-        }
-        if x < self.width - 1 {
-            neighbors.push(self.coords_to_index(x + 1, y));
-        }
+fn parse_weight(text: &str) -> Result<f64, String> {
+    match text.parse::<f64>() {
+        Ok(weight) if weight.is_finite() && weight >= 1.0 => Ok(weight),
+        _ => Err(format!("expected a number of at least 1, got '{}'", text)),
+    }
+}
 
-        neighbors
+fn parse_density(text: &str) -> Result<f64, String> {
+    match text.parse::<f64>() {
+        Ok(density) if (0.0..=1.0).contains(&density) => Ok(density),
+        _ => Err(format!("expected a number from 0 to 1, got '{}'", text)),
     }
+}
 
-    fn save_to_file(&self, filename: &str) -> io::Result<()> {
-        let mut content = String::new();
+/// A cell named on the command line.
+#[derive(Clone, Copy, Debug)]
+enum Anchor {
+    /// X, Y and the layer, 0 unless given.
+    At(usize, usize, usize),
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+}
 
-        for y in 0..self.height {
-            for x in 0..self.width {
-                if let Some(value) = self.get(x, y) {
-                    content.push_str(&format!("{:02X} ", value));
+impl Anchor {
+    fn parse(text: &str) -> Result<Self, String> {
+        let anchor = match text {
+            "top-left" => Anchor::TopLeft,
+            "top-right" => Anchor::TopRight,
+            "bottom-left" => Anchor::BottomLeft,
+            "bottom-right" => Anchor::BottomRight,
+            "center" => Anchor::Center,
+            _ => {
+                let coordinate = |value: &str| {
+                    value
+                        .trim()
+                        .parse::<usize>()
+                        .map_err(|_| format!("invalid coordinate '{}'", value))
+                };
+                match text.split(',').collect::<Vec<_>>()[..] {
+                    [x, y] => Anchor::At(coordinate(x)?, coordinate(y)?, 0),
+                    [x, y, z] => Anchor::At(coordinate(x)?, coordinate(y)?, coordinate(z)?),
+                    _ => return Err(
+                        "expected X,Y, X,Y,LAYER or top-left, top-right, bottom-left, bottom-right, center"
+                            .to_string(),
+                    ),
                 }
             }
-            content.push('\n');
+        };
+        Ok(anchor)
+    }
+
+    /// The coordinates this anchor names in `grid`, not necessarily inside it.
+    /// On a map of several layers the top corners are on the first layer,
+    /// the bottom ones on the last and the center on the middle one.
+    fn resolve(self, grid: &Grid) -> (usize, usize) {
+        let right = grid.width.saturating_sub(1);
+        let bottom = grid.height.saturating_sub(1);
+        let floor = grid.floor_height();
+        match self {
+            Anchor::At(x, y, z) => (x, z * floor + y),
+            Anchor::TopLeft => (0, 0),
+            Anchor::TopRight => (right, 0),
+            Anchor::BottomLeft => (0, bottom),
+            Anchor::BottomRight => (right, bottom),
+            Anchor::Center => (grid.width / 2, grid.layers / 2 * floor + floor / 2),
         }
+    }
 
-        fs::write(filename, content)
+    /// The coordinates this anchor names in `grid`, if they are inside it.
+    fn locate(self, grid: &Grid) -> Result<(usize, usize), String> {
+        match self {
+            Anchor::At(x, y, z)
+                if x >= grid.width || y >= grid.floor_height() || z >= grid.layers =>
+            {
+                Err(if grid.layers > 1 {
+                    format!(
+                        "({}, {}, {}) is outside the {}x{} grid of {} layers",
+                        x,
+                        y,
+                        z,
+                        grid.width,
+                        grid.floor_height(),
+                        grid.layers
+                    )
+                } else if z > 0 {
+                    format!("({}, {}, {}): the map has a single layer", x, y, z)
+                } else {
+                    format!(
+                        "({}, {}) is outside the {}x{} grid",
+                        x, y, grid.width, grid.height
+                    )
+                })
+            }
+            _ => Ok(self.resolve(grid)),
+        }
     }
+}
+
+fn value_to_color(value: u8) -> Color {
+    let [r, g, b] = value_to_rgb(value);
+    Color::Rgb { r, g, b }
+}
 
-    fn load_from_file(filename: &str) -> io::Result<Self> {
-        let content = fs::read_to_string(filename)?;
-        let lines: Vec<&str> = content.lines().collect();
-        let height = lines.len();
-        let mut width = 0;
-        let mut cells = Vec::new();
+/// The color of a cell of `value`, on the --palette's ramp.
+fn value_to_rgb(value: u8) -> [u8; 3] {
+    palette::current().rgb(value)
+}
 
-        for line in lines {
-            let values: Vec<&str> = line.split_whitespace().collect();
-            if width == 0 {
-                width = values.len();
-            }
+/// The whole terminal, raw and listening to the mouse, for as long as this
+/// lives; restored however the screen using it ends.
+struct FullScreen;
 
-            for val in values {
-                let byte = u8::from_str_radix(val, 16)
-                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-                cells.push(byte);
-            }
-        }
+impl FullScreen {
+    fn enter(out: &mut io::Stdout) -> io::Result<FullScreen> {
+        terminal::enable_raw_mode()?;
+        out.execute(terminal::EnterAlternateScreen)?;
+        out.execute(event::EnableMouseCapture)?;
+        out.execute(cursor::Hide)?;
+        Ok(FullScreen)
+    }
+}
 
-        Ok(Grid {
-            width,
-            height,
-            cells,
-        })
+impl Drop for FullScreen {
+    fn drop(&mut self) {
+        let mut out = io::stdout();
+        let _ = out.execute(cursor::Show);
+        let _ = out.execute(event::DisableMouseCapture);
+        let _ = out.execute(terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
     }
 }
 
-#[derive(Eq, PartialEq)]
-struct State {
-    cost: usize,
-    position: usize,
+/// Prints `grid` with the cells of `paths` in white, or as ** if plain,
+/// and with a `legend` (--legend) its rulers, S and E where the paths
+/// start and end, and its color scale under it. On a wrapping grid,
+/// ↔ at both ends of a row and ↕ above and below a column mark where a
+/// path goes over the edge and comes back on the other side. A map of
+/// several layers is printed floor by floor. `compact` (--compact) draws
+/// two cells to a character, one above the other as the ▀ half-block and
+/// the background under it, without their values, so maps three times as
+/// wide fit without zooming out.
+fn visualize_grid(
+    grid: &Grid,
+    paths: &[&[usize]],
+    compact: bool,
+    legend: Option<&Legend>,
+) -> io::Result<()> {
+    print_map(grid, paths, None, compact, legend)
 }
 
-impl Ord for State {
-    fn cmp(&self, other: &Self) -> Ordering {
-        other.cost.cmp(&self.cost)
-    }
+/// Prints `grid` as [`visualize_grid`] does, but with each of `paths` as
+/// its own letter in its own color, and the cells on more than one as **.
+fn overlay_grid(
+    grid: &Grid,
+    paths: &[&[usize]],
+    compact: bool,
+    legend: Option<&Legend>,
+) -> io::Result<()> {
+    let owners: Vec<usize> = (0..paths.len()).collect();
+    print_map(grid, paths, Some(&owners), compact, legend)
 }
 
-impl PartialOrd for State {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+fn print_map(
+    grid: &Grid,
+    paths: &[&[usize]],
+    owners: Option<&[usize]>,
+    compact: bool,
+    legend: Option<&Legend>,
+) -> io::Result<()> {
+    let legend = legend.map(|legend| legend.marking(paths));
+    draw_map(grid, paths, owners, compact, legend.as_ref())?;
+    if legend.is_some() {
+        let mut stdout = io::stdout();
+        legend::print_scale(&mut stdout, grid)?;
+        stdout.flush()?;
     }
+    Ok(())
 }
 
-fn value_to_color(value: u8) -> Color {
-    let t = value as f32 / 255.0;
-    if t < 0.33 {
-        let s = t / 0.33;
-        Color::Rgb {
-            r: 0,
-            g: (255.0 * s) as u8,
-            b: (255.0 * (1.0 - s)) as u8,
-        }
-    } else if t < 0.66 {
-        let s = (t - 0.33) / 0.33;
-        Color::Rgb {
-            r: (255.0 * s) as u8,
-            g: 255,
-            b: 0,
-        }
-    } else {
-        let s = (t - 0.66) / 0.34;
-        Color::Rgb {
-            r: 255,
-            g: (255.0 * (1.0 - s)) as u8,
-            b: 0,
+/// The map of [`visualize_grid`], floor by floor, without the scale;
+/// overlaid, `owners` says which path each of `paths` is.
+fn draw_map(
+    grid: &Grid,
+    paths: &[&[usize]],
+    owners: Option<&[usize]>,
+    compact: bool,
+    legend: Option<&Legend>,
+) -> io::Result<()> {
+    if grid.layers > 1 {
+        for z in 0..grid.layers {
+            let (floor, first) = layers::floor(grid, z);
+            let size = floor.len();
+            // The stretches of each path on this floor, between stairs,
+            // and whose each is.
+            let (runs, run_owners): (Vec<Vec<usize>>, Vec<usize>) = paths
+                .iter()
+                .enumerate()
+                .flat_map(|(index, path)| {
+                    let owner = owners.map_or(index, |owners| owners[index]);
+                    path.chunk_by(|a, b| a / size == b / size)
+                        .map(move |run| (run, owner))
+                })
+                .filter(|(run, _)| run[0] / size == z)
+                .map(|(run, owner)| (run.iter().map(|&cell| cell - first).collect(), owner))
+                .unzip();
+            let runs: Vec<&[usize]> = runs.iter().map(Vec::as_slice).collect();
+            let stairs = if style::plain() {
+                "as ##"
+            } else {
+                "in magenta"
+            };
+            println!("🏢 Layer {} of {}, staircases {}:", z, grid.layers, stairs);
+            let legend = legend.map(|legend| legend.floor(first, size));
+            let owners = owners.map(|_| &run_owners[..]);
+            draw_map(&floor, &runs, owners, compact, legend.as_ref())?;
         }
+        return Ok(());
     }
-}
 
-fn visualize_grid(grid: &Grid, path: Option<&HashSet<usize>>, animate: bool) -> io::Result<()> {
     let mut stdout = io::stdout();
 
-    if animate {
-        stdout.execute(terminal::Clear(terminal::ClearType::All))?;
-        stdout.execute(cursor::MoveTo(0, 0))?;
-    }
-
-    for y in 0..grid.height {
-        for x in 0..grid.width {
-            if let Some(value) = grid.get(x, y) {
-                let index = grid.coords_to_index(x, y);
-                let is_path = path.is_some_and(|p| p.contains(&index));
-                if is_path {
-                    stdout.execute(SetForegroundColor(Color::White))?;
-                    stdout.execute(Print(format!("{:02X} ", value)))?;
+    // Zoomed out to the terminal's width so rows don't wrap, with a block's
+    // room spare for the marks of a wrapping grid, and room for the row
+    // numbers of a legend.
+    let block_width = if compact { 1 } else { 3 };
+    let gutter = legend.map_or(0, |legend| legend.gutter(grid));
+    let view = match terminal::size() {
+        Ok((columns, _)) if stdout.is_terminal() => Viewport::fitted(
+            grid,
+            (
+                ((columns as usize).saturating_sub(gutter) / block_width)
+                    .saturating_sub(grid.wrap as usize),
+                grid.height,
+            ),
+        ),
+        _ => Viewport::fitted(grid, (grid.width, grid.height)),
+    };
+    if view.zoom > 1 {
+        println!(
+            "🔍 Zoomed out to fit: each cell shows the average of {}x{}",
+            view.zoom, view.zoom
+        );
+    }
+
+    let (across, down) = view.shown(grid);
+    // The rows and columns of blocks some path leaves by one edge.
+    let mut rows = vec![false; down];
+    let mut columns = vec![false; across];
+    for pair in paths.iter().flat_map(|path| path.windows(2)) {
+        let (from_x, from_y) = grid.index_to_coords(pair[0]);
+        let (to_x, to_y) = grid.index_to_coords(pair[1]);
+        if from_x.abs_diff(to_x) > 1 {
+            rows[from_y / view.zoom] = true;
+            rows[to_y / view.zoom] = true;
+        }
+        if from_y.abs_diff(to_y) > 1 {
+            columns[from_x / view.zoom] = true;
+            columns[to_x / view.zoom] = true;
+        }
+    }
+    let marked = rows.contains(&true) || columns.contains(&true);
+    let margin = if marked { "  " } else { "" };
+    let indent = format!("{}{}", " ".repeat(gutter), margin);
+    let column_marks = || -> String {
+        let marks: String = columns
+            .iter()
+            .map(|&crossed| {
+                let mark = if crossed { "↕" } else { " " };
+                format!("{:<1$}", mark, block_width)
+            })
+            .collect();
+        format!("{}{}\n", indent, marks.trim_end())
+    };
+
+    let path: HashSet<usize> = paths.iter().flat_map(|path| path.iter().copied()).collect();
+    let owned = owners.map(|owners| overlay::owners(paths, owners));
+    let mark = |x: usize, y: usize| legend.and_then(|legend| legend.mark(&view.block(grid, x, y)));
+    let look = |x: usize, y: usize| {
+        let block = view.block(grid, x, y);
+        if let Some(mark) = mark(x, y) {
+            return (palette::current().path(), format!("{}  ", mark));
+        }
+        match &owned {
+            Some(owned) => overlay::look(owned, &block)
+                .unwrap_or_else(|| screen::look(grid, &block, None, palette::current().path())),
+            None => screen::look(grid, &block, Some(&path), palette::current().path()),
+        }
+    };
+    if let Some(legend) = legend {
+        let ruler = legend.column_ruler(grid, &view, block_width);
+        stdout.queue(Print(format!("{}{}\n", indent, ruler)))?;
+    }
+    if columns.contains(&true) {
+        stdout.queue(Print(column_marks()))?;
+    }
+    // Compact, each line holds two rows of blocks.
+    let rows_per_line = if compact { 2 } else { 1 };
+    for (line, crossed) in rows.chunks(rows_per_line).enumerate() {
+        let crossed = crossed.contains(&true);
+        if let Some(legend) = legend {
+            let top = view.corner.1 + line * rows_per_line * view.zoom;
+            let label = legend.row_label(grid, top..top + rows_per_line * view.zoom);
+            style::color(&mut stdout, Color::Reset)?;
+            stdout.queue(Print(label))?;
+        }
+        if marked {
+            style::color(&mut stdout, Color::Reset)?;
+            stdout.queue(Print(if crossed { "↔ " } else { margin }))?;
+        }
+        for x in 0..across {
+            if compact {
+                let y = line * 2;
+                let (top, _) = look(x, y);
+                let bottom = if y + 1 < down {
+                    look(x, y + 1).0
                 } else {
-                    let color = value_to_color(value);
-                    stdout.execute(SetForegroundColor(color))?;
-                    stdout.execute(Print(format!("{:02X} ", value)))?;
+                    Color::Reset
+                };
+                // A start or end shows as its letter in place of the
+                // half-block.
+                let below = if y + 1 < down { mark(x, y + 1) } else { None };
+                match mark(x, y).or(below) {
+                    Some(mark) => {
+                        style::color(&mut stdout, palette::current().path())?;
+                        style::background(&mut stdout, Color::Reset)?;
+                        stdout.queue(Print(mark))?;
+                    }
+                    None => {
+                        style::color(&mut stdout, top)?;
+                        style::background(&mut stdout, bottom)?;
+                        stdout.queue(Print("▀"))?;
+                    }
                 }
+            } else {
+                let (color, text) = look(x, line);
+                style::color(&mut stdout, color)?;
+                stdout.queue(Print(text))?;
             }
         }
-        stdout.execute(Print("\n"))?;
+        if compact {
+            style::background(&mut stdout, Color::Reset)?;
+        }
+        if crossed {
+            style::color(&mut stdout, Color::Reset)?;
+            stdout.queue(Print("↔"))?;
+        }
+        stdout.queue(Print("\n"))?;
     }
-
-    stdout.execute(SetForegroundColor(Color::Reset))?;
-    stdout.flush()?;
-
-    if animate {
-        thread::sleep(Duration::from_millis(50));
+    style::color(&mut stdout, Color::Reset)?;
+    if columns.contains(&true) {
+        stdout.queue(Print(column_marks()))?;
     }
-
+    stdout.flush()?;
     Ok(())
 }
 
-fn dijkstra_min_path(
+fn run_flow_field(
     grid: &Grid,
-    start: usize,
-    end: usize,
-    animate: bool,
-) -> Option<(Vec<usize>, usize)> {
-    let mut dist = vec![usize::MAX; grid.cells.len()];
-    let mut prev = vec![None; grid.cells.len()];
-    let mut heap = BinaryHeap::new();
-    let mut visited = HashSet::new();
-
-    dist[start] = 0;
-    heap.push(State {
-        cost: 0,
-        position: start,
-    });
+    goal: usize,
+    queries: &[usize],
+    view: FlowView,
+    visualize: bool,
+    compact: bool,
+    legend: Option<&Legend>,
+) -> io::Result<()> {
+    let at = |cell: usize| grid.label(cell);
 
-    while let Some(State { cost, position }) = heap.pop() {
-        if position == end {
-            let mut path = Vec::new();
-            let mut current = Some(end);
+    let started = Instant::now();
+    let field = FlowField::new(grid, goal);
+    // Plain, without the times, which change from run to run.
+    let took =
+        |elapsed, shown: fn(String) -> String| style::took(elapsed).map_or(String::new(), shown);
+    println!(
+        "\n🌊 Flow field to {}: {} cells reach it{}",
+        at(goal),
+        field.reachable(),
+        took(started.elapsed(), |took| format!(", computed in {}", took))
+    );
+    if matches!(view, FlowView::Heatmap | FlowView::Both) {
+        println!("\n🎨 Cost to the goal (cheap to dear):");
+        flow::draw_heatmap(&field, grid)?;
+    }
+    if matches!(view, FlowView::Arrows | FlowView::Both) {
+        println!("\n🧭 Step toward the goal:");
+        flow::draw_arrows(&field, grid)?;
+    }
+    println!();
 
-            while let Some(pos) = current {
-                path.push(pos);
-                current = prev[pos];
+    for &start in queries {
+        let started = Instant::now();
+        let found = field.path_from(start);
+        let elapsed = started.elapsed();
+        match found {
+            Some((path, cost)) => {
+                println!(
+                    "✓ From {}: cost {}, {} steps{}",
+                    at(start),
+                    cost,
+                    path.len(),
+                    took(elapsed, |took| format!(" (answered in {})", took))
+                );
+                if visualize {
+                    visualize_grid(grid, &[&path], compact, legend)?;
+                }
             }
-
-            path.reverse();
-            return Some((path, cost));
+            None => println!("✗ From {}: walls cut it off from {}", at(start), at(goal)),
         }
+    }
 
-        if visited.contains(&position) {
-            continue;
-        }
+    Ok(())
+}
 
-        visited.insert(position);
+fn run_agents(
+    grid: &Grid,
+    agents_file: &str,
+    visualize: bool,
+    compact: bool,
+    legend: Option<&Legend>,
+    animate: bool,
+) -> io::Result<()> {
+    let anchors = agents::parse(&fs::read_to_string(agents_file)?).unwrap_or_else(|e| {
+        eprintln!("Invalid agents file {}: {}", agents_file, e);
+        std::process::exit(1);
+    });
+    let at = |cell: usize| grid.label(cell);
 
-        if animate {
-            let _ = visualize_grid(grid, Some(&visited), true);
+    let mut starts = HashSet::new();
+    let mut goals = HashSet::new();
+    let mut pairs = Vec::new();
+    for (index, (start, goal)) in anchors.into_iter().enumerate() {
+        let endpoint = |what: &str, anchor: Anchor| {
+            anchor
+                .locate(grid)
+                .and_then(|at| grid.endpoint(at))
+                .unwrap_or_else(|e| {
+                    eprintln!("Invalid {} of agent {}: {}", what, agents::label(index), e);
+                    std::process::exit(1);
+                })
+        };
+        let (start, goal) = (endpoint("start", start), endpoint("goal", goal));
+        if !starts.insert(start) || !goals.insert(goal) {
+            eprintln!(
+                "Agent {} shares its start or goal with an agent before it",
+                agents::label(index)
+            );
+            std::process::exit(1);
         }
+        pairs.push((start, goal));
+    }
 
-        for neighbor in grid.neighbors(position) {
-            if visited.contains(&neighbor) {
-                continue;
-            }
+    println!(
+        "\n🤖 Planning {} agents with cooperative A*...\n",
+        pairs.len()
+    );
+    let plans = hexpath_core::agents::plan(grid, &pairs);
 
-            let new_cost = cost + grid.cells[neighbor] as usize;
-            if new_cost < dist[neighbor] {
-                dist[neighbor] = new_cost;
-                prev[neighbor] = Some(position);
-                heap.push(State {
-                    cost: new_cost,
-                    position: neighbor,
-                });
+    let mut total_cost = 0;
+    let mut makespan = 0;
+    for (index, (&(start, goal), plan)) in pairs.iter().zip(&plans).enumerate() {
+        let name = agents::label(index);
+        match plan {
+            Some((path, cost)) => {
+                println!(
+                    "✓ Agent {}: {} → {}, cost {}, arrives at step {}",
+                    name,
+                    at(start),
+                    at(goal),
+                    cost,
+                    path.len() - 1
+                );
+                total_cost += cost;
+                makespan = makespan.max(path.len() - 1);
             }
+            None => println!(
+                "✗ Agent {}: {} → {} is stuck behind the agents planned before it",
+                name,
+                at(start),
+                at(goal)
+            ),
         }
     }
+    println!(" Total cost: {}", total_cost);
+    println!(" Makespan: {} steps", makespan);
 
-    None
+    if animate {
+        for step in 0..=makespan {
+            let positions: Vec<Option<usize>> = plans
+                .iter()
+                .map(|plan| {
+                    plan.as_ref()
+                        .map(|(path, _)| path[step.min(path.len() - 1)])
+                })
+                .collect();
+            agents::draw(grid, &positions, step)?;
+        }
+    } else if visualize {
+        println!("\n🎨 Agent paths visualization:");
+        let paths: Vec<&[usize]> = plans
+            .iter()
+            .flatten()
+            .map(|(path, _)| path.as_slice())
+            .collect();
+        visualize_grid(grid, &paths, compact, legend)?;
+    }
+
+    Ok(())
 }
 
-fn greedy_max_path(grid: &Grid, start: usize, end: usize) -> Option<(Vec<usize>, usize)> {
-    let mut path = vec![start];
-    let mut visited = HashSet::new();
-    let mut current = start;
-    let mut total_cost = 0;
+/// How long --max-path may take.
+fn budget(args: &Args) -> Budget {
+    Budget {
+        time: args.time_budget,
+        rounds: args.iterations.map(|rounds| rounds as usize),
+    }
+}
 
-    visited.insert(start);
+/// What is known about a map `generator` made.
+fn made_by(args: &Args, generator: String, seed: Option<u64>) -> MapInfo {
+    MapInfo {
+        generator: Some(generator),
+        seed,
+        annotations: args.annotate.iter().cloned().collect(),
+        ..MapInfo::default()
+    }
+}
 
-    while current != end {
-        let neighbors = grid.neighbors(current);
-        let mut best_neighbor = None;
-        let mut best_cost = 0;
+/// Splits `grid` into the --layers floors, unless it has them already, and
+/// adds the --stairs.
+fn add_layers(args: &Args, grid: &mut Grid) {
+    let split = match args.layers {
+        1 => Ok(()),
+        layers => layers::split(grid, layers as usize),
+    };
+    if let Err(e) = split {
+        eprintln!("Invalid --layers: {}", e);
+        std::process::exit(1);
+    }
+    if let Err(e) = layers::add_stairs(grid, &args.stairs) {
+        eprintln!("Invalid --stairs: {}", e);
+        std::process::exit(1);
+    }
+}
 
-        for &neighbor in &neighbors {
-            if !visited.contains(&neighbor) {
-                let cost = grid.cells[neighbor] as usize;
-                if cost > best_cost {
-                    best_cost = cost;
-                    best_neighbor = Some(neighbor);
-                }
-            }
+/// Rounds the cells to the --quantize levels and moves them to the
+/// --storage backend; says how, unless they stay as they were.
+fn store(args: &Args, grid: &mut Grid) -> Option<String> {
+    let mut how = Vec::new();
+    if let Some(levels) = args.quantize {
+        for index in 0..grid.len() {
+            let value = storage::quantize(grid.value(index), grid.wall, levels as usize);
+            grid.set_value(index, value);
         }
-
-        if let Some(next) = best_neighbor {
-            total_cost += best_cost;
-            current = next;
-            visited.insert(current);
-            path.push(current);
-        } else {
-            let mut found = false;
-            for &neighbor in &neighbors {
-                if !visited.contains(&neighbor) {
-                    current = neighbor;
-                    visited.insert(current);
-                    path.push(current);
-                    total_cost += grid.cells[neighbor] as usize;
-                    found = true;
-                    break;
-                }
-            }
-            if !found {
-                return None;
-            }
+        how.push(format!("quantized to {} levels", levels));
+    }
+    let before = grid.storage_bytes();
+    match args.storage {
+        Backend::Bytes => {}
+        Backend::Packed => {
+            let packed = Packed::new(grid.values());
+            how.push(format!("packed {} bits each", packed.bits()));
+            grid.set_storage(packed);
         }
-
-        if path.len() > grid.cells.len() {
-            return None;
+        Backend::Runs => {
+            let runs = Runs::new(grid.values());
+            how.push(format!("in {} runs", runs.count()));
+            grid.set_storage(runs);
         }
     }
+    if args.storage != Backend::Bytes {
+        how.push(format!(
+            "{} instead of {}",
+            size(grid.storage_bytes()),
+            size(before)
+        ));
+    }
+    (!how.is_empty()).then(|| how.join(", "))
+}
 
-    Some((path, total_cost))
+/// `bytes`, in the largest unit it makes at least one of.
+fn size(bytes: usize) -> String {
+    match bytes {
+        0..1024 => format!("{} B", bytes),
+        1024..1048576 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1048576.0),
+    }
+}
+
+/// Saves the map to --output, if given.
+fn save_output(args: &Args, grid: &Grid, info: &MapInfo) -> io::Result<()> {
+    if let Some(output_file) = &args.output {
+        let format = MapFormat::pick(args.map_format, output_file);
+        mapfile::save(grid, output_file, format, info.clone(), args.compress).unwrap_or_else(|e| {
+            eprintln!("Can't save to {}: {}", output_file, e);
+            std::process::exit(1);
+        });
+        if args.format == OutputFormat::Text {
+            println!("✓ Map saved to {}", output_file);
+        }
+    }
+    Ok(())
 }
 
 fn main() -> io::Result<()> {
     let args = Args::parse();
+    style::restore_on_panic();
+    progress::listen();
+    let (plain, colors) = match &args.command {
+        Some(Command::Replay(replay_args)) => (replay_args.plain, &replay_args.palette),
+        _ => (args.plain, &args.palette),
+    };
+    palette::set(colors.clone());
+    if plain {
+        style::set_plain();
+    }
+    match &args.command {
+        Some(Command::Bench(bench_args)) => {
+            bench::run(bench_args);
+            return Ok(());
+        }
+        Some(Command::Replay(replay_args)) => return replay::run(replay_args),
+        None => {}
+    }
+
+    let gen_spec = args
+        .generate
+        .as_ref()
+        .or(args.generate_maze.as_ref())
+        .or(args.generate_terrain.as_ref());
+    // One generator for the map and the --events, so one seed repeats the
+    // whole run. ChaCha8 is specified bit for bit, unlike StdRng, whose
+    // algorithm may change between versions of rand.
+    let seed = args.seed.unwrap_or_else(rand::random);
+    // With --format json only the document is printed; the seed is in it.
+    let text = args.format == OutputFormat::Text;
+    let stochastic = args.both && args.max_path.stochastic();
+    if text && args.seed.is_none() && (gen_spec.is_some() || args.events > 0 || stochastic) {
+        println!(
+            "🎲 Seed: {} (pass --seed {} to repeat this run)",
+            seed, seed
+        );
+    }
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+    let export_format = args.export.as_deref().map(|path| {
+        ImageFormat::pick(path).unwrap_or_else(|e| {
+            eprintln!("Invalid --export: {}", e);
+            std::process::exit(1);
+        })
+    });
 
-    let grid = if let Some(gen_spec) = &args.generate {
+    let (mut grid, info) = if let Some(gen_spec) = gen_spec {
         let parts: Vec<&str> = gen_spec.split('x').collect();
         if parts.len() != 2 {
             eprintln!("Invalid format. Use WxH (e.g., 10x10)");
@@ -335,54 +1046,535 @@ fn main() -> io::Result<()> {
 
         let width = parts[0].parse::<usize>().expect("Invalid width");
         let height = parts[1].parse::<usize>().expect("Invalid height");
-        let grid = Grid::generate_random(width, height);
-
-        if let Some(output_file) = &args.output {
-            grid.save_to_file(output_file)?;
-            println!("✓ Map saved to {}", output_file);
+        let wall = args.blocked.unwrap_or(DEFAULT_WALL);
+        let generate = |rng: &mut ChaCha8Rng| {
+            if args.generate_maze.is_some() {
+                (
+                    maze::generate(width, height, args.maze_algo, wall, rng),
+                    format!("maze ({:?})", args.maze_algo).to_lowercase(),
+                )
+            } else if args.generate_terrain.is_some() {
+                (
+                    noise::generate(width, height, args.noise, args.scale, wall, rng),
+                    format!("terrain ({:?} noise, scale {})", args.noise, args.scale)
+                        .to_lowercase(),
+                )
+            } else {
+                (
+                    Grid::generate_random(width, height, wall, args.obstacle_density, rng),
+                    format!("random (obstacle density {})", args.obstacle_density),
+                )
+            }
+        };
+        // Each floor made on its own, one after the other.
+        let (first, generator) = generate(&mut rng);
+        let mut floors = vec![first];
+        for _ in 1..args.layers {
+            floors.push(generate(&mut rng).0);
+        }
+        let mut grid = layers::stack(floors);
+        if grid.layers > 1 && args.stairs.is_empty() {
+            layers::add_random_shafts(&mut grid, &mut rng);
+        }
+        add_layers(&args, &mut grid);
+        if args.generate_maze.is_none() && args.generate_terrain.is_none() {
+            // Walls sprinkled at random mustn't land where paths go.
+            let anchors = [args.start, args.end]
+                .into_iter()
+                .chain(args.via.iter().copied());
+            let cells: Vec<usize> = anchors
+                .filter_map(|anchor| anchor.locate(&grid).ok())
+                .map(|(x, y)| grid.coords_to_index(x, y))
+                .collect();
+            grid.open(&cells, &mut rng);
         }
 
-        grid
+        let info = made_by(&args, generator, Some(seed));
+        save_output(&args, &grid, &info)?;
+        (grid, info)
+    } else if let Some(image_file) = &args.from_image {
+        let wall = args.blocked.unwrap_or(DEFAULT_WALL);
+        let (mut grid, generator) =
+            imagemap::load(image_file, args.invert, args.wall_threshold, wall).unwrap_or_else(
+                |e| {
+                    eprintln!("Can't load image {}: {}", image_file, e);
+                    std::process::exit(1);
+                },
+            );
+        add_layers(&args, &mut grid);
+        let info = made_by(&args, generator, None);
+        save_output(&args, &grid, &info)?;
+        (grid, info)
     } else if let Some(map_file) = &args.map_file {
-        Grid::load_from_file(map_file)?
+        let format = MapFormat::pick(args.map_format, map_file);
+        let (mut grid, info) = mapfile::load(
+            map_file,
+            format,
+            args.blocked,
+            DEFAULT_WALL,
+            args.width.map(|width| width as usize),
+        )
+        .unwrap_or_else(|e| {
+            eprintln!("Can't load map {}: {}", map_file, e);
+            std::process::exit(1);
+        });
+        add_layers(&args, &mut grid);
+        // Converted, to the format the --output name gives.
+        save_output(&args, &grid, &info)?;
+        if text {
+            if let Some(generator) = &info.generator {
+                match info.seed {
+                    Some(seed) => println!("📝 Map: {}, seed {}", generator, seed),
+                    None => println!("📝 Map: {}", generator),
+                }
+            }
+            for (key, value) in &info.annotations {
+                println!(" {}: {}", key, value);
+            }
+        }
+        (grid, info)
     } else {
-        eprintln!("Must specify --generate or provide a map file");
+        eprintln!(
+            "Must specify --generate, --generate-maze, --generate-terrain, --from-image or provide a map file"
+        );
         std::process::exit(1);
     };
 
-    println!("📊 Grid: {}x{}", grid.width, grid.height);
+    if args.diagonals {
+        grid.diagonal = Some(args.diagonal_cost);
+    }
+    grid.wrap = args.wrap;
+    grid.cost = args.cost_fn.clone();
+    let stored = store(&args, &mut grid);
+
+    if text {
+        if grid.layers > 1 {
+            println!(
+                "📊 Grid: {}x{}, {} layers, {} staircases",
+                grid.width,
+                grid.floor_height(),
+                grid.layers,
+                grid.stairs.len()
+            );
+        } else {
+            println!("📊 Grid: {}x{}", grid.width, grid.height);
+        }
+        if grid.cost.name() != "value" {
+            println!("💰 Moves cost: {}", grid.cost.name());
+        }
+        if let Some(stored) = &stored {
+            println!("🗜️ Cells: {}", stored);
+        }
+    }
 
-    if args.visualize && !args.animate {
+    let legend = args.legend.map(|every| Legend::new(every as usize));
+    if args.visualize && !args.animate && !args.interactive {
         println!("\n🎨 Map visualization:");
-        visualize_grid(&grid, None, false)?;
+        // No path yet, so the legend marks where they will start and end.
+        let located = |anchor: Anchor| {
+            let (x, y) = anchor.locate(&grid).ok()?;
+            Some(grid.coords_to_index(x, y))
+        };
+        let legend = legend.as_ref().map(|legend| Legend {
+            starts: located(args.start).into_iter().collect(),
+            ends: located(args.end).into_iter().collect(),
+            ..legend.clone()
+        });
+        visualize_grid(&grid, &[], args.compact, legend.as_ref())?;
+    }
+
+    if args.edit {
+        let save_to = args
+            .output
+            .as_ref()
+            .or(args.map_file.as_ref())
+            .map(|path| editor::SaveTo {
+                path: path.clone(),
+                format: MapFormat::pick(args.map_format, path),
+                info,
+                compress: args.compress,
+            });
+        let (start, end) = (args.start.resolve(&grid), args.end.resolve(&grid));
+        return editor::run(grid, start, end, args.algo[0], args.bidirectional, save_to);
     }
 
-    let start = 0;
-    let end = grid.cells.len() - 1;
+    if let Some(agents_file) = &args.agents {
+        return run_agents(
+            &grid,
+            agents_file,
+            args.visualize,
+            args.compact,
+            legend.as_ref(),
+            args.animate,
+        );
+    }
 
-    if args.both || (!args.visualize && args.output.is_none()) {
-        println!("\n🔍 Finding paths from top-left to bottom-right...\n");
+    let endpoint = |name: &str, anchor: Anchor| {
+        anchor
+            .locate(&grid)
+            .and_then(|at| grid.endpoint(at))
+            .unwrap_or_else(|e| {
+                eprintln!("Invalid --{}: {}", name, e);
+                std::process::exit(1);
+            })
+    };
+    let start = endpoint("start", args.start);
+    let end = endpoint("end", args.end);
+    let via: Vec<usize> = args
+        .via
+        .iter()
+        .map(|&anchor| endpoint("via", anchor))
+        .collect();
+    if args.optimize_order && via.len() > route::MAX_OPTIMIZED {
+        eprintln!(
+            "--optimize-order takes at most {} --via cells, got {}",
+            route::MAX_OPTIMIZED,
+            via.len()
+        );
+        std::process::exit(1);
+    }
+    if args.replan.is_some() || args.events > 0 {
+        let events = match &args.replan {
+            Some(script) => dstar::parse(&fs::read_to_string(script)?).unwrap_or_else(|e| {
+                eprintln!("Invalid replan script {}: {}", script, e);
+                std::process::exit(1);
+            }),
+            None => Vec::new(),
+        };
+        return dstar::run(
+            &mut grid,
+            start,
+            end,
+            events,
+            args.events,
+            &mut rng,
+            args.animate.then(|| Duration::from_millis(args.speed)),
+        );
+    }
 
-        if let Some((min_path, min_cost)) = dijkstra_min_path(&grid, start, end, args.animate) {
-            println!("✓ Minimum cost path found!");
-            println!(" Cost: {}", min_cost);
-            println!(" Length: {} steps", min_path.len());
+    if args.flow_field {
+        let queries: Vec<usize> = [start]
+            .into_iter()
+            .chain(args.query.iter().map(|&anchor| endpoint("query", anchor)))
+            .collect();
+        return run_flow_field(
+            &grid,
+            end,
+            &queries,
+            args.flow_view,
+            args.visualize,
+            args.compact,
+            legend.as_ref(),
+        );
+    }
 
-            if args.visualize {
-                println!("\n🎨 Minimum path visualization:");
-                let path_set: HashSet<usize> = min_path.into_iter().collect();
-                visualize_grid(&grid, Some(&path_set), false)?;
+    let weighted = (args.weight.is_some() || args.anytime).then(|| WeightedAStar {
+        weight: args
+            .weight
+            .unwrap_or(if args.anytime { ANYTIME_WEIGHT } else { 1.0 }),
+        anytime: args.anytime.then_some(args.time_budget),
+    });
+    if weighted.is_some() && !args.algo.contains(&Algo::Astar) {
+        eprintln!("Invalid --weight or --anytime: they weigh A*'s guesses; add astar to --algo");
+        std::process::exit(1);
+    }
+    // A* guesses the cost left as the cheapest step times the moves left:
+    // nothing at all on a map with a free step, however weighted. The path
+    // is still within the bound, only found no faster than Dijkstra's.
+    if weighted.is_some() && search::cheapest_step(&grid) == 0 {
+        eprintln!(
+            "⚠️ A step on this map costs 0, so A* guesses 0 for the cost left whatever --weight says, and searches as Dijkstra does; a --cost-fn whose steps all cost something, such as expr:to+1, lets the weight speed it up"
+        );
+    }
+    let pathfinders: Vec<_> = args
+        .algo
+        .iter()
+        .map(|algo| match (algo, &weighted) {
+            (Algo::Astar, Some(weighted)) => Box::new(*weighted),
+            _ => algo
+                .pathfinder(args.bidirectional, args.parallel)
+                .unwrap_or_else(|e| {
+                    eprintln!("Invalid --algo: {}", e);
+                    std::process::exit(1);
+                }),
+        })
+        .collect();
+
+    if let Some(pairs_file) = &args.pairs {
+        let invalid = |e: String| -> ! {
+            eprintln!("Invalid --pairs {}: {}", pairs_file, e);
+            std::process::exit(1);
+        };
+        let anchors =
+            report::parse_pairs(&fs::read_to_string(pairs_file)?).unwrap_or_else(|e| invalid(e));
+        let mut pairs = Vec::new();
+        for (number, (from, to)) in anchors.into_iter().enumerate() {
+            let cell = |anchor: Anchor| anchor.locate(&grid).and_then(|at| grid.endpoint(at));
+            match cell(from).and_then(|start| Ok((start, cell(to)?))) {
+                Ok(pair) => pairs.push(pair),
+                Err(e) => invalid(format!("pair {}: {}", number + 1, e)),
             }
-        } else {
-            println!("✗ No minimum path found");
         }
+        return report::batch(&args, &grid, &info, &pathfinders, &pairs);
+    }
+
+    if args.format == OutputFormat::Json {
+        return report::print(&args, &grid, &info, &pathfinders, start, &via, end);
+    }
+
+    // The paths found, by name, for --export, and the corners of the
+    // smoothed ones.
+    let mut found_paths = Vec::new();
+    let mut smoothed_paths = Vec::new();
+    if args.interactive {
+        return viewer::run(&grid, &pathfinders, start, &via, end, args.optimize_order);
+    }
+
+    let mut animation = Animation {
+        terminal: args
+            .animate
+            .then(|| Player::new(Duration::from_millis(args.speed))),
+        gif: args.export_gif.as_ref().map(|gif_file| {
+            GifRecorder::create(
+                &grid,
+                gif_file,
+                args.cell_size as usize,
+                args.gif_every as usize,
+            )
+            .unwrap_or_else(|e| {
+                eprintln!("Can't export to {}: {}", gif_file, e);
+                std::process::exit(1);
+            })
+        }),
+    };
+
+    if args.both
+        || args.export.is_some()
+        || args.export_gif.is_some()
+        || args.show_distances
+        || args.save_path.is_some()
+        || (!args.visualize && args.output.is_none())
+    {
+        let at = |cell: usize| grid.label(cell);
+        println!("\n🔍 Finding paths from {} to {}...\n", at(start), at(end));
+        let mut saved = args
+            .save_path
+            .as_ref()
+            .map(|_| SavedRuns::new(&grid, start, &via, end));
+        let interrupts = Interrupts::catch();
+        // More than one path is drawn on one map, and compared, once all
+        // the searches are done.
+        let overlaid = pathfinders.len() > 1 || args.both;
+        let mut compared = Vec::new();
+
+        for (i, pathfinder) in pathfinders.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            let mut distances = args.show_distances.then(|| DistanceField::new(&grid));
+            let mut log = saved.as_ref().map(|_| VisitLog::default());
+            let mut progress = Progress::new(&grid, pathfinder.name(), !args.animate);
+            let route = route::plan(
+                pathfinder.as_ref(),
+                &grid,
+                start,
+                &via,
+                end,
+                args.optimize_order,
+                &mut (&mut progress, (&mut animation, (&mut distances, &mut log))),
+            );
+            progress.done();
+            let search = &route.search;
+            animation.found(&grid, search.found.as_ref().map(|(path, _)| &path[..]));
+            if progress::stopped() {
+                println!(
+                    "⏹ Search stopped ({}) after {:.1} s",
+                    pathfinder.name(),
+                    progress.elapsed().as_secs_f64()
+                );
+                println!(" Expanded: {} of {} cells", search.expanded, grid.len());
+                break;
+            }
+            if let (Some(saved), Some(log)) = (&mut saved, log) {
+                saved.add(&grid, pathfinder.as_ref(), &route, log);
+            }
 
-        if args.both {
+            if let Some((min_path, min_cost)) = &search.found {
+                // An anytime search that got its weight down to 1 found a
+                // cheapest path after all.
+                if pathfinder.optimal() || search.bound == Some(1.0) {
+                    println!("✓ Minimum cost path found ({})!", pathfinder.name());
+                } else {
+                    println!(
+                        "✓ Path found ({}, not necessarily the cheapest)!",
+                        pathfinder.name()
+                    );
+                }
+                if !via.is_empty() {
+                    let stops: Vec<String> = route.stops.iter().map(|&stop| at(stop)).collect();
+                    println!(" Via: {}", stops.join(" → "));
+                }
+                println!(" Cost: {}", min_cost);
+                println!(" Length: {} steps", min_path.len());
+                println!(" Expanded: {} cells", search.expanded);
+                if let Some(bound) = search.bound.filter(|&bound| bound > 1.0) {
+                    println!(" Bound: costs at most {:.2} times the cheapest path", bound);
+                }
+                let smoothed = (args.smooth || pathfinder.any_angle())
+                    .then(|| anyangle::smooth(&grid, min_path));
+                if let Some(smoothed) = &smoothed {
+                    println!(
+                        " Smoothed: {} corners, cost {}, {:.1} cells end to end against {:.1} cell by cell",
+                        smoothed.corners.len(),
+                        smoothed.cost,
+                        anyangle::distance(&grid, &smoothed.corners),
+                        anyangle::distance(&grid, min_path)
+                    );
+                }
+                if args.explain {
+                    explain::print(&grid, min_path, *min_cost);
+                }
+
+                if args.export.is_some() {
+                    found_paths.push((pathfinder.name().to_string(), min_path.clone()));
+                    if let Some(smoothed) = &smoothed {
+                        let name = format!("{} smoothed", pathfinder.name());
+                        smoothed_paths.push((name, smoothed.corners.clone()));
+                    }
+                }
+                compared.push(Compared {
+                    name: pathfinder.name().to_string(),
+                    path: min_path.clone(),
+                    cost: *min_cost,
+                });
+                if let Some(smoothed) = &smoothed {
+                    compared.push(Compared {
+                        name: format!("{} smoothed", pathfinder.name()),
+                        path: smoothed.path.clone(),
+                        cost: smoothed.cost,
+                    });
+                }
+                if args.visualize && !overlaid {
+                    println!("\n🎨 {} path visualization:", pathfinder.name());
+                    visualize_grid(&grid, &[min_path], args.compact, legend.as_ref())?;
+                    if let Some(smoothed) = &smoothed {
+                        println!("\n🎨 {} smoothed path visualization:", pathfinder.name());
+                        visualize_grid(&grid, &[&smoothed.path], args.compact, legend.as_ref())?;
+                    }
+                }
+            } else {
+                let (from, to) = route.blocked.unwrap_or((start, end));
+                println!("✗ No path: walls cut {} off from {}", at(from), at(to));
+                println!(" Expanded: {} cells", search.expanded);
+            }
+            if let Some(distances) = &distances {
+                println!("\n🌡️ {} distances from the start:", pathfinder.name());
+                let path = search.found.as_ref().map_or(&[][..], |(path, _)| path);
+                distances.draw(&grid, path)?;
+            }
+        }
+
+        if let (Some(path_file), Some(saved)) = (&args.save_path, &saved) {
+            match saved.save(path_file) {
+                Ok(()) => println!(
+                    "\n✓ Searches saved to {} ({}); replay them with: hexpath replay MAP {}",
+                    path_file,
+                    saved.count(),
+                    path_file
+                ),
+                Err(e) => {
+                    eprintln!("Can't save to {}: {}", path_file, e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        drop(interrupts);
+
+        if args.both && !progress::stopped() {
             println!();
 
-            if let Some((max_path, max_cost)) = greedy_max_path(&grid, start, end) {
-                println!("✓ Maximum cost path found (greedy approximation)!");
+            // A generator of its own, so the path doesn't hang on how
+            // much of the seed the map took.
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            let longest = longest::find(&grid, start, end, args.max_path, budget(&args), &mut rng);
+            if let Some((max_path, max_cost)) = longest.found {
+                println!("✓ Maximum cost path found ({})!", longest.method);
                 println!(" Cost: {}", max_cost);
                 println!(" Length: {} steps", max_path.len());
+                if let Some((ran, rounds)) = longest.rounds {
+                    if ran < rounds {
+                        println!(
+                            " Rounds: {} of {}, before the time budget ran out",
+                            ran, rounds
+                        );
+                    } else {
+                        println!(" Rounds: {}", ran);
+                    }
+                    match longest.greedy {
+                        Some(greedy) => println!(
+                            " Greedy path: cost {}, this one {:+.1}% dearer",
+                            greedy,
+                            (max_cost as f64 / greedy.max(1) as f64 - 1.0) * 100.0
+                        ),
+                        None => println!(" Greedy path: none, the greedy walk ran into a dead end"),
+                    }
+                }
+                if args.explain {
+                    explain::print(&grid, &max_path, max_cost);
+                }
+
+                if args.export.is_some() {
+                    found_paths.push((format!("maximum ({})", longest.method), max_path.clone()));
+                }
+                compared.push(Compared {
+                    name: format!("maximum ({})", longest.method),
+                    path: max_path,
+                    cost: max_cost,
+                });
+            } else {
+                println!("✗ No maximum path found ({})", longest.miss);
+            }
+        }
+
+        if overlaid && !compared.is_empty() {
+            if args.visualize {
+                println!("\n🎨 Paths overlaid:");
+                let paths: Vec<&[usize]> = compared.iter().map(|run| &run.path[..]).collect();
+                overlay_grid(&grid, &paths, args.compact, legend.as_ref())?;
+            }
+            overlay::summarize(&grid, &compared)?;
+        }
+    }
+
+    if let (Some(gif_file), Some(gif)) = (&args.export_gif, animation.gif) {
+        match gif.finish() {
+            Ok(frames) => println!("✓ GIF saved to {} ({} frames)", gif_file, frames),
+            Err(e) => {
+                eprintln!("Can't export to {}: {}", gif_file, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let (Some(export_file), Some(format)) = (&args.export, export_format) {
+        export::export(
+            &grid,
+            export_file,
+            format,
+            &found_paths,
+            &smoothed_paths,
+            args.cell_size as usize,
+        )
+        .unwrap_or_else(|e| {
+            eprintln!("Can't export to {}: {}", export_file, e);
+            std::process::exit(1);
+        });
+        println!("✓ Image saved to {}", export_file);
+    }
 
-                if args.visualize {
+    if progress::stopped() {
+        std::process::exit(130);
+    }
+    Ok(())
+}
@@ -0,0 +1,789 @@
+//! Map files, in five formats:
+//!
+//! - `hex`, the original: rows of two-digit hex values split by spaces;
+//! - `csv`: rows of decimal values split by commas, for spreadsheets.
+//!   A hex or CSV map is a row a line, unless its first line is `WxH`, or
+//!   `--width` is given: then its cells are cut into rows that wide,
+//!   whatever lines they are on. Mistakes are told by line and column;
+//! - `json`: the size, the cells row by row, and what is known about the
+//!   map: the seed and generator that made it, its wall value and any
+//!   `--annotate` notes. A map of several layers has `layers`, its
+//!   `stairs` as `[x, y, layer]`, and its cells floor by floor, each
+//!   `height` rows;
+//! - `bin`, for huge maps: a 16-byte header, then the cells as raw bytes,
+//!   row by row, compressed with zstd if `--compress` was given;
+//! - `terrain`, to write by hand: a legend, then a blank line, then the
+//!   map drawn a character a cell. Each legend line is a character, a
+//!   name if wanted, `=`, and what stepping on it costs, or `blocked`:
+//!
+//!   ```text
+//!   . grass = 1
+//!   ~ water = 40
+//!   # = blocked
+//!
+//!   ..~~..
+//!   .##~..
+//!   ```
+//!
+//!   Its cells hold what they cost, which is what entering them costs
+//!   with the usual `--cost-fn value`, and its walls the wall value. A
+//!   map saved as terrain draws each value as the character the legend it
+//!   was loaded with gives it, or one of its own.
+//!
+//! The binary header is the magic `HXPM`, a version byte, a flags byte
+//! (bit 0: zstd), the wall value, a reserved zero byte, then the width and
+//! the height as little-endian `u32`s.
+//!
+//! Binary maps keep their wall value too, JSON maps everything; hex and
+//! CSV only the cells, terrain maps their legend too. The floors of a map
+//! of several layers are saved to those one under the other, to be cut
+//! apart again with `--layers`. The format follows the file's extension unless
+//! `--map-format` says otherwise, but a binary map is recognized by its
+//! magic whatever it is called.
+//!
+//! Any of them can be gzipped: a map is written gzipped when its name ends
+//! in `.gz`, its format then named by the extension before it, as in
+//! `map.hex.gz`, and read gunzipped whenever it starts with the gzip magic.
+
+use crate::Grid;
+use crate::layers::{self, Stair};
+use clap::ValueEnum;
+use flate2::Compression;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum MapFormat {
+    /// Rows of hex values split by spaces
+    Hex,
+    /// A JSON object with the cells and what is known about the map
+    Json,
+    /// Rows of decimal values split by commas
+    Csv,
+    /// A small header and the raw cell bytes, optionally compressed
+    Bin,
+    /// A legend of named terrains and their costs, then a character a cell
+    Terrain,
+}
+
+impl MapFormat {
+    /// `format` if given, else the format `path`'s extension names, or hex.
+    /// A `.gz` ending is passed over for the extension before it.
+    pub fn pick(format: Option<MapFormat>, path: &str) -> MapFormat {
+        format.unwrap_or_else(|| {
+            let path = Path::new(path);
+            let named = if gzipped(path) {
+                path.file_stem().map(Path::new)
+            } else {
+                Some(path)
+            };
+            match named
+                .and_then(Path::extension)
+                .and_then(|extension| extension.to_str())
+            {
+                Some(extension) if extension.eq_ignore_ascii_case("json") => MapFormat::Json,
+                Some(extension) if extension.eq_ignore_ascii_case("csv") => MapFormat::Csv,
+                Some(extension) if extension.eq_ignore_ascii_case("bin") => MapFormat::Bin,
+                Some(extension) if extension.eq_ignore_ascii_case("terrain") => MapFormat::Terrain,
+                _ => MapFormat::Hex,
+            }
+        })
+    }
+}
+
+/// What a map file can say about the map besides its cells.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MapInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generator: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// The wall value the map was made with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blocked: Option<u8>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+    /// The legend of a terrain map.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub terrain: Vec<Terrain>,
+}
+
+/// A kind of ground a terrain map draws as `symbol`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Terrain {
+    pub symbol: char,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    /// What its cells hold, and cost; `None` for walls.
+    pub cost: Option<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonMap {
+    width: usize,
+    /// Of each floor.
+    height: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    layers: Option<usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    stairs: Vec<[usize; 3]>,
+    #[serde(flatten)]
+    info: MapInfo,
+    cells: Cells,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Cells {
+    /// Row by row.
+    Flat(Vec<Vec<u8>>),
+    /// Floor by floor, each row by row.
+    Layered(Vec<Vec<Vec<u8>>>),
+}
+
+const MAGIC: &[u8; 4] = b"HXPM";
+const BINARY_VERSION: u8 = 1;
+/// Flag: the cells are zstd-compressed.
+const ZSTD: u8 = 1;
+const HEADER_LEN: usize = 16;
+const GZIP_MAGIC: &[u8; 2] = b"\x1f\x8b";
+/// What a terrain map draws the values its legend doesn't name as, in
+/// turn, cheapest first; walls are `#` unless the legend says.
+const SYMBOLS: &str =
+    ".abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789!$%&*+-/:;<>?@^_~";
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Saves `grid` to `path`; `compress` only matters to binary maps.
+pub fn save(
+    grid: &Grid,
+    path: &str,
+    format: MapFormat,
+    info: MapInfo,
+    compress: bool,
+) -> io::Result<()> {
+    let values: Vec<u8> = grid.values().collect();
+    let rows = values.chunks(grid.width.max(1));
+    let content = match format {
+        MapFormat::Bin => return write(path, to_binary(grid, compress)?),
+        MapFormat::Terrain => to_terrain(grid, &values, &info.terrain)?,
+        MapFormat::Hex => rows
+            .map(|row| {
+                row.iter()
+                    .map(|value| format!("{:02X} ", value))
+                    .collect::<String>()
+                    + "\n"
+            })
+            .collect(),
+        MapFormat::Csv => rows
+            .map(|row| {
+                let values: Vec<String> = row.iter().map(|value| value.to_string()).collect();
+                values.join(",") + "\n"
+            })
+            .collect(),
+        MapFormat::Json => {
+            let layered = grid.layers > 1;
+            let map = JsonMap {
+                width: grid.width,
+                height: grid.floor_height(),
+                layers: layered.then_some(grid.layers),
+                stairs: Vec::new(),
+                info: MapInfo {
+                    blocked: Some(grid.wall),
+                    ..info
+                },
+                cells: Cells::Flat(Vec::new()),
+            };
+            // One row of cells per line, not one cell, and the staircases
+            // on one line: pretty-printed without them, then filled in, as
+            // they come last.
+            let row = |row: &[u8]| serde_json::to_string(row).expect("bytes serialize");
+            let cells: Vec<String> = if layered {
+                let floor = grid.width.max(1) * grid.floor_height();
+                values
+                    .chunks(floor)
+                    .map(|floor| {
+                        let rows: Vec<String> = floor
+                            .chunks(grid.width.max(1))
+                            .map(|cells| format!("      {}", row(cells)))
+                            .collect();
+                        format!("    [\n{}\n    ]", rows.join(",\n"))
+                    })
+                    .collect()
+            } else {
+                rows.map(|cells| format!("    {}", row(cells))).collect()
+            };
+            let mut stairs: Vec<usize> = grid.stairs.iter().copied().collect();
+            stairs.sort_unstable();
+            let stairs: Vec<[usize; 3]> = stairs
+                .into_iter()
+                .map(|cell| {
+                    let (x, y, z) = grid.floor_coords(cell);
+                    [x, y, z]
+                })
+                .collect();
+            let stairs = if stairs.is_empty() {
+                String::new()
+            } else {
+                format!(
+                    "\"stairs\": {},\n  ",
+                    serde_json::to_string(&stairs).expect("numbers serialize")
+                )
+            };
+
+            let text = serde_json::to_string_pretty(&map).map_err(io::Error::other)?;
+            let head = text
+                .strip_suffix("\"cells\": []\n}")
+                .expect("cells are the last field");
+            if cells.is_empty() {
+                format!("{}{}\"cells\": []\n}}\n", head, stairs)
+            } else {
+                format!(
+                    "{}{}\"cells\": [\n{}\n  ]\n}}\n",
+                    head,
+                    stairs,
+                    cells.join(",\n")
+                )
+            }
+        }
+    };
+    write(path, content)
+}
+
+/// Whether `path` ends in `.gz`.
+fn gzipped(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("gz"))
+}
+
+/// Writes `bytes` to `path`, gzipped if it ends in `.gz`.
+fn write(path: &str, bytes: impl AsRef<[u8]>) -> io::Result<()> {
+    if !gzipped(Path::new(path)) {
+        return fs::write(path, bytes);
+    }
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = GzEncoder::new(file, Compression::default());
+    encoder.write_all(bytes.as_ref())?;
+    encoder.finish()?.flush()
+}
+
+/// The map in `path`. Its walls are the cells holding `blocked`, or else
+/// the value the file names, or else `default_wall`. A hex or CSV map is
+/// cut into rows `width` wide if given; any other must be that wide.
+pub fn load(
+    path: &str,
+    format: MapFormat,
+    blocked: Option<u8>,
+    default_wall: u8,
+    width: Option<usize>,
+) -> io::Result<(Grid, MapInfo)> {
+    let as_wide = |map_width: usize| match width {
+        Some(width) if width != map_width => Err(invalid(format!(
+            "the map is {} cells wide, not {}",
+            map_width, width
+        ))),
+        _ => Ok(()),
+    };
+    let mut bytes = fs::read(path)?;
+    if bytes.starts_with(GZIP_MAGIC) {
+        let mut plain = Vec::new();
+        MultiGzDecoder::new(&bytes[..])
+            .read_to_end(&mut plain)
+            .map_err(|e| invalid(format!("can't be gunzipped: {}", e)))?;
+        bytes = plain;
+    }
+    if bytes.starts_with(MAGIC) {
+        let (grid, info) = from_binary(&bytes, blocked)?;
+        as_wide(grid.width)?;
+        return Ok((grid, info));
+    }
+    if let MapFormat::Bin = format {
+        return Err(invalid("not a binary map"));
+    }
+    let content = String::from_utf8(bytes).map_err(|_| invalid("not a text map"))?;
+    // Floors and staircases only come with JSON maps.
+    let mut layers = 1;
+    let mut stairs = Vec::new();
+    let (rows, info) = match format {
+        MapFormat::Hex => (
+            parse_rows(
+                &content,
+                None,
+                "a hex byte",
+                |value| u8::from_str_radix(value, 16).ok(),
+                width,
+            )?,
+            MapInfo::default(),
+        ),
+        MapFormat::Csv => (
+            parse_rows(
+                &content,
+                Some(','),
+                "a number from 0 to 255",
+                |value| value.parse().ok(),
+                width,
+            )?,
+            MapInfo::default(),
+        ),
+        MapFormat::Json => {
+            let map: JsonMap =
+                serde_json::from_str(&content).map_err(|e| invalid(e.to_string()))?;
+            as_wide(map.width)?;
+            let floors = match map.cells {
+                Cells::Flat(rows) => vec![rows],
+                Cells::Layered(floors) => floors,
+            };
+            if map.layers.is_some_and(|layers| layers != floors.len()) || floors.is_empty() {
+                return Err(invalid(format!(
+                    "the cells are not {} layers",
+                    map.layers.unwrap_or(1)
+                )));
+            }
+            let uneven = |rows: &Vec<Vec<u8>>| {
+                rows.len() != map.height || rows.iter().any(|row| row.len() != map.width)
+            };
+            if floors.iter().any(uneven) {
+                return Err(invalid(format!(
+                    "the cells are not {} rows of {}",
+                    map.height, map.width
+                )));
+            }
+            layers = floors.len();
+            stairs = map.stairs;
+            (floors.concat(), map.info)
+        }
+        MapFormat::Terrain => {
+            let wall = blocked.unwrap_or(default_wall);
+            let (rows, terrain) = parse_terrain(&content, wall)?;
+            as_wide(rows.first().map_or(0, Vec::len))?;
+            let info = MapInfo {
+                blocked: Some(wall),
+                terrain,
+                ..MapInfo::default()
+            };
+            (rows, info)
+        }
+        MapFormat::Bin => unreachable!("binary maps are read above"),
+    };
+
+    // Hex, CSV and terrain rows are all as wide by now, JSON ones checked
+    // above.
+    let wall = blocked.or(info.blocked).unwrap_or(default_wall);
+    let mut grid = Grid::new(
+        rows.first().map_or(0, Vec::len),
+        rows.len(),
+        rows.concat(),
+        wall,
+    );
+    grid.layers = layers;
+    let stairs: Vec<Stair> = stairs
+        .into_iter()
+        .map(|[x, y, z]| (x, y, Some(z)))
+        .collect();
+    layers::add_stairs(&mut grid, &stairs).map_err(invalid)?;
+    Ok((grid, info))
+}
+
+fn to_binary(grid: &Grid, compress: bool) -> io::Result<Vec<u8>> {
+    let dimension = |value: usize| {
+        u32::try_from(value).map_err(|_| invalid("the map is too large for the binary format"))
+    };
+    let mut bytes = Vec::with_capacity(HEADER_LEN + grid.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&[
+        BINARY_VERSION,
+        if compress { ZSTD } else { 0 },
+        grid.wall,
+        0,
+    ]);
+    bytes.extend_from_slice(&dimension(grid.width)?.to_le_bytes());
+    bytes.extend_from_slice(&dimension(grid.height)?.to_le_bytes());
+    let cells: Vec<u8> = grid.values().collect();
+    if compress {
+        bytes.extend(zstd::encode_all(&cells[..], 0)?);
+    } else {
+        bytes.extend(cells);
+    }
+    Ok(bytes)
+}
+
+fn from_binary(bytes: &[u8], blocked: Option<u8>) -> io::Result<(Grid, MapInfo)> {
+    let header = bytes
+        .get(..HEADER_LEN)
+        .ok_or_else(|| invalid("the binary header is cut short"))?;
+    if header[4] != BINARY_VERSION {
+        return Err(invalid(format!(
+            "binary map version {}, only {} is known",
+            header[4], BINARY_VERSION
+        )));
+    }
+    let flags = header[5];
+    let wall = header[6];
+    let dimension =
+        |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().expect("4 bytes")) as usize;
+    let (width, height) = (dimension(8), dimension(12));
+
+    let body = &bytes[HEADER_LEN..];
+    let cells = if flags & ZSTD != 0 {
+        zstd::decode_all(body)?
+    } else {
+        body.to_vec()
+    };
+    if Some(cells.len()) != width.checked_mul(height) {
+        return Err(invalid(format!(
+            "{} cells, but the map is {}x{}",
+            cells.len(),
+            width,
+            height
+        )));
+    }
+
+    let grid = Grid::new(width, height, cells, blocked.unwrap_or(wall));
+    let info = MapInfo {
+        blocked: Some(wall),
+        ..MapInfo::default()
+    };
+    Ok((grid, info))
+}
+
+/// `values`, `grid`'s cells, as a terrain map: drawn as `legend` has them,
+/// the values it doesn't name as the first of `SYMBOLS` not taken.
+fn to_terrain(grid: &Grid, values: &[u8], legend: &[Terrain]) -> io::Result<String> {
+    let mut symbols: [Option<char>; 256] = [None; 256];
+    // Symbol, name and value, as the legend lists them.
+    let mut kinds: Vec<(char, &str, u8)> = Vec::new();
+    for terrain in legend {
+        let value = terrain.cost.unwrap_or(grid.wall);
+        if symbols[value as usize].is_none() {
+            symbols[value as usize] = Some(terrain.symbol);
+            kinds.push((terrain.symbol, &terrain.name, value));
+        }
+    }
+    let mut present = [false; 256];
+    for &value in values {
+        present[value as usize] = true;
+    }
+    let mut free = SYMBOLS
+        .chars()
+        .filter(|symbol| legend.iter().all(|terrain| terrain.symbol != *symbol));
+    let wall_free = legend.iter().all(|terrain| terrain.symbol != '#');
+    for value in 0..=255u8 {
+        if !present[value as usize] || symbols[value as usize].is_some() {
+            continue;
+        }
+        let symbol = if value == grid.wall && wall_free {
+            '#'
+        } else {
+            free.next().ok_or_else(|| {
+                let kinds = present.iter().filter(|&&present| present).count();
+                invalid(format!(
+                    "{} values, more than a terrain map has characters for; save it as hex",
+                    kinds
+                ))
+            })?
+        };
+        symbols[value as usize] = Some(symbol);
+        kinds.push((symbol, "", value));
+    }
+
+    let mut text = String::new();
+    for (symbol, name, value) in kinds {
+        let name = if name.is_empty() {
+            String::new()
+        } else {
+            format!("{} ", name)
+        };
+        let cost = if value == grid.wall {
+            "blocked".to_string()
+        } else {
+            value.to_string()
+        };
+        text.push_str(&format!("{} {}= {}\n", symbol, name, cost));
+    }
+    for row in values.chunks(grid.width.max(1)) {
+        text.push('\n');
+        text.extend(
+            row.iter()
+                .map(|&value| symbols[value as usize].expect("every value has one")),
+        );
+    }
+    text.push('\n');
+    Ok(text)
+}
+
+/// The rows of a terrain map, each cell holding what the legend says it
+/// costs, or `wall`, and the legend.
+fn parse_terrain(content: &str, wall: u8) -> io::Result<(Vec<Vec<u8>>, Vec<Terrain>)> {
+    let mut lines = content
+        .lines()
+        .enumerate()
+        .skip_while(|(_, line)| line.trim().is_empty());
+    let mut legend: Vec<Terrain> = Vec::new();
+    let mut values = HashMap::new();
+    for (index, line) in lines.by_ref() {
+        if line.trim().is_empty() {
+            break;
+        }
+        let number = index + 1;
+        let mut chars = line.chars();
+        let symbol = chars.next().expect("the line isn't blank");
+        let Some((name, cost)) = chars
+            .as_str()
+            .split_once('=')
+            .filter(|_| !symbol.is_whitespace())
+        else {
+            return Err(at(
+                number,
+                1,
+                format!(
+                    "'{}' must be a character, a name if wanted, = and a cost or blocked",
+                    line
+                ),
+            ));
+        };
+        let cost = cost.trim();
+        let column = line[..cost.as_ptr() as usize - line.as_ptr() as usize]
+            .chars()
+            .count()
+            + 1;
+        let cost = match cost {
+            "blocked" => None,
+            _ => match cost.parse::<u8>() {
+                Ok(cost) if cost == wall => {
+                    return Err(at(
+                        number,
+                        column,
+                        format!(
+                            "{} is the wall value: write blocked, or give --blocked another",
+                            cost
+                        ),
+                    ));
+                }
+                Ok(cost) => Some(cost),
+                Err(_) => {
+                    return Err(at(
+                        number,
+                        column,
+                        format!("'{}' must be a cost from 0 to 255, or blocked", cost),
+                    ));
+                }
+            },
+        };
+        if values.insert(symbol, cost.unwrap_or(wall)).is_some() {
+            return Err(at(
+                number,
+                1,
+                format!("'{}' is in the legend twice", symbol),
+            ));
+        }
+        legend.push(Terrain {
+            symbol,
+            name: name.trim().to_string(),
+            cost,
+        });
+    }
+    if legend.is_empty() {
+        return Err(invalid(
+            "no legend: start with a line like '. grass = 1' for each character, then a blank line",
+        ));
+    }
+
+    let mut rows = Vec::new();
+    for (index, line) in lines {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let cells = line
+            .trim_end()
+            .chars()
+            .enumerate()
+            .map(|(column, symbol)| match values.get(&symbol) {
+                Some(&value) => Ok(Cell {
+                    value,
+                    line: index + 1,
+                    column: column + 1,
+                }),
+                None => Err(at(
+                    index + 1,
+                    column + 1,
+                    format!("'{}' is not in the legend", symbol),
+                )),
+            })
+            .collect::<io::Result<Vec<Cell>>>()?;
+        rows.push(cells);
+    }
+    let rows = line_rows(rows, "every row of a terrain map is a line, all as long")?;
+    Ok((rows, legend))
+}
+
+/// A cell of a text map: its value, and where it starts in the file,
+/// line and column from 1.
+struct Cell {
+    value: u8,
+    line: usize,
+    column: usize,
+}
+
+/// The rows of a hex or CSV map, its values split by `separator`, or by
+/// whitespace if `None`, and read by `parse`, which says what it reads as
+/// `what`. Blank lines are skipped. Each line is a row, unless a `WxH`
+/// first line or `width` says how wide rows are: then the cells are read in
+/// order and cut into rows that wide, whatever lines they are on, `width`
+/// going before the first line.
+fn parse_rows(
+    content: &str,
+    separator: Option<char>,
+    what: &str,
+    parse: impl Fn(&str) -> Option<u8>,
+    width: Option<usize>,
+) -> io::Result<Vec<Vec<u8>>> {
+    let mut header = None;
+    let mut lines: Vec<Vec<Cell>> = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if lines.is_empty() && header.is_none() {
+            header = parse_header(line);
+            if header.is_some() {
+                continue;
+            }
+        }
+        let values: Vec<&str> = match separator {
+            Some(separator) => line.split(separator).map(str::trim).collect(),
+            None => line.split_whitespace().collect(),
+        };
+        let mut cells = Vec::with_capacity(values.len());
+        for value in values {
+            // Where `value` starts, in characters: it is a slice of `line`.
+            let offset = value.as_ptr() as usize - line.as_ptr() as usize;
+            let (line, column) = (index + 1, line[..offset].chars().count() + 1);
+            let value = match parse(value) {
+                Some(value) => value,
+                None if value.is_empty() => {
+                    return Err(at(line, column, "an empty cell".to_string()));
+                }
+                None => return Err(at(line, column, format!("'{}' is not {}", value, what))),
+            };
+            cells.push(Cell {
+                value,
+                line,
+                column,
+            });
+        }
+        lines.push(cells);
+    }
+
+    match (width, header) {
+        (Some(width), _) => cut_rows(lines, width, None),
+        (None, Some((width, height))) => cut_rows(lines, width, Some(height)),
+        (None, None) => line_rows(
+            lines,
+            "for rows split over several lines, give --width or a WxH first line",
+        ),
+    }
+}
+
+/// A `WxH` line, as the first line of a text map gives its size.
+fn parse_header(line: &str) -> Option<(usize, usize)> {
+    let (width, height) = line.trim().split_once(['x', 'X'])?;
+    let size = (width.trim().parse().ok()?, height.trim().parse().ok()?);
+    (size.0 > 0).then_some(size)
+}
+
+/// `message`, about what starts at `line` and `column`.
+fn at(line: usize, column: usize, message: String) -> io::Error {
+    invalid(format!("line {}, column {}: {}", line, column, message))
+}
+
+/// A row a line, every line as long as the first; `hint` says what to do
+/// about one that isn't.
+fn line_rows(lines: Vec<Vec<Cell>>, hint: &str) -> io::Result<Vec<Vec<u8>>> {
+    let Some(first) = lines.first() else {
+        return Ok(Vec::new());
+    };
+    let (width, first_line) = (first.len(), first[0].line);
+    for cells in &lines {
+        if let Some(extra) = cells.get(width) {
+            return Err(at(
+                extra.line,
+                extra.column,
+                format!(
+                    "cell {} of a row, but line {} has {}; {}",
+                    width + 1,
+                    first_line,
+                    width,
+                    hint
+                ),
+            ));
+        }
+        if cells.len() < width {
+            let last = &cells[cells.len() - 1];
+            return Err(invalid(format!(
+                "line {} has {} cells, line {} has {}; {}",
+                last.line,
+                cells.len(),
+                first_line,
+                width,
+                hint
+            )));
+        }
+    }
+    Ok(lines
+        .into_iter()
+        .map(|cells| cells.into_iter().map(|cell| cell.value).collect())
+        .collect())
+}
+
+/// Every cell in order, cut into rows `width` wide: `height` of them, if
+/// the header gives it.
+fn cut_rows(
+    lines: Vec<Vec<Cell>>,
+    width: usize,
+    height: Option<usize>,
+) -> io::Result<Vec<Vec<u8>>> {
+    let cells: Vec<Cell> = lines.into_iter().flatten().collect();
+    if let Some(height) = height {
+        if let Some(extra) = cells.get(width * height) {
+            return Err(at(
+                extra.line,
+                extra.column,
+                format!("past the {}x{} cells the first line gives", width, height),
+            ));
+        }
+        if cells.len() < width * height {
+            return Err(invalid(format!(
+                "the first line gives {}x{}, {} cells, but there are only {}",
+                width,
+                height,
+                width * height,
+                cells.len()
+            )));
+        }
+    }
+    let short = cells.len() % width;
+    if short > 0 {
+        let first = &cells[cells.len() - short];
+        return Err(at(
+            first.line,
+            first.column,
+            format!(
+                "the last row starts here with only {} of its {} cells",
+                short, width
+            ),
+        ));
+    }
+    Ok(cells
+        .chunks(width)
+        .map(|row| row.iter().map(|cell| cell.value).collect())
+        .collect())
+}
@@ -0,0 +1,140 @@
+//! Several paths on one map, when more than one search runs (several
+//! `--algo`, or `--both`): each path drawn as its own letter in its own
+//! color, the cells more than one takes as `**`, and under it how each
+//! compares with the first: the cells they share, where it parts from the
+//! first and where it comes back, and how much more or less it costs.
+
+use crate::{Grid, palette, style};
+use crossterm::style::Color;
+use std::collections::{HashMap, HashSet};
+use std::io;
+
+/// Path colors, in the order the paths were found, then again from the
+/// top.
+const COLORS: [Color; 6] = [
+    Color::Cyan,
+    Color::Magenta,
+    Color::Yellow,
+    Color::Green,
+    Color::Blue,
+    Color::Red,
+];
+
+/// Where parts and rejoins listed for a path stop.
+const SHOWN: usize = 5;
+
+/// The letter path `index` is drawn as.
+pub fn letter(index: usize) -> char {
+    (b'A' + (index % 26) as u8) as char
+}
+
+pub fn color(index: usize) -> Color {
+    COLORS[index % COLORS.len()]
+}
+
+/// Each cell on `paths`, with the path it is on, `owners[i]` for the i-th,
+/// or `None` if it is on more than one.
+pub fn owners(paths: &[&[usize]], owners: &[usize]) -> HashMap<usize, Option<usize>> {
+    let mut owned = HashMap::new();
+    for (path, &owner) in paths.iter().zip(owners) {
+        for &cell in path.iter() {
+            owned
+                .entry(cell)
+                .and_modify(|on: &mut Option<usize>| {
+                    if *on != Some(owner) {
+                        *on = None;
+                    }
+                })
+                .or_insert(Some(owner));
+        }
+    }
+    owned
+}
+
+/// How a block with a cell on a path is drawn: as the path's letter, or
+/// `**` in the palette's path color on more than one; `None` if no cell of
+/// it is on a path.
+pub fn look(owned: &HashMap<usize, Option<usize>>, block: &[usize]) -> Option<(Color, String)> {
+    let on: HashSet<Option<usize>> = block
+        .iter()
+        .filter_map(|cell| owned.get(cell).copied())
+        .collect();
+    let mut on = on.into_iter();
+    match (on.next()?, on.next()) {
+        (Some(owner), None) => Some((color(owner), format!("{0}{0} ", letter(owner)))),
+        _ => Some((palette::current().path(), "** ".to_string())),
+    }
+}
+
+/// A found path to compare.
+pub struct Compared {
+    pub name: String,
+    pub path: Vec<usize>,
+    pub cost: usize,
+}
+
+/// Prints which letter each path is, and how each after the first
+/// compares with it.
+pub fn summarize(grid: &Grid, paths: &[Compared]) -> io::Result<()> {
+    let Some(first) = paths.first() else {
+        return Ok(());
+    };
+    let at = |cell: usize| grid.label(cell);
+    let mut stdout = io::stdout();
+    println!("\n🔀 {} paths compared with {}:", paths.len(), first.name);
+    let reference: HashSet<usize> = first.path.iter().copied().collect();
+    for (index, compared) in paths.iter().enumerate() {
+        print!(" ");
+        style::color(&mut stdout, color(index))?;
+        print!("{0}{0}", letter(index));
+        style::color(&mut stdout, Color::Reset)?;
+        print!(" {}: cost {}", compared.name, compared.cost);
+        if index == 0 {
+            println!(", {} cells", compared.path.len());
+            continue;
+        }
+        let delta = compared.cost as i64 - first.cost as i64;
+        let delta = match delta {
+            0 => "same".to_string(),
+            _ => format!("{:+}", delta),
+        };
+        let shared = compared
+            .path
+            .iter()
+            .filter(|cell| reference.contains(cell))
+            .count();
+        print!(
+            " ({}), {} cells, {} shared",
+            delta,
+            compared.path.len(),
+            shared
+        );
+
+        // Where it steps off the first path, and back on.
+        let mut turns = Vec::new();
+        for pair in compared.path.windows(2) {
+            match (reference.contains(&pair[0]), reference.contains(&pair[1])) {
+                (true, false) => turns.push(format!("parts at {}", at(pair[0]))),
+                (false, true) => turns.push(format!("rejoins at {}", at(pair[1]))),
+                _ => {}
+            }
+        }
+        if turns.len() > SHOWN {
+            let more = turns.len() - SHOWN;
+            turns.truncate(SHOWN);
+            turns.push(format!("{} more", more));
+        }
+        if turns.is_empty() {
+            println!();
+        } else {
+            println!("; {}", turns.join(", "));
+        }
+    }
+
+    let on_every = reference
+        .iter()
+        .filter(|cell| paths.iter().all(|compared| compared.path.contains(cell)))
+        .count();
+    println!(" On every path: {} cells", on_every);
+    Ok(())
+}
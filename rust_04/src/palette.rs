@@ -0,0 +1,258 @@
+//! `--palette`: the colors maps are drawn in, in the terminal, in exported
+//! images and in GIFs. Cells take the color their value has on a ramp from
+//! cheap to dear: `classic`, blue through green and yellow to red;
+//! `viridis` and `magma`, which stay readable in grey and to the color
+//! blind; `grayscale`; or one read from a FILE of stops, a line each:
+//!
+//! ```text
+//! # value  color
+//! 0        #000080
+//! 128      #00C000
+//! 254      #FF0000
+//! path     #FFFFFF
+//! visited  200,200,200
+//! blocked  #202020
+//! ```
+//!
+//! Values between two stops are mixed from them, and those before the
+//! first or after the last take its color. The `path`, `visited` and
+//! `blocked` lines, each optional, set what paths, the cells a search
+//! expanded and walls are drawn in, which otherwise keep the colors they
+//! have with every palette.
+
+use crossterm::style::Color;
+use std::fs;
+use std::sync::OnceLock;
+
+static PALETTE: OnceLock<Palette> = OnceLock::new();
+
+/// Draws in `palette` from now on; only the first call counts.
+pub fn set(palette: Palette) {
+    let _ = PALETTE.set(palette);
+}
+
+/// The palette drawn in: the one set, else `classic`.
+pub fn current() -> &'static Palette {
+    PALETTE.get_or_init(|| Palette::new(Ramp::Classic))
+}
+
+/// Stops of the built-in ramps, evenly spaced from cheap to dear.
+const VIRIDIS: [[u8; 3]; 9] = [
+    [0x44, 0x01, 0x54],
+    [0x48, 0x28, 0x78],
+    [0x3E, 0x4A, 0x89],
+    [0x31, 0x68, 0x8E],
+    [0x26, 0x82, 0x8E],
+    [0x1F, 0x9E, 0x89],
+    [0x35, 0xB7, 0x79],
+    [0x6D, 0xCD, 0x59],
+    [0xFD, 0xE7, 0x25],
+];
+const MAGMA: [[u8; 3]; 9] = [
+    [0x00, 0x00, 0x04],
+    [0x1C, 0x10, 0x44],
+    [0x4F, 0x12, 0x7B],
+    [0x81, 0x25, 0x81],
+    [0xB5, 0x36, 0x7A],
+    [0xE5, 0x50, 0x64],
+    [0xFB, 0x87, 0x61],
+    [0xFE, 0xC2, 0x87],
+    [0xFC, 0xFD, 0xBF],
+];
+
+#[derive(Clone, Debug)]
+enum Ramp {
+    Classic,
+    /// Colors at values, sorted by value, at least one.
+    Stops(Vec<(u8, [u8; 3])>),
+}
+
+impl Ramp {
+    /// `colors` spread evenly from 0 to 255.
+    fn even(colors: &[[u8; 3]]) -> Self {
+        let last = colors.len() - 1;
+        Ramp::Stops(
+            colors
+                .iter()
+                .enumerate()
+                .map(|(index, &color)| ((index * 255 / last) as u8, color))
+                .collect(),
+        )
+    }
+
+    fn rgb(&self, value: u8) -> [u8; 3] {
+        match self {
+            Ramp::Classic => classic(value),
+            Ramp::Stops(stops) => {
+                let after = stops.partition_point(|&(at, _)| at <= value);
+                if after == 0 {
+                    return stops[0].1;
+                }
+                let (from, low) = stops[after - 1];
+                let Some(&(to, high)) = stops.get(after) else {
+                    return low;
+                };
+                let s = (value - from) as f32 / (to - from) as f32;
+                [0, 1, 2].map(|channel| {
+                    let (low, high) = (low[channel] as f32, high[channel] as f32);
+                    (low + (high - low) * s).round() as u8
+                })
+            }
+        }
+    }
+}
+
+/// The ramp hexpath always drew in: blue for cheap, through green and
+/// yellow, to red for dear.
+fn classic(value: u8) -> [u8; 3] {
+    let t = value as f32 / 255.0;
+    if t < 0.33 {
+        let s = t / 0.33;
+        [0, (255.0 * s) as u8, (255.0 * (1.0 - s)) as u8]
+    } else if t < 0.66 {
+        let s = (t - 0.33) / 0.33;
+        [(255.0 * s) as u8, 255, 0]
+    } else {
+        let s = (t - 0.66) / 0.34;
+        [255, (255.0 * (1.0 - s)) as u8, 0]
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Palette {
+    ramp: Ramp,
+    /// The colors a palette file gave, each `None` for the usual one.
+    path: Option<[u8; 3]>,
+    visited: Option<[u8; 3]>,
+    blocked: Option<[u8; 3]>,
+}
+
+impl Palette {
+    fn new(ramp: Ramp) -> Self {
+        Palette {
+            ramp,
+            path: None,
+            visited: None,
+            blocked: None,
+        }
+    }
+
+    /// A built-in palette by name, or one read from the file `text` names.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text {
+            "classic" => Ok(Palette::new(Ramp::Classic)),
+            "viridis" => Ok(Palette::new(Ramp::even(&VIRIDIS))),
+            "magma" => Ok(Palette::new(Ramp::even(&MAGMA))),
+            "grayscale" | "greyscale" => Ok(Palette::new(Ramp::even(&[[0x20; 3], [0xE0; 3]]))),
+            _ => {
+                let file = fs::read_to_string(text).map_err(|e| {
+                    format!(
+                        "'{}' is neither classic, viridis, magma nor grayscale, and can't be read as a file: {}",
+                        text, e
+                    )
+                })?;
+                Palette::read(&file).map_err(|e| format!("{}: {}", text, e))
+            }
+        }
+    }
+
+    /// A palette from the lines of a palette file; those starting with
+    /// `#` are comments.
+    fn read(file: &str) -> Result<Self, String> {
+        let mut palette = Palette::new(Ramp::Classic);
+        let mut stops = Vec::new();
+        for (number, line) in file.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fail = |e: String| format!("line {}: {}", number + 1, e);
+            let mut words = line.split_whitespace();
+            let (Some(what), Some(color), None) = (words.next(), words.next(), words.next()) else {
+                return Err(fail(format!(
+                    "'{}' must be a value or path, visited or blocked, then a color",
+                    line
+                )));
+            };
+            let color = parse_color(color).map_err(fail)?;
+            match what {
+                "path" => palette.path = Some(color),
+                "visited" => palette.visited = Some(color),
+                "blocked" => palette.blocked = Some(color),
+                _ => {
+                    let value = what.parse::<u8>().map_err(|_| {
+                        fail(format!(
+                            "'{}' must be a value from 0 to 255, or path, visited or blocked",
+                            what
+                        ))
+                    })?;
+                    stops.push((value, color));
+                }
+            }
+        }
+        if stops.is_empty() {
+            return Err("no stops: give at least one line of a value and its color".to_string());
+        }
+        stops.sort_by_key(|&(value, _)| value);
+        if let Some(pair) = stops.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(format!("value {} has two stops", pair[0].0));
+        }
+        palette.ramp = Ramp::Stops(stops);
+        Ok(palette)
+    }
+
+    /// The color of a cell of `value`.
+    pub fn rgb(&self, value: u8) -> [u8; 3] {
+        self.ramp.rgb(value)
+    }
+
+    /// Paths, white unless the palette says.
+    pub fn path(&self) -> Color {
+        self.path.map_or(Color::White, rgb)
+    }
+
+    pub fn path_rgb(&self) -> [u8; 3] {
+        self.path.unwrap_or([0xFF; 3])
+    }
+
+    /// Cells a search expanded, in `usual` unless the palette says.
+    pub fn visited(&self, usual: Color) -> Color {
+        self.visited.map_or(usual, rgb)
+    }
+
+    /// Cells a search expanded in a GIF, if the palette says; else they
+    /// are their own colors paled.
+    pub fn visited_rgb(&self) -> Option<[u8; 3]> {
+        self.visited
+    }
+
+    /// Walls, dark grey unless the palette says.
+    pub fn blocked(&self) -> Color {
+        self.blocked.map_or(Color::DarkGrey, rgb)
+    }
+
+    pub fn blocked_rgb(&self) -> [u8; 3] {
+        self.blocked.unwrap_or([0x40; 3])
+    }
+}
+
+fn rgb([r, g, b]: [u8; 3]) -> Color {
+    Color::Rgb { r, g, b }
+}
+
+/// `#RRGGBB`, or `R,G,B` in decimal.
+fn parse_color(text: &str) -> Result<[u8; 3], String> {
+    let bad = || format!("'{}' must be a color, as #RRGGBB or R,G,B", text);
+    if let Some(hex) = text.strip_prefix('#') {
+        if hex.len() != 6 || !hex.is_ascii() {
+            return Err(bad());
+        }
+        let channel = |at: usize| u8::from_str_radix(&hex[at..at + 2], 16).map_err(|_| bad());
+        return Ok([channel(0)?, channel(2)?, channel(4)?]);
+    }
+    let channels: Vec<u8> = text
+        .split(',')
+        .map(|channel| channel.trim().parse().map_err(|_| bad()))
+        .collect::<Result<_, _>>()?;
+    channels.try_into().map_err(|_| bad())
+}
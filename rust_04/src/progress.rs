@@ -0,0 +1,158 @@
+//! How far a long search has got, and ctrl-c to stop it. A search still
+//! running after half a second shows the cells it has expanded and the
+//! time taken on stderr, when that is a terminal and nothing is animated.
+//! While the searches run, ctrl-c stops the one under way, and what it did
+//! is printed as for any other; a second ctrl-c, or one at any other time,
+//! quits at once, the terminal put back first.
+
+use crate::search::{Expansion, Watch};
+use crate::{Grid, style};
+use crossterm::{
+    QueueableCommand, cursor,
+    terminal::{self, ClearType},
+};
+use signal_hook::consts::SIGINT;
+use signal_hook::iterator::Signals;
+use std::collections::HashSet;
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a search runs before its progress shows.
+const QUIET: Duration = Duration::from_millis(500);
+/// How often the progress line is redrawn.
+const REDRAW: Duration = Duration::from_millis(100);
+/// Expansions between looks at the clock.
+const EVERY: usize = 1024;
+
+/// Set by ctrl-c while it is caught.
+static STOP: AtomicBool = AtomicBool::new(false);
+static CATCHING: AtomicBool = AtomicBool::new(false);
+
+/// Handles ctrl-c for the rest of the run, on a thread of its own: it
+/// stops the search under way while they are caught, else quits.
+pub fn listen() {
+    let Ok(mut signals) = Signals::new([SIGINT]) else {
+        return;
+    };
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            if CATCHING.load(Ordering::SeqCst) && !STOP.swap(true, Ordering::SeqCst) {
+                continue;
+            }
+            style::restore();
+            std::process::exit(130);
+        }
+    });
+}
+
+/// Ctrl-c caught rather than quitting, for as long as this lives.
+pub struct Interrupts;
+
+impl Interrupts {
+    pub fn catch() -> Self {
+        STOP.store(false, Ordering::SeqCst);
+        CATCHING.store(true, Ordering::SeqCst);
+        Interrupts
+    }
+}
+
+impl Drop for Interrupts {
+    fn drop(&mut self) {
+        CATCHING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Stops the search under way, as ctrl-c does, if ctrl-c is caught; the
+/// animation, which reads ctrl-c as a key, asks here.
+pub fn stop() -> bool {
+    let catching = CATCHING.load(Ordering::SeqCst);
+    if catching {
+        STOP.store(true, Ordering::SeqCst);
+    }
+    catching
+}
+
+/// Whether ctrl-c stopped the searches.
+pub fn stopped() -> bool {
+    STOP.load(Ordering::SeqCst)
+}
+
+/// Watches a search only to show how far it has got, and to stop it.
+pub struct Progress {
+    name: &'static str,
+    cells: usize,
+    started: Instant,
+    /// Whether the line may be drawn.
+    shown: bool,
+    /// When the line was last drawn, if it has been.
+    drawn: Option<Instant>,
+}
+
+impl Progress {
+    /// The progress of `name` on `grid`, drawn only if `shown` and stderr
+    /// is a terminal.
+    pub fn new(grid: &Grid, name: &'static str, shown: bool) -> Self {
+        Progress {
+            name,
+            cells: grid.len(),
+            started: Instant::now(),
+            shown: shown && io::stderr().is_terminal(),
+            drawn: None,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Clears the line, once the search is over.
+    pub fn done(&mut self) {
+        if self.drawn.take().is_some() {
+            let mut err = io::stderr();
+            let _ = err.queue(cursor::MoveToColumn(0));
+            let _ = err.queue(terminal::Clear(ClearType::CurrentLine));
+            let _ = err.flush();
+        }
+    }
+
+    fn draw(&mut self, expanded: usize) -> io::Result<()> {
+        let mut err = io::stderr();
+        err.queue(cursor::MoveToColumn(0))?;
+        err.queue(terminal::Clear(ClearType::CurrentLine))?;
+        write!(
+            err,
+            "⏳ {}: {} of {} cells expanded ({}%) · {:.1} s · ctrl-c stops",
+            self.name,
+            expanded,
+            self.cells,
+            expanded * 100 / self.cells.max(1),
+            self.elapsed().as_secs_f64()
+        )?;
+        err.flush()
+    }
+}
+
+impl Watch for Progress {
+    fn watching(&self) -> bool {
+        false
+    }
+
+    fn expanded(&mut self, _grid: &Grid, _cells: &HashSet<usize>, _at: &Expansion) {}
+
+    fn keep_going(&mut self, expanded: usize) -> bool {
+        if stopped() {
+            return false;
+        }
+        if self.shown && expanded.is_multiple_of(EVERY) {
+            let now = Instant::now();
+            let due = self.drawn.is_none_or(|drawn| now - drawn >= REDRAW);
+            if due && now - self.started >= QUIET {
+                let _ = self.draw(expanded);
+                self.drawn = Some(now);
+            }
+        }
+        true
+    }
+}
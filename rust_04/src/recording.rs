@@ -0,0 +1,449 @@
+//! The frames of a search, for `--animate` in the terminal and for
+//! `--export-gif`. The GIF shows the expanded cells growing, one frame per
+//! `--gif-every` expansions, then flashes the path found.
+//!
+//! In the terminal each frame waits `--speed` milliseconds, and says which
+//! cell was just expanded, what it cost to reach and how many cells wait on
+//! the frontier. From a keyboard, space pauses, `n` steps one expansion at a
+//! time, `+` and `-` speed up and slow down, and `q` skips to the result.
+//!
+//! Frames are drawn offscreen on a `Canvas` of palette indices, against
+//! one palette for the whole GIF: the --palette's cell gradient, the same
+//! gradient paled (or its visited color) for expanded cells, the wall and
+//! the path. After the first frame
+//! of a search only the rectangle around the newly expanded cells is
+//! written, which keeps the file small and the recording fast.
+
+use crate::render::Canvas;
+use crate::screen::Screen;
+use crate::search::{Expansion, Watch};
+use crate::{Grid, style, value_to_rgb};
+use crate::{palette, progress};
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    terminal,
+};
+use gif::{Encoder, Frame, Repeat};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Shades of the cell gradient in the palette.
+const SHADES: usize = 120;
+/// Where the paled shades of expanded cells start.
+const EXPANDED: usize = SHADES;
+const WALL_INDEX: u8 = (2 * SHADES) as u8;
+const PATH_INDEX: u8 = WALL_INDEX + 1;
+
+/// Hundredths of a second each frame stays, as long as `--animate` waits
+/// by default.
+const FRAME_DELAY: u16 = 5;
+/// How long the path stays on and off when it flashes.
+const FLASH_ON: u16 = 40;
+const FLASH_OFF: u16 = 20;
+/// How long the last frame of a search stays.
+const HOLD: u16 = 150;
+
+/// Rows under the map for the status line and the keys.
+const STATUS_LINES: usize = 2;
+
+/// How far `+` and `-` go.
+const FASTEST: Duration = Duration::from_millis(1);
+const SLOWEST: Duration = Duration::from_secs(5);
+
+/// Draws a search in the terminal, records it into a GIF, or both.
+pub struct Animation {
+    pub terminal: Option<Player>,
+    pub gif: Option<GifRecorder>,
+}
+
+impl Animation {
+    /// Ends the animation of a search, flashing `path` in the GIF if one
+    /// was found.
+    pub fn found(&mut self, grid: &Grid, path: Option<&[usize]>) {
+        if let Some(player) = &mut self.terminal {
+            player.done();
+        }
+        if let Some(gif) = &mut self.gif {
+            gif.found(grid, path);
+        }
+    }
+}
+
+impl Watch for Animation {
+    fn watching(&self) -> bool {
+        self.terminal
+            .as_ref()
+            .is_some_and(|player| !player.skipping)
+            || self.gif.is_some()
+    }
+
+    fn expanded(&mut self, grid: &Grid, cells: &HashSet<usize>, at: &Expansion) {
+        if let Some(player) = self.terminal.as_mut().filter(|player| !player.skipping) {
+            player.frame(grid, cells, at);
+        }
+        if let Some(gif) = &mut self.gif {
+            gif.expanded(grid, cells);
+        }
+    }
+}
+
+/// Raw mode, so keys arrive as they are pressed, for as long as it is held.
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        Ok(RawMode)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Plays a search in the terminal, a frame per expansion.
+pub struct Player {
+    delay: Duration,
+    /// Whether keys can be read, stdin and stdout being a terminal.
+    keyboard: bool,
+    /// Held while a search plays, when there is a keyboard.
+    raw: Option<RawMode>,
+    screen: Screen,
+    paused: bool,
+    /// Whether `q` left the rest of the search undrawn.
+    skipping: bool,
+    /// The latest expansion, as the status line shows it.
+    at: String,
+}
+
+impl Player {
+    /// A player waiting `delay` after each frame.
+    pub fn new(delay: Duration) -> Self {
+        Player {
+            delay,
+            keyboard: io::stdin().is_terminal() && io::stdout().is_terminal(),
+            raw: None,
+            screen: Screen::new(STATUS_LINES),
+            paused: false,
+            skipping: false,
+            at: String::new(),
+        }
+    }
+
+    fn frame(&mut self, grid: &Grid, cells: &HashSet<usize>, at: &Expansion) {
+        if self.keyboard && self.raw.is_none() {
+            self.raw = RawMode::enable().ok();
+        }
+        let palette = palette::current();
+        let _ = self
+            .screen
+            .draw(grid, Some(cells), palette.visited(palette.path()));
+
+        let (x, y) = grid.index_to_coords(at.cell);
+        let cost = at.cost.map_or("-".to_string(), |cost| cost.to_string());
+        self.at = format!(
+            "({}, {}) · distance {} · frontier {}",
+            x, y, cost, at.frontier
+        );
+        self.status();
+        self.wait();
+    }
+
+    /// Writes the status line under the map, and the keys when they work.
+    fn status(&self) {
+        let mut lines = vec![format!(
+            "{} {} · {} ms a frame",
+            if self.paused { "⏸" } else { "▶" },
+            self.at,
+            self.delay.as_millis()
+        )];
+        if self.raw.is_some() {
+            lines.push("space pauses · n steps · +/- speed · q skips to the result".to_string());
+        }
+        let _ = self.screen.lines(&lines);
+    }
+
+    /// Waits out the delay, or while paused, handling keys meanwhile.
+    fn wait(&mut self) {
+        if self.raw.is_none() {
+            thread::sleep(self.delay);
+            return;
+        }
+        let deadline = Instant::now() + self.delay;
+        loop {
+            let waiting = deadline.saturating_duration_since(Instant::now());
+            if !self.paused && !event::poll(waiting).unwrap_or(false) {
+                return;
+            }
+            let Ok(event) = event::read() else {
+                return;
+            };
+            let Event::Key(key) = event else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char(' ') => {
+                    self.paused = !self.paused;
+                    if !self.paused {
+                        return;
+                    }
+                }
+                KeyCode::Char('n') => {
+                    self.paused = true;
+                    return;
+                }
+                KeyCode::Char('+') | KeyCode::Char('=') => {
+                    self.delay = (self.delay / 2).max(FASTEST);
+                }
+                KeyCode::Char('-') => self.delay = (self.delay * 2).min(SLOWEST),
+                KeyCode::Char('q') | KeyCode::Esc => {
+                    self.skipping = true;
+                    return;
+                }
+                // Raw mode keeps ctrl-c from interrupting, so it is done
+                // here: the search stops, or else hexpath quits.
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    if progress::stop() {
+                        self.skipping = true;
+                        return;
+                    }
+                    style::restore();
+                    std::process::exit(130);
+                }
+                _ => continue,
+            }
+            self.status();
+        }
+    }
+
+    /// Ends a search, handing the terminal back. What is printed next
+    /// moves the map, so the next search is drawn whole again.
+    fn done(&mut self) {
+        self.raw = None;
+        self.skipping = false;
+        self.screen = Screen::new(STATUS_LINES);
+    }
+}
+
+pub struct GifRecorder {
+    encoder: Encoder<BufWriter<File>>,
+    /// The last frame written.
+    canvas: Canvas<u8>,
+    /// The cells `canvas` shows expanded.
+    shown: Vec<bool>,
+    shown_count: usize,
+    /// Whether the next frame has to be written whole.
+    whole: bool,
+    every: usize,
+    /// Expansions since the last frame.
+    pending: usize,
+    /// The corners of the cells changed since the last frame.
+    dirty: Option<((usize, usize), (usize, usize))>,
+    frames: usize,
+    /// The first error writing the file, reported when it is finished.
+    error: Option<io::Error>,
+}
+
+fn shade(value: u8) -> usize {
+    (value as usize * (SHADES - 1) + 127) / 255
+}
+
+/// The palette index `cell` is drawn in, paled if `expanded`.
+fn index(grid: &Grid, cell: usize, expanded: bool) -> u8 {
+    if grid.is_wall(cell) {
+        WALL_INDEX
+    } else if expanded {
+        (EXPANDED + shade(grid.value(cell))) as u8
+    } else {
+        shade(grid.value(cell)) as u8
+    }
+}
+
+fn palette() -> Vec<u8> {
+    let gradient: Vec<[u8; 3]> = (0..SHADES)
+        .map(|shade| value_to_rgb((shade * 255 / (SHADES - 1)) as u8))
+        .collect();
+    let palette = palette::current();
+    let paled = gradient.iter().map(|rgb| {
+        palette
+            .visited_rgb()
+            .unwrap_or_else(|| rgb.map(|channel| ((channel as u16 + 255) / 2) as u8))
+    });
+    gradient
+        .iter()
+        .copied()
+        .chain(paled)
+        .chain([palette.blocked_rgb(), palette.path_rgb()])
+        .flatten()
+        .collect()
+}
+
+impl GifRecorder {
+    /// Starts a GIF of searches on `grid` at `path`, a frame every `every`
+    /// expansions.
+    pub fn create(grid: &Grid, path: &str, cell_size: usize, every: usize) -> io::Result<Self> {
+        let canvas = Canvas::new(grid, cell_size, |cell| index(grid, cell, false))?;
+        let too_large = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "the GIF would be {}x{} pixels, at most 65535 a side; lower --cell-size",
+                    canvas.width, canvas.height
+                ),
+            )
+        };
+        let width = u16::try_from(canvas.width).map_err(|_| too_large())?;
+        let height = u16::try_from(canvas.height).map_err(|_| too_large())?;
+
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder =
+            Encoder::new(file, width, height, &palette()).map_err(io::Error::other)?;
+        encoder
+            .set_repeat(Repeat::Infinite)
+            .map_err(io::Error::other)?;
+        Ok(GifRecorder {
+            encoder,
+            canvas,
+            shown: vec![false; grid.len()],
+            shown_count: 0,
+            whole: true,
+            every,
+            pending: 0,
+            dirty: None,
+            frames: 0,
+            error: None,
+        })
+    }
+
+    fn expanded(&mut self, grid: &Grid, cells: &HashSet<usize>) {
+        // Fewer cells than last time: a new search, or a new leg of one.
+        if cells.len() < self.shown_count {
+            self.clear(grid);
+        }
+        for &cell in cells {
+            if self.shown[cell] {
+                continue;
+            }
+            self.shown[cell] = true;
+            self.canvas.fill_cell(grid, cell, index(grid, cell, true));
+            let (x, y) = grid.index_to_coords(cell);
+            self.dirty = Some(match self.dirty {
+                Some(((left, top), (right, bottom))) => {
+                    ((left.min(x), top.min(y)), (right.max(x), bottom.max(y)))
+                }
+                None => ((x, y), (x, y)),
+            });
+        }
+        self.shown_count = cells.len();
+
+        self.pending += 1;
+        if self.pending < self.every && !self.whole {
+            return;
+        }
+        self.pending = 0;
+        if self.whole {
+            self.write_whole(FRAME_DELAY);
+        } else if let Some((from, to)) = self.dirty.take() {
+            let (left, top, width, height, pixels) = self.canvas.crop(from, to);
+            self.write(left, top, width, height, pixels, FRAME_DELAY);
+        }
+    }
+
+    /// Ends the recording of a search, flashing `path` if one was found.
+    fn found(&mut self, grid: &Grid, path: Option<&[usize]>) {
+        if let Some(path) = path {
+            let plain = self.canvas.pixels.clone();
+            self.canvas.draw_path(grid, path, PATH_INDEX);
+            let drawn = std::mem::replace(&mut self.canvas.pixels, plain);
+            let (width, height) = (self.canvas.width, self.canvas.height);
+            for (on, delay) in [
+                (true, FLASH_ON),
+                (false, FLASH_OFF),
+                (true, FLASH_ON),
+                (false, FLASH_OFF),
+                (true, HOLD),
+            ] {
+                let pixels = if on {
+                    drawn.clone()
+                } else {
+                    self.canvas.pixels.clone()
+                };
+                self.write(0, 0, width, height, pixels, delay);
+            }
+        } else {
+            self.write_whole(HOLD);
+        }
+        self.clear(grid);
+    }
+
+    /// Finishes the file, returning how many frames it holds.
+    pub fn finish(self) -> io::Result<usize> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        self.encoder
+            .into_inner()
+            .map_err(io::Error::other)?
+            .into_inner()
+            .map_err(|e| e.into_error())?;
+        Ok(self.frames)
+    }
+
+    /// Back to the bare map, written whole with the next frame.
+    fn clear(&mut self, grid: &Grid) {
+        for cell in 0..grid.len() {
+            if self.shown[cell] {
+                self.shown[cell] = false;
+                self.canvas.fill_cell(grid, cell, index(grid, cell, false));
+            }
+        }
+        self.shown_count = 0;
+        self.whole = true;
+        self.pending = 0;
+    }
+
+    fn write_whole(&mut self, delay: u16) {
+        self.whole = false;
+        self.dirty = None;
+        let pixels = self.canvas.pixels.clone();
+        let (width, height) = (self.canvas.width, self.canvas.height);
+        self.write(0, 0, width, height, pixels, delay);
+    }
+
+    fn write(
+        &mut self,
+        left: usize,
+        top: usize,
+        width: usize,
+        height: usize,
+        pixels: Vec<u8>,
+        delay: u16,
+    ) {
+        if self.error.is_some() {
+            return;
+        }
+        // The canvas was checked to fit in a GIF when it was made.
+        let frame = Frame {
+            left: left as u16,
+            top: top as u16,
+            width: width as u16,
+            height: height as u16,
+            buffer: Cow::Owned(pixels),
+            delay,
+            ..Frame::default()
+        };
+        match self.encoder.write_frame(&frame) {
+            Ok(()) => self.frames += 1,
+            Err(e) => self.error = Some(io::Error::other(e)),
+        }
+    }
+}
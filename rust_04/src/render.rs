@@ -0,0 +1,161 @@
+//! Maps drawn into pixels instead of the terminal, for `--export` and
+//! `--export-gif`: each cell a square of `cell_size` pixels, paths lines
+//! through the centers of their cells. The canvas holds whatever a pixel
+//! is to its user, RGB colors for PNG, palette indices for GIF.
+
+use crate::Grid;
+use std::io;
+
+/// Largest image drawn, in pixels, so a huge map with a large
+/// `--cell-size` fails plainly instead of running out of memory.
+const MAX_PIXELS: usize = 1 << 28;
+
+pub struct Canvas<P> {
+    pub width: usize,
+    pub height: usize,
+    cell_size: usize,
+    /// Row by row.
+    pub pixels: Vec<P>,
+}
+
+impl<P: Copy> Canvas<P> {
+    /// A canvas for `grid` with every cell as `color` says.
+    pub fn new(grid: &Grid, cell_size: usize, color: impl Fn(usize) -> P) -> io::Result<Self> {
+        let width = grid.width * cell_size;
+        let height = grid.height * cell_size;
+        if width == 0 || height == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "an empty map makes no image",
+            ));
+        }
+        if width
+            .checked_mul(height)
+            .is_none_or(|pixels| pixels > MAX_PIXELS)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "the image would be {}x{} pixels, lower --cell-size",
+                    width, height
+                ),
+            ));
+        }
+
+        let mut canvas = Canvas {
+            width,
+            height,
+            cell_size,
+            pixels: vec![color(0); width * height],
+        };
+        for cell in 0..grid.len() {
+            canvas.fill_cell(grid, cell, color(cell));
+        }
+        Ok(canvas)
+    }
+
+    pub fn fill_cell(&mut self, grid: &Grid, cell: usize, color: P) {
+        let (x, y) = grid.index_to_coords(cell);
+        for py in y * self.cell_size..(y + 1) * self.cell_size {
+            let row = py * self.width;
+            self.pixels[row + x * self.cell_size..row + (x + 1) * self.cell_size].fill(color);
+        }
+    }
+
+    /// Draws `path` as a line through the centers of its cells, or the
+    /// corners of a smoothed one.
+    pub fn draw_path(&mut self, grid: &Grid, path: &[usize], color: P) {
+        let cell_size = self.cell_size as isize;
+        let thickness = line_width(self.cell_size) as isize;
+        let (width, height) = (self.width as isize, self.height as isize);
+        let center =
+            |(x, y): (isize, isize)| (x * cell_size + cell_size / 2, y * cell_size + cell_size / 2);
+        // Each step is stamped with a square of the line's width at every
+        // pixel between the two centers, which also rounds the joints.
+        // What falls off the canvas, past a wrapped edge, is left out.
+        let mut stamp = |(x, y): (isize, isize)| {
+            let (left, top) = (x - thickness / 2, y - thickness / 2);
+            for py in top.max(0)..(top + thickness).min(height) {
+                for px in left.max(0)..(left + thickness).min(width) {
+                    self.pixels[(py * width + px) as usize] = color;
+                }
+            }
+        };
+
+        for stroke in strokes(grid, path) {
+            if let [only] = stroke[..] {
+                stamp(center(only));
+            }
+            for pair in stroke.windows(2) {
+                let (from_x, from_y) = center(pair[0]);
+                let (to_x, to_y) = center(pair[1]);
+                let pixels = (to_x - from_x).abs().max((to_y - from_y).abs()).max(1);
+                for i in 0..=pixels {
+                    let along = |from: isize, to: isize| from + (to - from) * i / pixels;
+                    stamp((along(from_x, to_x), along(from_y, to_y)));
+                }
+            }
+        }
+    }
+
+    /// The pixels of the cells from `(x, y)` to `(right, bottom)`, both
+    /// included, and where they start: (left, top, width, height, pixels).
+    pub fn crop(
+        &self,
+        (x, y): (usize, usize),
+        (right, bottom): (usize, usize),
+    ) -> (usize, usize, usize, usize, Vec<P>) {
+        let (left, top) = (x * self.cell_size, y * self.cell_size);
+        let width = (right + 1 - x) * self.cell_size;
+        let height = (bottom + 1 - y) * self.cell_size;
+        let pixels = (top..top + height)
+            .flat_map(|py| {
+                let row = py * self.width;
+                self.pixels[row + left..row + left + width].iter().copied()
+            })
+            .collect();
+        (left, top, width, height, pixels)
+    }
+}
+
+/// The cells `path`'s line goes through, as coordinates, in one stroke
+/// unless it moves over the edge of a wrapping grid: then the stroke goes on
+/// to the cell just past that edge, off the map, and the next one starts
+/// just past the opposite edge, so the line leaves on one side and comes
+/// back on the other instead of crossing the whole map. A move up or down
+/// the stairs ends the stroke on one floor and starts the next on the
+/// other. Corners of a smoothed path, further apart than a move, are
+/// joined straight.
+pub fn strokes(grid: &Grid, path: &[usize]) -> Vec<Vec<(isize, isize)>> {
+    let at = |cell: usize| {
+        let (x, y) = grid.index_to_coords(cell);
+        (x as isize, y as isize)
+    };
+    let Some(&first) = path.first() else {
+        return Vec::new();
+    };
+    let mut strokes = Vec::new();
+    let mut stroke = vec![at(first)];
+    for pair in path.windows(2) {
+        let (from, to) = (at(pair[0]), at(pair[1]));
+        let Some((dx, dy)) = grid.step(pair[0], pair[1]) else {
+            strokes.push(std::mem::replace(&mut stroke, vec![to]));
+            continue;
+        };
+        let past = (from.0 + dx, from.1 + dy);
+        if past == to || !grid.neighbors(pair[0]).contains(&pair[1]) {
+            stroke.push(to);
+            continue;
+        }
+        stroke.push(past);
+        strokes.push(std::mem::take(&mut stroke));
+        stroke.extend([(to.0 - dx, to.1 - dy), to]);
+    }
+    strokes.push(stroke);
+    strokes
+}
+
+/// How thick path lines are drawn.
+pub fn line_width(cell_size: usize) -> usize {
+    (cell_size / 4).max(1)
+}
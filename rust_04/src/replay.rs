@@ -0,0 +1,411 @@
+//! `--save-path FILE` and `hexpath replay`: the searches of a run saved as
+//! JSON, each path with every cell expanded on the way in order, then drawn
+//! or played again on the same map without searching, so a run can be
+//! shared and shown the same way every time.
+//!
+//! Cells are `[x, y]` pairs, `[x, y, layer]` on a map of several layers, as
+//! in `--format json`. The file keeps the map's size, wall value and a
+//! checksum of its cells, and how moves went on it (diagonals, wrapping,
+//! the cost model, the floors and staircases); a replay sets the map up the
+//! same way and refuses one whose cells don't match.
+
+use crate::layers::{self, Stair};
+use crate::legend::Legend;
+use crate::mapfile::{self, MapFormat};
+use crate::palette::Palette;
+use crate::recording::{Animation, GifRecorder, Player};
+use crate::report::coords;
+use crate::route::Route;
+use crate::search::{Expansion, Pathfinder, Watch};
+use crate::{DEFAULT_WALL, Grid, cost, visualize_grid};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::time::Duration;
+
+#[derive(clap::Args, Debug)]
+pub struct ReplayArgs {
+    /// The map the run was saved on
+    map_file: String,
+    /// The file --save-path wrote
+    saved: String,
+    /// Format of the map file; by default the one its extension names
+    /// (.json, .csv), else hex
+    #[arg(long, value_enum)]
+    map_format: Option<MapFormat>,
+    /// Play each search again, expansion by expansion, before drawing its
+    /// path
+    #[arg(short, long)]
+    animate: bool,
+    /// Milliseconds --animate waits between frames
+    #[arg(long, value_name = "MS", requires = "animate", default_value_t = 50)]
+    speed: u64,
+    /// Draw two cells to a character, so maps three times as wide fit
+    #[arg(long)]
+    compact: bool,
+    /// Number every Nth row and column, mark S and E and show the color
+    /// scale: as the main command's --legend
+    #[arg(long, value_name = "N", num_args = 0..=1, require_equals = true, default_missing_value = "10", value_parser = clap::value_parser!(u32).range(1..))]
+    legend: Option<u32>,
+    /// Print no colors, the same text on every run: as the main command's
+    /// --plain
+    #[arg(long, visible_alias = "no-color", conflicts_with_all = ["animate", "compact"])]
+    pub plain: bool,
+    /// The colors cells are drawn in: as the main command's --palette
+    #[arg(long, value_name = "NAME|FILE", value_parser = Palette::parse, default_value = "classic")]
+    pub palette: Palette,
+    /// Record the searches into an animated GIF, as --export-gif does
+    #[arg(long, value_name = "FILE")]
+    export_gif: Option<String>,
+    /// Pixels per cell in the GIF
+    #[arg(long, value_name = "PX", value_parser = clap::value_parser!(u16).range(1..), default_value_t = 16)]
+    cell_size: u16,
+    /// Expansions per frame of the GIF
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..), default_value_t = 1)]
+    gif_every: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Saved {
+    map: SavedMap,
+    start: Vec<usize>,
+    end: Vec<usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    via: Vec<Vec<usize>>,
+    runs: Vec<Run>,
+}
+
+/// The map a run was saved on, and how moves went on it.
+#[derive(Serialize, Deserialize)]
+struct SavedMap {
+    width: usize,
+    /// Of each floor.
+    height: usize,
+    layers: usize,
+    wall: u8,
+    /// Of the cell values, to tell the same map from another of its size.
+    checksum: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    diagonal_cost: Option<f64>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    wrap: bool,
+    cost_fn: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    stairs: Vec<Vec<usize>>,
+}
+
+/// One search of the run.
+#[derive(Serialize, Deserialize)]
+struct Run {
+    algorithm: String,
+    optimal: bool,
+    /// With `--via`: start, the waypoints in the order visited, end.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    stops: Vec<Vec<usize>>,
+    /// Start and end included; empty if none was found.
+    path: Vec<Vec<usize>>,
+    cost: Option<usize>,
+    expanded: usize,
+    /// When no path was found: the two stops walls cut apart.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    blocked: Option<[Vec<usize>; 2]>,
+    /// The expansions of each search the route took, in order.
+    legs: Vec<Vec<Visit>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Visit {
+    cell: Vec<usize>,
+    cost: Option<usize>,
+    frontier: usize,
+}
+
+/// The expansions of a search, leg by leg, as `--save-path` keeps them.
+#[derive(Default)]
+pub struct VisitLog {
+    legs: Vec<Vec<Visit>>,
+    /// Cells the latest search had expanded.
+    shown: usize,
+}
+
+impl Watch for VisitLog {
+    fn expanded(&mut self, grid: &Grid, cells: &HashSet<usize>, at: &Expansion) {
+        // Fewer cells than last time: the next leg of a route.
+        if cells.len() < self.shown || self.legs.is_empty() {
+            self.legs.push(Vec::new());
+        }
+        self.shown = cells.len();
+        if let Some(leg) = self.legs.last_mut() {
+            leg.push(Visit {
+                cell: coords(grid, at.cell),
+                cost: at.cost,
+                frontier: at.frontier,
+            });
+        }
+    }
+}
+
+/// The searches of a run, gathered for `--save-path`.
+pub struct SavedRuns(Saved);
+
+impl SavedRuns {
+    pub fn new(grid: &Grid, start: usize, via: &[usize], end: usize) -> Self {
+        SavedRuns(Saved {
+            map: SavedMap {
+                width: grid.width,
+                height: grid.floor_height(),
+                layers: grid.layers,
+                wall: grid.wall,
+                checksum: checksum(grid),
+                diagonal_cost: grid.diagonal,
+                wrap: grid.wrap,
+                cost_fn: grid.cost.name(),
+                stairs: grid.stairs.iter().map(|&cell| stair(grid, cell)).collect(),
+            },
+            start: coords(grid, start),
+            end: coords(grid, end),
+            via: via.iter().map(|&cell| coords(grid, cell)).collect(),
+            runs: Vec::new(),
+        })
+    }
+
+    /// Adds the route `pathfinder` found, and what `log` saw it expand.
+    pub fn add(&mut self, grid: &Grid, pathfinder: &dyn Pathfinder, route: &Route, log: VisitLog) {
+        let coords = |cell: usize| coords(grid, cell);
+        let (path, cost) = match &route.search.found {
+            Some((path, cost)) => (path.iter().map(|&cell| coords(cell)).collect(), Some(*cost)),
+            None => (Vec::new(), None),
+        };
+        self.0.runs.push(Run {
+            algorithm: pathfinder.name().to_string(),
+            optimal: pathfinder.optimal(),
+            stops: if self.0.via.is_empty() {
+                Vec::new()
+            } else {
+                route.stops.iter().map(|&cell| coords(cell)).collect()
+            },
+            path,
+            cost,
+            expanded: route.search.expanded,
+            blocked: route.blocked.map(|(from, to)| [coords(from), coords(to)]),
+            legs: log.legs,
+        });
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, serde_json::to_string(&self.0)?)
+    }
+
+    pub fn count(&self) -> usize {
+        self.0.runs.len()
+    }
+}
+
+/// A staircase as `[x, y, layer]`, as maps save them.
+fn stair(grid: &Grid, cell: usize) -> Vec<usize> {
+    let (x, y, z) = grid.floor_coords(cell);
+    vec![x, y, z]
+}
+
+/// FNV-1a over the cell values, as 16 hex digits.
+fn checksum(grid: &Grid) -> String {
+    let hash = grid
+        .values()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, value| {
+            (hash ^ value as u64).wrapping_mul(0x0100_0000_01b3)
+        });
+    format!("{:016x}", hash)
+}
+
+/// The cell at `coords`, as `[x, y]` or `[x, y, layer]`, if it is on the map.
+fn cell(grid: &Grid, coords: &[usize]) -> Result<usize, String> {
+    let (x, y, z) = match *coords {
+        [x, y] => (x, y, 0),
+        [x, y, z] => (x, y, z),
+        _ => {
+            return Err(format!(
+                "{:?} is not a cell: expected [x, y] or [x, y, layer]",
+                coords
+            ));
+        }
+    };
+    if x >= grid.width || y >= grid.floor_height() || z >= grid.layers {
+        return Err(format!("{:?} is outside the map", coords));
+    }
+    Ok(grid.coords_to_index(x, z * grid.floor_height() + y))
+}
+
+/// The cells of `list`.
+fn cells(grid: &Grid, list: &[Vec<usize>]) -> Result<Vec<usize>, String> {
+    list.iter().map(|coords| cell(grid, coords)).collect()
+}
+
+/// `hexpath replay`: loads the map and the saved run, then draws each path
+/// again, playing its search first with `--animate` or into a GIF.
+pub fn run(args: &ReplayArgs) -> io::Result<()> {
+    let fail = |what: &str, e: String| -> ! {
+        eprintln!("Can't replay {}: {}", what, e);
+        std::process::exit(1);
+    };
+    let saved: Saved = fs::read_to_string(&args.saved)
+        .map_err(|e| e.to_string())
+        .and_then(|text| serde_json::from_str(&text).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| fail(&args.saved, e));
+
+    let format = MapFormat::pick(args.map_format, &args.map_file);
+    let (mut grid, _) = mapfile::load(
+        &args.map_file,
+        format,
+        Some(saved.map.wall),
+        DEFAULT_WALL,
+        Some(saved.map.width),
+    )
+    .unwrap_or_else(|e| fail(&args.map_file, e.to_string()));
+    set_up(&mut grid, &saved.map).unwrap_or_else(|e| fail(&args.saved, e));
+
+    let mut animation = Animation {
+        terminal: args
+            .animate
+            .then(|| Player::new(Duration::from_millis(args.speed))),
+        gif: args.export_gif.as_ref().map(|gif_file| {
+            GifRecorder::create(
+                &grid,
+                gif_file,
+                args.cell_size as usize,
+                args.gif_every as usize,
+            )
+            .unwrap_or_else(|e| fail(gif_file, e.to_string()))
+        }),
+    };
+
+    let at = |cell: usize| grid.label(cell);
+    let (start, end) = (
+        cell(&grid, &saved.start).unwrap_or_else(|e| fail(&args.saved, e)),
+        cell(&grid, &saved.end).unwrap_or_else(|e| fail(&args.saved, e)),
+    );
+    if grid.layers > 1 {
+        println!(
+            "📊 Grid: {}x{}, {} layers, {} staircases",
+            grid.width,
+            grid.floor_height(),
+            grid.layers,
+            grid.stairs.len()
+        );
+    } else {
+        println!("📊 Grid: {}x{}", grid.width, grid.height);
+    }
+    println!(
+        "🎬 Replaying {}: from {} to {}",
+        args.saved,
+        at(start),
+        at(end)
+    );
+
+    let legend = args.legend.map(|every| Legend::new(every as usize));
+    for run in &saved.runs {
+        let invalid = |e: String| -> ! { fail(&args.saved, format!("{}: {}", run.algorithm, e)) };
+        let path = cells(&grid, &run.path).unwrap_or_else(|e| invalid(e));
+        let stops = cells(&grid, &run.stops).unwrap_or_else(|e| invalid(e));
+        println!();
+
+        if animation.watching() {
+            for leg in &run.legs {
+                let mut shown = HashSet::new();
+                for visit in leg {
+                    let at = Expansion {
+                        cell: cell(&grid, &visit.cell).unwrap_or_else(|e| invalid(e)),
+                        cost: visit.cost,
+                        frontier: visit.frontier,
+                    };
+                    shown.insert(at.cell);
+                    animation.expanded(&grid, &shown, &at);
+                }
+            }
+            animation.found(&grid, (!path.is_empty()).then_some(&path[..]));
+        }
+
+        match run.cost {
+            Some(cost) if !path.is_empty() => {
+                if run.optimal {
+                    println!("✓ Minimum cost path found ({})!", run.algorithm);
+                } else {
+                    println!(
+                        "✓ Path found ({}, not necessarily the cheapest)!",
+                        run.algorithm
+                    );
+                }
+                if !stops.is_empty() {
+                    let stops: Vec<String> = stops.iter().map(|&stop| at(stop)).collect();
+                    println!(" Via: {}", stops.join(" → "));
+                }
+                println!(" Cost: {}", cost);
+                println!(" Length: {} steps", path.len());
+                println!(" Expanded: {} cells", run.expanded);
+                println!("\n🎨 {} path visualization:", run.algorithm);
+                visualize_grid(&grid, &[&path], args.compact, legend.as_ref())?;
+            }
+            _ => {
+                let blocked = run
+                    .blocked
+                    .as_ref()
+                    .map(|[from, to]| (cell(&grid, from), cell(&grid, to)));
+                match blocked {
+                    Some((Ok(from), Ok(to))) => println!(
+                        "✗ No path ({}): walls cut {} off from {}",
+                        run.algorithm,
+                        at(from),
+                        at(to)
+                    ),
+                    _ => println!("✗ No path ({})", run.algorithm),
+                }
+                println!(" Expanded: {} cells", run.expanded);
+            }
+        }
+    }
+
+    if let (Some(gif_file), Some(gif)) = (&args.export_gif, animation.gif) {
+        match gif.finish() {
+            Ok(frames) => println!("✓ GIF saved to {} ({} frames)", gif_file, frames),
+            Err(e) => fail(gif_file, e.to_string()),
+        }
+    }
+    Ok(())
+}
+
+/// Sets `grid` up as the run was made on it, if it is the same map.
+fn set_up(grid: &mut Grid, map: &SavedMap) -> Result<(), String> {
+    if map.layers > 1 {
+        layers::split(grid, map.layers)?;
+    }
+    let size = |width: usize, height: usize, layers: usize| match layers {
+        1 => format!("{}x{}", width, height),
+        _ => format!("{}x{} of {} layers", width, height, layers),
+    };
+    if (grid.width, grid.floor_height(), grid.layers) != (map.width, map.height, map.layers) {
+        return Err(format!(
+            "the map it was saved on is {}, this one is {}",
+            size(map.width, map.height, map.layers),
+            size(grid.width, grid.floor_height(), grid.layers)
+        ));
+    }
+    if checksum(grid) != map.checksum {
+        return Err("it was saved on a different map of the same size".to_string());
+    }
+    let stairs: Vec<Stair> = map
+        .stairs
+        .iter()
+        .map(|coords| match coords[..] {
+            [x, y, z] => Ok((x, y, Some(z))),
+            _ => Err(format!(
+                "{:?} is not a staircase: expected [x, y, layer]",
+                coords
+            )),
+        })
+        .collect::<Result<_, _>>()?;
+    layers::add_stairs(grid, &stairs)?;
+    grid.diagonal = map.diagonal_cost;
+    grid.wrap = map.wrap;
+    grid.cost = cost::parse(&map.cost_fn)?;
+    Ok(())
+}
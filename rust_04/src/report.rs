@@ -0,0 +1,431 @@
+//! `--format json`: the searches' results as one JSON document on stdout,
+//! for scripts and other programs, instead of the lines meant for people.
+//! Cells are `[x, y]` pairs, `[x, y, layer]` on a map of several layers,
+//! and times are milliseconds. What isn't known,
+//! like the cost of a path not found, is `null`.
+//!
+//! `--pairs FILE` searches between many starts and ends on the one map
+//! loaded, and sums them up as a table, CSV with `--format csv`, or a JSON
+//! document with the results of each pair.
+
+use crate::explain::{self, Step};
+use crate::mapfile::MapInfo;
+use crate::search::{Pathfinder, Unwatched};
+use crate::{Anchor, Args, Grid, anyangle, budget, longest, route, style};
+use clap::ValueEnum;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::Serialize;
+use std::io;
+use std::time::Instant;
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Lines for people to read
+    Text,
+    /// One JSON document with every path found
+    Json,
+    /// A row per pair and strategy (with --pairs)
+    Csv,
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    grid: GridReport<'a>,
+    start: Vec<usize>,
+    end: Vec<usize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    via: Vec<Vec<usize>>,
+    results: Vec<Outcome>,
+    /// With `--both`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    maximum: Option<Outcome>,
+}
+
+#[derive(Serialize)]
+struct GridReport<'a> {
+    width: usize,
+    /// Of each floor.
+    height: usize,
+    /// On a map of several layers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    layers: Option<usize>,
+    /// The value of wall cells.
+    wall: u8,
+    /// With `--diagonals`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diagonal_cost: Option<f64>,
+    /// With `--wrap`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    wrap: bool,
+    /// With a `--cost-fn` other than `value`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cost_fn: Option<String>,
+    #[serde(flatten)]
+    info: &'a MapInfo,
+}
+
+/// `--pairs` as JSON.
+#[derive(Serialize)]
+struct Batch<'a> {
+    grid: GridReport<'a>,
+    pairs: Vec<Pair>,
+}
+
+#[derive(Serialize)]
+struct Pair {
+    start: Vec<usize>,
+    end: Vec<usize>,
+    results: Vec<Outcome>,
+}
+
+impl<'a> GridReport<'a> {
+    fn new(grid: &Grid, info: &'a MapInfo) -> Self {
+        GridReport {
+            width: grid.width,
+            height: grid.floor_height(),
+            layers: (grid.layers > 1).then_some(grid.layers),
+            wall: grid.wall,
+            diagonal_cost: grid.diagonal,
+            wrap: grid.wrap,
+            cost_fn: Some(grid.cost.name()).filter(|name| name != "value"),
+            info,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Outcome {
+    algorithm: String,
+    /// Whether the algorithm always finds a cheapest path; left out for
+    /// the maximum path.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    optimal: Option<bool>,
+    found: bool,
+    /// With `--via`: start, the waypoints in the order visited, end.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stops: Option<Vec<Vec<usize>>>,
+    /// Start and end included; empty if none was found.
+    path: Vec<Vec<usize>>,
+    cost: Option<usize>,
+    /// Cells on the path, as the text output counts them.
+    length: Option<usize>,
+    /// Left out for the maximum path.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expanded: Option<usize>,
+    /// From `--weight` or `--anytime`: at most how many times the cheapest
+    /// path's cost this one costs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bound: Option<f64>,
+    /// When no path was found: the two stops walls cut apart.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blocked: Option<[Vec<usize>; 2]>,
+    /// When no maximum path was found: why.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    miss: Option<&'static str>,
+    /// For a maximum path found at random: the rounds run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rounds: Option<usize>,
+    /// For a maximum path found at random: what the greedy one costs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    greedy_cost: Option<usize>,
+    /// With `--explain`: the path's cells, each with what entering it cost.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    steps: Option<Vec<Step>>,
+    /// With `--smooth`, or from theta-star: the path pulled taut.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    smoothed: Option<Smoothed>,
+    time_ms: f64,
+}
+
+#[derive(Serialize)]
+struct Smoothed {
+    corners: Vec<Vec<usize>>,
+    cost: usize,
+    /// Through the corners, center to center, in cells.
+    distance: f64,
+}
+
+/// Runs every pathfinder from `start` through `via` to `end`, and the
+/// maximum path search with `--both`, then prints what they found.
+pub fn print(
+    args: &Args,
+    grid: &Grid,
+    info: &MapInfo,
+    pathfinders: &[Box<dyn Pathfinder>],
+    start: usize,
+    via: &[usize],
+    end: usize,
+) -> io::Result<()> {
+    let at = |cell: usize| coords(grid, cell);
+    let cells = |path: &[usize]| path.iter().map(|&cell| at(cell)).collect::<Vec<_>>();
+
+    let results = pathfinders
+        .iter()
+        .map(|pathfinder| search(args, grid, pathfinder.as_ref(), start, via, end))
+        .collect();
+
+    let maximum = args.both.then(|| {
+        let began = Instant::now();
+        // Seeded as the text output's is, so the same seed finds the same
+        // path either way.
+        let seed = args.seed.or(info.seed).unwrap_or_else(rand::random);
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let longest = longest::find(grid, start, end, args.max_path, budget(args), &mut rng);
+        let time_ms = began.elapsed().as_secs_f64() * 1000.0;
+        let found = longest.found;
+        Outcome {
+            algorithm: format!("maximum ({})", longest.method),
+            optimal: None,
+            found: found.is_some(),
+            stops: None,
+            path: found.as_ref().map_or(Vec::new(), |(path, _)| cells(path)),
+            cost: found.as_ref().map(|&(_, cost)| cost),
+            length: found.as_ref().map(|(path, _)| path.len()),
+            expanded: None,
+            bound: None,
+            blocked: None,
+            miss: found.is_none().then_some(longest.miss),
+            rounds: longest.rounds.map(|(ran, _)| ran),
+            greedy_cost: longest.greedy,
+            steps: found
+                .as_ref()
+                .filter(|_| args.explain)
+                .map(|(path, _)| explain::steps(grid, path)),
+            smoothed: None,
+            time_ms,
+        }
+    });
+
+    let report = Report {
+        grid: GridReport::new(grid, info),
+        start: at(start),
+        end: at(end),
+        via: cells(via),
+        results,
+        maximum,
+    };
+    println!("{}", serde_json::to_string(&report)?);
+    Ok(())
+}
+
+/// `cell` as `[x, y]`, or `[x, y, layer]` on a map of several layers.
+pub fn coords(grid: &Grid, cell: usize) -> Vec<usize> {
+    let (x, y, z) = grid.floor_coords(cell);
+    if grid.layers > 1 {
+        vec![x, y, z]
+    } else {
+        vec![x, y]
+    }
+}
+
+/// Runs `pathfinder` from `start` through `via` to `end`.
+fn search(
+    args: &Args,
+    grid: &Grid,
+    pathfinder: &dyn Pathfinder,
+    start: usize,
+    via: &[usize],
+    end: usize,
+) -> Outcome {
+    let at = |cell: usize| coords(grid, cell);
+    let cells = |path: &[usize]| path.iter().map(|&cell| at(cell)).collect::<Vec<_>>();
+
+    let began = Instant::now();
+    let route = route::plan(
+        pathfinder,
+        grid,
+        start,
+        via,
+        end,
+        args.optimize_order,
+        &mut Unwatched,
+    );
+    let time_ms = began.elapsed().as_secs_f64() * 1000.0;
+    let found = route.search.found;
+    let smoothed = found
+        .as_ref()
+        .filter(|_| args.smooth || pathfinder.any_angle())
+        .map(|(path, _)| {
+            let smoothed = anyangle::smooth(grid, path);
+            Smoothed {
+                corners: cells(&smoothed.corners),
+                cost: smoothed.cost,
+                distance: anyangle::distance(grid, &smoothed.corners),
+            }
+        });
+    Outcome {
+        algorithm: pathfinder.name().to_string(),
+        optimal: Some(pathfinder.optimal()),
+        found: found.is_some(),
+        stops: (!via.is_empty()).then(|| cells(&route.stops)),
+        path: found.as_ref().map_or(Vec::new(), |(path, _)| cells(path)),
+        cost: found.as_ref().map(|&(_, cost)| cost),
+        length: found.as_ref().map(|(path, _)| path.len()),
+        expanded: Some(route.search.expanded),
+        bound: route.search.bound,
+        blocked: found
+            .is_none()
+            .then(|| route.blocked.unwrap_or((start, end)))
+            .map(|(from, to)| [at(from), at(to)]),
+        miss: None,
+        rounds: None,
+        greedy_cost: None,
+        steps: found
+            .as_ref()
+            .filter(|_| args.explain)
+            .map(|(path, _)| explain::steps(grid, path)),
+        smoothed,
+        time_ms,
+    }
+}
+
+/// Reads a `--pairs` file: a start and an end to a row, `X,Y,X,Y`, or
+/// `X,Y,LAYER,X,Y,LAYER` on a map of several layers. A first row that
+/// doesn't start with a number is taken for a header; blank lines and
+/// what follows a `#` are skipped.
+pub fn parse_pairs(text: &str) -> Result<Vec<(Anchor, Anchor)>, String> {
+    let mut pairs = Vec::new();
+    let mut first = true;
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if std::mem::take(&mut first) && fields[0].parse::<usize>().is_err() {
+            continue;
+        }
+        let error = |e: String| format!("line {}: {}", number + 1, e);
+        let coordinate = |value: &str| {
+            value
+                .parse::<usize>()
+                .map_err(|_| error(format!("invalid coordinate '{}'", value)))
+        };
+        let pair = match fields[..] {
+            [x, y, to_x, to_y] => (
+                Anchor::At(coordinate(x)?, coordinate(y)?, 0),
+                Anchor::At(coordinate(to_x)?, coordinate(to_y)?, 0),
+            ),
+            [x, y, z, to_x, to_y, to_z] => (
+                Anchor::At(coordinate(x)?, coordinate(y)?, coordinate(z)?),
+                Anchor::At(coordinate(to_x)?, coordinate(to_y)?, coordinate(to_z)?),
+            ),
+            _ => {
+                return Err(error(format!(
+                    "expected X,Y,X,Y or X,Y,LAYER,X,Y,LAYER, got '{}'",
+                    line
+                )));
+            }
+        };
+        pairs.push(pair);
+    }
+    Ok(pairs)
+}
+
+/// Runs every pathfinder between each of `pairs` of cells, then prints
+/// what they found in `--format`.
+pub fn batch(
+    args: &Args,
+    grid: &Grid,
+    info: &MapInfo,
+    pathfinders: &[Box<dyn Pathfinder>],
+    pairs: &[(usize, usize)],
+) -> io::Result<()> {
+    let began = Instant::now();
+    let pairs: Vec<Pair> = pairs
+        .iter()
+        .map(|&(start, end)| Pair {
+            start: coords(grid, start),
+            end: coords(grid, end),
+            results: pathfinders
+                .iter()
+                .map(|pathfinder| search(args, grid, pathfinder.as_ref(), start, &[], end))
+                .collect(),
+        })
+        .collect();
+    let elapsed = began.elapsed();
+
+    let at = |cell: &[usize]| {
+        let cell: Vec<String> = cell.iter().map(usize::to_string).collect();
+        cell.join(",")
+    };
+    let known = |value: Option<usize>, unknown: &str| {
+        value.map_or(unknown.to_string(), |value| value.to_string())
+    };
+    match args.format {
+        OutputFormat::Json => {
+            let batch = Batch {
+                grid: GridReport::new(grid, info),
+                pairs,
+            };
+            println!("{}", serde_json::to_string(&batch)?);
+        }
+        OutputFormat::Csv => {
+            let (start, end) = if grid.layers > 1 {
+                ("start_x,start_y,start_layer", "end_x,end_y,end_layer")
+            } else {
+                ("start_x,start_y", "end_x,end_y")
+            };
+            println!(
+                "pair,{},{},algorithm,found,cost,length,expanded,time_ms",
+                start, end
+            );
+            for (number, pair) in pairs.iter().enumerate() {
+                for outcome in &pair.results {
+                    println!(
+                        "{},{},{},{},{},{},{},{},{:.3}",
+                        number + 1,
+                        at(&pair.start),
+                        at(&pair.end),
+                        outcome.algorithm,
+                        outcome.found,
+                        known(outcome.cost, ""),
+                        known(outcome.length, ""),
+                        known(outcome.expanded, ""),
+                        outcome.time_ms
+                    );
+                }
+            }
+        }
+        OutputFormat::Text => {
+            println!(
+                "\n{:<5} {:<14} {:<14} {:<24} {:>10} {:>8} {:>10} {:>10}",
+                "Pair", "Start", "End", "Algorithm", "Cost", "Length", "Expanded", "ms"
+            );
+            for (number, pair) in pairs.iter().enumerate() {
+                for outcome in &pair.results {
+                    let ms = if style::plain() {
+                        "-".to_string()
+                    } else {
+                        format!("{:.3}", outcome.time_ms)
+                    };
+                    println!(
+                        "{:<5} {:<14} {:<14} {:<24} {:>10} {:>8} {:>10} {:>10}",
+                        number + 1,
+                        format!("({})", at(&pair.start).replace(',', ", ")),
+                        format!("({})", at(&pair.end).replace(',', ", ")),
+                        outcome.algorithm,
+                        known(outcome.cost, "-"),
+                        known(outcome.length, "-"),
+                        known(outcome.expanded, "-"),
+                        ms
+                    );
+                }
+            }
+            let searches = pairs.len() * pathfinders.len();
+            let found = pairs
+                .iter()
+                .flat_map(|pair| &pair.results)
+                .filter(|outcome| outcome.found)
+                .count();
+            println!(
+                "\n✓ {} of {} searches found a path{}",
+                found,
+                searches,
+                style::took(elapsed).map_or(String::new(), |took| format!(", in {}", took))
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,143 @@
+//! Animation frames drawn in place in the terminal, for `--animate`. Each
+//! frame is worked out whole, block by block as the static map is, then
+//! compared with the one before it: only the blocks that changed are
+//! written, in one batch of queued commands, so large maps neither flicker
+//! nor crawl. The first frame, and the first after the terminal is resized,
+//! is written whole.
+
+use crate::viewport::{self, Viewport};
+use crate::{Grid, palette, style, value_to_color};
+use crossterm::{
+    QueueableCommand, cursor,
+    style::{Color, Print, SetForegroundColor},
+    terminal,
+};
+use std::collections::HashSet;
+use std::io::{self, IsTerminal, Write};
+
+/// How a block of the map is drawn: in `marked` on `path`, in the
+/// palette's wall color if every cell in it is a wall, magenta if it holds a staircase, else in the color
+/// of its average value. Plain, as there are no colors, the path is `**`
+/// and staircases `##`.
+pub fn look(
+    grid: &Grid,
+    block: &[usize],
+    path: Option<&HashSet<usize>>,
+    marked: Color,
+) -> (Color, String) {
+    match viewport::average(grid, block) {
+        None => (palette::current().blocked(), "██ ".to_string()),
+        Some(value) => {
+            let on_path = path.is_some_and(|path| block.iter().any(|cell| path.contains(cell)));
+            let stairs = block.iter().any(|cell| grid.stairs.contains(cell));
+            if style::plain() && (on_path || stairs) {
+                let text = if on_path { "** " } else { "## " };
+                return (Color::Reset, text.to_string());
+            }
+            let color = if on_path {
+                marked
+            } else if stairs {
+                Color::Magenta
+            } else {
+                value_to_color(value)
+            };
+            (color, format!("{:02X} ", value))
+        }
+    }
+}
+
+pub struct Screen {
+    /// Rows kept under the map for `lines`.
+    reserved: usize,
+    /// How the last frame was seen, `None` before the first.
+    view: Option<Viewport>,
+    /// What each block of the last frame showed, row by row.
+    shown: Vec<(Color, String)>,
+    /// Rows the map took in the last frame.
+    down: usize,
+}
+
+impl Screen {
+    /// A screen keeping `reserved` rows under the map for lines of text.
+    pub fn new(reserved: usize) -> Self {
+        Screen {
+            reserved,
+            view: None,
+            shown: Vec::new(),
+            down: 0,
+        }
+    }
+
+    /// Draws `grid` with `path` in `marked`, zoomed out so it and the
+    /// reserved rows fit the terminal.
+    pub fn draw(
+        &mut self,
+        grid: &Grid,
+        path: Option<&HashSet<usize>>,
+        marked: Color,
+    ) -> io::Result<()> {
+        let mut out = io::stdout();
+        let view = match terminal::size() {
+            Ok((columns, rows)) if out.is_terminal() => Viewport::fitted(
+                grid,
+                (
+                    columns as usize / 3,
+                    (rows as usize).saturating_sub(self.reserved),
+                ),
+            ),
+            _ => Viewport::fitted(grid, (grid.width, grid.height)),
+        };
+        let (across, down) = view.shown(grid);
+        let frame: Vec<(Color, String)> = (0..down)
+            .flat_map(|y| (0..across).map(move |x| (x, y)))
+            .map(|(x, y)| look(grid, &view.block(grid, x, y), path, marked))
+            .collect();
+
+        let whole = self.view != Some(view);
+        if whole {
+            out.queue(terminal::Clear(terminal::ClearType::All))?;
+        }
+        // Where the terminal's cursor and color are, so moving and
+        // coloring are only written when they change.
+        let mut at = None;
+        let mut color = None;
+        for (index, block) in frame.iter().enumerate() {
+            if !whole && self.shown[index] == *block {
+                continue;
+            }
+            let (x, y) = (index % across, index / across);
+            if at != Some((x, y)) {
+                out.queue(cursor::MoveTo((x * 3) as u16, y as u16))?;
+            }
+            if color != Some(block.0) {
+                out.queue(SetForegroundColor(block.0))?;
+                color = Some(block.0);
+            }
+            out.queue(Print(&block.1))?;
+            at = Some((x + 1, y));
+        }
+        out.queue(SetForegroundColor(Color::Reset))?;
+        out.queue(cursor::MoveTo(0, down as u16))?;
+        out.flush()?;
+
+        self.view = Some(view);
+        self.shown = frame;
+        self.down = down;
+        Ok(())
+    }
+
+    /// Writes `lines` under the map, as many as the reserved rows hold,
+    /// clearing what was there, and leaves the cursor after them.
+    pub fn lines(&self, lines: &[String]) -> io::Result<()> {
+        let mut out = io::stdout();
+        let lines = &lines[..lines.len().min(self.reserved)];
+        for (row, line) in lines.iter().enumerate() {
+            out.queue(cursor::MoveTo(0, (self.down + row) as u16))?;
+            out.queue(Print(line))?;
+            out.queue(terminal::Clear(terminal::ClearType::UntilNewLine))?;
+        }
+        out.queue(cursor::MoveTo(0, (self.down + lines.len()) as u16))?;
+        out.queue(terminal::Clear(terminal::ClearType::FromCursorDown))?;
+        out.flush()
+    }
+}
@@ -0,0 +1,76 @@
+//! How maps are printed: in color, or with `--plain` in none at all, as
+//! text alone that is the same on every run, for pipes and for comparing
+//! with a saved copy. And the terminal put back to normal colors, with its
+//! cursor showing and out of raw mode, however hexpath ends: a panic or a
+//! ctrl-c in the middle of drawing included.
+
+use crossterm::{
+    QueueableCommand, cursor,
+    style::{Color, ResetColor, SetBackgroundColor, SetForegroundColor},
+    terminal,
+};
+use std::io::{self, IsTerminal, Write};
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+static PLAIN: AtomicBool = AtomicBool::new(false);
+
+/// Prints no colors from now on.
+pub fn set_plain() {
+    PLAIN.store(true, Ordering::SeqCst);
+}
+
+pub fn plain() -> bool {
+    PLAIN.load(Ordering::SeqCst)
+}
+
+/// Sets the color text is printed in, unless plain.
+pub fn color(out: &mut impl Write, color: Color) -> io::Result<()> {
+    if !plain() {
+        out.queue(SetForegroundColor(color))?;
+    }
+    Ok(())
+}
+
+/// Sets the color behind text, unless plain.
+pub fn background(out: &mut impl Write, color: Color) -> io::Result<()> {
+    if !plain() {
+        out.queue(SetBackgroundColor(color))?;
+    }
+    Ok(())
+}
+
+/// How long something took, as printed, unless plain: it changes from
+/// one run to the next.
+pub fn took(elapsed: Duration) -> Option<String> {
+    (!plain()).then(|| format!("{:.2?}", elapsed))
+}
+
+/// How far `value` is from 0 to `dearest`, as a digit from 0 to 9 twice,
+/// for cells plain output can't color.
+pub fn shade(value: usize, dearest: usize) -> String {
+    format!("{0}{0} ", value * 9 / dearest.max(1))
+}
+
+/// Puts the terminal back as it was: out of raw mode, and on stdout, if it
+/// is a terminal, the default colors and the cursor.
+pub fn restore() {
+    let _ = terminal::disable_raw_mode();
+    let mut out = io::stdout();
+    if out.is_terminal() && !plain() {
+        let _ = out.queue(ResetColor);
+        let _ = out.queue(cursor::Show);
+        let _ = out.flush();
+    }
+}
+
+/// Restores the terminal before a panic's message is printed, so it isn't
+/// left colored or raw.
+pub fn restore_on_panic() {
+    let report = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        restore();
+        report(info);
+    }));
+}
@@ -0,0 +1,215 @@
+//! `--visualize --interactive`: the map and its path on the whole screen,
+//! where clicking a cell and then another makes them the new start and
+//! end, the path found again and drawn at once, as often as wanted. Every
+//! `--algo` runs on each pair; the first one's path is drawn, and what each
+//! of them found is listed below the map.
+//!
+//! The map starts zoomed out to fit the screen. Arrows pan, `+` and `-`
+//! zoom in and out, `f` fits it again and `q` quits. Zoomed out, a click
+//! picks the first open cell of the block under it.
+
+use crate::route;
+use crate::search::{Pathfinder, Unwatched};
+use crate::viewport::{self, Viewport};
+use crate::{FullScreen, Grid, palette, value_to_color};
+use crossterm::{
+    ExecutableCommand, QueueableCommand, cursor,
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers, MouseButton, MouseEventKind},
+    style::{Color, Print, SetForegroundColor},
+    terminal,
+};
+use std::collections::HashSet;
+use std::io::{self, Stdout, Write};
+
+/// Lines below the map: what the searches found, then what to do next.
+const STATUS_LINES: u16 = 2;
+
+struct Viewer<'a> {
+    grid: &'a Grid,
+    pathfinders: &'a [Box<dyn Pathfinder>],
+    via: &'a [usize],
+    optimize: bool,
+    start: usize,
+    end: usize,
+    /// Whether the next click picks the end, the start being picked.
+    picking_end: bool,
+    path: HashSet<usize>,
+    view: Viewport,
+    found: String,
+    hint: String,
+}
+
+/// Shows the path from `start` through `via` to `end` until `q`, clicks
+/// picking new endpoints.
+pub fn run(
+    grid: &Grid,
+    pathfinders: &[Box<dyn Pathfinder>],
+    start: usize,
+    via: &[usize],
+    end: usize,
+    optimize: bool,
+) -> io::Result<()> {
+    let mut viewer = Viewer {
+        grid,
+        pathfinders,
+        via,
+        optimize,
+        start,
+        end,
+        picking_end: false,
+        path: HashSet::new(),
+        view: Viewport::fitted(grid, room()),
+        found: String::new(),
+        hint: String::new(),
+    };
+    viewer.search();
+
+    let mut out = io::stdout();
+    let _screen = FullScreen::enter(&mut out)?;
+    out.execute(terminal::Clear(terminal::ClearType::All))?;
+    loop {
+        viewer.draw(&mut out)?;
+        match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => {
+                let view = &mut viewer.view;
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => break,
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break,
+                    KeyCode::Left => view.pan(grid, -1, 0),
+                    KeyCode::Right => view.pan(grid, 1, 0),
+                    KeyCode::Up => view.pan(grid, 0, -1),
+                    KeyCode::Down => view.pan(grid, 0, 1),
+                    KeyCode::Char('+') | KeyCode::Char('=') => view.zoom_in(grid),
+                    KeyCode::Char('-') => view.zoom_out(grid),
+                    KeyCode::Char('f') => *view = Viewport::fitted(grid, room()),
+                    _ => {}
+                }
+            }
+            Event::Mouse(mouse) if mouse.kind == MouseEventKind::Down(MouseButton::Left) => {
+                viewer.click(mouse.column, mouse.row);
+            }
+            Event::Resize(..) => {
+                viewer.view = Viewport::fitted(grid, room());
+                out.execute(terminal::Clear(terminal::ClearType::All))?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// How many blocks fit across and down the screen, with the status lines.
+fn room() -> (usize, usize) {
+    let (width, height) = terminal::size().unwrap_or((80, 24));
+    (
+        width as usize / 3,
+        height.saturating_sub(STATUS_LINES) as usize,
+    )
+}
+
+impl Viewer<'_> {
+    fn at(&self, cell: usize) -> String {
+        self.grid.label(cell)
+    }
+
+    fn click(&mut self, column: u16, row: u16) {
+        let (across, down) = self.view.shown(self.grid);
+        let (x, y) = (column as usize / 3, row as usize);
+        if x >= across || y >= down {
+            return;
+        }
+        let block = self.view.block(self.grid, x, y);
+        let Some(&cell) = block.iter().find(|&&cell| !self.grid.is_wall(cell)) else {
+            let (x, y) = self.grid.index_to_coords(block[0]);
+            self.hint = if self.view.zoom > 1 {
+                format!("✗ ({}, {}) is all walls; click another cell", x, y)
+            } else {
+                format!("✗ ({}, {}) is a wall; click another cell", x, y)
+            };
+            return;
+        };
+
+        if self.picking_end {
+            self.end = cell;
+            self.picking_end = false;
+            self.search();
+        } else {
+            self.start = cell;
+            self.picking_end = true;
+            self.path.clear();
+            self.found = format!("Start: {}", self.at(cell));
+            self.hint = "Click the end".to_string();
+        }
+    }
+
+    fn search(&mut self) {
+        self.path.clear();
+        let mut found = Vec::new();
+        for pathfinder in self.pathfinders {
+            let route = route::plan(
+                pathfinder.as_ref(),
+                self.grid,
+                self.start,
+                self.via,
+                self.end,
+                self.optimize,
+                &mut Unwatched,
+            );
+            match route.search.found {
+                Some((path, cost)) => {
+                    found.push(format!(
+                        "{}: cost {}, {} steps",
+                        pathfinder.name(),
+                        cost,
+                        path.len()
+                    ));
+                    if self.path.is_empty() {
+                        self.path = path.into_iter().collect();
+                    }
+                }
+                None => found.push(format!("{}: no path", pathfinder.name())),
+            }
+        }
+        self.found = format!(
+            "{} → {}: {}",
+            self.at(self.start),
+            self.at(self.end),
+            found.join(" · ")
+        );
+        self.hint = "Click a new start · arrows pan · +/- zoom · f fits · q quits".to_string();
+    }
+
+    fn draw(&self, out: &mut Stdout) -> io::Result<()> {
+        let grid = self.grid;
+        let (across, down) = self.view.shown(grid);
+        for y in 0..down {
+            out.queue(cursor::MoveTo(0, y as u16))?;
+            for x in 0..across {
+                let block = self.view.block(grid, x, y);
+                let (color, text) = if block.contains(&self.start) {
+                    (Color::White, "S  ".to_string())
+                } else if block.contains(&self.end) && !self.picking_end {
+                    (Color::White, "E  ".to_string())
+                } else if let Some(value) = viewport::average(grid, &block) {
+                    if block.iter().any(|cell| self.path.contains(cell)) {
+                        (palette::current().path(), format!("{:02X} ", value))
+                    } else {
+                        (value_to_color(value), format!("{:02X} ", value))
+                    }
+                } else {
+                    (palette::current().blocked(), "██ ".to_string())
+                };
+                out.queue(SetForegroundColor(color))?;
+                out.queue(Print(text))?;
+            }
+            out.queue(terminal::Clear(terminal::ClearType::UntilNewLine))?;
+        }
+        out.queue(SetForegroundColor(Color::Reset))?;
+        out.queue(cursor::MoveTo(0, down as u16))?;
+        out.queue(terminal::Clear(terminal::ClearType::FromCursorDown))?;
+        out.queue(Print(&self.found))?;
+        out.queue(cursor::MoveTo(0, down as u16 + 1))?;
+        out.queue(Print(&self.hint))?;
+        out.flush()
+    }
+}
@@ -0,0 +1,119 @@
+//! The part of a map that fits on the terminal, and how closely it is
+//! seen. At zoom 1 each character cell shows one map cell; at zoom `k` it
+//! shows a `k`×`k` block of them, as their average value, or as a wall if
+//! every cell in it is one. Fitting picks the smallest zoom that shows the
+//! whole map.
+
+use crate::Grid;
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Viewport {
+    /// The map cell at the top-left of the screen.
+    pub corner: (usize, usize),
+    /// Map cells per side of a block.
+    pub zoom: usize,
+    /// How many blocks fit across and down the screen.
+    pub room: (usize, usize),
+}
+
+impl Viewport {
+    /// The whole of `grid`, zoomed out just enough to fit `room` blocks.
+    pub fn fitted(grid: &Grid, room: (usize, usize)) -> Self {
+        let room = (room.0.max(1), room.1.max(1));
+        let zoom = grid
+            .width
+            .div_ceil(room.0)
+            .max(grid.height.div_ceil(room.1))
+            .max(1);
+        Viewport {
+            corner: (0, 0),
+            zoom,
+            room,
+        }
+    }
+
+    /// How many blocks are shown across and down: those that fit and hold
+    /// some of the map.
+    pub fn shown(&self, grid: &Grid) -> (usize, usize) {
+        (
+            grid.width
+                .saturating_sub(self.corner.0)
+                .div_ceil(self.zoom)
+                .min(self.room.0),
+            grid.height
+                .saturating_sub(self.corner.1)
+                .div_ceil(self.zoom)
+                .min(self.room.1),
+        )
+    }
+
+    /// The map cells in the block `across` and `down` from the corner.
+    pub fn block(&self, grid: &Grid, across: usize, down: usize) -> Vec<usize> {
+        let left = self.corner.0 + across * self.zoom;
+        let top = self.corner.1 + down * self.zoom;
+        (top..(top + self.zoom).min(grid.height))
+            .flat_map(|y| (left..(left + self.zoom).min(grid.width)).map(move |x| (x, y)))
+            .map(|(x, y)| grid.coords_to_index(x, y))
+            .collect()
+    }
+
+    /// Moves by `across` and `down` blocks, staying on the map.
+    pub fn pan(&mut self, grid: &Grid, across: isize, down: isize) {
+        let step = |corner: usize, by: isize| corner.saturating_add_signed(by * self.zoom as isize);
+        self.corner = (step(self.corner.0, across), step(self.corner.1, down));
+        self.clamp(grid);
+    }
+
+    /// Halves the block size, keeping the middle of the screen in place.
+    pub fn zoom_in(&mut self, grid: &Grid) {
+        self.rezoom(grid, (self.zoom / 2).max(1));
+    }
+
+    /// Doubles the block size, up to showing the whole map.
+    pub fn zoom_out(&mut self, grid: &Grid) {
+        let whole = Viewport::fitted(grid, self.room).zoom;
+        self.rezoom(grid, (self.zoom * 2).min(whole));
+    }
+
+    fn rezoom(&mut self, grid: &Grid, zoom: usize) {
+        let middle = |corner: usize, room: usize, zoom: usize| corner + room * zoom / 2;
+        let (x, y) = (
+            middle(self.corner.0, self.room.0, self.zoom),
+            middle(self.corner.1, self.room.1, self.zoom),
+        );
+        self.zoom = zoom;
+        self.corner = (
+            x.saturating_sub(self.room.0 * zoom / 2),
+            y.saturating_sub(self.room.1 * zoom / 2),
+        );
+        self.clamp(grid);
+    }
+
+    /// Keeps the screen from showing past the right and bottom of the map
+    /// when it can, and the corner on block boundaries.
+    fn clamp(&mut self, grid: &Grid) {
+        let axis = |corner: usize, size: usize, room: usize| {
+            let last = size.saturating_sub(room * self.zoom);
+            corner.min(last.div_ceil(self.zoom) * self.zoom) / self.zoom * self.zoom
+        };
+        self.corner = (
+            axis(self.corner.0, grid.width, self.room.0),
+            axis(self.corner.1, grid.height, self.room.1),
+        );
+    }
+}
+
+/// The average value of the open cells in `block`, or `None` if all of
+/// them are walls.
+pub fn average(grid: &Grid, block: &[usize]) -> Option<u8> {
+    let open: Vec<u64> = block
+        .iter()
+        .filter(|&&cell| !grid.is_wall(cell))
+        .map(|&cell| grid.value(cell) as u64)
+        .collect();
+    if open.is_empty() {
+        None
+    } else {
+        Some((open.iter().sum::<u64>() / open.len() as u64) as u8)
+    }
+}